@@ -0,0 +1,457 @@
+//! `tanoshi-cli test`, runs every operation of an extension against the live
+//! source and checks what comes back, for the extensions repository CI
+
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+use tanoshi_lib::prelude::{ChapterInfo, MangaInfo, PageInfo, Paginated, SourceInfo};
+use tanoshi_vm::prelude::{ExtensionManager, detect_image_type};
+
+pub struct Options {
+    /// Searched for, the first word of a listed title when not set
+    pub query: Option<String>,
+    /// Manga to read the detail and chapters of, the first listed when not
+    /// set
+    pub manga: Option<String>,
+    /// Pages whose image is fetched
+    pub images: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    /// `popular`, `latest`, `search`, `detail`, `chapters`, `pages` or
+    /// `image N`
+    pub name: String,
+    pub status: Status,
+    pub duration_ms: u64,
+    /// What was returned, or why the check was skipped
+    pub note: String,
+    /// The error or the broken invariants of a failed check
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub source_id: i64,
+    pub source: String,
+    pub version: String,
+    pub passed: bool,
+    pub duration_ms: u64,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// One line per check, followed by its problems
+    pub fn to_human(&self) -> String {
+        let mut out = format!("{} {} (id {})\n", self.source, self.version, self.source_id);
+        for check in &self.checks {
+            let status = match check.status {
+                Status::Passed => "PASS",
+                Status::Failed => "FAIL",
+                Status::Skipped => "SKIP",
+            };
+            let _ = writeln!(
+                out,
+                "  {status} {:<10} {:>7}  {}",
+                check.name,
+                format_duration(check.duration_ms),
+                check.note
+            );
+            for problem in &check.problems {
+                let _ = writeln!(out, "       - {problem}");
+            }
+        }
+
+        let count = |status: fn(&Status) -> bool| {
+            self.checks
+                .iter()
+                .filter(|check| status(&check.status))
+                .count()
+        };
+        let _ = writeln!(
+            out,
+            "{}: {} passed, {} failed, {} skipped in {}",
+            if self.passed { "ok" } else { "FAILED" },
+            count(|status| matches!(status, Status::Passed)),
+            count(|status| matches!(status, Status::Failed)),
+            count(|status| matches!(status, Status::Skipped)),
+            format_duration(self.duration_ms)
+        );
+        out
+    }
+}
+
+fn format_duration(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{ms}ms")
+    } else {
+        format!("{:.2}s", ms as f64 / 1000.0)
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+#[derive(Default)]
+struct Harness {
+    checks: Vec<Check>,
+}
+
+impl Harness {
+    /// Time `operation`, then record it as passed when `validate` finds no
+    /// problem with its result
+    async fn check<T>(
+        &mut self,
+        name: impl Into<String>,
+        operation: impl Future<Output = Result<T>>,
+        validate: impl FnOnce(&T) -> (String, Vec<String>),
+    ) -> Option<T> {
+        let started = Instant::now();
+        let result = operation.await;
+        let duration_ms = millis(started.elapsed());
+
+        let (value, note, problems) = match result {
+            Ok(value) => {
+                let (note, problems) = validate(&value);
+                (Some(value), note, problems)
+            }
+            Err(error) => (None, String::new(), vec![format!("{error:#}")]),
+        };
+        self.checks.push(Check {
+            name: name.into(),
+            status: if problems.is_empty() {
+                Status::Passed
+            } else {
+                Status::Failed
+            },
+            duration_ms,
+            note,
+            problems,
+        });
+        value
+    }
+
+    fn skip(&mut self, name: impl Into<String>, reason: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            status: Status::Skipped,
+            duration_ms: 0,
+            note: reason.into(),
+            problems: vec![],
+        });
+    }
+
+    async fn listing(
+        &mut self,
+        name: &str,
+        operation: impl Future<Output = Result<Paginated<MangaInfo>>>,
+    ) -> Vec<MangaInfo> {
+        self.check(name, operation, |page| {
+            let note = format!(
+                "{} manga{}",
+                page.items.len(),
+                if page.has_next_page {
+                    ", more pages"
+                } else {
+                    ""
+                }
+            );
+            (note, manga_problems(&page.items))
+        })
+        .await
+        .map(|page| page.items)
+        .unwrap_or_default()
+    }
+
+    fn finish(self, source: &SourceInfo, started: Instant) -> Report {
+        Report {
+            source_id: source.id,
+            source: source.name.clone(),
+            version: source.version.to_string(),
+            passed: !self
+                .checks
+                .iter()
+                .any(|check| matches!(check.status, Status::Failed)),
+            duration_ms: millis(started.elapsed()),
+            checks: self.checks,
+        }
+    }
+}
+
+fn manga_problems(manga: &[MangaInfo]) -> Vec<String> {
+    let mut problems = vec![];
+    if manga.is_empty() {
+        problems.push("no manga returned".to_string());
+    }
+    let mut paths = HashSet::new();
+    for (index, manga) in manga.iter().enumerate() {
+        if manga.title.trim().is_empty() {
+            problems.push(format!("manga {index} ({}) has an empty title", manga.path));
+        }
+        if manga.path.trim().is_empty() {
+            problems.push(format!("manga {index} ({}) has an empty path", manga.title));
+        } else if !paths.insert(manga.path.as_str()) {
+            problems.push(format!("manga path {} is listed twice", manga.path));
+        }
+    }
+    problems
+}
+
+fn chapter_problems(chapters: &[ChapterInfo]) -> Vec<String> {
+    let mut problems = vec![];
+    if chapters.is_empty() {
+        problems.push("no chapters returned".to_string());
+    }
+    let mut paths = HashSet::new();
+    for chapter in chapters {
+        if chapter.title.trim().is_empty() {
+            problems.push(format!("chapter {} has an empty title", chapter.path));
+        }
+        if chapter.path.trim().is_empty() {
+            problems.push(format!("chapter {} has an empty path", chapter.title));
+        } else if !paths.insert(chapter.path.as_str()) {
+            problems.push(format!("chapter path {} is listed twice", chapter.path));
+        }
+        if !chapter.number.is_finite() || chapter.number < 0.0 {
+            problems.push(format!(
+                "chapter {} has no usable number ({})",
+                chapter.title, chapter.number
+            ));
+        }
+    }
+    problems
+}
+
+fn page_problems(pages: &[PageInfo]) -> Vec<String> {
+    let mut problems = vec![];
+    if pages.is_empty() {
+        problems.push("no pages returned".to_string());
+    }
+    for (index, page) in pages.iter().enumerate() {
+        if page.url.trim().is_empty() {
+            problems.push(format!("page {index} has an empty url"));
+        }
+    }
+    problems
+}
+
+/// Run the listings, then read the first manga and chapter found down to
+/// their images
+pub async fn run(
+    manager: &ExtensionManager,
+    source: &SourceInfo,
+    options: &Options,
+) -> Result<Report> {
+    let started = Instant::now();
+    let (source_id, capabilities) = (source.id, &source.capabilities);
+    let mut harness = Harness::default();
+
+    let mut listed = vec![];
+    if capabilities.popular {
+        listed.extend(
+            harness
+                .listing("popular", manager.get_popular_manga(source_id, 1))
+                .await,
+        );
+    } else {
+        harness.skip("popular", "not supported by the source");
+    }
+    if capabilities.latest {
+        listed.extend(
+            harness
+                .listing("latest", manager.get_latest_manga(source_id, 1))
+                .await,
+        );
+    } else {
+        harness.skip("latest", "not supported by the source");
+    }
+
+    let query = options.query.clone().or_else(|| {
+        listed
+            .iter()
+            .find_map(|manga| manga.title.split_whitespace().next())
+            .map(str::to_string)
+    });
+    match query {
+        Some(_) if !capabilities.search => harness.skip("search", "not supported by the source"),
+        Some(query) => {
+            let found = harness
+                .listing(
+                    "search",
+                    manager.search_manga(source_id, 1, Some(query), None),
+                )
+                .await;
+            listed.extend(found);
+        }
+        None => harness.skip("search", "no query, pass --query"),
+    }
+
+    let Some(manga_path) = options
+        .manga
+        .clone()
+        .or_else(|| listed.first().map(|manga| manga.path.clone()))
+    else {
+        for name in ["detail", "chapters", "pages"] {
+            harness.skip(name, "no manga listed, pass --manga");
+        }
+        return Ok(harness.finish(source, started));
+    };
+
+    harness
+        .check(
+            "detail",
+            manager.get_manga_detail(source_id, manga_path.clone()),
+            |manga| {
+                let mut problems = vec![];
+                if manga.title.trim().is_empty() {
+                    problems.push("the manga has an empty title".to_string());
+                }
+                (manga.title.clone(), problems)
+            },
+        )
+        .await;
+
+    let chapters = harness
+        .check(
+            "chapters",
+            manager.get_chapters(source_id, manga_path.clone()),
+            |chapters| {
+                (
+                    format!("{} chapters of {manga_path}", chapters.len()),
+                    chapter_problems(chapters),
+                )
+            },
+        )
+        .await
+        .unwrap_or_default();
+    let Some(chapter) = chapters.first() else {
+        harness.skip("pages", "no chapter to read");
+        return Ok(harness.finish(source, started));
+    };
+
+    let pages = harness
+        .check(
+            "pages",
+            manager.get_pages(source_id, chapter.path.clone()),
+            |pages| {
+                (
+                    format!("{} pages of {}", pages.len(), chapter.path),
+                    page_problems(pages),
+                )
+            },
+        )
+        .await
+        .unwrap_or_default();
+
+    for (index, page) in pages.into_iter().take(options.images).enumerate() {
+        let url = page.url.clone();
+        let image = async {
+            let bytes = manager.get_image_bytes(source_id, page).await?;
+            bytes.collect_bytes().await
+        };
+        harness
+            .check(
+                format!("image {}", index + 1),
+                image,
+                |bytes| match detect_image_type(bytes) {
+                    Some(mime) => (format!("{mime}, {} bytes", bytes.len()), vec![]),
+                    None => (
+                        format!("{} bytes", bytes.len()),
+                        vec![format!("{url} is not a known image type")],
+                    ),
+                },
+            )
+            .await;
+    }
+
+    Ok(harness.finish(source, started))
+}
+
+#[cfg(test)]
+mod tests {
+    use tanoshi_lib::prelude::{ChapterInfo, MangaInfo, PageInfo};
+
+    use super::{chapter_problems, manga_problems, page_problems};
+
+    fn manga(title: &str, path: &str) -> MangaInfo {
+        MangaInfo {
+            title: title.to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn chapter(title: &str, path: &str, number: f64) -> ChapterInfo {
+        ChapterInfo {
+            title: title.to_string(),
+            path: path.to_string(),
+            number,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn manga_need_a_title_and_a_unique_path() {
+        assert!(manga_problems(&[manga("One", "/one"), manga("Two", "/two")]).is_empty());
+        assert_eq!(manga_problems(&[]), ["no manga returned"]);
+        assert_eq!(
+            manga_problems(&[manga(" ", "/one"), manga("Two", ""), manga("One", "/one")]),
+            [
+                "manga 0 (/one) has an empty title",
+                "manga 1 (Two) has an empty path",
+                "manga path /one is listed twice",
+            ]
+        );
+    }
+
+    #[test]
+    fn chapters_need_a_title_a_unique_path_and_a_number() {
+        assert!(
+            chapter_problems(&[chapter("Ch. 1", "/1", 1.0), chapter("Ch. 1.5", "/1.5", 1.5)])
+                .is_empty()
+        );
+        assert_eq!(chapter_problems(&[]), ["no chapters returned"]);
+        assert_eq!(
+            chapter_problems(&[
+                chapter("", "/1", 1.0),
+                chapter("Ch. 2", "/1", f64::NAN),
+                chapter("Ch. 3", " ", -1.0),
+            ]),
+            [
+                "chapter /1 has an empty title",
+                "chapter path /1 is listed twice",
+                "chapter Ch. 2 has no usable number (NaN)",
+                "chapter Ch. 3 has an empty path",
+                "chapter Ch. 3 has no usable number (-1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_need_a_url() {
+        assert!(page_problems(&[PageInfo::new("https://example.com/1.jpg")]).is_empty());
+        assert_eq!(page_problems(&[]), ["no pages returned"]);
+        assert_eq!(
+            page_problems(&[
+                PageInfo::new("https://example.com/1.jpg"),
+                PageInfo::new("")
+            ]),
+            ["page 1 has an empty url"]
+        );
+    }
+}
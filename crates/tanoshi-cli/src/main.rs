@@ -1,11 +1,20 @@
 extern crate log;
 
-use std::path::PathBuf;
+mod harness;
+mod query;
 
-use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand};
 use serde::Serialize;
 use tanoshi_lib::prelude::SourceInfo;
-use tanoshi_vm::{prelude::ExtensionManager, PLUGIN_EXTENSION};
+use tanoshi_vm::{
+    prelude::{
+        sha256_hex, sign_plugin, verifying_key, ExtensionManager, ExtensionManagerOptions,
+        HttpFixtureMode, HttpOptions,
+    },
+    PLUGIN_EXTENSION,
+};
 
 const TARGET: &str = env!("TARGET");
 
@@ -21,7 +30,34 @@ struct Opts {
 #[derive(Subcommand)]
 enum Command {
     /// Generate index.json
-    GenerateJson,
+    GenerateJson {
+        /// File with the base64 encoded ed25519 secret key to sign the
+        /// extensions with
+        #[clap(long)]
+        signing_key: Option<PathBuf>,
+    },
+    /// Run every operation of an extension against its source and check the
+    /// results, exits with an error when a check fails
+    Test {
+        /// The extension library, or a template file
+        plugin: PathBuf,
+        /// Search query, the first word of a listed title by default
+        #[clap(long)]
+        query: Option<String>,
+        /// Path of the manga to read, the first one listed by default
+        #[clap(long)]
+        manga: Option<String>,
+        /// Number of page images to fetch
+        #[clap(long, default_value_t = 3)]
+        images: usize,
+        /// Print the report as JSON
+        #[clap(long)]
+        json: bool,
+        #[clap(flatten)]
+        fixtures: FixtureArgs,
+    },
+    #[clap(flatten)]
+    Query(query::Query),
 }
 
 #[derive(Debug, Serialize)]
@@ -30,6 +66,72 @@ struct SourceIndex {
     source: SourceInfo,
     rustc_version: String,
     lib_version: String,
+    sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+/// Where the HTTP exchanges of the extension are recorded to or replayed
+/// from
+#[derive(Args)]
+struct FixtureArgs {
+    /// Save every HTTP exchange of the extension to a fixture file
+    #[clap(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Answer the extension's HTTP requests from a fixture file, without
+    /// network access
+    #[clap(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+}
+
+impl FixtureArgs {
+    fn mode(&self) -> Option<HttpFixtureMode> {
+        match (&self.record, &self.replay) {
+            (Some(path), _) => Some(HttpFixtureMode::Record(path.clone())),
+            (None, Some(path)) => Some(HttpFixtureMode::Replay(path.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Load a single extension library or template file from a scratch plugin
+/// directory, so nothing stored next to it is loaded as well
+async fn load_plugin(
+    path: &Path,
+    fixtures: &FixtureArgs,
+) -> anyhow::Result<(ExtensionManager, SourceInfo, PathBuf)> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("{} is not a plugin file", path.display()))?;
+    #[cfg(target_os = "linux")]
+    let name = if name.ends_with(PLUGIN_EXTENSION) {
+        name.strip_prefix("lib").unwrap_or(name)
+    } else {
+        name
+    };
+
+    let dir = std::env::temp_dir().join(format!("tanoshi-cli-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await?;
+    tokio::fs::copy(path, dir.join(name)).await?;
+
+    let extension_manager = ExtensionManager::new_with_options(
+        &dir,
+        ExtensionManagerOptions {
+            http: HttpOptions {
+                fixtures: fixtures.mode(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+    extension_manager.load_all().await?;
+    let mut sources = extension_manager.list().await?;
+    if sources.len() != 1 {
+        anyhow::bail!("{} did not load as a source", path.display());
+    }
+
+    Ok((extension_manager, sources.remove(0), dir))
 }
 
 #[tokio::main]
@@ -39,7 +141,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opts: Opts = Opts::parse();
 
     match opts.subcmd {
-        Command::GenerateJson => {
+        Command::GenerateJson { signing_key } => {
+            let signing_key = match signing_key {
+                Some(path) => {
+                    let key = tokio::fs::read_to_string(path).await?;
+                    println!("signing with public key {}", verifying_key(&key)?);
+                    Some(key)
+                }
+                None => None,
+            };
+
             let target_dir_path = PathBuf::new().join("output").join(TARGET);
             tokio::fs::create_dir_all(&target_dir_path).await?;
 
@@ -61,16 +172,61 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let mut indexes = vec![];
             for source in source_list {
                 let (rustc_version, lib_version) = extension_manager.get_version(source.id)?;
+                let plugin_path = extension_manager
+                    .plugin_path(source.id)?
+                    .ok_or_else(|| anyhow::anyhow!("{} has no library", source.name))?;
+                let contents = tokio::fs::read(plugin_path).await?;
+                let signature = signing_key
+                    .as_deref()
+                    .map(|key| sign_plugin(&source.name, source.version, &contents, key))
+                    .transpose()?;
                 indexes.push(SourceIndex {
                     source,
                     rustc_version,
                     lib_version,
+                    sha256: sha256_hex(&contents),
+                    signature,
                 });
             }
 
             let json = serde_json::to_string(&indexes)?;
             tokio::fs::write(target_dir_path.join("index").with_extension("json"), json).await?;
         }
+        Command::Test {
+            plugin,
+            query,
+            manga,
+            images,
+            json,
+            fixtures,
+        } => {
+            let (extension_manager, source, dir) = load_plugin(&plugin, &fixtures).await?;
+            let options = harness::Options {
+                query,
+                manga,
+                images,
+            };
+            let report = harness::run(&extension_manager, &source, &options).await;
+            let _ = tokio::fs::remove_dir_all(dir).await;
+            let report = report?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report.to_human());
+            }
+            if !report.passed {
+                std::process::exit(1);
+            }
+        }
+        Command::Query(query) => {
+            let plugin = query.plugin();
+            let (extension_manager, source, dir) =
+                load_plugin(&plugin.plugin, &plugin.fixtures).await?;
+            let result = query.run(&extension_manager, source.id).await;
+            let _ = tokio::fs::remove_dir_all(dir).await;
+            result?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,478 @@
+//! Subcommands calling a single operation of an extension, to debug it
+//! without running the server
+
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow, bail};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+use tanoshi_lib::prelude::{
+    ChapterInfo, Input, InputType, MangaInfo, PageInfo, Paginated, TriState,
+};
+use tanoshi_vm::prelude::{ExtensionManager, detect_image_type};
+
+use crate::FixtureArgs;
+
+#[derive(Args)]
+pub struct PluginArgs {
+    /// The extension library, or a template file
+    pub plugin: PathBuf,
+    /// Print JSON instead of a table
+    #[clap(long)]
+    json: bool,
+    #[clap(flatten)]
+    pub fixtures: FixtureArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Query {
+    /// List popular manga
+    Popular {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+        #[clap(long, default_value_t = 1)]
+        page: i64,
+    },
+    /// List latest updated manga
+    Latest {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+        #[clap(long, default_value_t = 1)]
+        page: i64,
+    },
+    /// Search manga, see `filters` for the filters a source has
+    Search {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+        query: Option<String>,
+        /// Set a filter, e.g. `Genre=include`, `Status=Completed`,
+        /// `Sort=Views:desc` or `Adult=true`
+        #[clap(long = "filter", value_name = "NAME=VALUE")]
+        filters: Vec<String>,
+        #[clap(long, default_value_t = 1)]
+        page: i64,
+    },
+    /// List the search filters of a source
+    Filters {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+    },
+    /// Show the detail of a manga
+    Detail {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+        path: String,
+    },
+    /// List the chapters of a manga
+    Chapters {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+        path: String,
+    },
+    /// List the pages of a chapter
+    Pages {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+        path: String,
+    },
+    /// Download a page image
+    Image {
+        #[clap(flatten)]
+        plugin: PluginArgs,
+        /// Image url, or a page as printed by `pages --json`
+        page: String,
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+}
+
+impl Query {
+    pub fn plugin(&self) -> &PluginArgs {
+        match self {
+            Query::Popular { plugin, .. }
+            | Query::Latest { plugin, .. }
+            | Query::Search { plugin, .. }
+            | Query::Filters { plugin }
+            | Query::Detail { plugin, .. }
+            | Query::Chapters { plugin, .. }
+            | Query::Pages { plugin, .. }
+            | Query::Image { plugin, .. } => plugin,
+        }
+    }
+
+    pub async fn run(self, manager: &ExtensionManager, source_id: i64) -> Result<()> {
+        let json = self.plugin().json;
+        match self {
+            Query::Popular { page, .. } => {
+                print_manga(manager.get_popular_manga(source_id, page).await?, json)
+            }
+            Query::Latest { page, .. } => {
+                print_manga(manager.get_latest_manga(source_id, page).await?, json)
+            }
+            Query::Search {
+                query,
+                filters,
+                page,
+                ..
+            } => {
+                let filters = if filters.is_empty() {
+                    None
+                } else {
+                    let mut inputs = manager.filter_list(source_id).await?;
+                    for filter in &filters {
+                        let (name, value) = filter
+                            .split_once('=')
+                            .ok_or_else(|| anyhow!("filter {filter} is not NAME=VALUE"))?;
+                        set_filter(&mut inputs, name, value)?;
+                    }
+                    Some(inputs)
+                };
+                let found = manager
+                    .search_manga(source_id, page, query, filters)
+                    .await?;
+                print_manga(found, json)
+            }
+            Query::Filters { .. } => {
+                let inputs = manager.filter_list(source_id).await?;
+                if json {
+                    return print_json(&inputs);
+                }
+                let mut rows = vec![];
+                filter_rows(&inputs, "", &mut rows);
+                print_table(&["name", "kind", "values"], rows);
+                Ok(())
+            }
+            Query::Detail { path, .. } => {
+                let manga = manager.get_manga_detail(source_id, path).await?;
+                if json {
+                    return print_json(&manga);
+                }
+                print_table(&["field", "value"], manga_fields(&manga));
+                Ok(())
+            }
+            Query::Chapters { path, .. } => {
+                let chapters = manager.get_chapters(source_id, path).await?;
+                if json {
+                    return print_json(&chapters);
+                }
+                print_table(
+                    &["number", "title", "path", "uploaded"],
+                    chapters.iter().map(chapter_row).collect(),
+                );
+                Ok(())
+            }
+            Query::Pages { path, .. } => {
+                let pages = manager.get_pages(source_id, path).await?;
+                if json {
+                    return print_json(&pages);
+                }
+                print_table(
+                    &["page", "url"],
+                    pages
+                        .iter()
+                        .enumerate()
+                        .map(|(index, page)| vec![(index + 1).to_string(), page.url.clone()])
+                        .collect(),
+                );
+                Ok(())
+            }
+            Query::Image { page, output, .. } => {
+                let page = if page.trim_start().starts_with('{') {
+                    serde_json::from_str::<PageInfo>(&page)?
+                } else {
+                    PageInfo::new(page)
+                };
+                let bytes = manager
+                    .get_image_bytes(source_id, page)
+                    .await?
+                    .collect_bytes()
+                    .await?;
+                tokio::fs::write(&output, &bytes).await?;
+                println!(
+                    "wrote {} bytes of {} to {}",
+                    bytes.len(),
+                    detect_image_type(&bytes).unwrap_or("unknown type"),
+                    output.display()
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+fn print_json(value: &impl Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_manga(page: Paginated<MangaInfo>, json: bool) -> Result<()> {
+    if json {
+        return print_json(&page);
+    }
+    print_table(
+        &["title", "path"],
+        page.items
+            .iter()
+            .map(|manga| vec![manga.title.clone(), manga.path.clone()])
+            .collect(),
+    );
+    if page.has_next_page {
+        println!("more on the next page");
+    }
+    Ok(())
+}
+
+/// Print rows under a header, padding each column to its widest cell
+fn print_table(header: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths = header
+        .iter()
+        .map(|title| title.chars().count())
+        .collect::<Vec<_>>();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    line(header.to_vec());
+    for row in &rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn manga_fields(manga: &MangaInfo) -> Vec<Vec<String>> {
+    let mut fields = vec![
+        ("title", manga.title.clone()),
+        ("alternative titles", manga.alternative_titles.join(", ")),
+        ("author", manga.author.join(", ")),
+        ("artist", manga.artist.join(", ")),
+        ("genre", manga.genre.join(", ")),
+        (
+            "status",
+            manga
+                .status
+                .map(|status| status.to_string())
+                .unwrap_or_default(),
+        ),
+        (
+            "content rating",
+            manga
+                .content_rating
+                .map(|rating| rating.to_string())
+                .unwrap_or_default(),
+        ),
+        ("path", manga.path.clone()),
+        ("cover url", manga.cover_url.clone()),
+        ("description", manga.description.clone().unwrap_or_default()),
+    ];
+    for (site, id) in &manga.external_ids {
+        fields.push(("external id", format!("{site}: {id}")));
+    }
+    fields
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(field, value)| vec![field.to_string(), value])
+        .collect()
+}
+
+fn chapter_row(chapter: &ChapterInfo) -> Vec<String> {
+    vec![
+        chapter.number.to_string(),
+        chapter.title.clone(),
+        chapter.path.clone(),
+        chapter.uploaded.to_string(),
+    ]
+}
+
+fn input_value(value: &InputType) -> String {
+    match value {
+        InputType::String(value) => value.clone(),
+        InputType::Number(value) => value.to_string(),
+        InputType::Boolean(value) => value.to_string(),
+    }
+}
+
+fn filter_rows(inputs: &[Input], group: &str, rows: &mut Vec<Vec<String>>) {
+    for input in inputs {
+        let name = format!("{group}{}", input.name());
+        let (kind, values) = match input {
+            Input::Text { .. } => ("text", String::new()),
+            Input::Checkbox { .. } => ("checkbox", "true, false".to_string()),
+            Input::Select { values, .. } => (
+                "select",
+                values
+                    .iter()
+                    .map(input_value)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Input::Sort { values, .. } => (
+                "sort",
+                format!(
+                    "{}, with :asc or :desc",
+                    values
+                        .iter()
+                        .map(input_value)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ),
+            Input::State { .. } => ("state", "include, exclude, ignore".to_string()),
+            Input::Group { state, .. } => {
+                rows.push(vec![name.clone(), "group".to_string(), String::new()]);
+                filter_rows(state, &format!("  {group}"), rows);
+                continue;
+            }
+        };
+        rows.push(vec![name, kind.to_string(), values]);
+    }
+}
+
+/// The filter called `name`, looking into groups as well
+fn find_filter<'a>(inputs: &'a mut [Input], name: &str) -> Option<&'a mut Input> {
+    for input in inputs {
+        if input.name().eq_ignore_ascii_case(name) {
+            return Some(input);
+        }
+        if let Input::Group { state, .. } = input
+            && let Some(input) = find_filter(state, name)
+        {
+            return Some(input);
+        }
+    }
+    None
+}
+
+fn value_index(name: &str, values: &[InputType], value: &str) -> Result<i64> {
+    values
+        .iter()
+        .position(|candidate| input_value(candidate).eq_ignore_ascii_case(value))
+        .or_else(|| value.parse().ok().filter(|index| *index < values.len()))
+        .map(|index| index as i64)
+        .ok_or_else(|| {
+            anyhow!(
+                "{value} is not a value of {name}, expected one of {}",
+                values
+                    .iter()
+                    .map(input_value)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// Set the state of the filter called `name` from its command line value
+fn set_filter(inputs: &mut [Input], name: &str, value: &str) -> Result<()> {
+    let input = find_filter(inputs, name).ok_or_else(|| anyhow!("no filter called {name}"))?;
+    match input {
+        Input::Text { state, .. } => *state = Some(value.to_string()),
+        Input::Checkbox { state, .. } => {
+            *state = Some(match value.to_lowercase().as_str() {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                _ => bail!("{name} is a checkbox, expected true or false"),
+            })
+        }
+        Input::Select { values, state, .. } => *state = Some(value_index(name, values, value)?),
+        Input::Sort {
+            values, selection, ..
+        } => {
+            let (value, ascending) = match value.rsplit_once(':') {
+                Some((value, "asc")) => (value, true),
+                Some((value, "desc")) => (value, false),
+                _ => (value, true),
+            };
+            *selection = Some((value_index(name, values, value)?, ascending));
+        }
+        Input::State { selected, .. } => {
+            *selected = Some(match value.to_lowercase().as_str() {
+                "include" | "included" => TriState::Included,
+                "exclude" | "excluded" => TriState::Excluded,
+                "ignore" | "ignored" => TriState::Ignored,
+                _ => bail!("{name} is a state, expected include, exclude or ignore"),
+            })
+        }
+        Input::Group { .. } => bail!("{name} is a group, set the filters in it by their name"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tanoshi_lib::prelude::{Input, TriState};
+
+    use super::set_filter;
+
+    fn filters() -> Vec<Input> {
+        vec![
+            Input::Text {
+                name: "Author".to_string(),
+                state: None,
+            },
+            Input::Select {
+                name: "Status".to_string(),
+                values: vec!["Ongoing".into(), "Completed".into()],
+                state: None,
+            },
+            Input::Sort {
+                name: "Sort".to_string(),
+                values: vec!["Title".into(), "Views".into()],
+                selection: None,
+            },
+            Input::Group {
+                name: "Genres".to_string(),
+                state: vec![Input::State {
+                    name: "Action".to_string(),
+                    selected: None,
+                }],
+            },
+        ]
+    }
+
+    #[test]
+    fn filters_are_set_from_their_command_line_values() {
+        let mut inputs = filters();
+        set_filter(&mut inputs, "author", "Oda").unwrap();
+        set_filter(&mut inputs, "Status", "completed").unwrap();
+        set_filter(&mut inputs, "Sort", "Views:desc").unwrap();
+        set_filter(&mut inputs, "Action", "exclude").unwrap();
+
+        assert!(matches!(&inputs[0], Input::Text { state: Some(state), .. } if state == "Oda"));
+        assert!(matches!(inputs[1], Input::Select { state: Some(1), .. }));
+        assert!(matches!(
+            inputs[2],
+            Input::Sort {
+                selection: Some((1, false)),
+                ..
+            }
+        ));
+        let Input::Group { state, .. } = &inputs[3] else {
+            panic!("expected a group");
+        };
+        assert!(matches!(
+            state[0],
+            Input::State {
+                selected: Some(TriState::Excluded),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn unknown_filters_and_values_are_rejected() {
+        let mut inputs = filters();
+        assert!(set_filter(&mut inputs, "Year", "2020").is_err());
+        assert!(set_filter(&mut inputs, "Status", "Hiatus").is_err());
+        assert!(set_filter(&mut inputs, "Genres", "include").is_err());
+        assert!(set_filter(&mut inputs, "Status", "0").is_ok());
+    }
+}
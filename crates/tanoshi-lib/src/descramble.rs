@@ -0,0 +1,169 @@
+//! Helpers for [`crate::extensions::Extension::process_image`] to undo common
+//! page obfuscation.
+
+use bytes::Bytes;
+
+/// XOR every byte with `key`, repeating the key over the whole image
+pub fn xor(bytes: &[u8], key: &[u8]) -> Bytes {
+    if key.is_empty() {
+        return Bytes::copy_from_slice(bytes);
+    }
+
+    bytes
+        .iter()
+        .zip(key.iter().cycle())
+        .map(|(byte, key)| byte ^ key)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Reassemble an image split into a `columns` x `rows` grid of shuffled tiles.
+/// `order[i]` is the position of the tile in the scrambled image that belongs
+/// at position `i`, positions counting left to right then top to bottom.
+/// Pixels that don't fill a whole tile on the right and bottom edges are kept
+/// in place. The result is encoded as PNG.
+///
+/// Fails when `order` is not a permutation of the grid's tiles or the image
+/// is smaller than the grid.
+#[cfg(feature = "descramble")]
+pub fn reorder_tiles(
+    bytes: &[u8],
+    columns: u32,
+    rows: u32,
+    order: &[usize],
+) -> anyhow::Result<Bytes> {
+    use anyhow::bail;
+    use image::{GenericImageView, ImageFormat, RgbaImage};
+
+    let Some(tiles) = columns
+        .checked_mul(rows)
+        .and_then(|tiles| usize::try_from(tiles).ok())
+        .filter(|&tiles| tiles > 0)
+    else {
+        bail!("invalid {columns}x{rows} tile grid");
+    };
+    let mut seen = vec![false; order.len()];
+    if order.len() != tiles
+        || !order
+            .iter()
+            .all(|&tile| tile < tiles && !std::mem::replace(&mut seen[tile], true))
+    {
+        bail!("tile order must have each of the {tiles} tiles of the grid once");
+    }
+
+    let scrambled = image::load_from_memory(bytes)?;
+    let (width, height) = scrambled.dimensions();
+    let tile_width = width / columns;
+    let tile_height = height / rows;
+    if tile_width == 0 || tile_height == 0 {
+        bail!("{width}x{height} image is smaller than the {columns}x{rows} tile grid");
+    }
+
+    let mut image = RgbaImage::from(scrambled.clone());
+    for (position, &tile) in order.iter().enumerate() {
+        let (x, y) = tile_origin(position, columns, tile_width, tile_height);
+        let (from_x, from_y) = tile_origin(tile, columns, tile_width, tile_height);
+        let piece = scrambled
+            .view(from_x, from_y, tile_width, tile_height)
+            .to_image();
+        image::imageops::replace(&mut image, &piece, x as i64, y as i64);
+    }
+
+    let mut output = std::io::Cursor::new(vec![]);
+    image.write_to(&mut output, ImageFormat::Png)?;
+
+    Ok(output.into_inner().into())
+}
+
+#[cfg(feature = "descramble")]
+fn tile_origin(index: usize, columns: u32, tile_width: u32, tile_height: u32) -> (u32, u32) {
+    let index = index as u32;
+    (
+        (index % columns) * tile_width,
+        (index / columns) * tile_height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xor_round_trips() {
+        let image = b"\x89PNG not really an image";
+        let key = b"key";
+        let scrambled = xor(image, key);
+        assert_ne!(scrambled.as_ref(), image);
+        assert_eq!(scrambled[0], image[0] ^ b'k');
+        assert_eq!(scrambled[3], image[3] ^ b'k');
+        assert_eq!(xor(&scrambled, key).as_ref(), image);
+    }
+
+    #[test]
+    fn xor_with_empty_key_keeps_the_image() {
+        assert_eq!(xor(b"image", b"").as_ref(), b"image");
+    }
+
+    #[cfg(feature = "descramble")]
+    mod tiles {
+        use image::{ImageFormat, Rgba, RgbaImage};
+
+        use super::super::reorder_tiles;
+
+        /// A `width` x `height` image where every pixel has its own color
+        fn numbered(width: u32, height: u32) -> RgbaImage {
+            RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 0, 255]))
+        }
+
+        fn png(image: &RgbaImage) -> Vec<u8> {
+            let mut output = std::io::Cursor::new(vec![]);
+            image.write_to(&mut output, ImageFormat::Png).unwrap();
+            output.into_inner()
+        }
+
+        fn decode(bytes: &[u8]) -> RgbaImage {
+            image::load_from_memory(bytes).unwrap().to_rgba8()
+        }
+
+        #[test]
+        fn reordered_tiles_round_trip() {
+            // 7x5 with a 3x2 grid of 2x2 tiles leaves an edge on both sides.
+            let original = numbered(7, 5);
+            let order = [4, 0, 5, 2, 1, 3];
+            let mut inverse = [0; 6];
+            for (position, &tile) in order.iter().enumerate() {
+                inverse[tile] = position;
+            }
+
+            let scrambled = decode(&reorder_tiles(&png(&original), 3, 2, &order).unwrap());
+            assert_ne!(scrambled, original);
+            // The tile at position 0 comes from tile 4, at column 1 row 1.
+            assert_eq!(scrambled.get_pixel(0, 0), original.get_pixel(2, 2));
+            // Edges that are not part of a tile stay in place.
+            assert_eq!(scrambled.get_pixel(6, 1), original.get_pixel(6, 1));
+            assert_eq!(scrambled.get_pixel(3, 4), original.get_pixel(3, 4));
+
+            let restored = reorder_tiles(&png(&scrambled), 3, 2, &inverse).unwrap();
+            assert_eq!(decode(&restored), original);
+        }
+
+        #[test]
+        fn invalid_tile_orders_are_rejected() {
+            let image = png(&numbered(4, 4));
+            for (columns, rows, order) in [
+                (2, 2, vec![0, 1, 2]),
+                (2, 2, vec![0, 1, 2, 3, 0]),
+                (2, 2, vec![0, 1, 2, 4]),
+                (2, 2, vec![0, 1, 1, 3]),
+                (0, 2, vec![]),
+                (u32::MAX, 2, vec![0, 1]),
+                (8, 1, (0..8).collect()),
+            ] {
+                assert!(
+                    reorder_tiles(&image, columns, rows, &order).is_err(),
+                    "{columns}x{rows} {order:?}"
+                );
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
-use std::{collections::HashMap};
-
-use crate::models::{ChapterInfo, Input, MangaInfo, SourceInfo};
-use anyhow::Result;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::http::HttpClient;
+use crate::models::{
+    ChapterInfo, Credentials, Input, MangaInfo, PageInfo, Paginated, Preference, PreferenceValues,
+    ResolvedPath, SourceInfo, SourceSession,
+};
+use anyhow::{Result, bail};
 use bytes::Bytes;
 
 pub trait Extension: Send + Sync {
@@ -41,6 +45,93 @@ pub trait Extension: Send + Sync {
     fn get_pages(&self, path: String) -> Result<Vec<String>>;
 
     fn get_image_bytes(&self, url: String) -> Result<Bytes>;
+
+    // Methods below were added after 0.38, new ones go at the end. Plugins
+    // are only loaded when built against the same `LIB_VERSION`, bump it
+    // with any change to this trait or the models it passes.
+
+    /// Paged variant of [`Extension::get_popular_manga`]. Override it to tell
+    /// the host whether another page exists; the default wraps the bare list
+    /// and assumes a next page whenever the list is not empty.
+    fn get_popular_manga_paged(&self, page: i64) -> Result<Paginated<MangaInfo>> {
+        self.get_popular_manga(page).map(Paginated::from)
+    }
+
+    /// Paged variant of [`Extension::get_latest_manga`].
+    fn get_latest_manga_paged(&self, page: i64) -> Result<Paginated<MangaInfo>> {
+        self.get_latest_manga(page).map(Paginated::from)
+    }
+
+    /// Paged variant of [`Extension::search_manga`].
+    fn search_manga_paged(
+        &self,
+        page: i64,
+        query: Option<String>,
+        filters: Option<Vec<Input>>,
+    ) -> Result<Paginated<MangaInfo>> {
+        self.search_manga(page, query, filters).map(Paginated::from)
+    }
+
+    /// Pages with everything needed to fetch them. Override it when a page
+    /// needs headers, a request body or a key; the default wraps the urls
+    /// from [`Extension::get_pages`].
+    fn get_page_list(&self, path: String) -> Result<Vec<PageInfo>> {
+        self.get_pages(path)
+            .map(|pages| pages.into_iter().map(PageInfo::from).collect())
+    }
+
+    /// Fetch a page returned by [`Extension::get_page_list`], the default
+    /// only uses its url.
+    fn get_page_bytes(&self, page: PageInfo) -> Result<Bytes> {
+        self.get_image_bytes(page.url)
+    }
+
+    /// Post-process a fetched page, e.g. to descramble it with the helpers in
+    /// [`crate::descramble`]. Runs in the extension worker right after
+    /// [`Extension::get_page_bytes`], the host caches and downloads what it
+    /// returns.
+    fn process_image(&self, _page: &PageInfo, bytes: Bytes) -> Result<Bytes> {
+        Ok(bytes)
+    }
+
+    /// Similar or recommended series the source lists for a manga
+    fn get_related_manga(&self, _path: String) -> Result<Vec<MangaInfo>> {
+        Ok(vec![])
+    }
+
+    /// Map a link to this source's website to a manga and optionally one of
+    /// its chapters, `None` when the url isn't a manga or chapter page.
+    fn resolve_url(&self, _url: String) -> Result<Option<ResolvedPath>> {
+        Ok(None)
+    }
+
+    /// Typed preferences, the host validates values against it before
+    /// calling [`Extension::set_preference_values`]
+    fn preference_schema(&self) -> Vec<Preference> {
+        vec![]
+    }
+
+    fn set_preference_values(&mut self, _values: PreferenceValues) -> Result<()> {
+        Ok(())
+    }
+
+    /// Log in to the source website, the returned session is stored by the
+    /// host and restored with [`Extension::restore_session`]
+    fn login(&mut self, _credentials: Credentials) -> Result<SourceSession> {
+        bail!("source does not support login")
+    }
+
+    fn restore_session(&mut self, _session: SourceSession) -> Result<()> {
+        Ok(())
+    }
+
+    fn logout(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_logged_in(&self) -> Result<bool> {
+        Ok(false)
+    }
 }
 
 /// A type represents an extension
@@ -50,9 +141,16 @@ pub struct PluginDeclaration {
     pub register: unsafe fn(&mut dyn PluginRegistrar),
 }
 
-/// A trait for register an extension
+/// A trait for register an extension. Part of the plugin ABI like
+/// [`Extension`], a change to it or to [`export_plugin`] needs a
+/// `LIB_VERSION` bump.
 pub trait PluginRegistrar {
     fn register_function(&mut self, extension: Box<dyn Extension>);
+
+    /// HTTP client the host offers the extension, see [`crate::http`]
+    fn http_client(&self) -> Option<Arc<dyn HttpClient>> {
+        None
+    }
 }
 
 /// macro for export an extension
@@ -65,7 +163,20 @@ macro_rules! export_plugin {
             $crate::extensions::PluginDeclaration {
                 rustc_version: $crate::RUSTC_VERSION,
                 core_version: $crate::LIB_VERSION,
-                register: $register,
+                register: {
+                    // Install the host's HTTP client in this library's copy
+                    // of `tanoshi_lib` before the extension is built.
+                    #[allow(unused_unsafe)]
+                    unsafe fn __tanoshi_register(
+                        registrar: &mut dyn $crate::extensions::PluginRegistrar,
+                    ) {
+                        if let Some(client) = registrar.http_client() {
+                            $crate::http::set_client(client);
+                        }
+                        unsafe { ($register)(registrar) }
+                    }
+                    __tanoshi_register
+                },
             };
     };
 }
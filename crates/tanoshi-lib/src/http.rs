@@ -0,0 +1,104 @@
+//! HTTP requests made through the host. The host keeps a cookie jar per
+//! source, applies the configured user agent, proxy and the source's rate
+//! limit, and answers repeated requests from its cache when the website
+//! reports the page unchanged.
+//!
+//! ```ignore
+//! let response = Request::get("https://example.com/manga").send()?;
+//! let html = response.text()?;
+//! ```
+
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+
+/// Sends requests on behalf of an extension, implemented by the host
+pub trait HttpClient: Send + Sync {
+    fn send(&self, request: Request) -> Result<Response>;
+}
+
+static CLIENT: OnceLock<Arc<dyn HttpClient>> = OnceLock::new();
+
+/// Install the client [`Request::send`] uses. Called by
+/// [`crate::export_plugin`] before the extension registers itself, only the
+/// first client is kept.
+pub fn set_client(client: Arc<dyn HttpClient>) {
+    let _ = CLIENT.set(client);
+}
+
+/// The client installed by the host, if any
+pub fn client() -> Option<Arc<dyn HttpClient>> {
+    CLIENT.get().cloned()
+}
+
+/// A type represent an HTTP request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    /// Header names and values in the order they are sent, a name may repeat
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Bytes>,
+}
+
+impl Request {
+    pub fn new(method: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            method: method.into(),
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new("GET", url)
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new("POST", url)
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Send the request with the client the host installed
+    pub fn send(self) -> Result<Response> {
+        client()
+            .ok_or_else(|| anyhow!("the host did not provide an HTTP client"))?
+            .send(self)
+    }
+}
+
+/// A type represent an HTTP response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Bytes,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// First value of a header, names are compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> Result<String> {
+        Ok(String::from_utf8(self.body.to_vec())?)
+    }
+}
@@ -1,5 +1,7 @@
+pub mod descramble;
 pub mod error;
 pub mod extensions;
+pub mod http;
 pub mod models;
 pub mod prelude;
 
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 /// A type represent chapter, normalized across source
 ///
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ChapterInfo {
     pub source_id: i64,
     pub title: String,
@@ -9,4 +9,15 @@ pub struct ChapterInfo {
     pub number: f64,
     pub scanlator: Option<String>,
     pub uploaded: i64,
+    /// Volume as shown by the source, e.g. `3` or `Special`
+    #[serde(default)]
+    pub volume: Option<String>,
+    /// ISO 639-1 code of the chapter language, for multi-language sources
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub page_count: Option<i64>,
+    /// Link to read the chapter on the source website
+    #[serde(default)]
+    pub external_url: Option<String>,
 }
@@ -0,0 +1,67 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// Account credentials for sources that only serve some content to logged-in
+/// users
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    /// Anything else the site asks for, e.g. a one-time code
+    #[serde(default)]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+// keep passwords out of logs
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"********")
+            .field("extra", &self.extra.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Opaque login state returned by an extension, e.g. cookies or a token.
+/// The host stores it and hands it back through
+/// [`crate::extensions::Extension::restore_session`] after a restart.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SourceSession {
+    pub data: String,
+    /// Unix timestamp after which the session should not be restored
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+impl SourceSession {
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            expires_at: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl fmt::Debug for SourceSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceSession")
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
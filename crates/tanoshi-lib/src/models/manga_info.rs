@@ -1,14 +1,211 @@
-use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Well known keys for [`MangaInfo::external_ids`], matching tracker names
+pub mod external_id {
+    pub const MYANIMELIST: &str = "myanimelist";
+    pub const ANILIST: &str = "anilist";
+    pub const MANGAUPDATES: &str = "mangaupdates";
+}
+
+/// Publication status of a manga
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MangaStatus {
+    Ongoing,
+    Completed,
+    Hiatus,
+    Cancelled,
+}
+
+impl MangaStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MangaStatus::Ongoing => "Ongoing",
+            MangaStatus::Completed => "Completed",
+            MangaStatus::Hiatus => "Hiatus",
+            MangaStatus::Cancelled => "Cancelled",
+        }
+    }
+}
+
+impl fmt::Display for MangaStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parse the free-form status strings sources usually scrape
+impl FromStr for MangaStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "ongoing" | "publishing" | "releasing" | "on going" => Ok(MangaStatus::Ongoing),
+            "completed" | "complete" | "finished" | "ended" => Ok(MangaStatus::Completed),
+            "hiatus" | "on hiatus" | "paused" => Ok(MangaStatus::Hiatus),
+            "cancelled" | "canceled" | "discontinued" | "dropped" => Ok(MangaStatus::Cancelled),
+            _ => Err(format!("unknown manga status {s}")),
+        }
+    }
+}
+
+/// Content rating of a manga, from least to most explicit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum ContentRating {
+    Safe,
+    Suggestive,
+    Erotica,
+    Pornographic,
+}
+
+impl ContentRating {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentRating::Safe => "Safe",
+            ContentRating::Suggestive => "Suggestive",
+            ContentRating::Erotica => "Erotica",
+            ContentRating::Pornographic => "Pornographic",
+        }
+    }
+}
+
+impl fmt::Display for ContentRating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ContentRating {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "safe" => Ok(ContentRating::Safe),
+            "suggestive" => Ok(ContentRating::Suggestive),
+            "erotica" => Ok(ContentRating::Erotica),
+            "pornographic" => Ok(ContentRating::Pornographic),
+            _ => Err(format!("unknown content rating {s}")),
+        }
+    }
+}
 
 /// A type represent manga details, normalized across source
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct MangaInfo {
     pub source_id: i64,
     pub title: String,
+    #[serde(default)]
+    pub alternative_titles: Vec<String>,
     pub author: Vec<String>,
+    #[serde(default)]
+    pub artist: Vec<String>,
     pub genre: Vec<String>,
-    pub status: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_status")]
+    pub status: Option<MangaStatus>,
+    #[serde(default)]
+    pub content_rating: Option<ContentRating>,
     pub description: Option<String>,
     pub path: String,
     pub cover_url: String,
+    /// Ids of this manga on other sites, keyed by [`external_id`] names
+    #[serde(default)]
+    pub external_ids: BTreeMap<String, String>,
+}
+
+/// Accept both `MangaStatus` and the free-form strings older extensions send,
+/// an unrecognized status is dropped instead of failing the whole manga.
+fn deserialize_status<'de, D>(deserializer: D) -> Result<Option<MangaStatus>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let status = Option::<String>::deserialize(deserializer)?;
+    Ok(status.and_then(|status| status.parse().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_parses_common_spellings() {
+        for (status, expected) in [
+            ("Ongoing", MangaStatus::Ongoing),
+            (" publishing ", MangaStatus::Ongoing),
+            ("COMPLETE", MangaStatus::Completed),
+            ("on hiatus", MangaStatus::Hiatus),
+            ("Canceled", MangaStatus::Cancelled),
+            ("dropped", MangaStatus::Cancelled),
+        ] {
+            assert_eq!(status.parse::<MangaStatus>(), Ok(expected), "{status}");
+        }
+        assert!("unknown".parse::<MangaStatus>().is_err());
+        assert!("".parse::<MangaStatus>().is_err());
+    }
+
+    #[test]
+    fn content_rating_round_trips_through_display() {
+        for rating in [
+            ContentRating::Safe,
+            ContentRating::Suggestive,
+            ContentRating::Erotica,
+            ContentRating::Pornographic,
+        ] {
+            assert_eq!(rating.to_string().parse::<ContentRating>(), Ok(rating));
+        }
+        assert_eq!(" SAFE ".parse::<ContentRating>(), Ok(ContentRating::Safe));
+        assert!("mature".parse::<ContentRating>().is_err());
+    }
+
+    fn manga_with_status(status: serde_json::Value) -> MangaInfo {
+        serde_json::from_value(serde_json::json!({
+            "source_id": 1,
+            "title": "Title",
+            "author": [],
+            "genre": [],
+            "status": status,
+            "description": null,
+            "path": "/manga",
+            "cover_url": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn status_deserializes_from_variants_and_free_form_strings() {
+        assert_eq!(
+            manga_with_status("Completed".into()).status,
+            Some(MangaStatus::Completed)
+        );
+        assert_eq!(
+            manga_with_status("Releasing".into()).status,
+            Some(MangaStatus::Ongoing)
+        );
+        assert_eq!(manga_with_status(serde_json::Value::Null).status, None);
+    }
+
+    #[test]
+    fn unknown_status_is_dropped_instead_of_failing_the_manga() {
+        let manga = manga_with_status("Season 2 airing".into());
+        assert_eq!(manga.status, None);
+        assert_eq!(manga.title, "Title");
+    }
+
+    #[test]
+    fn missing_status_and_new_fields_default() {
+        let manga: MangaInfo = serde_json::from_value(serde_json::json!({
+            "source_id": 1,
+            "title": "Title",
+            "author": [],
+            "genre": [],
+            "description": null,
+            "path": "/manga",
+            "cover_url": "",
+        }))
+        .unwrap();
+        assert_eq!(manga.status, None);
+        assert_eq!(manga.content_rating, None);
+        assert!(manga.alternative_titles.is_empty());
+        assert!(manga.external_ids.is_empty());
+    }
 }
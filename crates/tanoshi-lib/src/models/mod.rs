@@ -7,8 +7,23 @@ pub use manga_info::*;
 pub mod chapter_info;
 pub use chapter_info::*;
 
+pub mod page_info;
+pub use page_info::*;
+
+pub mod paginated;
+pub use paginated::*;
+
+pub mod resolved_path;
+pub use resolved_path::*;
+
 pub mod input;
 pub use input::*;
 
+pub mod preference;
+pub use preference::*;
+
+pub mod credentials;
+pub use credentials::*;
+
 pub mod version;
 pub use version::*;
@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A type represent how to fetch a page image, the host passes it back
+/// unchanged to [`crate::extensions::Extension::get_page_bytes`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PageInfo {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// HTTP method, `GET` when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Opaque value for the extension, e.g. a descrambling key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl PageInfo {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether fetching this page needs more than its url
+    pub fn is_plain(&self) -> bool {
+        self.headers.is_empty()
+            && self.method.is_none()
+            && self.body.is_none()
+            && self.token.is_none()
+    }
+}
+
+impl From<String> for PageInfo {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
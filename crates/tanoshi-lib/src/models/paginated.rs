@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// A type represent one page of a source listing
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub has_next_page: bool,
+    #[serde(default)]
+    pub total: Option<i64>,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(items: Vec<T>, has_next_page: bool) -> Self {
+        Self {
+            items,
+            has_next_page,
+            total: None,
+        }
+    }
+
+    pub fn with_total(mut self, total: i64) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    pub fn map<U, F>(self, f: F) -> Paginated<U>
+    where
+        F: FnMut(T) -> U,
+    {
+        Paginated {
+            items: self.items.into_iter().map(f).collect(),
+            has_next_page: self.has_next_page,
+            total: self.total,
+        }
+    }
+}
+
+/// Compatibility for listings that only return a bare list: a non-empty page
+/// is assumed to be followed by another one, which matches how callers paged
+/// through sources before `has_next_page` existed.
+impl<T> From<Vec<T>> for Paginated<T> {
+    fn from(items: Vec<T>) -> Self {
+        let has_next_page = !items.is_empty();
+        Self::new(items, has_next_page)
+    }
+}
@@ -0,0 +1,410 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Placeholder sent to clients instead of a secret value, sending it back
+/// keeps the saved value
+pub const SECRET_MASK: &str = "********";
+
+/// Values of a source preferences, keyed by [`Preference::key`]
+pub type PreferenceValues = BTreeMap<String, PreferenceValue>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PreferenceValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl From<String> for PreferenceValue {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<&str> for PreferenceValue {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<i64> for PreferenceValue {
+    fn from(n: i64) -> Self {
+        Self::Integer(n)
+    }
+}
+
+impl From<bool> for PreferenceValue {
+    fn from(b: bool) -> Self {
+        Self::Boolean(b)
+    }
+}
+
+/// What kind of value a preference holds, with its constraints
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PreferenceKind {
+    Text {
+        max_length: Option<usize>,
+    },
+    /// Text that is never sent back to clients
+    Password,
+    Url,
+    Integer {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Toggle,
+    Select {
+        options: Vec<String>,
+    },
+}
+
+impl PreferenceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PreferenceKind::Text { .. } => "Text",
+            PreferenceKind::Password => "Password",
+            PreferenceKind::Url => "Url",
+            PreferenceKind::Integer { .. } => "Integer",
+            PreferenceKind::Toggle => "Toggle",
+            PreferenceKind::Select { .. } => "Select",
+        }
+    }
+}
+
+/// A type represent one field of a source preferences
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Preference {
+    pub key: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub kind: PreferenceKind,
+    #[serde(default)]
+    pub default: Option<PreferenceValue>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl Preference {
+    pub fn new(key: impl Into<String>, name: impl Into<String>, kind: PreferenceKind) -> Self {
+        Self {
+            key: key.into(),
+            name: name.into(),
+            description: None,
+            kind,
+            default: None,
+            required: false,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_default(mut self, default: impl Into<PreferenceValue>) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub fn is_secret(&self) -> bool {
+        matches!(self.kind, PreferenceKind::Password)
+    }
+
+    /// Check a value against this preference kind and constraints
+    pub fn validate(&self, value: &PreferenceValue) -> Result<(), String> {
+        match (&self.kind, value) {
+            (PreferenceKind::Text { max_length }, PreferenceValue::String(s)) => match max_length {
+                Some(max_length) if s.chars().count() > *max_length => {
+                    Err(format!("must be at most {max_length} characters"))
+                }
+                _ => Ok(()),
+            },
+            (PreferenceKind::Password, PreferenceValue::String(_)) => Ok(()),
+            (PreferenceKind::Url, PreferenceValue::String(s)) => {
+                let host = s
+                    .strip_prefix("https://")
+                    .or_else(|| s.strip_prefix("http://"));
+                match host {
+                    Some(host) if !host.is_empty() && !host.starts_with('/') => Ok(()),
+                    _ => Err("must be an http or https url".to_string()),
+                }
+            }
+            (PreferenceKind::Integer { min, max }, PreferenceValue::Integer(n)) => {
+                match (min, max) {
+                    (Some(min), _) if n < min => Err(format!("must be at least {min}")),
+                    (_, Some(max)) if n > max => Err(format!("must be at most {max}")),
+                    _ => Ok(()),
+                }
+            }
+            (PreferenceKind::Toggle, PreferenceValue::Boolean(_)) => Ok(()),
+            (PreferenceKind::Select { options }, PreferenceValue::String(s)) => {
+                if options.contains(s) {
+                    Ok(())
+                } else {
+                    Err(format!("must be one of {}", options.join(", ")))
+                }
+            }
+            (kind, _) => {
+                let kind = kind.as_str().to_lowercase();
+                let article = if kind.starts_with(['a', 'e', 'i', 'o', 'u']) {
+                    "an"
+                } else {
+                    "a"
+                };
+                Err(format!("expected {article} {kind} value"))
+            }
+        }
+    }
+}
+
+/// A validation failure for a single preference
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PreferenceError {
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for PreferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid preferences: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+pub struct PreferenceErrors(pub Vec<PreferenceError>);
+
+/// Validate values against a schema, collecting every field error instead of
+/// stopping at the first one
+pub fn validate_preferences(
+    schema: &[Preference],
+    values: &PreferenceValues,
+) -> Result<(), PreferenceErrors> {
+    let mut errors = vec![];
+    for key in values.keys() {
+        if !schema.iter().any(|preference| &preference.key == key) {
+            errors.push(PreferenceError {
+                key: key.clone(),
+                message: "unknown preference".to_string(),
+            });
+        }
+    }
+    for preference in schema {
+        let result = match values.get(&preference.key).or(preference.default.as_ref()) {
+            Some(value) => preference.validate(value),
+            None if preference.required => Err("is required".to_string()),
+            None => Ok(()),
+        };
+        if let Err(message) = result {
+            errors.push(PreferenceError {
+                key: preference.key.clone(),
+                message,
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PreferenceErrors(errors))
+    }
+}
+
+/// Fill missing values with the schema defaults
+pub fn with_default_preferences(
+    schema: &[Preference],
+    mut values: PreferenceValues,
+) -> PreferenceValues {
+    for preference in schema {
+        if let Some(default) = &preference.default {
+            values
+                .entry(preference.key.clone())
+                .or_insert_with(|| default.clone());
+        }
+    }
+    values
+}
+
+/// Replace secret values with [`SECRET_MASK`] before sending them to clients
+pub fn mask_secret_preferences(
+    schema: &[Preference],
+    mut values: PreferenceValues,
+) -> PreferenceValues {
+    for preference in schema.iter().filter(|preference| preference.is_secret()) {
+        if let Some(value) = values.get_mut(&preference.key) {
+            *value = PreferenceValue::from(SECRET_MASK);
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Vec<Preference> {
+        vec![
+            Preference::new(
+                "name",
+                "Name",
+                PreferenceKind::Text {
+                    max_length: Some(5),
+                },
+            ),
+            Preference::new("token", "Token", PreferenceKind::Password).required(),
+            Preference::new("mirror", "Mirror", PreferenceKind::Url)
+                .with_default("https://example.com"),
+            Preference::new(
+                "per_page",
+                "Per page",
+                PreferenceKind::Integer {
+                    min: Some(1),
+                    max: Some(100),
+                },
+            )
+            .with_default(20),
+            Preference::new("nsfw", "Show NSFW", PreferenceKind::Toggle).with_default(false),
+            Preference::new(
+                "quality",
+                "Quality",
+                PreferenceKind::Select {
+                    options: vec!["low".to_string(), "high".to_string()],
+                },
+            ),
+        ]
+    }
+
+    fn values(values: &[(&str, PreferenceValue)]) -> PreferenceValues {
+        values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn errors(values: PreferenceValues) -> Vec<(String, String)> {
+        match validate_preferences(&schema(), &values) {
+            Ok(()) => vec![],
+            Err(PreferenceErrors(errors)) => errors
+                .into_iter()
+                .map(|error| (error.key, error.message))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn valid_values_pass() {
+        let valid = values(&[
+            ("name", "abcde".into()),
+            ("token", "secret".into()),
+            ("mirror", "http://mirror.example.com/path".into()),
+            ("per_page", 100.into()),
+            ("nsfw", true.into()),
+            ("quality", "high".into()),
+        ]);
+        assert_eq!(errors(valid), vec![]);
+    }
+
+    #[test]
+    fn constraints_are_checked_per_kind() {
+        let cases: [(&str, PreferenceValue, &str); 8] = [
+            ("name", "abcdef".into(), "must be at most 5 characters"),
+            (
+                "mirror",
+                "ftp://example.com".into(),
+                "must be an http or https url",
+            ),
+            (
+                "mirror",
+                "https:///path".into(),
+                "must be an http or https url",
+            ),
+            ("per_page", 0.into(), "must be at least 1"),
+            ("per_page", 101.into(), "must be at most 100"),
+            ("per_page", "20".into(), "expected an integer value"),
+            ("nsfw", "yes".into(), "expected a toggle value"),
+            ("quality", "medium".into(), "must be one of low, high"),
+        ];
+        for (key, value, message) in cases {
+            let invalid = values(&[("token", "secret".into()), (key, value.clone())]);
+            assert_eq!(
+                errors(invalid),
+                vec![(key.to_string(), message.to_string())],
+                "{key} = {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn text_length_counts_characters() {
+        let valid = values(&[("token", "secret".into()), ("name", "日本語です".into())]);
+        assert_eq!(errors(valid), vec![]);
+    }
+
+    #[test]
+    fn every_field_error_is_reported() {
+        let invalid = values(&[
+            ("unknown", true.into()),
+            ("per_page", 500.into()),
+            ("quality", 1.into()),
+        ]);
+        assert_eq!(
+            errors(invalid),
+            vec![
+                ("unknown".to_string(), "unknown preference".to_string()),
+                ("token".to_string(), "is required".to_string()),
+                ("per_page".to_string(), "must be at most 100".to_string()),
+                ("quality".to_string(), "expected a select value".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn defaults_are_validated_and_filled() {
+        let schema = vec![
+            Preference::new("token", "Token", PreferenceKind::Password)
+                .required()
+                .with_default("anonymous"),
+            Preference::new(
+                "per_page",
+                "Per page",
+                PreferenceKind::Integer {
+                    min: Some(1),
+                    max: None,
+                },
+            )
+            .with_default(0),
+        ];
+        // A default satisfies a required preference, but is checked too.
+        let error = validate_preferences(&schema, &PreferenceValues::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid preferences: per_page: must be at least 1"
+        );
+        assert!(validate_preferences(&schema, &values(&[("per_page", 3.into())])).is_ok());
+
+        assert_eq!(
+            with_default_preferences(&schema, values(&[("per_page", 3.into())])),
+            values(&[("token", "anonymous".into()), ("per_page", 3.into())])
+        );
+    }
+
+    #[test]
+    fn secrets_are_masked() {
+        let masked = mask_secret_preferences(
+            &schema(),
+            values(&[("token", "secret".into()), ("name", "abc".into())]),
+        );
+        assert_eq!(
+            masked,
+            values(&[("token", SECRET_MASK.into()), ("name", "abc".into())])
+        );
+    }
+}
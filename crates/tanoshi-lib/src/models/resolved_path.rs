@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// A type represent where a website url points to in a source
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResolvedPath {
+    pub manga_path: String,
+    pub chapter_path: Option<String>,
+}
@@ -1,15 +1,68 @@
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Lang {
+    #[default]
     All,
     Single(String),
     Multi(Vec<String>),
 }
 
+/// What a source supports, so the host can hide modes it lacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Capabilities {
+    pub popular: bool,
+    pub latest: bool,
+    pub search: bool,
+    /// Search without a query, e.g. by filters only, is not supported
+    pub search_requires_query: bool,
+    pub filters: bool,
+    pub requires_login: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            popular: true,
+            latest: true,
+            search: true,
+            search_requires_query: false,
+            filters: true,
+            requires_login: false,
+        }
+    }
+}
+
+/// How many requests a source accepts in a period, the host enforces it
+/// with a token bucket that allows bursts up to `requests`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    pub fn per_second(requests: u32) -> Self {
+        Self {
+            requests,
+            period: Duration::from_secs(1),
+        }
+    }
+
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            requests,
+            period: Duration::from_secs(60),
+        }
+    }
+}
+
 /// A type represent source
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct SourceInfo {
     pub id: i64,
     pub name: String,
@@ -18,4 +71,8 @@ pub struct SourceInfo {
     pub icon: &'static str,
     pub languages: Lang,
     pub nsfw: bool,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
 }
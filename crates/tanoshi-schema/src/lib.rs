@@ -1,7 +1,7 @@
 pub mod model;
 
 use graphql_client::GraphQLQuery;
-use model::Input;
+use model::{Input, MangaStatus};
 
 type NaiveDateTime = String;
 
@@ -39,6 +39,14 @@ pub struct GetPopularManga;
 )]
 pub struct FetchSourceFilters;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/fetch_source_capabilities.graphql",
+    response_derives = "Debug, Clone"
+)]
+pub struct FetchSourceCapabilities;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -51,7 +59,8 @@ pub struct BrowseFavorites;
 #[graphql(
     schema_path = "graphql/schema.graphql",
     query_path = "graphql/fetch_manga_by_source_path.graphql",
-    response_derives = "Debug"
+    response_derives = "Debug",
+    extern_enums("MangaStatus")
 )]
 pub struct FetchMangaBySourcePath;
 
@@ -59,10 +68,19 @@ pub struct FetchMangaBySourcePath;
 #[graphql(
     schema_path = "graphql/schema.graphql",
     query_path = "graphql/fetch_manga_detail.graphql",
-    response_derives = "Debug"
+    response_derives = "Debug",
+    extern_enums("MangaStatus")
 )]
 pub struct FetchMangaDetail;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/fetch_related_manga.graphql",
+    response_derives = "Debug"
+)]
+pub struct FetchRelatedManga;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -223,6 +241,22 @@ pub struct UpdateSource;
 )]
 pub struct UninstallSource;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/source_login.graphql",
+    response_derives = "Debug"
+)]
+pub struct SourceLogin;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/source_logout.graphql",
+    response_derives = "Debug"
+)]
+pub struct SourceLogout;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -56,6 +56,27 @@ pub struct Category {
     pub count: i64,
 }
 
+/// `MangaStatus` of the schema, shared by the queries that fetch it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MangaStatus {
+    Ongoing,
+    Completed,
+    Hiatus,
+    Cancelled,
+}
+
+impl std::fmt::Display for MangaStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MangaStatus::Ongoing => "Ongoing",
+            MangaStatus::Completed => "Completed",
+            MangaStatus::Hiatus => "Hiatus",
+            MangaStatus::Cancelled => "Cancelled",
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
 pub enum TriState {
     #[default]
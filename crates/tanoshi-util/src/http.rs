@@ -30,23 +30,16 @@ impl Request {
     }
 
     pub fn set(self, name: &str, key: &str) -> Request {
-        let headers = match self.headers {
-            Some(mut headers) => {
-                if let Some(header) = headers.get_mut(name) {
-                    header.push(key.to_string());
-                } else {
-                    headers.insert(name.to_string(), vec![key.to_string()]);
-                }
-
-                Some(headers)
-            }
-            None => None,
-        };
+        let mut headers = self.headers.unwrap_or_default();
+        headers
+            .entry(name.to_string())
+            .or_default()
+            .push(key.to_string());
 
         Request {
             method: self.method,
             url: self.url,
-            headers,
+            headers: Some(headers),
             body: self.body,
         }
     }
@@ -63,28 +56,39 @@ pub struct Response {
     pub status: i32,
 }
 
+/// Sends the request through the HTTP client the host gave the extension,
+/// see [`tanoshi_lib::http`]. Failures come back as status 9999 with the
+/// error as body.
 #[cfg(all(not(feature = "__test"), not(feature = "host")))]
 pub fn http_request(req: Request) -> Response {
-    if let Err(err) = crate::shim::write_object(req) {
-        return Response {
+    let mut request = tanoshi_lib::http::Request::new(req.method, req.url);
+    for (name, values) in req.headers.unwrap_or_default() {
+        for value in values {
+            request = request.header(name.clone(), value);
+        }
+    }
+    if let Some(body) = req.body {
+        request = request.body(body);
+    }
+
+    match request.send() {
+        Ok(response) => {
+            let mut headers = Headers::new();
+            for (name, value) in response.headers {
+                headers.entry(name).or_default().push(value);
+            }
+            Response {
+                headers,
+                body: String::from_utf8_lossy(&response.body).into_owned(),
+                status: i32::from(response.status),
+            }
+        }
+        Err(err) => Response {
             headers: HashMap::new(),
             body: format!("{err}"),
             status: 9999,
-        };
+        },
     }
-
-    unsafe { host_http_request() };
-    crate::shim::read_object().unwrap_or_else(|err| Response {
-        headers: HashMap::new(),
-        body: format!("{err}"),
-        status: 9999,
-    })
-}
-
-#[cfg(all(not(feature = "__test"), not(feature = "host")))]
-#[link(wasm_import_module = "tanoshi")]
-unsafe extern "C" {
-    fn host_http_request();
 }
 
 #[cfg(any(feature = "__test", feature = "host"))]
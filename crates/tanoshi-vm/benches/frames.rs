@@ -0,0 +1,32 @@
+//! Throughput of large image responses through the worker frame encodings,
+//! run with `cargo bench -p tanoshi-vm --bench frames`
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use tanoshi_vm::extension::worker::bench::{read_image_frame, write_image_frame};
+
+const IMAGE_SIZE: usize = 4 * 1024 * 1024;
+
+fn image_frames(c: &mut Criterion) {
+    let image: Vec<u8> = (0..IMAGE_SIZE).map(|i| (i * 31 % 251) as u8).collect();
+
+    let mut group = c.benchmark_group("image_frame");
+    group.throughput(Throughput::Bytes(IMAGE_SIZE as u64));
+    group.sample_size(20);
+    for (name, binary) in [("json", false), ("binary", true)] {
+        group.bench_with_input(
+            BenchmarkId::new("round_trip", name),
+            &binary,
+            |b, &binary| {
+                b.iter(|| {
+                    let mut frame = Vec::new();
+                    write_image_frame(&mut frame, image.clone(), binary);
+                    read_image_frame(&frame, binary)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, image_frames);
+criterion_main!(benches);
@@ -54,7 +54,7 @@ async fn main() {
     println!("{:?}", manga);
 
     let manga = bus
-        .get_manga_detail(source_id, manga[2].path.clone())
+        .get_manga_detail(source_id, manga.items[2].path.clone())
         .await
         .unwrap();
 
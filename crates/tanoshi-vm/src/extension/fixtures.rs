@@ -0,0 +1,320 @@
+//! Recorded HTTP exchanges of extensions, so their tests can run without a
+//! network. Every request an extension sends, from a worker process or in
+//! process, goes through the host client, see [`super::http`], which either
+//! saves each exchange to a fixture file or answers from one.
+//!
+//! A fixture file is JSON, text bodies are kept readable so fixtures can be
+//! reviewed and edited:
+//!
+//! ```json
+//! {
+//!   "exchanges": [
+//!     {
+//!       "method": "GET",
+//!       "url": "https://example.com/popular?page=1",
+//!       "status": 200,
+//!       "headers": [["content-type", "text/html"]],
+//!       "body": { "text": "<html>...</html>" }
+//!     }
+//!   ]
+//! }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Mutex as StdMutex,
+};
+
+use anyhow::{Context, Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use fnv::FnvHashMap;
+use serde::{Deserialize, Serialize};
+use tanoshi_lib::http::{Request, Response};
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+/// Whether extension requests are recorded to, or answered from, a fixture
+/// file
+#[derive(Clone, Debug)]
+pub enum HttpFixtureMode {
+    /// Send requests and save every exchange, replacing the file
+    Record(PathBuf),
+    /// Answer requests from the file and fail the ones it has no answer for,
+    /// nothing is sent
+    Replay(PathBuf),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Body {
+    Text(String),
+    Base64(String),
+}
+
+impl Body {
+    fn new(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Body::Text(text.to_string()),
+            Err(_) => Body::Base64(STANDARD.encode(bytes)),
+        }
+    }
+
+    fn bytes(&self) -> Result<Bytes> {
+        Ok(match self {
+            Body::Text(text) => Bytes::copy_from_slice(text.as_bytes()),
+            Body::Base64(encoded) => STANDARD.decode(encoded)?.into(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_body: Option<Body>,
+    status: u16,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+/// Requests match on their method, url and body, headers change with
+/// cookies and overrides
+fn request_key(method: &str, url: &str, body: Option<&Body>) -> String {
+    let body = match body {
+        Some(Body::Text(text)) => text.as_str(),
+        Some(Body::Base64(encoded)) => encoded.as_str(),
+        None => "",
+    };
+    format!("{} {url} {body}", method.to_uppercase())
+}
+
+#[derive(Debug, Deserialize)]
+struct FixtureFile {
+    exchanges: Vec<Exchange>,
+}
+
+/// Start of a fixture file, up to the first exchange
+const FIXTURE_HEAD: &str = "{\n  \"exchanges\": [\n";
+/// End of a fixture file, after the last exchange
+const FIXTURE_TAIL: &str = "\n  ]\n}\n";
+
+/// A fixture file being recorded. Each exchange is written over the tail of
+/// the previous write, followed by a new tail, so the file is complete after
+/// every exchange and an interrupted run keeps what it recorded.
+#[derive(Default)]
+pub(crate) struct FixtureRecorder {
+    file: Option<tokio::fs::File>,
+    // Offset of the tail, where the next exchange goes.
+    tail: u64,
+}
+
+impl FixtureRecorder {
+    async fn append(&mut self, path: &Path, exchange: &Exchange) -> Result<()> {
+        let json = serde_json::to_string_pretty(exchange)?;
+        let mut entry = String::with_capacity(json.len() + 16);
+        let file = match self.file.as_mut() {
+            Some(file) => {
+                entry.push_str(",\n");
+                file
+            }
+            None => {
+                let file = self.file.insert(tokio::fs::File::create(path).await?);
+                file.write_all(FIXTURE_HEAD.as_bytes()).await?;
+                self.tail = FIXTURE_HEAD.len() as u64;
+                file
+            }
+        };
+        for (index, line) in json.lines().enumerate() {
+            if index > 0 {
+                entry.push('\n');
+            }
+            entry.push_str("    ");
+            entry.push_str(line);
+        }
+
+        file.seek(SeekFrom::Start(self.tail)).await?;
+        file.write_all(entry.as_bytes()).await?;
+        file.write_all(FIXTURE_TAIL.as_bytes()).await?;
+        file.flush().await?;
+        self.tail += entry.len() as u64;
+        Ok(())
+    }
+}
+
+pub(crate) enum HttpFixtures {
+    Record {
+        path: PathBuf,
+        recorder: Mutex<FixtureRecorder>,
+    },
+    Replay {
+        path: PathBuf,
+        // Responses in recorded order per request. The last one is repeated
+        // once the others are used up.
+        responses: StdMutex<FnvHashMap<String, VecDeque<Response>>>,
+        load_error: Option<String>,
+    },
+}
+
+impl HttpFixtures {
+    pub(crate) fn new(mode: &HttpFixtureMode) -> Self {
+        match mode {
+            HttpFixtureMode::Record(path) => HttpFixtures::Record {
+                path: path.clone(),
+                recorder: Mutex::new(FixtureRecorder::default()),
+            },
+            HttpFixtureMode::Replay(path) => {
+                let (responses, load_error) = match load(path) {
+                    Ok(responses) => (responses, None),
+                    Err(error) => {
+                        error!("failed to load HTTP fixtures: {error:#}");
+                        (FnvHashMap::default(), Some(format!("{error:#}")))
+                    }
+                };
+                HttpFixtures::Replay {
+                    path: path.clone(),
+                    responses: StdMutex::new(responses),
+                    load_error,
+                }
+            }
+        }
+    }
+
+    /// The recorded response to `request`, when replaying
+    pub(crate) fn replay(&self, request: &Request) -> Option<Result<Response>> {
+        let HttpFixtures::Replay {
+            path,
+            responses,
+            load_error,
+        } = self
+        else {
+            return None;
+        };
+
+        let mut responses = match responses.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let response = responses
+            .get_mut(&request_key(
+                &request.method,
+                &request.url,
+                request.body.as_deref().map(Body::new).as_ref(),
+            ))
+            .and_then(|queue| {
+                if queue.len() > 1 {
+                    queue.pop_front()
+                } else {
+                    queue.front().cloned()
+                }
+            });
+        Some(response.ok_or_else(|| {
+            let reason = match load_error {
+                Some(error) => format!(", it failed to load: {error}"),
+                None => String::new(),
+            };
+            anyhow!(
+                "{} {} is not recorded in {}{reason}",
+                request.method,
+                request.url,
+                path.display()
+            )
+        }))
+    }
+
+    /// Save an exchange, when recording
+    pub(crate) async fn record(&self, request: &Request, response: &Response) {
+        let HttpFixtures::Record { path, recorder } = self else {
+            return;
+        };
+
+        let exchange = Exchange {
+            method: request.method.clone(),
+            url: request.url.clone(),
+            request_body: request.body.as_deref().map(Body::new),
+            status: response.status,
+            headers: response.headers.clone(),
+            body: Body::new(&response.body),
+        };
+        if let Err(error) = recorder.lock().await.append(path, &exchange).await {
+            error!(
+                "failed to save HTTP fixtures to {}: {error}",
+                path.display()
+            );
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<FnvHashMap<String, VecDeque<Response>>> {
+    let json = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let file: FixtureFile = serde_json::from_slice(&json)
+        .with_context(|| format!("{} is not a fixture file", path.display()))?;
+
+    let mut responses = FnvHashMap::<String, VecDeque<Response>>::default();
+    for exchange in file.exchanges {
+        let key = request_key(
+            &exchange.method,
+            &exchange.url,
+            exchange.request_body.as_ref(),
+        );
+        responses.entry(key).or_default().push_back(Response {
+            status: exchange.status,
+            headers: exchange.headers,
+            body: exchange.body.bytes()?,
+        });
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use tanoshi_lib::http::{Request, Response};
+
+    use super::{HttpFixtureMode, HttpFixtures};
+
+    #[tokio::test]
+    async fn recorded_exchanges_are_replayed_in_order() {
+        let path =
+            std::env::temp_dir().join(format!("tanoshi-vm-fixtures-{}.json", std::process::id()));
+        let page = Request::get("https://example.com/page");
+        let image = Request::get("https://example.com/image.png");
+        let response = |status, body: &[u8]| Response {
+            status,
+            headers: vec![("content-type".to_string(), "text/html".to_string())],
+            body: body.to_vec().into(),
+        };
+
+        let recorder = HttpFixtures::new(&HttpFixtureMode::Record(path.clone()));
+        assert!(recorder.replay(&page).is_none());
+        recorder.record(&page, &response(503, b"busy")).await;
+        // Complete after every exchange, not only at the end.
+        let file: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(file["exchanges"].as_array().unwrap().len(), 1);
+        recorder.record(&page, &response(200, b"<html>")).await;
+        recorder
+            .record(&image, &response(200, &[0x89, b'P', b'N', b'G', 0xFF]))
+            .await;
+
+        let replayer = HttpFixtures::new(&HttpFixtureMode::Replay(path.clone()));
+        let replay = |request: &Request| replayer.replay(request).unwrap().unwrap();
+        assert_eq!(replay(&page), response(503, b"busy"));
+        assert_eq!(replay(&page), response(200, b"<html>"));
+        assert_eq!(replay(&page), response(200, b"<html>"));
+        assert_eq!(replay(&image).body.as_ref(), [0x89, b'P', b'N', b'G', 0xFF]);
+
+        let error = replayer
+            .replay(&Request::get("https://example.com/other"))
+            .unwrap()
+            .unwrap_err();
+        assert!(error.to_string().contains("is not recorded"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,772 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex as StdMutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use bytes::Bytes;
+use fnv::FnvHashMap;
+use reqwest::{
+    Method, StatusCode, Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::{self, HeaderValue},
+};
+use tanoshi_lib::{
+    http::{HttpClient, Request, Response},
+    prelude::RateLimit,
+};
+use tokio::{
+    sync::{Mutex, broadcast},
+    time::Instant,
+};
+
+use super::{
+    fixtures::{HttpFixtureMode, HttpFixtures},
+    overrides::{RequestOverrides, SourceChallenge, is_challenge},
+    rate_limit::TokenBucket,
+    sandbox::{EGRESS_PORTS, is_public_address},
+};
+
+pub const DEFAULT_HTTP_USER_AGENT: &str = concat!("Tanoshi/", env!("CARGO_PKG_VERSION"));
+pub const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_HTTP_CACHE_SIZE: usize = 32 * 1024 * 1024;
+/// Largest response body handed to an extension
+const MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 10;
+
+/// Settings of the HTTP client the host offers extensions, see
+/// [`tanoshi_lib::http`]
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+    pub user_agent: String,
+    /// HTTP or HTTPS proxy every extension request goes through
+    pub proxy: Option<String>,
+    pub timeout: Duration,
+    /// Bytes of responses kept per source to revalidate with their ETag or
+    /// Last-Modified, zero disables the cache
+    pub cache_size: usize,
+    /// Record every exchange to a fixture file, or answer from one without
+    /// sending anything, for testing extensions offline
+    pub fixtures: Option<HttpFixtureMode>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_HTTP_USER_AGENT.to_string(),
+            proxy: None,
+            timeout: DEFAULT_HTTP_TIMEOUT,
+            cache_size: DEFAULT_HTTP_CACHE_SIZE,
+            fixtures: None,
+        }
+    }
+}
+
+/// Overrides of a source, shared by its client and cookie jar so a change
+/// applies to the next request
+type OverrideSlot = StdMutex<Arc<RequestOverrides>>;
+
+/// Host side of [`tanoshi_lib::http`], shared by every source. Each source
+/// gets its own client, so cookies never leak between sources, and the
+/// client is shared by all of the source's worker processes.
+pub(crate) struct HostHttp {
+    options: HttpOptions,
+    dir: PathBuf,
+    // Set when workers run in a private network, so the host does not reach
+    // further on their behalf than the egress proxy would.
+    egress: Option<Arc<EgressPolicy>>,
+    fixtures: Option<Arc<HttpFixtures>>,
+    sources: StdMutex<FnvHashMap<i64, Arc<SourceHttp>>>,
+    overrides: StdMutex<FnvHashMap<i64, Arc<OverrideSlot>>>,
+    challenges: broadcast::Sender<SourceChallenge>,
+}
+
+impl HostHttp {
+    pub(crate) fn new(
+        mut options: HttpOptions,
+        dir: PathBuf,
+        allowed_private_hosts: Option<Vec<String>>,
+    ) -> Self {
+        if let Some(proxy) = options.proxy.as_deref()
+            && let Err(error) = reqwest::Proxy::all(proxy)
+        {
+            warn!("configured extension HTTP proxy {proxy:?} is invalid, ignoring it: {error}");
+            options.proxy = None;
+        }
+        let egress = allowed_private_hosts.map(|mut allowed_private_hosts| {
+            // The configured proxy is reached by the host, not the extension.
+            if let Some(host) = options
+                .proxy
+                .as_deref()
+                .and_then(|proxy| Url::parse(proxy).ok())
+                .and_then(|proxy| proxy.host_str().map(str::to_owned))
+            {
+                allowed_private_hosts.push(host);
+            }
+            Arc::new(EgressPolicy {
+                allowed_private_hosts,
+            })
+        });
+        let fixtures = options
+            .fixtures
+            .as_ref()
+            .map(|mode| Arc::new(HttpFixtures::new(mode)));
+        Self {
+            options,
+            dir,
+            egress,
+            fixtures,
+            sources: StdMutex::new(FnvHashMap::default()),
+            overrides: StdMutex::new(FnvHashMap::default()),
+            challenges: broadcast::channel(16).0,
+        }
+    }
+
+    fn override_slot(&self, source_id: i64) -> Arc<OverrideSlot> {
+        let mut overrides = match self.overrides.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        overrides.entry(source_id).or_default().clone()
+    }
+
+    /// Replace the overrides of a source, they may be set before the source
+    /// sends its first request
+    pub(crate) fn set_overrides(&self, source_id: i64, overrides: RequestOverrides) {
+        let slot = self.override_slot(source_id);
+        *match slot.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        } = Arc::new(overrides);
+    }
+
+    pub(crate) fn overrides(&self, source_id: i64) -> Arc<RequestOverrides> {
+        current_overrides(&self.override_slot(source_id))
+    }
+
+    pub(crate) fn subscribe_challenges(&self) -> broadcast::Receiver<SourceChallenge> {
+        self.challenges.subscribe()
+    }
+
+    /// Whether the last response a source got was a challenge page
+    pub(crate) fn is_challenged(&self, source_id: i64) -> bool {
+        let sources = match self.sources.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        sources
+            .get(&source_id)
+            .is_some_and(|source| source.challenged.load(Ordering::Acquire))
+    }
+
+    /// The client of a source, created with its cookie jar loaded from disk
+    /// the first time the source asks. The jar is named after the source id,
+    /// the name comes from the extension and may hold anything.
+    pub(crate) fn source(
+        &self,
+        source_id: i64,
+        source_name: &str,
+        rate_limit: Option<RateLimit>,
+    ) -> Result<Arc<SourceHttp>> {
+        let mut sources = match self.sources.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(source) = sources.get(&source_id) {
+            return Ok(source.clone());
+        }
+
+        let overrides = self.override_slot(source_id);
+        let cookies = Arc::new(CookieJar::load(
+            self.dir.join(format!("{source_id}.cookies.json")),
+            overrides.clone(),
+        ));
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&self.options.user_agent)
+            .timeout(self.options.timeout)
+            .cookie_provider(cookies.clone());
+        if let Some(proxy) = self.options.proxy.as_deref() {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        if let Some(egress) = self.egress.clone() {
+            let redirects = egress.clone();
+            builder = builder
+                .dns_resolver(egress)
+                .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        attempt.error("too many redirects")
+                    } else if let Err(error) = redirects.check(attempt.url()) {
+                        attempt.error(error)
+                    } else {
+                        attempt.follow()
+                    }
+                }));
+        }
+        let client = builder
+            .build()
+            .context("failed to build the extension HTTP client")?;
+
+        let source = Arc::new(SourceHttp {
+            source_id,
+            source_name: source_name.to_string(),
+            client,
+            cookies,
+            overrides,
+            challenges: self.challenges.clone(),
+            challenged: AtomicBool::new(false),
+            rate_limiter: rate_limit.and_then(TokenBucket::new),
+            egress: self.egress.clone(),
+            fixtures: self.fixtures.clone(),
+            cache: StdMutex::new(ResponseCache::new(self.options.cache_size)),
+        });
+        sources.insert(source_id, source.clone());
+        Ok(source)
+    }
+}
+
+/// A source's HTTP client, see [`HostHttp`]
+pub(crate) struct SourceHttp {
+    source_id: i64,
+    source_name: String,
+    client: reqwest::Client,
+    cookies: Arc<CookieJar>,
+    overrides: Arc<OverrideSlot>,
+    challenges: broadcast::Sender<SourceChallenge>,
+    /// Set while the source gets challenge pages, so admins are notified
+    /// once when it starts
+    challenged: AtomicBool,
+    /// Enforces the source's declared rate limit on every request
+    rate_limiter: Option<Arc<TokenBucket>>,
+    egress: Option<Arc<EgressPolicy>>,
+    fixtures: Option<Arc<HttpFixtures>>,
+    cache: StdMutex<ResponseCache>,
+}
+
+impl SourceHttp {
+    pub(crate) async fn send(&self, request: Request) -> Result<Response> {
+        let Some(fixtures) = self.fixtures.as_ref() else {
+            return self.fetch(request).await;
+        };
+        if let Some(response) = fixtures.replay(&request) {
+            return response;
+        }
+        let response = self.fetch(request.clone()).await?;
+        fixtures.record(&request, &response).await;
+        Ok(response)
+    }
+
+    async fn fetch(&self, request: Request) -> Result<Response> {
+        let Request {
+            method,
+            url,
+            mut headers,
+            body,
+        } = request;
+        current_overrides(&self.overrides).apply_headers(&mut headers);
+        let url = Url::parse(&url).with_context(|| format!("invalid request url {url:?}"))?;
+        if let Some(egress) = self.egress.as_ref() {
+            egress
+                .check(&url)
+                .map_err(|error| anyhow!("{url}: {error}"))?;
+        }
+        let method = Method::from_bytes(method.as_bytes())
+            .with_context(|| format!("invalid request method {method:?}"))?;
+
+        // Requests that revalidate on their own bypass the cache.
+        let cacheable = method == Method::GET
+            && body.is_none()
+            && !headers.iter().any(|(name, _)| {
+                name.eq_ignore_ascii_case(header::IF_NONE_MATCH.as_str())
+                    || name.eq_ignore_ascii_case(header::IF_MODIFIED_SINCE.as_str())
+            });
+        let validators = if cacheable {
+            self.cache()
+                .get(url.as_str())
+                .map(|cached| (cached.etag.clone(), cached.last_modified.clone()))
+        } else {
+            None
+        };
+
+        let mut builder = self.client.request(method.clone(), url.clone());
+        for (name, value) in &headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some((etag, last_modified)) = validators {
+            if let Some(etag) = etag {
+                builder = builder.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = last_modified {
+                builder = builder.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        if let Some(body) = body {
+            builder = builder.body(body);
+        }
+
+        if let Some(rate_limiter) = self.rate_limiter.as_ref() {
+            rate_limiter.acquire().await;
+        }
+        let started = Instant::now();
+        let response = builder.send().await;
+        self.cookies.save_if_changed().await;
+        let response = response.with_context(|| format!("{method} {url} failed"))?;
+        let status = response.status();
+
+        if status == StatusCode::NOT_MODIFIED
+            && cacheable
+            && let Some(cached) = self.cache().get(url.as_str())
+        {
+            debug!(
+                "extension http: source={} {method} {url} -> not modified, {} bytes from cache in {:?}",
+                self.source_name,
+                cached.response.body.len(),
+                started.elapsed()
+            );
+            return Ok(cached.response.clone());
+        }
+
+        let response_headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_owned(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let body = read_body(response)
+            .await
+            .with_context(|| format!("{method} {url} failed"))?;
+        debug!(
+            "extension http: source={} {method} {url} -> {status}, {} bytes in {:?}",
+            self.source_name,
+            body.len(),
+            started.elapsed()
+        );
+        let response = Response {
+            status: status.as_u16(),
+            headers: response_headers,
+            body,
+        };
+        if cacheable && status == StatusCode::OK {
+            self.cache().store(url.as_str(), &response);
+        }
+
+        if is_challenge(&response) {
+            if !self.challenged.swap(true, Ordering::AcqRel) {
+                warn!(
+                    "source {} got a challenge page from {url}",
+                    self.source_name
+                );
+                let _ = self.challenges.send(SourceChallenge {
+                    source_id: self.source_id,
+                    source_name: self.source_name.clone(),
+                    url: url.to_string(),
+                    status: response.status,
+                });
+            }
+        } else if response.is_success() {
+            self.challenged.store(false, Ordering::Release);
+        }
+
+        Ok(response)
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, ResponseCache> {
+        match self.cache.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// A source's client for extensions running in process, e.g. template
+/// sources. Their calls run on blocking threads, which wait for the request
+/// on the runtime.
+pub(crate) struct BlockingSourceHttp {
+    pub(crate) http: Arc<SourceHttp>,
+    pub(crate) runtime: tokio::runtime::Handle,
+}
+
+impl HttpClient for BlockingSourceHttp {
+    fn send(&self, request: Request) -> Result<Response> {
+        self.runtime.block_on(self.http.send(request))
+    }
+}
+
+fn current_overrides(slot: &OverrideSlot) -> Arc<RequestOverrides> {
+    match slot.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+async fn read_body(mut response: reqwest::Response) -> Result<Bytes> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_RESPONSE_SIZE as u64)
+    {
+        bail!("response exceeds {MAX_RESPONSE_SIZE} bytes");
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > MAX_RESPONSE_SIZE {
+            bail!("response exceeds {MAX_RESPONSE_SIZE} bytes");
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.into())
+}
+
+/// A source's cookies, saved next to its preferences whenever a response
+/// changes them so logins survive restarts
+struct CookieJar {
+    store: StdMutex<cookie_store::CookieStore>,
+    path: PathBuf,
+    changed: AtomicBool,
+    /// Cookies set by an admin, sent on top of the stored ones and never
+    /// saved with them
+    overrides: Arc<OverrideSlot>,
+    // Serializes saves so an older snapshot never overwrites a newer one.
+    save: Mutex<()>,
+}
+
+impl CookieJar {
+    fn load(path: PathBuf, overrides: Arc<OverrideSlot>) -> Self {
+        let store = match std::fs::File::open(&path) {
+            Ok(file) => cookie_store::serde::json::load_all(io::BufReader::new(file))
+                .unwrap_or_else(|error| {
+                    warn!("failed to read cookies {}: {error}", path.display());
+                    cookie_store::CookieStore::default()
+                }),
+            Err(_) => cookie_store::CookieStore::default(),
+        };
+        Self {
+            store: StdMutex::new(store),
+            path,
+            changed: AtomicBool::new(false),
+            overrides,
+            save: Mutex::new(()),
+        }
+    }
+
+    fn store(&self) -> std::sync::MutexGuard<'_, cookie_store::CookieStore> {
+        match self.store.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    async fn save_if_changed(&self) {
+        let _save = self.save.lock().await;
+        if !self.changed.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        // Session cookies are kept too, a worker restart should not log the
+        // source out.
+        let mut contents = Vec::new();
+        if let Err(error) = cookie_store::serde::json::save_incl_expired_and_nonpersistent(
+            &self.store(),
+            &mut contents,
+        ) {
+            warn!(
+                "failed to serialize cookies {}: {error}",
+                self.path.display()
+            );
+            return;
+        }
+        if let Err(error) = write_atomically(&self.path, &contents).await {
+            warn!("failed to save cookies {}: {error}", self.path.display());
+        }
+    }
+}
+
+async fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("json.tmp");
+    tokio::fs::write(&temporary, contents).await?;
+    tokio::fs::rename(&temporary, path).await
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| cookie_store::RawCookie::parse(value.to_owned()).ok())
+            .collect::<Vec<_>>();
+        if cookies.is_empty() {
+            return;
+        }
+        self.store()
+            .store_response_cookies(cookies.into_iter(), url);
+        self.changed.store(true, Ordering::Release);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let cookies = self
+            .store()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let cookies = current_overrides(&self.overrides)
+            .merge_cookies(Some(cookies.as_str()).filter(|cookies| !cookies.is_empty()))?;
+        HeaderValue::from_str(&cookies).ok()
+    }
+}
+
+struct CachedResponse {
+    response: Response,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Responses kept to revalidate, evicted oldest first once their bodies
+/// exceed the capacity
+struct ResponseCache {
+    capacity: usize,
+    size: usize,
+    entries: FnvHashMap<String, CachedResponse>,
+    order: VecDeque<String>,
+}
+
+impl ResponseCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            entries: FnvHashMap::default(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, url: &str) -> Option<&CachedResponse> {
+        self.entries.get(url)
+    }
+
+    fn store(&mut self, url: &str, response: &Response) {
+        let etag = response.header(header::ETAG.as_str()).map(str::to_owned);
+        let last_modified = response
+            .header(header::LAST_MODIFIED.as_str())
+            .map(str::to_owned);
+        let no_store = response
+            .header(header::CACHE_CONTROL.as_str())
+            .is_some_and(|value| value.to_ascii_lowercase().contains("no-store"));
+        self.remove(url);
+        // A single response may take up to a quarter of the cache.
+        let length = response.body.len();
+        if (etag.is_none() && last_modified.is_none()) || no_store || length > self.capacity / 4 {
+            return;
+        }
+
+        while self.size + length > self.capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(&oldest) {
+                self.size -= evicted.response.body.len();
+            }
+        }
+        self.size += length;
+        self.order.push_back(url.to_owned());
+        self.entries.insert(
+            url.to_owned(),
+            CachedResponse {
+                response: response.clone(),
+                etag,
+                last_modified,
+            },
+        );
+    }
+
+    fn remove(&mut self, url: &str) {
+        if let Some(removed) = self.entries.remove(url) {
+            self.size -= removed.response.body.len();
+            self.order.retain(|cached| cached != url);
+        }
+    }
+}
+
+/// What extensions may reach through the host while their workers run in a
+/// private network, the same as through the egress proxy: ports 80 and 443
+/// on public addresses, and anything on the allowed private hosts.
+struct EgressPolicy {
+    allowed_private_hosts: Vec<String>,
+}
+
+impl EgressPolicy {
+    fn is_allowed_host(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.allowed_private_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+
+    /// Checks what the resolver never sees, the port and literal addresses.
+    fn check(&self, url: &Url) -> std::result::Result<(), &'static str> {
+        let Some(host) = url.host_str() else {
+            return Err("url has no host");
+        };
+        if self.is_allowed_host(host) {
+            return Ok(());
+        }
+        if !url
+            .port_or_known_default()
+            .is_some_and(|port| EGRESS_PORTS.contains(&port))
+        {
+            return Err("only ports 80 and 443 are reachable");
+        }
+        match host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+        {
+            Ok(address) if !is_public_address(address) => Err("address is not public"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Resolve for EgressPolicy {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowed = self.is_allowed_host(name.as_str());
+        Box::pin(async move {
+            let addresses = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|address| allowed || is_public_address(address.ip()))
+                .collect::<Vec<_>>();
+            if addresses.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "host only resolves to private addresses",
+                )
+                .into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves `responses` in order, one connection each, and returns the
+    /// request heads it received.
+    async fn serve(responses: Vec<&'static str>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut head = Vec::new();
+                while !head.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte).await.unwrap();
+                    head.push(byte[0]);
+                }
+                requests.push(String::from_utf8(head).unwrap().to_ascii_lowercase());
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            requests
+        });
+        (format!("http://{address}/chapter"), server)
+    }
+
+    #[tokio::test]
+    async fn unchanged_responses_come_from_the_cache_and_cookies_persist() {
+        let dir =
+            std::env::temp_dir().join(format!("tanoshi-vm-http-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nSet-Cookie: session=abc; Max-Age=3600\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\npages",
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+
+        let http = HostHttp::new(HttpOptions::default(), dir.clone(), None);
+        let source = http.source(1, "Cached Source", None).unwrap();
+        let first = source.send(Request::get(&url)).await.unwrap();
+        let second = source.send(Request::get(&url)).await.unwrap();
+        assert_eq!(first.body, Bytes::from_static(b"pages"));
+        assert_eq!(second.status, 200);
+        assert_eq!(second.body, first.body);
+        assert!(dir.join("1.cookies.json").is_file());
+
+        // A fresh host reads the saved jar.
+        let http = HostHttp::new(HttpOptions::default(), dir.clone(), None);
+        let source = http.source(1, "Cached Source", None).unwrap();
+        source.send(Request::get(&url)).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+        assert!(requests[1].contains("cookie: session=abc"));
+        assert!(requests[2].contains("cookie: session=abc"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn cookie_jars_stay_in_the_data_dir_whatever_the_source_name() {
+        let root =
+            std::env::temp_dir().join(format!("tanoshi-vm-http-jars-{}", std::process::id()));
+        let dir = root.join("data");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: session=first\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+
+        let http = HostHttp::new(HttpOptions::default(), dir.clone(), None);
+        let hostile = http.source(1, "../Escaped/Source", None).unwrap();
+        hostile.send(Request::get(&url)).await.unwrap();
+        // Differs only in case, but must not see the other source's cookies.
+        let other = http.source(2, "../escaped/source", None).unwrap();
+        other.send(Request::get(&url)).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(!requests[1].contains("cookie: session=first"));
+        assert!(dir.join("1.cookies.json").is_file());
+        let mut outside = std::fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        outside.sort();
+        assert_eq!(outside, ["data"]);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn private_network_policy_rejects_local_targets() {
+        let policy = EgressPolicy {
+            allowed_private_hosts: vec!["nas.lan".to_string()],
+        };
+        let check = |url: &str| policy.check(&Url::parse(url).unwrap());
+
+        assert!(check("https://example.com/manga").is_ok());
+        assert!(check("http://93.184.215.14/").is_ok());
+        assert!(check("http://nas.lan:8080/").is_ok());
+        assert!(check("http://127.0.0.1/").is_err());
+        assert!(check("http://[::1]/").is_err());
+        assert!(check("http://192.168.1.10/").is_err());
+        assert!(check("https://example.com:8443/").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+/// Bytes of a page image as they arrive from the extension. The first chunk
+/// is already received, so a failed fetch surfaces before anything is sent
+/// to the client.
+pub struct ImageStream {
+    first: Option<Bytes>,
+    receiver: Option<mpsc::Receiver<Result<Bytes>>>,
+}
+
+impl ImageStream {
+    pub(crate) fn new(first: Option<Bytes>, receiver: mpsc::Receiver<Result<Bytes>>) -> Self {
+        Self {
+            first,
+            receiver: Some(receiver),
+        }
+    }
+
+    /// A stream of an image that is already in memory
+    pub fn from_bytes(bytes: Bytes) -> Self {
+        Self {
+            first: Some(bytes),
+            receiver: None,
+        }
+    }
+
+    /// The first chunk, enough to sniff the image type
+    pub fn first_chunk(&self) -> &[u8] {
+        self.first.as_deref().unwrap_or_default()
+    }
+
+    /// Wait for the whole image
+    pub async fn collect_bytes(mut self) -> Result<Bytes> {
+        let Some(mut receiver) = self.receiver.take() else {
+            return Ok(self.first.unwrap_or_default());
+        };
+        let mut bytes = BytesMut::new();
+        if let Some(first) = self.first.take() {
+            bytes.extend_from_slice(&first);
+        }
+        while let Some(chunk) = receiver.recv().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok(bytes.freeze())
+    }
+}
+
+impl Stream for ImageStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(first) = self.first.take() {
+            return Poll::Ready(Some(Ok(first)));
+        }
+        match self.receiver.as_mut() {
+            Some(receiver) => receiver.poll_recv(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// The MIME type of an image from its first bytes, e.g. the first chunk of
+/// an [`ImageStream`]
+pub fn detect_image_type(bytes: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, signature: &[u8]| {
+        bytes
+            .get(offset..)
+            .is_some_and(|rest| rest.starts_with(signature))
+    };
+    if at(0, &[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if at(0, b"\x89PNG") {
+        Some("image/png")
+    } else if at(0, b"GIF8") {
+        Some("image/gif")
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        Some("image/webp")
+    } else if at(4, b"ftypavif") {
+        Some("image/avif")
+    } else if at(0, b"BM") {
+        Some("image/bmp")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_types_are_detected_from_their_signature() {
+        let cases: [(&[u8], Option<&str>); 8] = [
+            (&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10], Some("image/jpeg")),
+            (b"\x89PNG\r\n\x1a\n", Some("image/png")),
+            (b"GIF89a", Some("image/gif")),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", Some("image/webp")),
+            (b"\0\0\0\x1cftypavif", Some("image/avif")),
+            (b"BM\x36\0", Some("image/bmp")),
+            (b"RIFF\x24\0\0\0WAVE", None),
+            (b"<html>", None),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(detect_image_type(bytes), expected, "{bytes:?}");
+        }
+        assert_eq!(detect_image_type(&[]), None);
+        assert_eq!(detect_image_type(b"RIFF"), None);
+    }
+
+    #[tokio::test]
+    async fn collect_bytes_joins_first_chunk_and_the_rest() {
+        let (sender, receiver) = mpsc::channel(4);
+        sender.send(Ok(Bytes::from_static(b"cd"))).await.unwrap();
+        sender.send(Ok(Bytes::from_static(b"ef"))).await.unwrap();
+        drop(sender);
+
+        let stream = ImageStream::new(Some(Bytes::from_static(b"ab")), receiver);
+        assert_eq!(stream.first_chunk(), b"ab");
+        assert_eq!(stream.collect_bytes().await.unwrap(), "abcdef");
+    }
+
+    #[tokio::test]
+    async fn collect_bytes_reports_errors_after_the_first_chunk() {
+        let (sender, receiver) = mpsc::channel(4);
+        sender
+            .send(Err(anyhow::anyhow!("worker crashed")))
+            .await
+            .unwrap();
+        drop(sender);
+
+        let stream = ImageStream::new(Some(Bytes::from_static(b"ab")), receiver);
+        assert!(stream.collect_bytes().await.is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeSet,
     future::Future,
     panic::AssertUnwindSafe,
     path::{Path, PathBuf},
@@ -12,24 +13,45 @@ use std::{
 use anyhow::{Result, anyhow, bail};
 use bytes::Bytes;
 use fnv::FnvHashMap;
-use tanoshi_lib::prelude::{ChapterInfo, Input, Lang, MangaInfo, SourceInfo};
-use tokio::sync::{Mutex as AsyncMutex, OwnedSemaphorePermit};
+use notify::{RecursiveMode, Watcher};
+use tanoshi_lib::prelude::{
+    ChapterInfo, Credentials, Input, Lang, MangaInfo, PageInfo, Paginated, Preference,
+    PreferenceValue, PreferenceValues, ResolvedPath, SECRET_MASK, SourceInfo, SourceSession,
+    mask_secret_preferences, validate_preferences, with_default_preferences,
+};
+use tokio::{
+    sync::{Mutex as AsyncMutex, OwnedSemaphorePermit, broadcast, mpsc},
+    task::JoinHandle,
+};
 
 use crate::{
-    PLUGIN_EXTENSION,
+    PLUGIN_EXTENSION, WASM_PLUGIN_EXTENSION,
     prelude::{Source, SourceEntry},
 };
 
+use super::http::{BlockingSourceHttp, HostHttp, HttpOptions};
+use super::image_stream::ImageStream;
+use super::overrides::{RequestOverrides, SourceChallenge};
+#[cfg(target_os = "linux")]
+use super::sandbox::EgressProxy;
+use super::sandbox::{SandboxLayerStatus, SandboxOptions, WorkerSandbox};
+use super::secret::SecretKey;
 use super::source::{
-    SOURCE_MAX_ABANDONED_CALLS, SourceAdmission, SourceHealth, panic_payload_message,
+    LibraryFingerprint, SOURCE_MAX_ABANDONED_CALLS, SourceAdmission, SourceHealth,
+    SourceHealthReport, panic_payload_message,
 };
+use super::template::{TemplateSource, is_template_file_name};
+use super::verify::PluginVerification;
 use super::worker::{
-    WorkerCallError, WorkerClient, WorkerErrorKind, WorkerRequest, WorkerValue, resolve_worker_path,
+    CHUNK_BUFFER, IMAGE_STREAM_PROTOCOL_VERSION, SavedPreferences, SealedPreferences, WorkerCall,
+    WorkerCallError, WorkerClient, WorkerErrorKind, WorkerPoolOptions, WorkerReply, WorkerRequest,
+    WorkerValue, resolve_worker_path,
 };
 
 const STAGED_LIBRARY_PREFIX: &str = ".tanoshi-staged-";
 const INSTALL_TEMP_PREFIX: &str = ".tanoshi-install-";
 const INSTALL_BACKUP_PREFIX: &str = ".tanoshi-backup-";
+const VERSIONS_DIR: &str = "versions";
 const CALL_RUNNING: u8 = 0;
 const CALL_ABANDONED: u8 = 1;
 const CALL_COMPLETE: u8 = 2;
@@ -38,7 +60,13 @@ pub const DEFAULT_MAX_CONCURRENT_CALLS: usize = 30;
 pub const DEFAULT_ADMISSION_TIMEOUT: Duration = Duration::from_secs(1);
 pub const DEFAULT_METADATA_TIMEOUT: Duration = Duration::from_secs(30);
 pub const DEFAULT_IMAGE_TIMEOUT: Duration = Duration::from_secs(120);
+pub const DEFAULT_MIN_WORKER_PROCESSES: usize = 1;
+pub const DEFAULT_MAX_WORKER_PROCESSES: usize = 4;
+pub const DEFAULT_WORKER_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+pub const DEFAULT_KEEP_VERSIONS: usize = 3;
 const MIN_EXTENSION_TIMEOUT: Duration = Duration::from_millis(1);
+/// How long the plugin directory must be quiet before changes are applied
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
 
 struct AbandonedCallTracker {
     state: AtomicU8,
@@ -105,8 +133,18 @@ fn normalize_plugin_name(name: &str) -> String {
         .file_name()
         .and_then(|name| name.to_str())
         .unwrap_or(name);
-    let extension = format!(".{PLUGIN_EXTENSION}");
-    name.strip_suffix(&extension).unwrap_or(name).to_lowercase()
+    let name = name
+        .strip_suffix(&format!(".{PLUGIN_EXTENSION}"))
+        .or_else(|| name.strip_suffix(&format!(".{WASM_PLUGIN_EXTENSION}")))
+        .unwrap_or(name);
+    name.to_lowercase()
+}
+
+/// Whether `name` is a native or WebAssembly plugin library
+fn is_library_file_name(name: &str) -> bool {
+    Path::new(name).extension().is_some_and(|extension| {
+        extension == PLUGIN_EXTENSION || extension == WASM_PLUGIN_EXTENSION
+    })
 }
 
 fn entry_plugin_name(entry: &SourceEntry) -> String {
@@ -118,6 +156,20 @@ fn entry_plugin_name(entry: &SourceEntry) -> String {
         .unwrap_or_else(|| normalize_plugin_name(entry.source_name()))
 }
 
+/// Whether a worker entry runs the library currently at `plugin_path`. Only
+/// the file's size and modification time are compared, installs and
+/// rollbacks move a new file into place, so both change with the library.
+async fn is_loaded_library(entry: &SourceEntry, plugin_path: &Path) -> bool {
+    let Some(loaded) = entry.library_fingerprint else {
+        return false;
+    };
+    let plugin_path = plugin_path.to_path_buf();
+    matches!(
+        tokio::task::spawn_blocking(move || LibraryFingerprint::of(&plugin_path)).await,
+        Ok(Ok(current)) if current == loaded
+    )
+}
+
 fn unexpected_worker_value(expected: &str, value: WorkerValue) -> anyhow::Error {
     anyhow!("expected {expected} response, got {value:?}")
 }
@@ -136,6 +188,34 @@ fn decode_inputs(value: WorkerValue) -> Result<Vec<Input>> {
     }
 }
 
+fn decode_preference_schema(value: WorkerValue) -> Result<Vec<Preference>> {
+    match value {
+        WorkerValue::PreferenceSchema(value) => Ok(value),
+        value => Err(unexpected_worker_value("preference schema", value)),
+    }
+}
+
+fn decode_session(value: WorkerValue) -> Result<SourceSession> {
+    match value {
+        WorkerValue::Session(value) => Ok(value),
+        value => Err(unexpected_worker_value("session", value)),
+    }
+}
+
+fn decode_bool(value: WorkerValue) -> Result<bool> {
+    match value {
+        WorkerValue::Bool(value) => Ok(value),
+        value => Err(unexpected_worker_value("bool", value)),
+    }
+}
+
+fn decode_manga_page(value: WorkerValue) -> Result<Paginated<MangaInfo>> {
+    match value {
+        WorkerValue::MangaPage(value) => Ok(value),
+        value => Err(unexpected_worker_value("manga page", value)),
+    }
+}
+
 fn decode_manga_list(value: WorkerValue) -> Result<Vec<MangaInfo>> {
     match value {
         WorkerValue::MangaList(value) => Ok(value),
@@ -157,7 +237,14 @@ fn decode_chapters(value: WorkerValue) -> Result<Vec<ChapterInfo>> {
     }
 }
 
-fn decode_pages(value: WorkerValue) -> Result<Vec<String>> {
+fn decode_resolved_path(value: WorkerValue) -> Result<Option<ResolvedPath>> {
+    match value {
+        WorkerValue::ResolvedPath(value) => Ok(value),
+        value => Err(unexpected_worker_value("resolved path", value)),
+    }
+}
+
+fn decode_pages(value: WorkerValue) -> Result<Vec<PageInfo>> {
     match value {
         WorkerValue::Pages(value) => Ok(value),
         value => Err(unexpected_worker_value("pages", value)),
@@ -166,11 +253,18 @@ fn decode_pages(value: WorkerValue) -> Result<Vec<String>> {
 
 fn decode_image(value: WorkerValue) -> Result<Bytes> {
     match value {
-        WorkerValue::Image { bytes } => Ok(Bytes::from(bytes)),
+        WorkerValue::Image { bytes } => Ok(bytes),
         value => Err(unexpected_worker_value("image", value)),
     }
 }
 
+fn decode_image_stream_end(value: WorkerValue) -> Result<()> {
+    match value {
+        WorkerValue::Unit => Ok(()),
+        value => Err(unexpected_worker_value("image stream end", value)),
+    }
+}
+
 fn cleanup_managed_libraries(dir: &Path) {
     let current_process_marker = format!("-{}-", std::process::id());
     let entries = match std::fs::read_dir(dir) {
@@ -212,6 +306,19 @@ struct PluginFileReplacement {
     backup_path: Option<PathBuf>,
 }
 
+/// Operations that reach the source website and count against its declared
+/// rate limit, metadata such as filters and preferences does not.
+const RATE_LIMITED_OPERATIONS: &[&str] = &[
+    "get_popular_manga",
+    "get_latest_manga",
+    "search_manga",
+    "get_manga_detail",
+    "get_chapters",
+    "get_related_manga",
+    "get_pages",
+    "get_image_bytes",
+];
+
 #[derive(Clone, Copy)]
 struct ExtensionCall {
     operation: &'static str,
@@ -222,10 +329,7 @@ struct ExtensionCall {
 #[derive(Debug)]
 pub enum ExtensionError {
     MissingSource,
-    Operational {
-        kind: &'static str,
-        message: String,
-    },
+    Operational { kind: &'static str, message: String },
 }
 
 impl ExtensionError {
@@ -252,19 +356,32 @@ fn missing_source_error() -> anyhow::Error {
     anyhow::Error::new(ExtensionError::MissingSource)
 }
 
-fn operational_extension_error(
-    kind: &'static str,
-    message: impl Into<String>,
-) -> anyhow::Error {
+fn operational_extension_error(kind: &'static str, message: impl Into<String>) -> anyhow::Error {
     anyhow::Error::new(ExtensionError::operational(kind, message))
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct ExtensionManagerOptions {
     pub max_concurrent_calls: usize,
     pub admission_timeout: Duration,
     pub metadata_timeout: Duration,
     pub image_timeout: Duration,
+    /// Worker processes kept running per source, even when idle
+    pub min_worker_processes: usize,
+    /// Worker processes a busy source may scale up to
+    pub max_worker_processes: usize,
+    /// How long a worker beyond the minimum may sit idle before it is stopped
+    pub worker_idle_timeout: Duration,
+    /// Replaced versions of each extension kept to roll back to
+    pub keep_versions: usize,
+    /// Reload plugin libraries when their files in the plugin directory are
+    /// added, replaced or removed, see [`ExtensionManager::watch`]
+    pub hot_reload: bool,
+    /// Seals secret preference values saved in the plugin directory, they
+    /// are not saved at all without one
+    pub preference_key: Option<SecretKey>,
+    pub sandbox: SandboxOptions,
+    pub http: HttpOptions,
 }
 
 impl Default for ExtensionManagerOptions {
@@ -274,10 +391,38 @@ impl Default for ExtensionManagerOptions {
             admission_timeout: DEFAULT_ADMISSION_TIMEOUT,
             metadata_timeout: DEFAULT_METADATA_TIMEOUT,
             image_timeout: DEFAULT_IMAGE_TIMEOUT,
+            min_worker_processes: DEFAULT_MIN_WORKER_PROCESSES,
+            max_worker_processes: DEFAULT_MAX_WORKER_PROCESSES,
+            worker_idle_timeout: DEFAULT_WORKER_IDLE_TIMEOUT,
+            keep_versions: DEFAULT_KEEP_VERSIONS,
+            hot_reload: false,
+            preference_key: None,
+            sandbox: SandboxOptions::default(),
+            http: HttpOptions::default(),
         }
     }
 }
 
+/// What the plugin directory watcher did with a changed file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtensionReloadKind {
+    Loaded,
+    Reloaded,
+    Unloaded,
+}
+
+/// Sent once the plugin directory watcher applied a changed file, or failed
+/// to, see [`ExtensionManager::subscribe_reloads`]
+#[derive(Clone, Debug)]
+pub struct ExtensionReload {
+    /// Name of the changed file in the plugin directory
+    pub file: String,
+    pub source_id: Option<i64>,
+    pub kind: ExtensionReloadKind,
+    /// Why the change was not applied, the previous version keeps serving
+    pub error: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct ExtensionManager {
     dir: PathBuf,
@@ -285,6 +430,11 @@ pub struct ExtensionManager {
     lifecycle_locks: Arc<StdMutex<FnvHashMap<String, Arc<AsyncMutex<()>>>>>,
     options: ExtensionManagerOptions,
     worker_path: PathBuf,
+    http: Arc<HostHttp>,
+    reloads: broadcast::Sender<ExtensionReload>,
+    // Started with the first sandboxed worker that needs a private network.
+    #[cfg(target_os = "linux")]
+    egress_proxy: Arc<tokio::sync::OnceCell<Arc<EgressProxy>>>,
 }
 
 pub fn dummy_source_info(id: i64) -> SourceInfo {
@@ -296,6 +446,7 @@ pub fn dummy_source_info(id: i64) -> SourceInfo {
         icon: "",
         languages: Lang::All,
         nsfw: false,
+        ..Default::default()
     }
 }
 
@@ -342,14 +493,46 @@ impl ExtensionManager {
             );
             options.image_timeout = MIN_EXTENSION_TIMEOUT;
         }
+        if options.min_worker_processes == 0 {
+            warn!("configured minimum extension workers is zero; using one worker instead");
+            options.min_worker_processes = 1;
+        }
+        if options.max_worker_processes < options.min_worker_processes {
+            warn!(
+                "configured maximum extension workers {} is below the minimum {}; using {} instead",
+                options.max_worker_processes,
+                options.min_worker_processes,
+                options.min_worker_processes
+            );
+            options.max_worker_processes = options.min_worker_processes;
+        }
+        if options.worker_idle_timeout.is_zero() {
+            warn!(
+                "configured extension worker idle timeout is zero; using {:?} instead",
+                MIN_EXTENSION_TIMEOUT
+            );
+            options.worker_idle_timeout = MIN_EXTENSION_TIMEOUT;
+        }
         let dir = PathBuf::new().join(extension_dir);
         cleanup_managed_libraries(&dir);
+        let http = HostHttp::new(
+            options.http.clone(),
+            dir.clone(),
+            options
+                .sandbox
+                .private_network
+                .then(|| options.sandbox.allowed_private_hosts.clone()),
+        );
         Self {
             dir,
             extensions: Arc::new(RwLock::new(FnvHashMap::default())),
             lifecycle_locks: Arc::new(StdMutex::new(FnvHashMap::default())),
             options,
             worker_path: resolve_worker_path(),
+            http: Arc::new(http),
+            reloads: broadcast::channel(16).0,
+            #[cfg(target_os = "linux")]
+            egress_proxy: Arc::new(tokio::sync::OnceCell::new()),
         }
     }
 
@@ -381,13 +564,32 @@ impl ExtensionManager {
         Ok(source_path.parent().unwrap_or(&self.dir).join(name))
     }
 
-    fn stage_library(&self, plugin_path: &Path) -> Result<PathBuf> {
+    /// Where the library `name` is in the plugin directory. Without an
+    /// extension it is the native library, or the WebAssembly one when only
+    /// that exists.
+    fn library_path(&self, name: &str) -> PathBuf {
+        let plugin_path = self.dir.join(normalize_plugin_name(name));
+        let native_path = plugin_path.with_extension(PLUGIN_EXTENSION);
+        let wasm_path = plugin_path.with_extension(WASM_PLUGIN_EXTENSION);
+        match Path::new(name).extension() {
+            Some(extension) if extension == WASM_PLUGIN_EXTENSION => wasm_path,
+            Some(extension) if extension == PLUGIN_EXTENSION => native_path,
+            _ if !native_path.exists() && wasm_path.exists() => wasm_path,
+            _ => native_path,
+        }
+    }
+
+    /// Copy a plugin file for a worker to load, along with the fingerprint
+    /// of the file it was copied from. The fingerprint is taken first, so a
+    /// change during the copy still counts as a new library.
+    fn stage_library(&self, plugin_path: &Path) -> Result<(PathBuf, LibraryFingerprint)> {
+        let fingerprint = LibraryFingerprint::of(plugin_path)?;
         let staged_path = self.unique_managed_path(plugin_path, STAGED_LIBRARY_PREFIX)?;
         if let Err(error) = std::fs::copy(plugin_path, &staged_path) {
             let _ = std::fs::remove_file(&staged_path);
             return Err(error.into());
         }
-        Ok(staged_path)
+        Ok((staged_path, fingerprint))
     }
 
     fn cleanup_managed_file(path: &Path) {
@@ -550,7 +752,7 @@ impl ExtensionManager {
         &self,
         source_id: i64,
         call: ExtensionCall,
-        request: WorkerRequest,
+        request: impl Into<WorkerCall>,
         decode: impl FnOnce(WorkerValue) -> Result<T> + Send + 'static,
         invoke: F,
     ) -> Result<T>
@@ -567,7 +769,7 @@ impl ExtensionManager {
         &self,
         entry: Arc<SourceEntry>,
         call: ExtensionCall,
-        request: WorkerRequest,
+        request: impl Into<WorkerCall>,
         decode: D,
         invoke: F,
     ) -> Result<T>
@@ -586,7 +788,7 @@ impl ExtensionManager {
         &self,
         entry: Arc<SourceEntry>,
         call: ExtensionCall,
-        request: WorkerRequest,
+        request: impl Into<WorkerCall>,
         decode: D,
         invoke: F,
     ) -> Result<T>
@@ -605,7 +807,37 @@ impl ExtensionManager {
         &self,
         entry: Arc<SourceEntry>,
         call: ExtensionCall,
-        request: WorkerRequest,
+        request: impl Into<WorkerCall>,
+        decode: D,
+        invoke: F,
+    ) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&SourceEntry) -> Result<T> + Send + 'static,
+        D: FnOnce(WorkerValue) -> Result<T> + Send + 'static,
+    {
+        // Wait for the rate limit before taking a permit so a throttled call
+        // does not hold a concurrency slot, nor count towards its latency.
+        if let Some(rate_limiter) = entry.rate_limiter.as_ref()
+            && RATE_LIMITED_OPERATIONS.contains(&call.operation)
+        {
+            rate_limiter.acquire().await;
+        }
+
+        let health = entry.health.clone();
+        let started = tokio::time::Instant::now();
+        let result = self
+            .run_blocking_call(entry, call, request, decode, invoke)
+            .await;
+        health.record_call(call.operation, started.elapsed(), result.as_ref().err());
+        result
+    }
+
+    async fn run_blocking_call<T, F, D>(
+        &self,
+        entry: Arc<SourceEntry>,
+        call: ExtensionCall,
+        request: impl Into<WorkerCall>,
         decode: D,
         invoke: F,
     ) -> Result<T>
@@ -622,7 +854,7 @@ impl ExtensionManager {
         let permit = self.acquire_permit(&entry, operation).await?;
         if let Some(worker) = entry.worker() {
             return self
-                .call_worker(entry, call, worker, permit, request, decode)
+                .call_worker(entry, call, worker, permit, request.into(), decode)
                 .await;
         }
 
@@ -690,9 +922,7 @@ impl ExtensionManager {
                     }
                     return Err(operational_extension_error(
                         "extension-panicked",
-                        format!(
-                            "source {source_id} ({source_name}) {operation} panicked"
-                        ),
+                        format!("source {source_id} ({source_name}) {operation} panicked"),
                     ));
                 }
                 bail!(
@@ -715,12 +945,12 @@ impl ExtensionManager {
                 error!(
                     "EXTENSION TIMEOUT: source_id={source_id} source={source_name:?} operation={operation} exceeded {timeout:?}; native call may still be running and its permit remains held"
                 );
-                return Err(operational_extension_error(
+                Err(operational_extension_error(
                     "extension-timeout",
                     format!(
                         "source {source_id} ({source_name}) {operation} exceeded {timeout:?}; native call may still be running"
                     ),
-                ));
+                ))
             }
         }
     }
@@ -731,7 +961,7 @@ impl ExtensionManager {
         call: ExtensionCall,
         worker: Arc<WorkerClient>,
         permit: OwnedSemaphorePermit,
-        request: WorkerRequest,
+        request: WorkerCall,
         decode: D,
     ) -> Result<T>
     where
@@ -773,9 +1003,7 @@ impl ExtensionManager {
                 );
                 Err(operational_extension_error(
                     "extension-worker-supervisor",
-                    format!(
-                        "source {source_id} ({source_name}) {operation} supervisor panicked"
-                    ),
+                    format!("source {source_id} ({source_name}) {operation} supervisor panicked"),
                 ))
             }
             Err(error) => Err(operational_extension_error(
@@ -792,7 +1020,7 @@ impl ExtensionManager {
         call: ExtensionCall,
         worker: Arc<WorkerClient>,
         permit: OwnedSemaphorePermit,
-        request: WorkerRequest,
+        request: WorkerCall,
         decode: D,
     ) -> Result<T>
     where
@@ -807,7 +1035,7 @@ impl ExtensionManager {
         let source_id = entry.source_id;
         let source_name = entry.source_name().to_owned();
         let health = entry.health.clone();
-        let result = worker.request(request, timeout).await;
+        let WorkerReply { process, result } = worker.request(request, timeout).await;
         drop(permit);
 
         match result {
@@ -823,6 +1051,9 @@ impl ExtensionManager {
                         ),
                     )
                 })?;
+                if let Some(process) = process.as_ref() {
+                    process.health().record_success();
+                }
                 health.record_success();
                 Ok(value)
             }
@@ -845,6 +1076,19 @@ impl ExtensionManager {
                     ),
                 ))
             }
+            // Terminating the worker for another call's timeout says nothing
+            // about this call; the timed-out call reports for both.
+            Err(WorkerCallError::Interrupted) => {
+                warn!(
+                    "EXTENSION WORKER INTERRUPTED: source_id={source_id} source={source_name:?} operation={operation} was interrupted because another call timed out and the worker was terminated"
+                );
+                Err(operational_extension_error(
+                    "extension-worker-interrupted",
+                    format!(
+                        "source {source_id} ({source_name}) {operation} was interrupted because another call timed out"
+                    ),
+                ))
+            }
             // The source was unloaded or replaced mid-call; the retired
             // entry's health no longer matters.
             Err(WorkerCallError::Stopped) => {
@@ -856,29 +1100,53 @@ impl ExtensionManager {
                 ))
             }
             Err(error) => {
-                let (kind, message, quarantine) = match error {
+                // Timeouts and crashes already ended the process, a panic
+                // after a mutating call leaves its state unknown.
+                let (kind, message, recycle) = match error {
                     WorkerCallError::Timeout => (
                         "TIMEOUT",
                         format!("exceeded {timeout:?}; the worker was terminated"),
-                        false,
+                        true,
                     ),
-                    WorkerCallError::Crashed(message) => ("CRASH", message, quarantine_on_panic),
+                    WorkerCallError::Crashed(message) => ("CRASH", message, true),
                     WorkerCallError::Remote { kind, message } => {
                         ("PANIC", format!("{kind:?}: {message}"), quarantine_on_panic)
                     }
-                    WorkerCallError::QueueTimeout | WorkerCallError::Stopped => {
+                    WorkerCallError::QueueTimeout
+                    | WorkerCallError::Stopped
+                    | WorkerCallError::Interrupted => {
                         unreachable!("handled above")
                     }
                 };
-                let quarantined = if quarantine {
-                    health.quarantine();
-                    true
-                } else {
+                // Failures are charged to the process first, other panics
+                // recycle it once its own health quarantines it. Each
+                // recycled process counts as one failure against the source,
+                // so only a source whose replacements keep failing is
+                // quarantined.
+                let recycled = match process.as_ref() {
+                    Some(process) if recycle => {
+                        process.health().quarantine();
+                        true
+                    }
+                    Some(process) => process.health().record_failure(),
+                    None => true,
+                };
+                let quarantined = if recycled {
                     health.record_failure()
+                } else {
+                    health.mark_degraded();
+                    false
                 };
                 error!(
                     "EXTENSION WORKER {kind}: source_id={source_id} source={source_name:?} operation={operation} {message}"
                 );
+                if recycled && let Some(process) = process.as_ref() {
+                    worker.recycle(process).await;
+                    warn!(
+                        "EXTENSION WORKER RECYCLED: source_id={source_id} source={source_name:?} operation={operation} pid={:?}; a replacement starts with the next call",
+                        process.pid()
+                    );
+                }
                 if quarantined {
                     error!(
                         "EXTENSION QUARANTINED: source_id={source_id} source={source_name:?} operation={operation}; replace or reload the extension before retrying"
@@ -893,9 +1161,7 @@ impl ExtensionManager {
                 };
                 Err(operational_extension_error(
                     error_kind,
-                    format!(
-                        "source {source_id} ({source_name}) {operation} failed: {message}"
-                    ),
+                    format!("source {source_id} ({source_name}) {operation} failed: {message}"),
                 ))
             }
         }
@@ -905,16 +1171,210 @@ impl ExtensionManager {
         let mut read_dir = tokio::fs::read_dir(&self.dir).await?;
         while let Some(entry) = read_dir.next_entry().await? {
             let name = entry.file_name().to_string_lossy().into_owned();
-            if name.ends_with(PLUGIN_EXTENSION)
+            if is_library_file_name(&name)
                 && !is_managed_library_name(&name)
                 && let Err(e) = self.load(&name).await
             {
                 error!("failed to load {name}: {e}");
+            } else if is_template_file_name(&name)
+                && let Err(e) = self.load_template(&entry.path()).await
+            {
+                error!("failed to load {name}: {e:#}");
             }
         }
         Ok(())
     }
 
+    /// Load a template source, replacing the one previously loaded from the
+    /// same file, see [`super::template`]
+    pub async fn load_template(&self, path: &Path) -> Result<i64> {
+        let template = TemplateSource::load(path)?;
+        let http = self
+            .http
+            .source(template.source_id(), template.source_name(), None)?;
+        let template = template.with_client(Arc::new(BlockingSourceHttp {
+            http,
+            runtime: tokio::runtime::Handle::current(),
+        }));
+        let entry = Arc::new(
+            Source::from(Box::new(template))
+                .with_plugin_path(path.to_path_buf())
+                .into_entry(self.options.max_concurrent_calls)?,
+        );
+
+        let source_id = entry.source_id;
+        let plugin_name = entry_plugin_name(&entry);
+        let lifecycle_lock = self.lifecycle_lock(&plugin_name)?;
+        let _lifecycle_guard = lifecycle_lock.lock_owned().await;
+        let previous = self.entry_for_plugin(&plugin_name)?;
+        for entry in self.replace_plugin_entry(entry, previous.as_ref())? {
+            if let Some(worker) = entry.worker() {
+                worker.shutdown().await;
+            }
+        }
+
+        Ok(source_id)
+    }
+
+    /// Load a plugin library that was added or replaced in the plugin
+    /// directory, swapping it in the way [`Self::install`] does so the
+    /// previous version keeps serving if the new one fails to load. Returns
+    /// `None` when the file is the library already loaded, as after an
+    /// install or a rollback.
+    async fn reload_library(&self, name: &str) -> Result<Option<i64>> {
+        let plugin_name = normalize_plugin_name(name);
+        let plugin_path = self.library_path(name);
+        let lifecycle_lock = self.lifecycle_lock(&plugin_name)?;
+        let _lifecycle_guard = lifecycle_lock.lock_owned().await;
+
+        let previous = self.entry_for_plugin(&plugin_name)?;
+        if let Some(previous) = previous.as_ref()
+            && is_loaded_library(previous, &plugin_path).await
+        {
+            return Ok(None);
+        }
+
+        let entry = self
+            .load_worker_from_path(&plugin_path, plugin_path.clone())
+            .await?;
+        if let Err(error) = self.apply_saved_preferences(&entry).await {
+            if let Some(worker) = entry.worker() {
+                worker.shutdown().await;
+            }
+            return Err(error);
+        }
+
+        let previous_worker = previous.as_ref().and_then(|entry| entry.worker());
+        if let Some(worker) = previous_worker.as_ref() {
+            worker.pause().await;
+        }
+        let retired = match self.replace_plugin_entry(entry.clone(), previous.as_ref()) {
+            Ok(retired) => retired,
+            Err(error) => {
+                if let Some(worker) = entry.worker() {
+                    worker.shutdown().await;
+                }
+                if let Some(worker) = previous_worker.as_ref() {
+                    worker.resume();
+                }
+                return Err(error);
+            }
+        };
+        for entry in retired {
+            if let Some(worker) = entry.worker() {
+                worker.shutdown().await;
+            }
+        }
+
+        Ok(Some(entry.source_id))
+    }
+
+    /// Watch the plugin directory and reload template sources whose files
+    /// are added, changed or removed, and plugin libraries as well with
+    /// [`ExtensionManagerOptions::hot_reload`]. Changes are applied once the
+    /// directory is quiet for a moment, as editors and compilers often write
+    /// a file in several steps, and reported to [`Self::subscribe_reloads`].
+    pub fn watch(&self) -> Result<JoinHandle<()>> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    for path in event.paths {
+                        let _ = tx.send(path);
+                    }
+                }
+                Err(error) => warn!("failed to watch the extension directory: {error}"),
+            })?;
+        watcher.watch(&self.dir, RecursiveMode::NonRecursive)?;
+
+        let manager = self.clone();
+        Ok(tokio::spawn(async move {
+            // dropping the watcher stops it
+            let _watcher = watcher;
+            let mut changed = BTreeSet::new();
+            while let Some(path) = rx.recv().await {
+                changed.insert(path);
+                loop {
+                    match tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await {
+                        Ok(Some(path)) => {
+                            changed.insert(path);
+                        }
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+
+                for path in std::mem::take(&mut changed) {
+                    manager.reload_changed_file(&path).await;
+                }
+            }
+        }))
+    }
+
+    async fn reload_changed_file(&self, path: &Path) {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return;
+        };
+        let is_library =
+            self.options.hot_reload && is_library_file_name(name) && !is_managed_library_name(name);
+        if !is_library && !is_template_file_name(name) {
+            return;
+        }
+
+        let previous = match self.entry_for_plugin(&normalize_plugin_name(name)) {
+            Ok(previous) => previous,
+            Err(e) => {
+                error!("failed to reload {}: {e}", path.display());
+                return;
+            }
+        };
+        let (kind, result) = if path.is_file() {
+            let kind = if previous.is_some() {
+                ExtensionReloadKind::Reloaded
+            } else {
+                ExtensionReloadKind::Loaded
+            };
+            let result = if is_library {
+                self.reload_library(name).await
+            } else {
+                self.load_template(path).await.map(Some)
+            };
+            (kind, result)
+        } else {
+            let Some(previous) = previous.as_ref() else {
+                return;
+            };
+            // The file may only be gone for a moment, as when an editor or
+            // `cp` replaces it, so the kept versions stay for a rollback.
+            let result = self.unload_source(previous.source_id, false).await;
+            (
+                ExtensionReloadKind::Unloaded,
+                result.map(|()| Some(previous.source_id)),
+            )
+        };
+
+        let (source_id, error) = match result {
+            Ok(None) => return,
+            Ok(Some(source_id)) => {
+                info!("{kind:?} {} as source {source_id}", path.display());
+                (Some(source_id), None)
+            }
+            Err(e) => {
+                error!("failed to reload {}: {e:#}", path.display());
+                (
+                    previous.map(|previous| previous.source_id),
+                    Some(format!("{e:#}")),
+                )
+            }
+        };
+        let _ = self.reloads.send(ExtensionReload {
+            file: name.to_string(),
+            source_id,
+            kind,
+            error,
+        });
+    }
+
     pub async fn exists(&self, source_id: i64) -> Result<bool> {
         Ok(self.read()?.get(&source_id).is_some())
     }
@@ -927,7 +1387,14 @@ impl ExtensionManager {
             .collect())
     }
 
-    pub async fn install(&self, repo_url: &str, name: &str) -> Result<()> {
+    /// Download an extension from a repository and load it once it passes
+    /// `verification`
+    pub async fn install(
+        &self,
+        repo_url: &str,
+        name: &str,
+        verification: &PluginVerification,
+    ) -> Result<()> {
         let plugin_name = normalize_plugin_name(name);
         let source_file_url = format!(
             "{}/{}/{}.{}",
@@ -939,15 +1406,142 @@ impl ExtensionManager {
 
         info!("downloading {source_file_url}");
 
-        let contents = reqwest::get(&source_file_url).await?.bytes().await?;
-        let plugin_path = self.dir.join(&plugin_name).with_extension(PLUGIN_EXTENSION);
+        let contents = reqwest::get(&source_file_url)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        verification
+            .verify(&contents)
+            .map_err(|error| anyhow!("{source_file_url} failed verification: {error}"))?;
+
+        self.install_library(&plugin_name, &contents).await?;
+
+        info!("installed extension {name}");
+
+        Ok(())
+    }
+
+    /// Roll a source back to a version kept by [`Self::source_versions`], the
+    /// installed version is kept in its place
+    pub async fn rollback(&self, source_id: i64, version: &str) -> Result<()> {
+        let entry = self.entry(source_id)?;
+        if entry.source_info.version == version {
+            bail!("{} {version} is already installed", entry.source_name());
+        }
+        let plugin_name = entry_plugin_name(&entry);
+        let archived_path = self.archived_version_path(&plugin_name, version);
+        let contents = match tokio::fs::read(&archived_path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                bail!("version {version} of {} is not kept", entry.source_name())
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        self.install_library(&plugin_name, &contents).await?;
+        Self::cleanup_managed_file(&archived_path);
+
+        info!("rolled back extension {} to {version}", entry.source_name());
+
+        Ok(())
+    }
+
+    /// Versions of a source kept to roll back to, most recent first
+    pub fn source_versions(&self, source_id: i64) -> Result<Vec<String>> {
+        let plugin_name = entry_plugin_name(&*self.entry(source_id)?);
+
+        Ok(self
+            .archived_versions(&plugin_name)?
+            .into_iter()
+            .map(|(version, _)| version)
+            .collect())
+    }
+
+    fn archived_version_path(&self, plugin_name: &str, version: &str) -> PathBuf {
+        let version = version
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        self.dir
+            .join(VERSIONS_DIR)
+            .join(plugin_name)
+            .join(format!("{version}.{PLUGIN_EXTENSION}"))
+    }
+
+    fn archived_versions(&self, plugin_name: &str) -> Result<Vec<(String, PathBuf)>> {
+        let dir = self.dir.join(VERSIONS_DIR).join(plugin_name);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error.into()),
+        };
+
+        let extension = format!(".{PLUGIN_EXTENSION}");
+        let mut versions = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(version) = name.strip_suffix(&extension) else {
+                continue;
+            };
+            let modified = entry.metadata()?.modified().unwrap_or(UNIX_EPOCH);
+            versions.push((modified, version.to_string(), entry.path()));
+        }
+        versions.sort_by(|a, b| b.0.cmp(&a.0));
+
+        Ok(versions
+            .into_iter()
+            .map(|(_, version, path)| (version, path))
+            .collect())
+    }
+
+    /// Keep a replaced library to roll back to, dropping the oldest versions
+    /// beyond [`ExtensionManagerOptions::keep_versions`]
+    fn archive_version(&self, plugin_name: &str, version: &str, path: &Path) -> Result<()> {
+        if self.options.keep_versions == 0 {
+            Self::cleanup_managed_file(path);
+            return Ok(());
+        }
+
+        let archived_path = self.archived_version_path(plugin_name, version);
+        if let Some(parent) = archived_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(path, &archived_path)?;
+        // the modification time orders the versions, a rename keeps the one
+        // of the original install
+        std::fs::File::options()
+            .write(true)
+            .open(&archived_path)?
+            .set_modified(SystemTime::now())?;
+
+        for (_, path) in self
+            .archived_versions(plugin_name)?
+            .into_iter()
+            .skip(self.options.keep_versions)
+        {
+            Self::cleanup_managed_file(&path);
+        }
+
+        Ok(())
+    }
+
+    async fn install_library(&self, plugin_name: &str, contents: &[u8]) -> Result<()> {
+        let plugin_path = self.dir.join(plugin_name).with_extension(PLUGIN_EXTENSION);
         let temporary_path = self.unique_managed_path(&plugin_path, INSTALL_TEMP_PREFIX)?;
 
         if let Err(error) = tokio::fs::write(&temporary_path, contents).await {
             Self::cleanup_managed_file(&temporary_path);
             return Err(error.into());
         }
-        let lifecycle_lock = self.lifecycle_lock(&plugin_name)?;
+        let lifecycle_lock = self.lifecycle_lock(plugin_name)?;
         let _lifecycle_guard = lifecycle_lock.lock_owned().await;
 
         let validation_entry = match self
@@ -968,7 +1562,7 @@ impl ExtensionManager {
         // Resolve the current registration before mutating the plugin file.
         // Keep its staged worker serving until the replacement is ready, then
         // pause it only for the map swap below.
-        let previous = self.entry_for_plugin(&plugin_name)?;
+        let previous = self.entry_for_plugin(plugin_name)?;
         let previous_worker = previous.as_ref().and_then(|entry| entry.worker());
 
         let replacement = match self
@@ -1047,11 +1641,24 @@ impl ExtensionManager {
         }
 
         if let Some(backup_path) = replacement.backup_path {
-            Self::cleanup_managed_file(&backup_path);
+            match previous.as_ref() {
+                Some(previous) => {
+                    if let Err(error) = self.archive_version(
+                        plugin_name,
+                        previous.source_info.version,
+                        &backup_path,
+                    ) {
+                        warn!(
+                            "failed to keep version {} of {plugin_name}: {error}",
+                            previous.source_info.version
+                        );
+                        Self::cleanup_managed_file(&backup_path);
+                    }
+                }
+                None => Self::cleanup_managed_file(&backup_path),
+            }
         }
 
-        info!("installed extension {name}");
-
         Ok(())
     }
 
@@ -1060,7 +1667,7 @@ impl ExtensionManager {
         source_path: &Path,
         plugin_path: PathBuf,
     ) -> Result<Arc<SourceEntry>> {
-        let staged_path = self.stage_library(source_path)?;
+        let (staged_path, library_fingerprint) = self.stage_library(source_path)?;
         info!(
             "load {:?} from {:?}",
             staged_path.display(),
@@ -1083,6 +1690,13 @@ impl ExtensionManager {
             self.worker_path.clone(),
             self.options.metadata_timeout,
             Some(staged_path.clone()),
+            WorkerPoolOptions {
+                min_processes: self.options.min_worker_processes,
+                max_processes: self.options.max_worker_processes,
+                idle_timeout: self.options.worker_idle_timeout,
+            },
+            self.worker_sandbox().await?,
+            Some(self.http.clone()),
         );
         let (source_info, rustc_version, lib_version) = match worker.start().await {
             Ok(metadata) => metadata,
@@ -1100,42 +1714,158 @@ impl ExtensionManager {
             worker,
             self.options.max_concurrent_calls,
             plugin_path,
+            library_fingerprint,
             rustc_version,
             lib_version,
         )))
     }
 
+    /// The sandbox policy for a new worker, starting the egress proxy the
+    /// first time a private network is requested.
+    async fn worker_sandbox(&self) -> Result<Option<WorkerSandbox>> {
+        let sandbox = &self.options.sandbox;
+        if !sandbox.is_enabled() {
+            return Ok(None);
+        }
+
+        #[cfg(target_os = "linux")]
+        let egress_proxy = if sandbox.private_network {
+            let proxy = self
+                .egress_proxy
+                .get_or_try_init(|| async {
+                    EgressProxy::bind(sandbox.allowed_private_hosts.clone()).map(Arc::new)
+                })
+                .await;
+            match proxy {
+                Ok(proxy) => Some(proxy.path().to_path_buf()),
+                Err(error) if !sandbox.strict => {
+                    // The worker reports its private network as unavailable.
+                    warn!("failed to start the extension egress proxy: {error}");
+                    None
+                }
+                Err(error) => bail!("failed to start the extension egress proxy: {error}"),
+            }
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        let egress_proxy = None;
+
+        Ok(Some(sandbox.worker_sandbox(egress_proxy)))
+    }
+
     /// Applies the source's persisted preferences to a freshly loaded entry
     /// before it is published in the source map.
     async fn apply_saved_preferences(&self, entry: &Arc<SourceEntry>) -> Result<()> {
         let source_name = entry.source_info.name.to_lowercase();
-        let Some(preferences) =
-            tokio::fs::read_to_string(self.dir.join(&source_name).with_extension("json"))
-                .await
-                .ok()
-                .and_then(|s| serde_json::from_str::<Vec<Input>>(&s).ok())
-        else {
+        let Some(preferences) = self.saved_preferences(&source_name).await else {
             return Ok(());
         };
 
         info!("set preferences for {source_name}");
-        let saved_preferences = preferences.clone();
-        self.call_blocking_mut_entry(
-            entry.clone(),
+        self.send_preferences(entry, "load_preferences", preferences)
+            .await
+    }
+
+    fn preferences_path(&self, source_name: &str) -> PathBuf {
+        self.dir.join(source_name).with_extension("json")
+    }
+
+    async fn saved_preferences(&self, source_name: &str) -> Option<SavedPreferences> {
+        let json = tokio::fs::read_to_string(self.preferences_path(source_name))
+            .await
+            .ok()?;
+        let Ok(SealedPreferences {
+            mut values,
+            secrets,
+        }) = serde_json::from_str(&json)
+        else {
+            return serde_json::from_str::<SavedPreferences>(&json).ok();
+        };
+
+        let opened = self
+            .options
+            .preference_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("no preference key is configured"))
+            .and_then(|key| key.open(&secrets))
+            .and_then(|secrets| Ok(serde_json::from_slice::<PreferenceValues>(&secrets)?));
+        match opened {
+            Ok(secrets) => values.extend(secrets),
+            Err(error) => {
+                warn!(
+                    "failed to open the secret preferences of {source_name}, they have to be set again: {error}"
+                )
+            }
+        }
+        Some(SavedPreferences::Values(values))
+    }
+
+    /// Save preferences next to the plugin, with the values of secret
+    /// preferences sealed by the preference key
+    async fn write_preferences(
+        &self,
+        source_name: &str,
+        preferences: &SavedPreferences,
+        schema: &[Preference],
+    ) -> Result<()> {
+        let is_secret = |key: &String| {
+            schema
+                .iter()
+                .any(|preference| preference.is_secret() && &preference.key == key)
+        };
+        let json = match preferences {
+            SavedPreferences::Values(values) if values.keys().any(is_secret) => {
+                let (secrets, values): (PreferenceValues, PreferenceValues) = values
+                    .clone()
+                    .into_iter()
+                    .partition(|(key, _)| is_secret(key));
+                match self.options.preference_key.as_ref() {
+                    Some(key) => serde_json::to_string_pretty(&SealedPreferences {
+                        values,
+                        secrets: key.seal(&serde_json::to_vec(&secrets)?)?,
+                    })?,
+                    None => {
+                        warn!(
+                            "no preference key is configured, secret preferences of {source_name} are not saved"
+                        );
+                        serde_json::to_string_pretty(&SavedPreferences::Values(values))?
+                    }
+                }
+            }
+            preferences => serde_json::to_string_pretty(preferences)?,
+        };
+
+        tokio::fs::write(self.preferences_path(source_name), json).await?;
+        Ok(())
+    }
+
+    /// Sends preferences to the extension and remembers them for workers
+    /// spawned later.
+    async fn send_preferences(
+        &self,
+        entry: &Arc<SourceEntry>,
+        operation: &'static str,
+        preferences: SavedPreferences,
+    ) -> Result<()> {
+        let extension_preferences = preferences.clone();
+        self.call_blocking_mut_entry(
+            entry.clone(),
             ExtensionCall {
-                operation: "load_preferences",
+                operation,
                 timeout: self.options.metadata_timeout,
                 quarantine_on_panic: true,
             },
-            WorkerRequest::SetPreferences {
-                preferences: preferences.clone(),
-            },
+            preferences.clone().into_request(),
             decode_unit,
-            move |extension| extension.set_preferences(preferences),
+            move |extension| match extension_preferences {
+                SavedPreferences::Values(values) => extension.set_preference_values(values),
+                SavedPreferences::Inputs(preferences) => extension.set_preferences(preferences),
+            },
         )
         .await?;
         if let Some(worker) = entry.worker() {
-            worker.set_startup_preferences(saved_preferences);
+            worker.set_startup_preferences(preferences);
         }
         Ok(())
     }
@@ -1144,7 +1874,7 @@ impl ExtensionManager {
         let plugin_name = normalize_plugin_name(name);
         let lifecycle_lock = self.lifecycle_lock(&plugin_name)?;
         let _lifecycle_guard = lifecycle_lock.lock_owned().await;
-        let plugin_path = self.dir.join(&plugin_name).with_extension(PLUGIN_EXTENSION);
+        let plugin_path = self.library_path(name);
         let entry = self
             .load_worker_from_path(&plugin_path, plugin_path.clone())
             .await?;
@@ -1215,7 +1945,12 @@ impl ExtensionManager {
         Ok(retired)
     }
 
+    /// Uninstall a source, together with the versions kept for rollback
     pub async fn unload(&self, source_id: i64) -> Result<()> {
+        self.unload_source(source_id, true).await
+    }
+
+    async fn unload_source(&self, source_id: i64, remove_versions: bool) -> Result<()> {
         loop {
             let entry = match self.read()?.get(&source_id).cloned() {
                 Some(entry) => entry,
@@ -1272,6 +2007,19 @@ impl ExtensionManager {
             if let Some(worker) = worker {
                 worker.shutdown().await;
             }
+            if !remove_versions {
+                info!("unloaded extension {source_id} ({})", entry.source_name());
+                return Ok(());
+            }
+            let versions = self.dir.join(VERSIONS_DIR).join(&plugin_name);
+            if let Err(error) = std::fs::remove_dir_all(&versions)
+                && error.kind() != std::io::ErrorKind::NotFound
+            {
+                warn!(
+                    "failed to remove kept versions {}: {error}",
+                    versions.display()
+                );
+            }
             info!(
                 "uninstalled extension {source_id} ({})",
                 entry.source_name()
@@ -1286,14 +2034,99 @@ impl ExtensionManager {
 
     pub fn get_version(&self, source_id: i64) -> Result<(String, String)> {
         let sources = self.read()?;
-        let source = sources
-            .get(&source_id)
-            .ok_or_else(missing_source_error)?;
+        let source = sources.get(&source_id).ok_or_else(missing_source_error)?;
         let rustc_version = source.rustc_version.clone();
         let lib_version = source.lib_version.clone();
         Ok((rustc_version, lib_version))
     }
 
+    /// Library a source was loaded from, `None` for sources inserted in
+    /// process
+    pub fn plugin_path(&self, source_id: i64) -> Result<Option<PathBuf>> {
+        Ok(self.entry(source_id)?.plugin_path().map(Path::to_path_buf))
+    }
+
+    /// Sandbox layers of the source's worker, empty for sources running in
+    /// process or in an unsandboxed worker.
+    pub fn sandbox_status(&self, source_id: i64) -> Result<Vec<SandboxLayerStatus>> {
+        Ok(self
+            .entry(source_id)?
+            .worker()
+            .map(|worker| worker.sandbox_status())
+            .unwrap_or_default())
+    }
+
+    /// Health of a source and latency of its recent calls
+    pub fn source_health(&self, source_id: i64) -> Result<SourceHealthReport> {
+        let entry = self.entry(source_id)?;
+        let worker_restarts = entry
+            .worker()
+            .map(|worker| worker.restarts())
+            .unwrap_or_default();
+
+        Ok(entry.health.report(worker_restarts))
+    }
+
+    /// Lift a source's quarantine so it accepts calls again, e.g. after the
+    /// website it scrapes recovered
+    pub fn unquarantine(&self, source_id: i64) -> Result<()> {
+        let entry = self.entry(source_id)?;
+        entry.health.reset();
+        info!(
+            "source_id={source_id} source={:?} was un-quarantined",
+            entry.source_name()
+        );
+
+        Ok(())
+    }
+
+    /// Replace the worker processes of a source and lift its quarantine.
+    /// Sources running in process can only be un-quarantined.
+    pub async fn restart(&self, source_id: i64) -> Result<()> {
+        let entry = self.entry(source_id)?;
+        let Some(worker) = entry.worker() else {
+            bail!(
+                "source {source_id} ({}) runs in process and has no worker to restart",
+                entry.source_name()
+            );
+        };
+        entry.health.reset();
+        worker.restart().await?;
+        info!(
+            "source_id={source_id} source={:?} workers were restarted",
+            entry.source_name()
+        );
+
+        Ok(())
+    }
+
+    /// Replace the cookies and headers sent with every host HTTP request of
+    /// a source
+    pub fn set_request_overrides(&self, source_id: i64, overrides: RequestOverrides) {
+        self.http.set_overrides(source_id, overrides);
+    }
+
+    pub fn request_overrides(&self, source_id: i64) -> Arc<RequestOverrides> {
+        self.http.overrides(source_id)
+    }
+
+    /// Notified once each time a source starts getting anti-bot challenge
+    /// pages instead of content
+    pub fn subscribe_challenges(&self) -> broadcast::Receiver<SourceChallenge> {
+        self.http.subscribe_challenges()
+    }
+
+    /// Notified each time the plugin directory watcher applies a changed
+    /// file, see [`Self::watch`]
+    pub fn subscribe_reloads(&self) -> broadcast::Receiver<ExtensionReload> {
+        self.reloads.subscribe()
+    }
+
+    /// Whether the last host HTTP response of a source was a challenge page
+    pub fn is_challenged(&self, source_id: i64) -> bool {
+        self.http.is_challenged(source_id)
+    }
+
     pub fn get_source_info(&self, source_id: i64) -> Result<SourceInfo> {
         let entry = self.read()?.get(&source_id).cloned();
         if let Some(entry) = entry {
@@ -1337,6 +2170,62 @@ impl ExtensionManager {
     }
 
     pub async fn set_preferences(&self, source_id: i64, preferences: Vec<Input>) -> Result<()> {
+        self.save_preferences(source_id, SavedPreferences::Inputs(preferences))
+            .await
+    }
+
+    pub async fn get_preference_schema(&self, source_id: i64) -> Result<Vec<Preference>> {
+        let entry = self.entry(source_id)?;
+        self.preference_schema_for_entry(entry).await
+    }
+
+    async fn preference_schema_for_entry(
+        &self,
+        entry: Arc<SourceEntry>,
+    ) -> Result<Vec<Preference>> {
+        self.call_blocking_entry(
+            entry,
+            ExtensionCall {
+                operation: "get_preference_schema",
+                timeout: self.options.metadata_timeout,
+                quarantine_on_panic: false,
+            },
+            WorkerRequest::GetPreferenceSchema,
+            decode_preference_schema,
+            |extension| Ok(extension.preference_schema()),
+        )
+        .await
+    }
+
+    /// Saved preference values with defaults filled in and secrets masked
+    pub async fn get_preference_values(&self, source_id: i64) -> Result<PreferenceValues> {
+        let entry = self.entry(source_id)?;
+        let source_name = entry.source_info.name.to_lowercase();
+        let schema = self.preference_schema_for_entry(entry).await?;
+        let values = match self.saved_preferences(&source_name).await {
+            Some(SavedPreferences::Values(values)) => values,
+            _ => PreferenceValues::new(),
+        };
+
+        Ok(mask_secret_preferences(
+            &schema,
+            with_default_preferences(&schema, values),
+        ))
+    }
+
+    /// Validates values against the source preference schema before sending
+    /// them to the extension, a [`tanoshi_lib::prelude::PreferenceErrors`] is
+    /// returned with every invalid field.
+    pub async fn set_preference_values(
+        &self,
+        source_id: i64,
+        values: PreferenceValues,
+    ) -> Result<()> {
+        self.save_preferences(source_id, SavedPreferences::Values(values))
+            .await
+    }
+
+    async fn save_preferences(&self, source_id: i64, preferences: SavedPreferences) -> Result<()> {
         let initial_entry = self.entry(source_id)?;
         let plugin_name = entry_plugin_name(&initial_entry);
         drop(initial_entry);
@@ -1347,17 +2236,17 @@ impl ExtensionManager {
         let manager = self.clone();
         let task = tokio::spawn(async move {
             manager
-                .set_preferences_for_plugin(plugin_name, preferences)
+                .save_preferences_for_plugin(plugin_name, preferences)
                 .await
         });
         task.await
             .map_err(|error| anyhow!("preference save task failed: {error}"))?
     }
 
-    async fn set_preferences_for_plugin(
+    async fn save_preferences_for_plugin(
         &self,
         plugin_name: String,
-        preferences: Vec<Input>,
+        preferences: SavedPreferences,
     ) -> Result<()> {
         let lifecycle_lock = self.lifecycle_lock(&plugin_name)?;
         let _lifecycle_guard = lifecycle_lock.lock_owned().await;
@@ -1365,39 +2254,139 @@ impl ExtensionManager {
             .entry_for_plugin(&plugin_name)?
             .ok_or_else(missing_source_error)?;
         let source_name = entry.source_info.name.to_lowercase();
-        let extension_preferences = preferences.clone();
+        let (preferences, schema) = match preferences {
+            SavedPreferences::Values(values) => {
+                let schema = self.preference_schema_for_entry(entry.clone()).await?;
+                let values = self
+                    .validated_preference_values(&schema, &source_name, values)
+                    .await?;
+                (SavedPreferences::Values(values), schema)
+            }
+            preferences => (preferences, vec![]),
+        };
+        self.send_preferences(&entry, "set_preferences", preferences.clone())
+            .await?;
+
+        self.write_preferences(&source_name, &preferences, &schema)
+            .await
+    }
+
+    async fn validated_preference_values(
+        &self,
+        schema: &[Preference],
+        source_name: &str,
+        mut values: PreferenceValues,
+    ) -> Result<PreferenceValues> {
+        let saved = match self.saved_preferences(source_name).await {
+            Some(SavedPreferences::Values(values)) => values,
+            _ => PreferenceValues::new(),
+        };
+        // A masked secret coming back from a client means "unchanged"
+        for preference in schema.iter().filter(|preference| preference.is_secret()) {
+            let mask = PreferenceValue::from(SECRET_MASK);
+            if values.get(&preference.key) == Some(&mask) {
+                match saved.get(&preference.key) {
+                    Some(value) => values.insert(preference.key.clone(), value.clone()),
+                    None => values.remove(&preference.key),
+                };
+            }
+        }
+        validate_preferences(schema, &values)?;
+
+        Ok(with_default_preferences(schema, values))
+    }
+
+    /// Logs in to the source, the session is restored whenever its worker
+    /// respawns. Callers persist the returned session to restore it after
+    /// the extension is reloaded.
+    pub async fn login(&self, source_id: i64, credentials: Credentials) -> Result<SourceSession> {
+        let entry = self.entry(source_id)?;
+        let session = self
+            .call_blocking_mut_entry(
+                entry.clone(),
+                ExtensionCall {
+                    operation: "login",
+                    timeout: self.options.metadata_timeout,
+                    quarantine_on_panic: true,
+                },
+                WorkerRequest::Login {
+                    credentials: credentials.clone(),
+                },
+                decode_session,
+                move |extension| extension.login(credentials),
+            )
+            .await?;
+        if let Some(worker) = entry.worker() {
+            worker.set_startup_session(Some(session.clone()));
+        }
+        Ok(session)
+    }
+
+    pub async fn restore_session(&self, source_id: i64, session: SourceSession) -> Result<()> {
+        let entry = self.entry(source_id)?;
+        let extension_session = session.clone();
         self.call_blocking_mut_entry(
             entry.clone(),
             ExtensionCall {
-                operation: "set_preferences",
+                operation: "restore_session",
                 timeout: self.options.metadata_timeout,
                 quarantine_on_panic: true,
             },
-            WorkerRequest::SetPreferences {
-                preferences: extension_preferences.clone(),
+            WorkerRequest::RestoreSession {
+                session: session.clone(),
             },
             decode_unit,
-            move |extension| extension.set_preferences(extension_preferences),
+            move |extension| extension.restore_session(extension_session),
         )
         .await?;
         if let Some(worker) = entry.worker() {
-            worker.set_startup_preferences(preferences.clone());
+            worker.set_startup_session(Some(session));
         }
+        Ok(())
+    }
 
-        tokio::fs::write(
-            self.dir.join(source_name).with_extension("json"),
-            serde_json::to_string_pretty(&preferences)?,
+    pub async fn logout(&self, source_id: i64) -> Result<()> {
+        let entry = self.entry(source_id)?;
+        if let Some(worker) = entry.worker() {
+            worker.set_startup_session(None);
+        }
+        self.call_blocking_mut_entry(
+            entry,
+            ExtensionCall {
+                operation: "logout",
+                timeout: self.options.metadata_timeout,
+                quarantine_on_panic: true,
+            },
+            WorkerRequest::Logout,
+            decode_unit,
+            |extension| extension.logout(),
         )
-        .await?;
+        .await
+    }
 
-        Ok(())
+    pub async fn is_logged_in(&self, source_id: i64) -> Result<bool> {
+        self.call_blocking(
+            source_id,
+            ExtensionCall {
+                operation: "is_logged_in",
+                timeout: self.options.metadata_timeout,
+                quarantine_on_panic: false,
+            },
+            WorkerRequest::IsLoggedIn,
+            decode_bool,
+            |extension| extension.is_logged_in(),
+        )
+        .await
     }
 
     pub async fn get_popular_manga(
         &self,
         source_id: i64,
         page: i64,
-    ) -> Result<Vec<tanoshi_lib::prelude::MangaInfo>> {
+    ) -> Result<Paginated<MangaInfo>> {
+        if !self.entry(source_id)?.source_info.capabilities.popular {
+            return Ok(Paginated::new(vec![], false));
+        }
         self.call_blocking(
             source_id,
             ExtensionCall {
@@ -1406,8 +2395,8 @@ impl ExtensionManager {
                 quarantine_on_panic: false,
             },
             WorkerRequest::GetPopularManga { page },
-            decode_manga_list,
-            move |extension| extension.get_popular_manga(page),
+            decode_manga_page,
+            move |extension| extension.get_popular_manga_paged(page),
         )
         .await
     }
@@ -1416,7 +2405,10 @@ impl ExtensionManager {
         &self,
         source_id: i64,
         page: i64,
-    ) -> Result<Vec<tanoshi_lib::prelude::MangaInfo>> {
+    ) -> Result<Paginated<MangaInfo>> {
+        if !self.entry(source_id)?.source_info.capabilities.latest {
+            return Ok(Paginated::new(vec![], false));
+        }
         self.call_blocking(
             source_id,
             ExtensionCall {
@@ -1425,8 +2417,8 @@ impl ExtensionManager {
                 quarantine_on_panic: false,
             },
             WorkerRequest::GetLatestManga { page },
-            decode_manga_list,
-            move |extension| extension.get_latest_manga(page),
+            decode_manga_page,
+            move |extension| extension.get_latest_manga_paged(page),
         )
         .await
     }
@@ -1437,7 +2429,16 @@ impl ExtensionManager {
         page: i64,
         query: Option<String>,
         filters: Option<Vec<Input>>,
-    ) -> Result<Vec<tanoshi_lib::prelude::MangaInfo>> {
+    ) -> Result<Paginated<MangaInfo>> {
+        let capabilities = self.entry(source_id)?.source_info.capabilities;
+        if !capabilities.search {
+            return Ok(Paginated::new(vec![], false));
+        }
+        if capabilities.search_requires_query
+            && query.as_deref().is_none_or(|query| query.trim().is_empty())
+        {
+            bail!("source {source_id} requires a search query");
+        }
         self.call_blocking(
             source_id,
             ExtensionCall {
@@ -1450,8 +2451,8 @@ impl ExtensionManager {
                 query: query.clone(),
                 filters: filters.clone(),
             },
-            decode_manga_list,
-            move |extension| extension.search_manga(page, query, filters),
+            decode_manga_page,
+            move |extension| extension.search_manga_paged(page, query, filters),
         )
         .await
     }
@@ -1494,35 +2495,112 @@ impl ExtensionManager {
         .await
     }
 
-    pub async fn get_pages(&self, source_id: i64, path: String) -> Result<Vec<String>> {
+    pub async fn get_related_manga(&self, source_id: i64, path: String) -> Result<Vec<MangaInfo>> {
         self.call_blocking(
             source_id,
             ExtensionCall {
-                operation: "get_pages",
+                operation: "get_related_manga",
                 timeout: self.options.metadata_timeout,
                 quarantine_on_panic: false,
             },
-            WorkerRequest::GetPages { path: path.clone() },
-            decode_pages,
-            move |extension| extension.get_pages(path),
+            WorkerRequest::GetRelatedManga { path: path.clone() },
+            decode_manga_list,
+            move |extension| extension.get_related_manga(path),
         )
         .await
     }
 
-    pub async fn get_image_bytes(&self, source_id: i64, url: String) -> Result<Bytes> {
+    pub async fn resolve_url(&self, source_id: i64, url: String) -> Result<Option<ResolvedPath>> {
         self.call_blocking(
             source_id,
             ExtensionCall {
-                operation: "get_image_bytes",
-                timeout: self.options.image_timeout,
+                operation: "resolve_url",
+                timeout: self.options.metadata_timeout,
                 quarantine_on_panic: false,
             },
-            WorkerRequest::GetImageBytes { url: url.clone() },
-            decode_image,
-            move |extension| extension.get_image_bytes(url),
+            WorkerRequest::ResolveUrl { url: url.clone() },
+            decode_resolved_path,
+            move |extension| extension.resolve_url(url),
         )
         .await
     }
+
+    pub async fn get_pages(&self, source_id: i64, path: String) -> Result<Vec<PageInfo>> {
+        self.call_blocking(
+            source_id,
+            ExtensionCall {
+                operation: "get_pages",
+                timeout: self.options.metadata_timeout,
+                quarantine_on_panic: false,
+            },
+            WorkerRequest::GetPages { path: path.clone() },
+            decode_pages,
+            move |extension| extension.get_page_list(path),
+        )
+        .await
+    }
+
+    /// Fetch a page image as a stream of chunks. Workers that predate
+    /// streaming, and in-process sources, send the image as a single chunk.
+    pub async fn get_image_bytes(&self, source_id: i64, page: PageInfo) -> Result<ImageStream> {
+        let entry = self.entry(source_id)?;
+        let streaming = entry
+            .worker()
+            .is_some_and(|worker| worker.protocol_version() >= IMAGE_STREAM_PROTOCOL_VERSION);
+        let call = ExtensionCall {
+            operation: "get_image_bytes",
+            timeout: self.options.image_timeout,
+            quarantine_on_panic: false,
+        };
+
+        let (sender, mut receiver) = mpsc::channel(CHUNK_BUFFER);
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let result = if streaming {
+                let request = WorkerCall {
+                    request: WorkerRequest::StreamImage { page },
+                    chunks: Some(sender.clone()),
+                };
+                manager
+                    .call_blocking_entry(entry, call, request, decode_image_stream_end, |_| {
+                        unreachable!("streamed images are only requested from workers")
+                    })
+                    .await
+            } else {
+                let image_sender = sender.clone();
+                let invoke_sender = sender.clone();
+                manager
+                    .call_blocking_entry(
+                        entry,
+                        call,
+                        WorkerRequest::GetImageBytes { page: page.clone() },
+                        // The whole image is the only chunk, so the empty
+                        // channel always has room for it.
+                        move |value| {
+                            let _ = image_sender.try_send(Ok(decode_image(value)?));
+                            Ok(())
+                        },
+                        move |extension| {
+                            let bytes = extension.get_page_bytes(page.clone())?;
+                            let _ =
+                                invoke_sender.try_send(Ok(extension.process_image(&page, bytes)?));
+                            Ok(())
+                        },
+                    )
+                    .await
+            };
+            if let Err(error) = result {
+                let _ = sender.send(Err(error)).await;
+            }
+        });
+
+        // Wait for the first chunk so errors reach the caller directly.
+        match receiver.recv().await {
+            Some(Ok(first)) => Ok(ImageStream::new(Some(first), receiver)),
+            Some(Err(error)) => Err(error),
+            None => Ok(ImageStream::new(None, receiver)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1539,12 +2617,19 @@ mod tests {
 
     use anyhow::{Result, bail};
     use bytes::Bytes;
-    use tanoshi_lib::prelude::{ChapterInfo, Extension, Input, Lang, MangaInfo, SourceInfo};
+    use tanoshi_lib::prelude::{
+        ChapterInfo, Extension, Input, Lang, MangaInfo, Preference, PreferenceKind,
+        PreferenceValue, PreferenceValues, SECRET_MASK, SourceInfo,
+    };
     use tokio::sync::{Semaphore, oneshot};
 
-    use crate::prelude::Source;
+    use crate::prelude::{SecretKey, Source, SourceHealthState};
 
-    use super::{ExtensionManager, UNIQUE_PATH_COUNTER};
+    use super::{
+        ExtensionManager, ExtensionManagerOptions, ExtensionReloadKind, LibraryFingerprint,
+        PLUGIN_EXTENSION, SavedPreferences, UNIQUE_PATH_COUNTER, VERSIONS_DIR,
+        WASM_PLUGIN_EXTENSION, is_library_file_name, normalize_plugin_name,
+    };
 
     struct PreferenceExtension {
         source_id: i64,
@@ -1561,6 +2646,7 @@ mod tests {
                 icon: "",
                 languages: Lang::All,
                 nsfw: false,
+                ..Default::default()
             }
         }
 
@@ -1569,6 +2655,17 @@ mod tests {
             Ok(())
         }
 
+        fn preference_schema(&self) -> Vec<Preference> {
+            vec![
+                Preference::new("token", "Token", PreferenceKind::Password),
+                Preference::new(
+                    "lang",
+                    "Language",
+                    PreferenceKind::Text { max_length: None },
+                ),
+            ]
+        }
+
         fn get_popular_manga(&self, _page: i64) -> Result<Vec<MangaInfo>> {
             bail!("unused test operation")
         }
@@ -1654,6 +2751,31 @@ mod tests {
         drop(recovered_permit);
     }
 
+    #[tokio::test]
+    async fn staged_libraries_remember_the_file_they_were_copied_from() {
+        let test_id = UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!(
+            "tanoshi-vm-library-fingerprint-{}-{test_id}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let manager = ExtensionManager::new(&dir);
+        let plugin_path = dir.join("fingerprint.so");
+        std::fs::write(&plugin_path, b"version 1").unwrap();
+        let (staged_path, fingerprint) = manager.stage_library(&plugin_path).unwrap();
+        assert_eq!(std::fs::read(&staged_path).unwrap(), b"version 1");
+        assert_eq!(LibraryFingerprint::of(&plugin_path).unwrap(), fingerprint);
+
+        // Installs move the new library into place.
+        let replacement = dir.join("replacement.so");
+        std::fs::write(&replacement, b"version 2!").unwrap();
+        std::fs::rename(&replacement, &plugin_path).unwrap();
+        assert_ne!(LibraryFingerprint::of(&plugin_path).unwrap(), fingerprint);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[tokio::test]
     async fn preference_save_waits_for_install_and_targets_replacement_entry() {
         let test_id = UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed);
@@ -1696,4 +2818,264 @@ mod tests {
 
         std::fs::remove_dir_all(dir).unwrap();
     }
+
+    #[tokio::test]
+    async fn secret_preferences_are_sealed_at_rest() {
+        let dir = std::env::temp_dir().join(format!(
+            "tanoshi-vm-sealed-preferences-{}-{}",
+            std::process::id(),
+            UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manager = ExtensionManager::new_with_options(
+            &dir,
+            ExtensionManagerOptions {
+                preference_key: Some(SecretKey::new("server secret", "preferences").unwrap()),
+                ..Default::default()
+            },
+        );
+        let entry = preference_entry(1, Arc::new(AtomicUsize::new(0)), &dir.join("sealed.so"));
+        manager.insert_entry(entry).unwrap();
+        let values = |token: &str, lang: &str| {
+            PreferenceValues::from([
+                ("token".to_string(), PreferenceValue::from(token)),
+                ("lang".to_string(), PreferenceValue::from(lang)),
+            ])
+        };
+
+        manager
+            .set_preference_values(1, values("hunter2", "en"))
+            .await
+            .unwrap();
+        let file = std::fs::read_to_string(dir.join("race source.json")).unwrap();
+        assert!(file.contains("\"en\""));
+        assert!(!file.contains("hunter2"));
+        assert_eq!(
+            manager.get_preference_values(1).await.unwrap(),
+            values(SECRET_MASK, "en")
+        );
+
+        // A masked secret sent back keeps the sealed value.
+        manager
+            .set_preference_values(1, values(SECRET_MASK, "fr"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            manager.saved_preferences("race source").await,
+            Some(SavedPreferences::Values(saved)) if saved == values("hunter2", "fr")
+        ));
+
+        // Without the key only the plain values are read back.
+        let keyless = ExtensionManager::new(&dir);
+        assert!(matches!(
+            keyless.saved_preferences("race source").await,
+            Some(SavedPreferences::Values(saved))
+                if saved == PreferenceValues::from([("lang".to_string(), "fr".into())])
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn health_reports_recent_errors_and_unquarantine_readmits_calls() {
+        let dir = std::env::temp_dir().join(format!(
+            "tanoshi-vm-source-health-{}-{}",
+            std::process::id(),
+            UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let manager = ExtensionManager::new(&dir);
+        let entry = preference_entry(1, Arc::new(AtomicUsize::new(0)), &dir.join("health.so"));
+        manager.insert_entry(entry.clone()).unwrap();
+
+        for _ in 0..2 {
+            assert!(manager.get_popular_manga(1, 1).await.is_err());
+        }
+        let report = manager.source_health(1).unwrap();
+        assert_eq!(report.state, SourceHealthState::Healthy);
+        assert_eq!((report.recent_calls, report.recent_errors), (2, 2));
+        assert_eq!(report.error_rate(), 1.0);
+        assert_eq!(report.operations.len(), 1);
+        assert_eq!(report.operations[0].operation, "get_popular_manga");
+        assert_eq!(report.operations[0].calls, 2);
+        assert!(
+            report
+                .last_error
+                .is_some_and(|error| error.contains("unused test operation"))
+        );
+
+        entry.health.quarantine();
+        assert_eq!(
+            manager.source_health(1).unwrap().state,
+            SourceHealthState::Quarantined
+        );
+        let rejected = manager.get_popular_manga(1, 1).await.unwrap_err();
+        assert!(rejected.to_string().contains("quarantined"));
+
+        manager.unquarantine(1).unwrap();
+        let report = manager.source_health(1).unwrap();
+        assert_eq!(report.state, SourceHealthState::Healthy);
+        assert_eq!(report.recent_calls, 3);
+        assert!(manager.restart(1).await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replaced_versions_are_kept_up_to_the_limit() {
+        let dir = std::env::temp_dir().join(format!(
+            "tanoshi-vm-keep-versions-{}-{}",
+            std::process::id(),
+            UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let manager = ExtensionManager::new_with_options(
+            &dir,
+            ExtensionManagerOptions {
+                keep_versions: 2,
+                ..Default::default()
+            },
+        );
+        let entry = preference_entry(1, Arc::new(AtomicUsize::new(0)), &dir.join("keep.so"));
+        manager.insert_entry(entry).unwrap();
+
+        for version in ["0.1.0", "0.2.0", "0.3.0"] {
+            let replaced = dir.join(format!("replaced-{version}"));
+            std::fs::write(&replaced, version).unwrap();
+            manager.archive_version("keep", version, &replaced).unwrap();
+            assert!(!replaced.exists());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(manager.source_versions(1).unwrap(), ["0.3.0", "0.2.0"]);
+
+        let error = manager.rollback(1, "0.1.0").await.unwrap_err();
+        assert!(error.to_string().contains("not kept"));
+        assert!(manager.rollback(1, "test").await.is_err());
+
+        manager.unload(1).await.unwrap();
+        assert!(!dir.join(VERSIONS_DIR).join("keep").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn libraries_removed_from_the_plugin_dir_keep_their_versions() {
+        let dir = std::env::temp_dir().join(format!(
+            "tanoshi-vm-removed-library-{}-{}",
+            std::process::id(),
+            UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let manager = ExtensionManager::new_with_options(
+            &dir,
+            ExtensionManagerOptions {
+                hot_reload: true,
+                ..Default::default()
+            },
+        );
+        let library = dir.join(format!("keep.{PLUGIN_EXTENSION}"));
+        let insert = || {
+            let entry = preference_entry(1, Arc::new(AtomicUsize::new(0)), &library);
+            manager.insert_entry(entry).unwrap();
+        };
+        insert();
+        let replaced = dir.join("replaced-0.1.0");
+        std::fs::write(&replaced, "0.1.0").unwrap();
+        manager.archive_version("keep", "0.1.0", &replaced).unwrap();
+
+        // The watcher sees the library disappear.
+        manager.reload_changed_file(&library).await;
+        assert!(!manager.exists(1).await.unwrap());
+        let kept = std::fs::read_dir(dir.join(VERSIONS_DIR).join("keep")).unwrap();
+        assert_eq!(kept.count(), 1);
+
+        // Uninstalling still drops them.
+        insert();
+        manager.remove(1).await.unwrap();
+        assert!(!dir.join(VERSIONS_DIR).join("keep").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn wasm_libraries_are_found_next_to_native_ones() {
+        let dir = std::env::temp_dir().join(format!(
+            "tanoshi-vm-wasm-library-{}-{}",
+            std::process::id(),
+            UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manager = ExtensionManager::new(&dir);
+        let native = |name: &str| dir.join(format!("{name}.{PLUGIN_EXTENSION}"));
+        let wasm = |name: &str| dir.join(format!("{name}.{WASM_PLUGIN_EXTENSION}"));
+        std::fs::write(wasm("portable"), "").unwrap();
+        std::fs::write(native("both"), "").unwrap();
+        std::fs::write(wasm("both"), "").unwrap();
+
+        assert!(is_library_file_name("portable.wasm"));
+        assert!(!is_library_file_name("portable.wat"));
+        assert_eq!(normalize_plugin_name("Portable.wasm"), "portable");
+        assert_eq!(manager.library_path("portable"), wasm("portable"));
+        assert_eq!(manager.library_path("portable.wasm"), wasm("portable"));
+        assert_eq!(manager.library_path("both"), native("both"));
+        assert_eq!(manager.library_path("both.wasm"), wasm("both"));
+        assert_eq!(manager.library_path("missing"), native("missing"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn watched_template_files_are_loaded_reloaded_and_unloaded() {
+        let dir = std::env::temp_dir().join(format!(
+            "tanoshi-vm-watch-{}-{}",
+            std::process::id(),
+            UNIQUE_PATH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template = |version: &str| {
+            format!(
+                "id: 9002\nname: Watched\nurl: https://example.com\nversion: {version}\n\
+                 detail: {{}}\n\
+                 chapters: {{ items: li, title: a, path: {{ selector: a, attr: href }} }}\n\
+                 pages: {{ items: img, image: {{ attr: src }} }}\n"
+            )
+        };
+
+        let manager = ExtensionManager::new(&dir);
+        let mut reloads = manager.subscribe_reloads();
+        let watcher = manager.watch().unwrap();
+        let path = dir.join("watched.yml");
+        let mut next_reload = async || {
+            tokio::time::timeout(Duration::from_secs(10), reloads.recv())
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        std::fs::write(&path, template("0.1.0")).unwrap();
+        let reload = next_reload().await;
+        assert_eq!(reload.file, "watched.yml");
+        assert_eq!(reload.kind, ExtensionReloadKind::Loaded);
+        assert_eq!((reload.source_id, reload.error), (Some(9002), None));
+
+        std::fs::write(&path, template("0.2.0")).unwrap();
+        assert_eq!(next_reload().await.kind, ExtensionReloadKind::Reloaded);
+        assert_eq!(manager.get_source_info(9002).unwrap().version, "0.2.0");
+
+        std::fs::write(&path, "id: [").unwrap();
+        let reload = next_reload().await;
+        assert_eq!(reload.kind, ExtensionReloadKind::Reloaded);
+        assert!(reload.error.is_some());
+        assert_eq!(manager.get_source_info(9002).unwrap().version, "0.2.0");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(next_reload().await.kind, ExtensionReloadKind::Unloaded);
+        assert!(!manager.exists(9002).await.unwrap());
+
+        watcher.abort();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -5,3 +5,32 @@ pub mod manager;
 pub use manager::*;
 
 pub mod worker;
+
+mod fixtures;
+pub use fixtures::HttpFixtureMode;
+
+mod http;
+pub use http::{
+    DEFAULT_HTTP_CACHE_SIZE, DEFAULT_HTTP_TIMEOUT, DEFAULT_HTTP_USER_AGENT, HttpOptions,
+};
+
+mod image_stream;
+pub use image_stream::{ImageStream, detect_image_type};
+
+mod overrides;
+pub use overrides::{RequestOverride, RequestOverrideKind, RequestOverrides, SourceChallenge};
+
+mod rate_limit;
+
+mod sandbox;
+pub use sandbox::{SandboxLayer, SandboxLayerStatus, SandboxOptions, SandboxState};
+
+mod secret;
+pub use secret::SecretKey;
+
+mod template;
+
+mod verify;
+pub use verify::{PluginVerification, sha256_hex, sign_plugin, verifying_key};
+
+mod wasm;
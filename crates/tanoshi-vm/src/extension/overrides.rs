@@ -0,0 +1,229 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tanoshi_lib::http::Response;
+
+/// Whether a [`RequestOverride`] is sent as a cookie or as a header
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RequestOverrideKind {
+    Cookie,
+    Header,
+}
+
+impl RequestOverrideKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestOverrideKind::Cookie => "cookie",
+            RequestOverrideKind::Header => "header",
+        }
+    }
+}
+
+impl std::str::FromStr for RequestOverrideKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cookie" => Ok(RequestOverrideKind::Cookie),
+            "header" => Ok(RequestOverrideKind::Header),
+            _ => Err(anyhow::anyhow!("unknown request override kind {s:?}")),
+        }
+    }
+}
+
+/// A cookie or header an admin set for a source, e.g. the clearance cookie
+/// of an anti-bot challenge solved in a browser. It is added to every request
+/// the source makes through the host and replaces a cookie or header of the
+/// same name set by the extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestOverride {
+    pub kind: RequestOverrideKind,
+    pub name: String,
+    pub value: String,
+    /// Unix timestamp after which the override is no longer sent
+    pub expires_at: Option<i64>,
+}
+
+impl RequestOverride {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Every override of a source
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestOverrides(pub Vec<RequestOverride>);
+
+impl RequestOverrides {
+    fn active(&self, kind: RequestOverrideKind) -> impl Iterator<Item = &RequestOverride> {
+        let now = unix_now();
+        self.0
+            .iter()
+            .filter(move |entry| entry.kind == kind && !entry.is_expired(now))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Replace headers of the same name, names are compared
+    /// case-insensitively
+    pub fn apply_headers(&self, headers: &mut Vec<(String, String)>) {
+        for entry in self.active(RequestOverrideKind::Header) {
+            headers.retain(|(name, _)| !name.eq_ignore_ascii_case(&entry.name));
+            headers.push((entry.name.clone(), entry.value.clone()));
+        }
+    }
+
+    /// Merge the override cookies into the value of a `Cookie` header, `None`
+    /// when there are no cookies at all
+    pub fn merge_cookies(&self, cookie: Option<&str>) -> Option<String> {
+        let overrides = self.active(RequestOverrideKind::Cookie).collect::<Vec<_>>();
+        let mut cookies = cookie
+            .into_iter()
+            .flat_map(|cookie| cookie.split(';'))
+            .map(str::trim)
+            .filter(|cookie| !cookie.is_empty())
+            .filter(|cookie| {
+                let name = cookie.split('=').next().unwrap_or_default().trim();
+                !overrides.iter().any(|entry| entry.name == name)
+            })
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        cookies.extend(
+            overrides
+                .iter()
+                .map(|entry| format!("{}={}", entry.name, entry.value)),
+        );
+
+        (!cookies.is_empty()).then(|| cookies.join("; "))
+    }
+
+    /// Apply the overrides to the headers of a page, see
+    /// [`tanoshi_lib::prelude::PageInfo`]
+    pub fn apply_to_page_headers(&self, headers: &mut BTreeMap<String, String>) {
+        let mut list = std::mem::take(headers).into_iter().collect::<Vec<_>>();
+        self.apply_headers(&mut list);
+
+        let cookie = list
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+            .map(|index| list.remove(index).1);
+        if let Some(cookie) = self.merge_cookies(cookie.as_deref()) {
+            list.push(("Cookie".to_string(), cookie));
+        }
+
+        headers.extend(list);
+    }
+}
+
+/// Sent when a source starts receiving anti-bot challenge pages instead of
+/// content, see [`super::ExtensionManager::subscribe_challenges`]
+#[derive(Clone, Debug)]
+pub struct SourceChallenge {
+    pub source_id: i64,
+    pub source_name: String,
+    pub url: String,
+    pub status: u16,
+}
+
+/// Whether a response is an anti-bot challenge page, currently Cloudflare's
+pub(crate) fn is_challenge(response: &Response) -> bool {
+    if response
+        .header("cf-mitigated")
+        .is_some_and(|value| value.eq_ignore_ascii_case("challenge"))
+    {
+        return true;
+    }
+    if !matches!(response.status, 403 | 429 | 503)
+        || !response
+            .header("server")
+            .is_some_and(|server| server.to_ascii_lowercase().contains("cloudflare"))
+    {
+        return false;
+    }
+
+    let body = String::from_utf8_lossy(&response.body);
+    ["/cdn-cgi/challenge-platform/", "cf-chl-", "cf_chl_opt"]
+        .iter()
+        .any(|marker| body.contains(marker))
+}
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(kind: RequestOverrideKind, name: &str, expires_at: Option<i64>) -> RequestOverride {
+        RequestOverride {
+            kind,
+            name: name.to_string(),
+            value: format!("{name}-override"),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn expired_overrides_are_skipped_and_active_ones_replace_the_extensions() {
+        let overrides = RequestOverrides(vec![
+            entry(RequestOverrideKind::Cookie, "cf_clearance", None),
+            entry(RequestOverrideKind::Cookie, "stale", Some(1)),
+            entry(RequestOverrideKind::Header, "User-Agent", None),
+        ]);
+
+        let mut headers = BTreeMap::from([
+            ("user-agent".to_string(), "extension".to_string()),
+            ("Referer".to_string(), "https://example.com".to_string()),
+            (
+                "cookie".to_string(),
+                "session=1; cf_clearance=old".to_string(),
+            ),
+        ]);
+        overrides.apply_to_page_headers(&mut headers);
+
+        assert_eq!(
+            headers,
+            BTreeMap::from([
+                ("User-Agent".to_string(), "User-Agent-override".to_string()),
+                ("Referer".to_string(), "https://example.com".to_string()),
+                (
+                    "Cookie".to_string(),
+                    "session=1; cf_clearance=cf_clearance-override".to_string()
+                ),
+            ])
+        );
+        assert_eq!(RequestOverrides::default().merge_cookies(None), None);
+    }
+
+    #[test]
+    fn cloudflare_challenges_are_detected() {
+        let challenge = Response {
+            status: 403,
+            headers: vec![("Server".to_string(), "cloudflare".to_string())],
+            body: "<script src=\"/cdn-cgi/challenge-platform/h/b/orchestrate\"></script>".into(),
+        };
+        assert!(is_challenge(&challenge));
+
+        let mitigated = Response {
+            status: 200,
+            headers: vec![("cf-mitigated".to_string(), "challenge".to_string())],
+            body: Default::default(),
+        };
+        assert!(is_challenge(&mitigated));
+
+        let forbidden = Response {
+            status: 403,
+            headers: vec![("Server".to_string(), "cloudflare".to_string())],
+            body: "access denied".into(),
+        };
+        assert!(!is_challenge(&forbidden));
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use tanoshi_lib::prelude::RateLimit;
+use tokio::time::Instant;
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token bucket enforcing a source's declared [`RateLimit`]. Callers reserve
+/// a token up front and wait out any debt, so concurrent calls queue in
+/// arrival order instead of retrying.
+pub(crate) struct TokenBucket {
+    capacity: f64,
+    tokens_per_second: f64,
+    state: StdMutex<BucketState>,
+}
+
+impl TokenBucket {
+    /// Returns `None` for limits that cannot be enforced, e.g. zero requests.
+    pub(crate) fn new(rate_limit: RateLimit) -> Option<Arc<Self>> {
+        if rate_limit.requests == 0 || rate_limit.period.is_zero() {
+            return None;
+        }
+        let capacity = f64::from(rate_limit.requests);
+        Some(Arc::new(Self {
+            capacity,
+            tokens_per_second: capacity / rate_limit.period.as_secs_f64(),
+            state: StdMutex::new(BucketState {
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            }),
+        }))
+    }
+
+    fn reserve(&self) -> Duration {
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.tokens_per_second).min(self.capacity);
+        state.refilled_at = now;
+        state.tokens -= 1.0;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.tokens_per_second)
+        }
+    }
+
+    pub(crate) async fn acquire(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn token_bucket_allows_burst_then_spaces_calls() {
+        let bucket = TokenBucket::new(RateLimit {
+            requests: 2,
+            period: Duration::from_millis(200),
+        })
+        .unwrap();
+        let started = Instant::now();
+
+        bucket.acquire().await;
+        bucket.acquire().await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        bucket.acquire().await;
+        bucket.acquire().await;
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
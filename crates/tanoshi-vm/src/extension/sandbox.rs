@@ -0,0 +1,886 @@
+// The egress proxy and the layers themselves only exist on Linux.
+#![cfg_attr(not(target_os = "linux"), allow(dead_code, unused_imports))]
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// Environment variable carrying the [`WorkerSandbox`] policy to a worker.
+/// An environment variable rather than a flag keeps older worker binaries,
+/// which reject unknown arguments, starting unsandboxed.
+pub(crate) const SANDBOX_ENV: &str = "TANOSHI_EXTENSION_SANDBOX";
+
+/// Ports the egress proxy connects to for hosts outside
+/// [`SandboxOptions::allowed_private_hosts`]
+pub(crate) const EGRESS_PORTS: [u16; 2] = [80, 443];
+/// Longest request head the egress proxy reads before giving up
+const MAX_EGRESS_HEAD: usize = 16 * 1024;
+
+/// Directories a Landlock-restricted worker may still read, everything else,
+/// including the database, config and downloads, is out of reach. Of `/proc`
+/// only the worker's own entry is readable, not the server's environment.
+#[cfg(target_os = "linux")]
+const LANDLOCK_READ_PATHS: &[&str] = &[
+    "/usr",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/etc",
+    "/proc/self",
+    "/proc/cpuinfo",
+    "/proc/meminfo",
+    "/sys",
+    "/nix/store",
+    "/dev/urandom",
+];
+#[cfg(target_os = "linux")]
+const LANDLOCK_WRITE_PATHS: &[&str] = &["/dev/null"];
+
+/// Hardening applied to extension worker processes. Every layer is best
+/// effort unless `strict` is set, the layers a worker could not apply are
+/// reported in its [`SandboxLayerStatus`] instead.
+#[derive(Clone, Debug, Default)]
+pub struct SandboxOptions {
+    /// `RLIMIT_AS` of a worker in MiB
+    pub memory_limit_mb: Option<u64>,
+    /// `RLIMIT_CPU` of a worker in seconds of CPU time. This is a budget for
+    /// the whole life of the process, not per call: a busy worker that uses
+    /// it up is killed with `SIGXCPU` even if every call was quick, failing
+    /// the calls in flight, and the pool starts a fresh worker for the next
+    /// one. Size it for the work a worker does between idle shutdowns.
+    pub cpu_time_limit_secs: Option<u64>,
+    /// `RLIMIT_NOFILE` of a worker
+    pub max_open_files: Option<u64>,
+    /// Deny system calls outside an allowlist
+    pub seccomp: bool,
+    /// Restrict the filesystem to read-only system directories
+    pub landlock: bool,
+    /// Run workers in a private network namespace whose only way out is an
+    /// HTTP proxy on the host
+    pub private_network: bool,
+    /// Hosts the proxy may reach on private addresses and any port, e.g. a
+    /// self-hosted server on the local network
+    pub allowed_private_hosts: Vec<String>,
+    /// Refuse to start a worker that could not apply every enabled layer
+    pub strict: bool,
+}
+
+impl SandboxOptions {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.memory_limit_mb.is_some()
+            || self.cpu_time_limit_secs.is_some()
+            || self.max_open_files.is_some()
+            || self.seccomp
+            || self.landlock
+            || self.private_network
+    }
+
+    pub(crate) fn worker_sandbox(&self, egress_proxy: Option<PathBuf>) -> WorkerSandbox {
+        WorkerSandbox {
+            memory_limit_mb: self.memory_limit_mb,
+            cpu_time_limit_secs: self.cpu_time_limit_secs,
+            max_open_files: self.max_open_files,
+            seccomp: self.seccomp,
+            landlock: self.landlock,
+            private_network: self.private_network,
+            egress_proxy,
+            strict: self.strict,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SandboxLayer {
+    MemoryLimit,
+    CpuTimeLimit,
+    OpenFileLimit,
+    Seccomp,
+    Landlock,
+    PrivateNetwork,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", content = "reason")]
+pub enum SandboxState {
+    Applied,
+    Disabled,
+    /// Enabled but not supported or permitted on this system
+    Unavailable(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SandboxLayerStatus {
+    pub layer: SandboxLayer,
+    pub state: SandboxState,
+}
+
+/// The part of [`SandboxOptions`] a worker applies to itself
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct WorkerSandbox {
+    memory_limit_mb: Option<u64>,
+    cpu_time_limit_secs: Option<u64>,
+    max_open_files: Option<u64>,
+    seccomp: bool,
+    landlock: bool,
+    private_network: bool,
+    /// Unix socket of the host's egress proxy
+    egress_proxy: Option<PathBuf>,
+    strict: bool,
+}
+
+/// Worker side of the sandbox. Limits and the network namespace are entered
+/// before the plugin is loaded, while the worker is still single threaded;
+/// Landlock and seccomp follow once the library is mapped.
+pub(crate) struct Sandbox {
+    policy: WorkerSandbox,
+    report: Vec<SandboxLayerStatus>,
+    #[cfg(target_os = "linux")]
+    egress: Option<std::net::TcpListener>,
+}
+
+impl Sandbox {
+    pub(crate) fn from_env() -> Result<Option<Self>> {
+        let Some(policy) = std::env::var_os(SANDBOX_ENV) else {
+            return Ok(None);
+        };
+        let policy = serde_json::from_str(&policy.to_string_lossy())?;
+        Ok(Some(Self {
+            policy,
+            report: Vec::new(),
+            #[cfg(target_os = "linux")]
+            egress: None,
+        }))
+    }
+
+    fn record(&mut self, layer: SandboxLayer, enabled: bool, apply: impl FnOnce() -> Result<()>) {
+        let state = if !enabled {
+            SandboxState::Disabled
+        } else {
+            match apply() {
+                Ok(()) => SandboxState::Applied,
+                Err(error) => {
+                    log::warn!("extension sandbox layer {layer:?} is unavailable: {error:#}");
+                    SandboxState::Unavailable(format!("{error:#}"))
+                }
+            }
+        };
+        self.report.push(SandboxLayerStatus { layer, state });
+    }
+
+    /// Resource limits and the private network namespace.
+    pub(crate) fn enter(&mut self) {
+        let policy = self.policy.clone();
+        self.record(
+            SandboxLayer::MemoryLimit,
+            policy.memory_limit_mb.is_some(),
+            || {
+                set_limit(
+                    Limit::Memory,
+                    policy.memory_limit_mb.unwrap_or_default() << 20,
+                )
+            },
+        );
+        self.record(
+            SandboxLayer::CpuTimeLimit,
+            policy.cpu_time_limit_secs.is_some(),
+            || {
+                set_limit(
+                    Limit::CpuTime,
+                    policy.cpu_time_limit_secs.unwrap_or_default(),
+                )
+            },
+        );
+        self.record(
+            SandboxLayer::OpenFileLimit,
+            policy.max_open_files.is_some(),
+            || set_limit(Limit::OpenFiles, policy.max_open_files.unwrap_or_default()),
+        );
+        #[cfg(target_os = "linux")]
+        let mut egress = None;
+        self.record(SandboxLayer::PrivateNetwork, policy.private_network, || {
+            #[cfg(target_os = "linux")]
+            {
+                let Some(proxy) = policy.egress_proxy.as_deref() else {
+                    bail!("the host did not provide an egress proxy");
+                };
+                egress = Some(linux::enter_private_network(proxy)?);
+                Ok(())
+            }
+            #[cfg(not(target_os = "linux"))]
+            unsupported()
+        });
+        #[cfg(target_os = "linux")]
+        {
+            self.egress = egress;
+        }
+    }
+
+    /// Landlock and seccomp, then starts forwarding the egress proxy. Returns
+    /// the status of every layer for the worker's readiness response.
+    pub(crate) fn lock_down(mut self) -> Result<Vec<SandboxLayerStatus>> {
+        let policy = self.policy.clone();
+        self.record(SandboxLayer::Landlock, policy.landlock, || {
+            #[cfg(target_os = "linux")]
+            return linux::restrict_filesystem();
+            #[cfg(not(target_os = "linux"))]
+            unsupported()
+        });
+        self.record(SandboxLayer::Seccomp, policy.seccomp, || {
+            #[cfg(target_os = "linux")]
+            return linux::restrict_syscalls();
+            #[cfg(not(target_os = "linux"))]
+            unsupported()
+        });
+        if policy.strict
+            && let Some(status) = self
+                .report
+                .iter()
+                .find(|status| matches!(status.state, SandboxState::Unavailable(_)))
+        {
+            bail!(
+                "strict extension sandbox could not apply {:?}: {:?}",
+                status.layer,
+                status.state
+            );
+        }
+
+        #[cfg(target_os = "linux")]
+        if let (Some(listener), Some(proxy)) = (self.egress.take(), policy.egress_proxy) {
+            linux::forward_egress(listener, proxy);
+        }
+        Ok(self.report)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> Result<()> {
+    bail!("only supported on Linux")
+}
+
+#[derive(Clone, Copy)]
+enum Limit {
+    Memory,
+    CpuTime,
+    OpenFiles,
+}
+
+#[cfg(target_os = "linux")]
+fn set_limit(limit: Limit, value: u64) -> Result<()> {
+    let resource = match limit {
+        Limit::Memory => libc::RLIMIT_AS,
+        Limit::CpuTime => libc::RLIMIT_CPU,
+        Limit::OpenFiles => libc::RLIMIT_NOFILE,
+    };
+    // Soft and hard limit alike, so the extension cannot raise it again.
+    let value = value as libc::rlim_t;
+    let rlimit = libc::rlimit {
+        rlim_cur: value,
+        rlim_max: value,
+    };
+    // SAFETY: `rlimit` is a valid, initialized struct for the whole call.
+    if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_limit(_limit: Limit, _value: u64) -> Result<()> {
+    unsupported()
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        collections::BTreeMap,
+        io,
+        net::{Ipv4Addr, TcpListener, TcpStream},
+        os::{
+            fd::{AsRawFd, FromRawFd, OwnedFd},
+            unix::net::UnixStream,
+        },
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::{Context, Result, bail};
+    use landlock::{
+        ABI, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus,
+        path_beneath_rules,
+    };
+    use seccompiler::{
+        BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+        SeccompRule, TargetArch,
+    };
+
+    use super::{LANDLOCK_READ_PATHS, LANDLOCK_WRITE_PATHS};
+
+    /// System calls a worker may make once seccomp is applied. Anything
+    /// else, e.g. `execve`, `ptrace`, `mount` or `unshare`, fails with
+    /// `EPERM`.
+    const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+        // Files and descriptors
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_readv,
+        libc::SYS_writev,
+        libc::SYS_pread64,
+        libc::SYS_pwrite64,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_fstat,
+        libc::SYS_newfstatat,
+        libc::SYS_statx,
+        libc::SYS_statfs,
+        libc::SYS_fstatfs,
+        libc::SYS_lseek,
+        libc::SYS_getdents64,
+        libc::SYS_readlinkat,
+        libc::SYS_faccessat,
+        libc::SYS_faccessat2,
+        libc::SYS_fcntl,
+        libc::SYS_dup,
+        libc::SYS_dup3,
+        libc::SYS_pipe2,
+        libc::SYS_flock,
+        libc::SYS_fsync,
+        libc::SYS_fdatasync,
+        libc::SYS_getcwd,
+        // Memory
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mprotect,
+        libc::SYS_mremap,
+        libc::SYS_madvise,
+        libc::SYS_brk,
+        libc::SYS_membarrier,
+        // Threads, signals and time
+        libc::SYS_clone,
+        libc::SYS_clone3,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        libc::SYS_futex,
+        libc::SYS_set_robust_list,
+        libc::SYS_get_robust_list,
+        libc::SYS_set_tid_address,
+        libc::SYS_rseq,
+        libc::SYS_sched_yield,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_prctl,
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_tgkill,
+        libc::SYS_getpid,
+        libc::SYS_gettid,
+        libc::SYS_getppid,
+        libc::SYS_getuid,
+        libc::SYS_geteuid,
+        libc::SYS_getgid,
+        libc::SYS_getegid,
+        libc::SYS_getresuid,
+        libc::SYS_getresgid,
+        libc::SYS_getrandom,
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_getres,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_gettimeofday,
+        libc::SYS_uname,
+        libc::SYS_sysinfo,
+        libc::SYS_getrlimit,
+        libc::SYS_prlimit64,
+        // Polling
+        libc::SYS_epoll_create1,
+        libc::SYS_epoll_ctl,
+        libc::SYS_epoll_pwait,
+        libc::SYS_eventfd2,
+        libc::SYS_ppoll,
+        libc::SYS_pselect6,
+        libc::SYS_timerfd_create,
+        libc::SYS_timerfd_settime,
+        libc::SYS_timerfd_gettime,
+        // Sockets
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_connect,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_accept,
+        libc::SYS_accept4,
+        libc::SYS_getsockopt,
+        libc::SYS_setsockopt,
+        libc::SYS_getsockname,
+        libc::SYS_getpeername,
+        libc::SYS_sendto,
+        libc::SYS_recvfrom,
+        libc::SYS_sendmsg,
+        libc::SYS_recvmsg,
+        libc::SYS_sendmmsg,
+        libc::SYS_recvmmsg,
+        libc::SYS_shutdown,
+        // Legacy variants still used by older libc builds
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_open,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_stat,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_lstat,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_access,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_readlink,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_dup2,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_pipe,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_poll,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_select,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_epoll_wait,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_epoll_create,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_arch_prctl,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_time,
+    ];
+
+    /// `ioctl` requests a worker may not make, the rest are allowed. They push
+    /// input into or reconfigure a terminal the worker shares with the server.
+    const DENIED_IOCTLS: &[libc::Ioctl] = &[libc::TIOCSTI, libc::TIOCLINUX];
+
+    /// Moves the worker into new user and network namespaces with only a
+    /// loopback interface, and returns a listener there that the extension
+    /// uses as its HTTP proxy.
+    pub(super) fn enter_private_network(proxy: &Path) -> Result<TcpListener> {
+        if !proxy.exists() {
+            bail!("egress proxy socket {} does not exist", proxy.display());
+        }
+        // SAFETY: plain libc calls without pointers.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        // SAFETY: unshare takes no pointers; it fails cleanly when the
+        // process is multithreaded or user namespaces are not permitted.
+        if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error()).context("failed to unshare namespaces");
+        }
+        // Map the user onto itself, files keep their owners inside.
+        std::fs::write("/proc/self/setgroups", "deny")?;
+        std::fs::write("/proc/self/uid_map", format!("{uid} {uid} 1"))?;
+        std::fs::write("/proc/self/gid_map", format!("{gid} {gid} 1"))?;
+        bring_loopback_up().context("failed to bring up the loopback interface")?;
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let proxy = format!("http://{}", listener.local_addr()?);
+        for name in [
+            "HTTP_PROXY",
+            "HTTPS_PROXY",
+            "ALL_PROXY",
+            "http_proxy",
+            "https_proxy",
+            "all_proxy",
+        ] {
+            // SAFETY: called before the plugin is loaded and before the
+            // worker starts any thread, nothing reads the environment
+            // concurrently.
+            unsafe { std::env::set_var(name, &proxy) };
+        }
+        for name in ["NO_PROXY", "no_proxy"] {
+            // SAFETY: as above.
+            unsafe { std::env::remove_var(name) };
+        }
+        Ok(listener)
+    }
+
+    fn bring_loopback_up() -> io::Result<()> {
+        // SAFETY: plain socket call, the descriptor is owned right after.
+        let socket =
+            unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if socket < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `socket` is a fresh descriptor nothing else owns.
+        let socket = unsafe { OwnedFd::from_raw_fd(socket) };
+        // SAFETY: `ifreq` is plain old data, all zeroes is a valid value.
+        let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+        for (name, byte) in request.ifr_name.iter_mut().zip(b"lo\0") {
+            *name = *byte as libc::c_char;
+        }
+        // SAFETY: `request` outlives both calls and names the interface.
+        unsafe {
+            if libc::ioctl(socket.as_raw_fd(), libc::SIOCGIFFLAGS as _, &mut request) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            if libc::ioctl(socket.as_raw_fd(), libc::SIOCSIFFLAGS as _, &request) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Relays every connection to the loopback proxy listener to the host's
+    /// egress proxy, which decides what may be reached.
+    pub(super) fn forward_egress(listener: TcpListener, proxy: PathBuf) {
+        std::thread::spawn(move || {
+            for client in listener.incoming() {
+                let Ok(client) = client else {
+                    continue;
+                };
+                match UnixStream::connect(&proxy) {
+                    Ok(upstream) => relay(client, upstream),
+                    Err(error) => {
+                        log::warn!("failed to reach the extension egress proxy: {error}");
+                    }
+                }
+            }
+        });
+    }
+
+    fn relay(client: TcpStream, upstream: UnixStream) {
+        let (Ok(mut client_reader), Ok(mut upstream_reader)) =
+            (client.try_clone(), upstream.try_clone())
+        else {
+            return;
+        };
+        let (mut client_writer, mut upstream_writer) = (client, upstream);
+        std::thread::spawn(move || {
+            let _ = io::copy(&mut client_reader, &mut upstream_writer);
+            let _ = upstream_writer.shutdown(std::net::Shutdown::Write);
+        });
+        std::thread::spawn(move || {
+            let _ = io::copy(&mut upstream_reader, &mut client_writer);
+            let _ = client_writer.shutdown(std::net::Shutdown::Write);
+        });
+    }
+
+    /// Read-only access to system directories, no access to anything else.
+    pub(super) fn restrict_filesystem() -> Result<()> {
+        let abi = ABI::V5;
+        let existing = |paths: &'static [&'static str]| {
+            paths
+                .iter()
+                .copied()
+                .filter(|path| Path::new(path).exists())
+        };
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .create()?
+            .add_rules(path_beneath_rules(
+                existing(LANDLOCK_READ_PATHS),
+                AccessFs::from_read(abi),
+            ))?
+            .add_rules(path_beneath_rules(
+                existing(LANDLOCK_WRITE_PATHS),
+                AccessFs::from_read(abi) | AccessFs::WriteFile,
+            ))?
+            .restrict_self()?;
+        if status.ruleset == RulesetStatus::NotEnforced {
+            bail!("the kernel does not support Landlock");
+        }
+        Ok(())
+    }
+
+    /// Installs the syscall allowlist on every thread of the worker.
+    pub(super) fn restrict_syscalls() -> Result<()> {
+        let arch = TargetArch::try_from(std::env::consts::ARCH)
+            .map_err(|error| anyhow::anyhow!("{error}"))?;
+        let mut rules = ALLOWED_SYSCALLS
+            .iter()
+            .map(|syscall| (*syscall, Vec::new()))
+            .collect::<BTreeMap<_, _>>();
+        // The kernel reads the request as a 32 bit value, so compare only
+        // the low half and ignore whatever the upper bits are set to.
+        let ioctl_requests = DENIED_IOCTLS
+            .iter()
+            .map(|request| {
+                SeccompCondition::new(1, SeccompCmpArgLen::Dword, SeccompCmpOp::Ne, *request)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        rules.insert(libc::SYS_ioctl, vec![SeccompRule::new(ioctl_requests)?]);
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Errno(libc::EPERM as u32),
+            SeccompAction::Allow,
+            arch,
+        )?;
+        let program = BpfProgram::try_from(filter)?;
+        seccompiler::apply_filter_all_threads(&program)?;
+        Ok(())
+    }
+}
+
+/// Host side of [`SandboxOptions::private_network`]. Workers reach the
+/// network only through this proxy on a unix socket, which tunnels `CONNECT`
+/// and forwards plain HTTP requests to public addresses on ports 80 and 443.
+#[cfg(target_os = "linux")]
+pub(crate) struct EgressProxy {
+    path: PathBuf,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(target_os = "linux")]
+impl EgressProxy {
+    pub(crate) fn bind(allowed_private_hosts: Vec<String>) -> Result<Self> {
+        use std::os::unix::fs::PermissionsExt;
+
+        static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tanoshi-egress-{}-{}.sock",
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        // Only the server user, and so its workers, may use the proxy.
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        let allowed_private_hosts = std::sync::Arc::new(allowed_private_hosts);
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((client, _)) = listener.accept().await else {
+                    continue;
+                };
+                let allowed_private_hosts = allowed_private_hosts.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = serve_egress(client, &allowed_private_hosts).await {
+                        log::debug!("extension egress connection failed: {error}");
+                    }
+                });
+            }
+        });
+        Ok(Self { path, task })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for EgressProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn serve_egress(
+    mut client: tokio::net::UnixStream,
+    allowed_private_hosts: &[String],
+) -> io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut head = Vec::new();
+    let mut buffer = [0; 4096];
+    let head_end = loop {
+        if let Some(end) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if head.len() > MAX_EGRESS_HEAD {
+            return client
+                .write_all(b"HTTP/1.1 431 Request Header Fields Too Large\r\n\r\n")
+                .await;
+        }
+        let read = client.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buffer[..read]);
+    };
+    let Some(target) = EgressTarget::parse(&head[..head_end]) else {
+        return client.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await;
+    };
+    let mut upstream = match target.connect(allowed_private_hosts).await {
+        Ok(upstream) => upstream,
+        Err(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+            log::warn!(
+                "extension egress to {}:{} denied: {error}",
+                target.host,
+                target.port
+            );
+            return client.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n").await;
+        }
+        Err(_) => return client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n").await,
+    };
+    if target.tunnel {
+        client
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await?;
+        upstream.write_all(&head[head_end..]).await?;
+    } else {
+        upstream.write_all(&head).await?;
+    }
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// Where a proxied request wants to go
+#[derive(Debug, Eq, PartialEq)]
+struct EgressTarget {
+    host: String,
+    port: u16,
+    /// `CONNECT` rather than a plain HTTP request in absolute form
+    tunnel: bool,
+}
+
+impl EgressTarget {
+    fn parse(head: &[u8]) -> Option<Self> {
+        let line = head.split(|byte| *byte == b'\r').next()?;
+        let line = std::str::from_utf8(line).ok()?;
+        let mut parts = line.split(' ');
+        let (method, target) = (parts.next()?, parts.next()?);
+        let (authority, default_port, tunnel) = if method.eq_ignore_ascii_case("CONNECT") {
+            (target, None, true)
+        } else {
+            let rest = target.strip_prefix("http://")?;
+            (rest.split('/').next()?, Some(80), false)
+        };
+        // Bracketed IPv6 literals carry colons of their own.
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, Some(port.parse().ok()?)),
+            _ => (authority, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return None;
+        }
+        Some(Self {
+            host: host.to_ascii_lowercase(),
+            port: port?,
+            tunnel,
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn connect(&self, allowed_private_hosts: &[String]) -> io::Result<tokio::net::TcpStream> {
+        let allowed = allowed_private_hosts
+            .iter()
+            .any(|host| host.eq_ignore_ascii_case(&self.host));
+        if !allowed && !EGRESS_PORTS.contains(&self.port) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "only ports 80 and 443 are reachable",
+            ));
+        }
+        let mut last_error = io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "host only resolves to private addresses",
+        );
+        for address in tokio::net::lookup_host((self.host.as_str(), self.port)).await? {
+            if !allowed && !is_public_address(address.ip()) {
+                continue;
+            }
+            match tokio::net::TcpStream::connect(address).await {
+                Ok(stream) => return Ok(stream),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+/// Whether an address is on the public internet, so a sandboxed extension
+/// cannot reach the server itself or anything else on the local network
+pub(crate) fn is_public_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_ipv4(address),
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(mapped) => is_public_ipv4(mapped),
+            None => is_public_ipv6(address),
+        },
+    }
+}
+
+fn is_public_ipv4(address: Ipv4Addr) -> bool {
+    let [first, second, ..] = address.octets();
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_multicast()
+        || address.is_documentation()
+        // Shared address space, 100.64.0.0/10
+        || (first == 100 && (second & 0xc0) == 64)
+        || first == 0
+        || first >= 240)
+}
+
+fn is_public_ipv6(address: Ipv6Addr) -> bool {
+    let first = address.segments()[0];
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_multicast()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link local, fe80::/10
+        || (first & 0xffc0) == 0xfe80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn egress_targets_are_parsed_from_connect_and_absolute_requests() {
+        assert_eq!(
+            EgressTarget::parse(b"CONNECT Example.com:443 HTTP/1.1\r\nHost: example.com\r\n\r\n"),
+            Some(EgressTarget {
+                host: "example.com".to_string(),
+                port: 443,
+                tunnel: true,
+            })
+        );
+        assert_eq!(
+            EgressTarget::parse(b"GET http://example.com/a/b HTTP/1.1\r\n\r\n"),
+            Some(EgressTarget {
+                host: "example.com".to_string(),
+                port: 80,
+                tunnel: false,
+            })
+        );
+        assert_eq!(
+            EgressTarget::parse(b"CONNECT [2001:db8::1]:8443 HTTP/1.1\r\n\r\n"),
+            Some(EgressTarget {
+                host: "2001:db8::1".to_string(),
+                port: 8443,
+                tunnel: true,
+            })
+        );
+        assert_eq!(
+            EgressTarget::parse(b"CONNECT example.com HTTP/1.1\r\n\r\n"),
+            None
+        );
+        assert_eq!(EgressTarget::parse(b"GET /relative HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn only_public_addresses_are_reachable() {
+        for address in ["93.184.216.34", "2606:2800:220:1::1"] {
+            assert!(is_public_address(address.parse().unwrap()), "{address}");
+        }
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.10",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(address.parse().unwrap()), "{address}");
+        }
+    }
+}
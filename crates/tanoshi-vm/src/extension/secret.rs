@@ -0,0 +1,109 @@
+//! Authenticated encryption for secrets the host keeps at rest, such as
+//! secret source preferences and source logins.
+
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    hkdf::{HKDF_SHA256, Salt},
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// AES-256-GCM key derived from the server secret for one purpose, a value
+/// sealed for one purpose can't be opened by a key for another
+#[derive(Clone)]
+pub struct SecretKey {
+    key: Arc<LessSafeKey>,
+}
+
+impl SecretKey {
+    pub fn new(secret: &str, purpose: &str) -> Result<Self> {
+        let info = [purpose.as_bytes()];
+        let prk = Salt::new(HKDF_SHA256, b"tanoshi").extract(secret.as_bytes());
+        let key = prk
+            .expand(&info, &AES_256_GCM)
+            .map_err(|_| anyhow!("failed to derive the {purpose} key"))?;
+
+        Ok(Self {
+            key: Arc::new(LessSafeKey::new(UnboundKey::from(key))),
+        })
+    }
+
+    /// Encrypt `plaintext` under a random nonce, as url safe base64
+    pub fn seal(&self, plaintext: &[u8]) -> Result<String> {
+        let mut nonce = [0_u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("failed to generate a nonce"))?;
+
+        let mut sealed = nonce.to_vec();
+        let mut ciphertext = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut ciphertext,
+            )
+            .map_err(|_| anyhow!("failed to encrypt"))?;
+        sealed.extend(ciphertext);
+
+        Ok(URL_SAFE_NO_PAD.encode(sealed))
+    }
+
+    /// Decrypt a value from [`SecretKey::seal`], fails when it was sealed by
+    /// another key or changed since
+    pub fn open(&self, sealed: &str) -> Result<Vec<u8>> {
+        let mut sealed = URL_SAFE_NO_PAD.decode(sealed)?;
+        if sealed.len() < NONCE_LEN {
+            bail!("sealed value is too short");
+        }
+
+        let (nonce, ciphertext) = sealed.split_at_mut(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow!("sealed value has an invalid nonce"))?;
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt, sealed by another key or changed"))?;
+
+        Ok(plaintext.to_vec())
+    }
+}
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecretKey;
+
+    #[test]
+    fn sealed_values_open_only_with_the_same_secret_and_purpose() {
+        let key = SecretKey::new("server secret", "preferences").unwrap();
+        let sealed = key.seal(b"hunter2").unwrap();
+        assert_ne!(key.seal(b"hunter2").unwrap(), sealed);
+        assert_eq!(key.open(&sealed).unwrap(), b"hunter2");
+
+        let other_secret = SecretKey::new("another secret", "preferences").unwrap();
+        assert!(other_secret.open(&sealed).is_err());
+        let other_purpose = SecretKey::new("server secret", "credentials").unwrap();
+        assert!(other_purpose.open(&sealed).is_err());
+    }
+
+    #[test]
+    fn changed_values_fail_to_open() {
+        let key = SecretKey::new("server secret", "preferences").unwrap();
+        let mut sealed = key.seal(b"hunter2").unwrap().into_bytes();
+        let last = sealed.len() - 1;
+        sealed[last] = if sealed[last] == b'A' { b'B' } else { b'A' };
+
+        assert!(key.open(std::str::from_utf8(&sealed).unwrap()).is_err());
+        assert!(key.open("").is_err());
+        assert!(key.open("not base64!").is_err());
+    }
+}
@@ -1,20 +1,27 @@
 use std::{
     any::Any,
+    collections::VecDeque,
     path::{Path, PathBuf},
     sync::{
-        Arc, RwLock,
+        Arc, Mutex as StdMutex, RwLock,
         atomic::{AtomicU8, AtomicU32, AtomicUsize, Ordering},
     },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Result, anyhow};
+use fnv::FnvHashMap;
 use libloading::Library;
 use once_cell::sync::OnceCell;
-use tanoshi_lib::prelude::{Extension, SourceInfo};
+use tanoshi_lib::{
+    http::HttpClient,
+    prelude::{Extension, SourceInfo},
+};
 use tokio::sync::Semaphore;
 
 use std::panic::{AssertUnwindSafe, catch_unwind};
 
+use super::rate_limit::TokenBucket;
 use super::worker::{WorkerClient, WorkerSourceInfo};
 
 pub(crate) const SOURCE_HEALTH_FAILURE_THRESHOLD: u32 = 3;
@@ -23,6 +30,8 @@ pub(crate) const SOURCE_MAX_ABANDONED_CALLS: usize = 3;
 const HEALTHY: u8 = 0;
 const DEGRADED: u8 = 1;
 const QUARANTINED: u8 = 2;
+/// Calls, and latency samples per operation, kept for [`SourceHealthReport`]
+const SOURCE_METRICS_WINDOW: usize = 100;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub(crate) enum SourceAdmission {
@@ -31,10 +40,70 @@ pub(crate) enum SourceAdmission {
     Quarantined,
 }
 
+/// Health state of a source as reported to users, see
+/// [`super::ExtensionManager::source_health`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SourceHealthState {
+    Healthy,
+    /// Recent calls failed or timed out, calls are still admitted
+    Degraded,
+    /// Too many timed-out in-process calls are still running, new calls are
+    /// rejected until they finish
+    CircuitOpen,
+    /// The source failed repeatedly, new calls are rejected until an admin
+    /// un-quarantines, restarts or reloads it
+    Quarantined,
+}
+
+#[derive(Clone, Debug)]
+pub struct OperationLatency {
+    pub operation: &'static str,
+    /// Calls the percentiles are computed from
+    pub calls: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+}
+
+/// Snapshot of a source's health and of its most recent calls
+#[derive(Clone, Debug)]
+pub struct SourceHealthReport {
+    pub state: SourceHealthState,
+    pub abandoned_calls: usize,
+    pub recent_calls: usize,
+    pub recent_errors: usize,
+    pub operations: Vec<OperationLatency>,
+    pub last_error: Option<String>,
+    /// Unix timestamp of `last_error`
+    pub last_error_at: Option<i64>,
+    /// Worker processes replaced after a crash, timeout or panic, or by an
+    /// admin
+    pub worker_restarts: u64,
+}
+
+impl SourceHealthReport {
+    /// Share of the recent calls that failed, zero without calls
+    pub fn error_rate(&self) -> f64 {
+        if self.recent_calls == 0 {
+            0.0
+        } else {
+            self.recent_errors as f64 / self.recent_calls as f64
+        }
+    }
+}
+
+#[derive(Default)]
+struct CallMetrics {
+    /// Whether each recent call failed, oldest first
+    outcomes: VecDeque<bool>,
+    latencies: FnvHashMap<&'static str, VecDeque<Duration>>,
+    last_error: Option<(String, i64)>,
+}
+
 pub(crate) struct SourceHealth {
     state: AtomicU8,
     read_panics: AtomicU32,
     abandoned_calls: AtomicUsize,
+    metrics: StdMutex<CallMetrics>,
 }
 
 impl SourceHealth {
@@ -43,9 +112,95 @@ impl SourceHealth {
             state: AtomicU8::new(HEALTHY),
             read_panics: AtomicU32::new(0),
             abandoned_calls: AtomicUsize::new(0),
+            metrics: StdMutex::new(CallMetrics::default()),
         })
     }
 
+    fn metrics(&self) -> std::sync::MutexGuard<'_, CallMetrics> {
+        match self.metrics.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Record the outcome and duration of a call for [`Self::report`]
+    pub(crate) fn record_call(
+        &self,
+        operation: &'static str,
+        elapsed: Duration,
+        error: Option<&anyhow::Error>,
+    ) {
+        let mut metrics = self.metrics();
+        if metrics.outcomes.len() == SOURCE_METRICS_WINDOW {
+            metrics.outcomes.pop_front();
+        }
+        metrics.outcomes.push_back(error.is_some());
+
+        let latencies = metrics.latencies.entry(operation).or_default();
+        if latencies.len() == SOURCE_METRICS_WINDOW {
+            latencies.pop_front();
+        }
+        latencies.push_back(elapsed);
+
+        if let Some(error) = error {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs() as i64)
+                .unwrap_or_default();
+            metrics.last_error = Some((error.to_string(), now));
+        }
+    }
+
+    pub(crate) fn report(&self, worker_restarts: u64) -> SourceHealthReport {
+        let state = match self.admission() {
+            SourceAdmission::Quarantined => SourceHealthState::Quarantined,
+            SourceAdmission::CircuitOpen { .. } => SourceHealthState::CircuitOpen,
+            SourceAdmission::Allowed if self.state.load(Ordering::Acquire) == DEGRADED => {
+                SourceHealthState::Degraded
+            }
+            SourceAdmission::Allowed => SourceHealthState::Healthy,
+        };
+
+        let metrics = self.metrics();
+        let mut operations = metrics
+            .latencies
+            .iter()
+            .map(|(operation, latencies)| {
+                let mut sorted = latencies.iter().copied().collect::<Vec<_>>();
+                sorted.sort_unstable();
+                let percentile = |percent: usize| sorted[(sorted.len() - 1) * percent / 100];
+                OperationLatency {
+                    operation,
+                    calls: sorted.len(),
+                    p50: percentile(50),
+                    p95: percentile(95),
+                }
+            })
+            .collect::<Vec<_>>();
+        operations.sort_by_key(|latency| latency.operation);
+
+        SourceHealthReport {
+            state,
+            abandoned_calls: self.abandoned_calls.load(Ordering::Acquire),
+            recent_calls: metrics.outcomes.len(),
+            recent_errors: metrics.outcomes.iter().filter(|failed| **failed).count(),
+            operations,
+            last_error: metrics.last_error.as_ref().map(|(error, _)| error.clone()),
+            last_error_at: metrics.last_error.as_ref().map(|(_, at)| *at),
+            worker_restarts,
+        }
+    }
+
+    /// Lift a quarantine and forget past failures. Timed-out in-process calls
+    /// that are still running keep the circuit open until they finish.
+    pub(crate) fn reset(&self) {
+        self.read_panics.store(0, Ordering::Release);
+        self.state.store(HEALTHY, Ordering::Release);
+        if self.abandoned_calls.load(Ordering::Acquire) > 0 {
+            self.mark_degraded();
+        }
+    }
+
     pub(crate) fn admission(&self) -> SourceAdmission {
         if self.state.load(Ordering::Acquire) == QUARANTINED {
             return SourceAdmission::Quarantined;
@@ -119,7 +274,7 @@ impl SourceHealth {
         }
     }
 
-    fn mark_degraded(&self) {
+    pub(crate) fn mark_degraded(&self) {
         let mut state = self.state.load(Ordering::Acquire);
         while state != QUARANTINED {
             match self
@@ -185,6 +340,25 @@ impl Drop for LoadedLibrary {
 }
 
 /// A loaded source that can outlive its entry in the manager's source map.
+/// Size and modification time of a plugin file when a worker entry was
+/// loaded from it, to tell a replaced library from the loaded one without
+/// reading either
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct LibraryFingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl LibraryFingerprint {
+    pub(crate) fn of(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
 pub struct SourceEntry {
     pub(crate) source_id: i64,
     pub(crate) source_info: SourceInfo,
@@ -194,10 +368,14 @@ pub struct SourceEntry {
     pub(crate) extension: Option<RwLock<Box<dyn Extension>>>,
     pub(crate) worker: Option<Arc<WorkerClient>>,
     pub(crate) limiter: Arc<Semaphore>,
+    /// Enforces the rate limit declared in `source_info`, if any.
+    pub(crate) rate_limiter: Option<Arc<TokenBucket>>,
     pub(crate) health: Arc<SourceHealth>,
     #[allow(dead_code)]
     pub(crate) library: Option<LoadedLibrary>,
     pub(crate) plugin_path: Option<PathBuf>,
+    /// Plugin file the worker's library was staged from, `None` in process
+    pub(crate) library_fingerprint: Option<LibraryFingerprint>,
     pub(crate) rustc_version: String,
     pub(crate) lib_version: String,
 }
@@ -248,19 +426,23 @@ impl SourceEntry {
         worker: Arc<WorkerClient>,
         max_concurrent_calls: usize,
         plugin_path: PathBuf,
+        library_fingerprint: LibraryFingerprint,
         rustc_version: String,
         lib_version: String,
     ) -> Self {
         let source_info = source_info.into_source_info();
+        let rate_limiter = source_info.rate_limit.and_then(TokenBucket::new);
         Self {
             source_id: source_info.id,
             source_info,
             extension: None,
             worker: Some(worker),
             limiter: Arc::new(Semaphore::new(max_concurrent_calls.max(1))),
+            rate_limiter,
             health: SourceHealth::new(),
             library: None,
             plugin_path: Some(plugin_path),
+            library_fingerprint: Some(library_fingerprint),
             rustc_version,
             lib_version,
         }
@@ -271,6 +453,9 @@ pub struct Source {
     pub(crate) extension: OnceCell<Box<dyn Extension>>,
     pub(crate) library: Option<LoadedLibrary>,
     pub(crate) plugin_path: Option<PathBuf>,
+    /// Offered to the extension while it registers, see
+    /// [`tanoshi_lib::http`]
+    pub(crate) http_client: Option<Arc<dyn HttpClient>>,
     pub rustc_version: String,
     pub lib_version: String,
 }
@@ -280,6 +465,7 @@ impl Source {
         Source {
             library: Some(LoadedLibrary::new(lib)),
             plugin_path: None,
+            http_client: None,
             rustc_version: rustc_version.to_string(),
             lib_version: lib_version.to_string(),
             extension: OnceCell::new(),
@@ -290,6 +476,7 @@ impl Source {
         Self {
             library: None,
             plugin_path: None,
+            http_client: None,
             rustc_version: tanoshi_lib::RUSTC_VERSION.to_string(),
             lib_version: tanoshi_lib::LIB_VERSION.to_string(),
             extension: OnceCell::from(extension),
@@ -301,6 +488,11 @@ impl Source {
         self
     }
 
+    pub(crate) fn with_http_client(mut self, client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
     pub(crate) fn into_entry(self, max_concurrent_calls: usize) -> Result<SourceEntry> {
         let max_concurrent_calls = max_concurrent_calls.max(1);
         let extension = self
@@ -323,6 +515,7 @@ impl Source {
             extension,
             library,
             plugin_path,
+            http_client: _,
             rustc_version,
             lib_version,
         } = self;
@@ -330,6 +523,7 @@ impl Source {
             .into_inner()
             .ok_or_else(|| anyhow!("extension not initiated"))?;
         let source_id = source_info.id;
+        let rate_limiter = source_info.rate_limit.and_then(TokenBucket::new);
 
         Ok(SourceEntry {
             source_id,
@@ -337,9 +531,11 @@ impl Source {
             extension: Some(RwLock::new(extension)),
             worker: None,
             limiter: Arc::new(Semaphore::new(max_concurrent_calls)),
+            rate_limiter,
             health: SourceHealth::new(),
             library,
             plugin_path,
+            library_fingerprint: None,
             rustc_version,
             lib_version,
         })
@@ -353,4 +549,8 @@ impl tanoshi_lib::extensions::PluginRegistrar for Source {
             .map_err(|_| "extension already initiated")
             .unwrap();
     }
+
+    fn http_client(&self) -> Option<Arc<dyn HttpClient>> {
+        self.http_client.clone()
+    }
 }
@@ -0,0 +1,770 @@
+//! Sources described by a YAML file in the plugin directory instead of a
+//! compiled extension. A template lists URL templates and CSS selectors for
+//! each operation, values are taken from an element's text, inner HTML or an
+//! attribute and can be post-processed with a regex.
+//!
+//! ```yaml
+//! id: 9001
+//! name: Example
+//! url: https://example.com
+//! version: 0.1.0
+//! popular:
+//!   url: "{url}/popular?page={page}"
+//!   items: div.manga
+//!   title: a.title
+//!   path: { selector: a.title, attr: href }
+//!   cover: { selector: img, attr: data-src }
+//! search:
+//!   url: "{url}/search?q={query}&page={page}"
+//!   items: div.manga
+//!   title: a.title
+//!   path: { selector: a.title, attr: href }
+//! detail:
+//!   title: h1
+//!   author: .authors a
+//!   genre: .genres a
+//!   status: .status
+//!   description: .summary
+//!   cover: { selector: .cover img, attr: src }
+//! chapters:
+//!   items: ul.chapters li
+//!   title: a
+//!   path: { selector: a, attr: href }
+//!   uploaded: { selector: time, attr: datetime }
+//!   date_format: "%Y-%m-%d"
+//! pages:
+//!   items: div.reader img
+//!   image: { attr: data-src }
+//! ```
+//!
+//! URL templates can use `{url}`, `{page}`, `{query}` and `{path}`. Without
+//! a `url`, detail, chapters and pages fetch the manga or chapter path
+//! resolved against the source url.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use bytes::Bytes;
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use tanoshi_lib::{
+    http::{HttpClient, Request},
+    prelude::{Capabilities, ChapterInfo, Extension, Input, Lang, MangaInfo, SourceInfo},
+};
+
+/// File names the manager loads as templates, JSON ones need the
+/// `.template.json` suffix to tell them from preference and cookie files
+pub(crate) fn is_template_file_name(name: &str) -> bool {
+    name.ends_with(".yml") || name.ends_with(".yaml") || name.ends_with(".template.json")
+}
+
+#[derive(Debug, Deserialize)]
+struct Template {
+    id: i64,
+    name: String,
+    url: String,
+    #[serde(default = "default_version")]
+    version: String,
+    #[serde(default)]
+    icon: String,
+    #[serde(default)]
+    languages: Lang,
+    #[serde(default)]
+    nsfw: bool,
+    /// Sent with every request, including images
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    popular: Option<ListTemplate>,
+    latest: Option<ListTemplate>,
+    search: Option<ListTemplate>,
+    detail: DetailTemplate,
+    chapters: ChaptersTemplate,
+    pages: PagesTemplate,
+}
+
+fn default_version() -> String {
+    "0.0.0".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct ListTemplate {
+    url: String,
+    items: String,
+    title: Field,
+    path: Field,
+    cover: Option<Field>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DetailTemplate {
+    url: Option<String>,
+    title: Option<Field>,
+    author: Option<Field>,
+    artist: Option<Field>,
+    genre: Option<Field>,
+    status: Option<Field>,
+    description: Option<Field>,
+    cover: Option<Field>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChaptersTemplate {
+    url: Option<String>,
+    items: String,
+    title: Field,
+    path: Field,
+    /// Parsed from the title when missing
+    number: Option<Field>,
+    scanlator: Option<Field>,
+    uploaded: Option<Field>,
+    /// `chrono` format of `uploaded`, unix timestamps are used as is
+    date_format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PagesTemplate {
+    url: Option<String>,
+    items: String,
+    image: Field,
+}
+
+/// Where a value comes from, a bare string is a selector whose text is used
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Field {
+    Selector(String),
+    Spec {
+        /// Relative to the item, the item itself when missing
+        selector: Option<String>,
+        /// `text` (default), `html` or the name of an attribute
+        attr: Option<String>,
+        /// Keeps the first capture group, or the whole match without groups
+        regex: Option<String>,
+        /// Replaces the match instead, with `$1` style references
+        replace: Option<String>,
+    },
+}
+
+enum Attr {
+    Text,
+    Html,
+    Named(String),
+}
+
+struct Extractor {
+    selector: Option<Selector>,
+    attr: Attr,
+    regex: Option<Regex>,
+    replace: Option<String>,
+}
+
+impl Extractor {
+    fn compile(field: &Field) -> Result<Self> {
+        let (selector, attr, regex, replace) = match field {
+            Field::Selector(selector) => (Some(selector.as_str()), None, None, None),
+            Field::Spec {
+                selector,
+                attr,
+                regex,
+                replace,
+            } => (
+                selector.as_deref(),
+                attr.as_deref(),
+                regex.as_deref(),
+                replace.clone(),
+            ),
+        };
+
+        Ok(Self {
+            selector: selector.map(parse_selector).transpose()?,
+            attr: match attr.unwrap_or("text") {
+                "text" => Attr::Text,
+                "html" => Attr::Html,
+                name => Attr::Named(name.to_string()),
+            },
+            regex: regex
+                .map(|regex| Regex::new(regex).with_context(|| format!("invalid regex {regex:?}")))
+                .transpose()?,
+            replace,
+        })
+    }
+
+    /// Every non-empty value below `element`
+    fn all(&self, element: ElementRef) -> Vec<String> {
+        let elements = match self.selector.as_ref() {
+            Some(selector) => element.select(selector).collect::<Vec<_>>(),
+            None => vec![element],
+        };
+
+        elements
+            .into_iter()
+            .filter_map(|element| {
+                let value = match &self.attr {
+                    Attr::Text => element.text().collect::<Vec<_>>().join(" "),
+                    Attr::Html => element.inner_html(),
+                    Attr::Named(name) => element.value().attr(name)?.to_string(),
+                };
+                self.post_process(&value)
+            })
+            .collect()
+    }
+
+    fn first(&self, element: ElementRef) -> Option<String> {
+        self.all(element).into_iter().next()
+    }
+
+    fn post_process(&self, value: &str) -> Option<String> {
+        let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        let value = match self.regex.as_ref() {
+            Some(regex) => {
+                let captures = regex.captures(&value)?;
+                match self.replace.as_deref() {
+                    Some(replace) => {
+                        let mut replaced = String::new();
+                        captures.expand(replace, &mut replaced);
+                        replaced
+                    }
+                    None => captures
+                        .get(1)
+                        .or_else(|| captures.get(0))?
+                        .as_str()
+                        .to_string(),
+                }
+            }
+            None => value,
+        };
+
+        let value = value.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    }
+}
+
+fn parse_selector(selector: &str) -> Result<Selector> {
+    Selector::parse(selector).map_err(|error| anyhow!("invalid selector {selector:?}: {error}"))
+}
+
+fn compile_optional(field: Option<&Field>) -> Result<Option<Extractor>> {
+    field.map(Extractor::compile).transpose()
+}
+
+struct ListScraper {
+    url: String,
+    items: Selector,
+    title: Extractor,
+    path: Extractor,
+    cover: Option<Extractor>,
+}
+
+impl ListScraper {
+    fn compile(list: &ListTemplate) -> Result<Self> {
+        Ok(Self {
+            url: list.url.clone(),
+            items: parse_selector(&list.items)?,
+            title: Extractor::compile(&list.title)?,
+            path: Extractor::compile(&list.path)?,
+            cover: compile_optional(list.cover.as_ref())?,
+        })
+    }
+}
+
+struct DetailScraper {
+    url: Option<String>,
+    title: Option<Extractor>,
+    author: Option<Extractor>,
+    artist: Option<Extractor>,
+    genre: Option<Extractor>,
+    status: Option<Extractor>,
+    description: Option<Extractor>,
+    cover: Option<Extractor>,
+}
+
+struct ChaptersScraper {
+    url: Option<String>,
+    items: Selector,
+    title: Extractor,
+    path: Extractor,
+    number: Option<Extractor>,
+    scanlator: Option<Extractor>,
+    uploaded: Option<Extractor>,
+    date_format: Option<String>,
+}
+
+struct PagesScraper {
+    url: Option<String>,
+    items: Selector,
+    image: Extractor,
+}
+
+/// A source scraped as described by a template file
+pub(crate) struct TemplateSource {
+    source_info: SourceInfo,
+    base_url: Url,
+    headers: BTreeMap<String, String>,
+    popular: Option<ListScraper>,
+    latest: Option<ListScraper>,
+    search: Option<ListScraper>,
+    detail: DetailScraper,
+    chapters: ChaptersScraper,
+    pages: PagesScraper,
+    client: Option<Arc<dyn HttpClient>>,
+}
+
+impl TemplateSource {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents).with_context(|| format!("invalid template {}", path.display()))
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let template: Template =
+            serde_yaml2::from_str(contents).map_err(|error| anyhow!("{error}"))?;
+        let base_url = Url::parse(&template.url)
+            .with_context(|| format!("invalid source url {:?}", template.url))?;
+
+        let capabilities = Capabilities {
+            popular: template.popular.is_some(),
+            latest: template.latest.is_some(),
+            search: template.search.is_some(),
+            search_requires_query: template
+                .search
+                .as_ref()
+                .is_some_and(|search| search.url.contains("{query}")),
+            filters: false,
+            requires_login: false,
+        };
+
+        Ok(Self {
+            source_info: SourceInfo {
+                id: template.id,
+                name: template.name,
+                url: template.url,
+                // template sources are reloaded rarely, leaking keeps the
+                // `'static` fields of `SourceInfo`
+                version: Box::leak(template.version.into_boxed_str()),
+                icon: Box::leak(template.icon.into_boxed_str()),
+                languages: template.languages,
+                nsfw: template.nsfw,
+                capabilities,
+                rate_limit: None,
+            },
+            base_url,
+            headers: template.headers,
+            popular: template
+                .popular
+                .as_ref()
+                .map(ListScraper::compile)
+                .transpose()?,
+            latest: template
+                .latest
+                .as_ref()
+                .map(ListScraper::compile)
+                .transpose()?,
+            search: template
+                .search
+                .as_ref()
+                .map(ListScraper::compile)
+                .transpose()?,
+            detail: DetailScraper {
+                url: template.detail.url.clone(),
+                title: compile_optional(template.detail.title.as_ref())?,
+                author: compile_optional(template.detail.author.as_ref())?,
+                artist: compile_optional(template.detail.artist.as_ref())?,
+                genre: compile_optional(template.detail.genre.as_ref())?,
+                status: compile_optional(template.detail.status.as_ref())?,
+                description: compile_optional(template.detail.description.as_ref())?,
+                cover: compile_optional(template.detail.cover.as_ref())?,
+            },
+            chapters: ChaptersScraper {
+                url: template.chapters.url.clone(),
+                items: parse_selector(&template.chapters.items)?,
+                title: Extractor::compile(&template.chapters.title)?,
+                path: Extractor::compile(&template.chapters.path)?,
+                number: compile_optional(template.chapters.number.as_ref())?,
+                scanlator: compile_optional(template.chapters.scanlator.as_ref())?,
+                uploaded: compile_optional(template.chapters.uploaded.as_ref())?,
+                date_format: template.chapters.date_format.clone(),
+            },
+            pages: PagesScraper {
+                url: template.pages.url.clone(),
+                items: parse_selector(&template.pages.items)?,
+                image: Extractor::compile(&template.pages.image)?,
+            },
+            client: None,
+        })
+    }
+
+    pub(crate) fn with_client(mut self, client: Arc<dyn HttpClient>) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub(crate) fn source_id(&self) -> i64 {
+        self.source_info.id
+    }
+
+    pub(crate) fn source_name(&self) -> &str {
+        &self.source_info.name
+    }
+
+    fn render(&self, template: &str, page: i64, query: &str, path: &str) -> String {
+        let query = url::form_urlencoded::byte_serialize(query.as_bytes()).collect::<String>();
+        template
+            .replace("{url}", self.base_url.as_str().trim_end_matches('/'))
+            .replace("{page}", &page.to_string())
+            .replace("{query}", &query)
+            .replace("{path}", path)
+    }
+
+    /// A link resolved against the page it was scraped from, or the source
+    /// url when that page url is unusable
+    fn absolute(&self, page: &str, link: &str) -> String {
+        Url::parse(page)
+            .unwrap_or_else(|_| self.base_url.clone())
+            .join(link)
+            .map(String::from)
+            .unwrap_or_else(|_| link.to_string())
+    }
+
+    fn path_url(&self, template: Option<&str>, path: &str) -> String {
+        match template {
+            Some(template) => self.absolute("", &self.render(template, 1, "", path)),
+            None => self.absolute("", path),
+        }
+    }
+
+    fn fetch(&self, url: &str) -> Result<Bytes> {
+        let client = self
+            .client
+            .as_ref()
+            .ok_or_else(|| anyhow!("the host did not provide an HTTP client"))?;
+        let mut request = Request::get(url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = client.send(request)?;
+        if !response.is_success() {
+            bail!("GET {url} returned {}", response.status);
+        }
+        Ok(response.body)
+    }
+
+    fn fetch_html(&self, url: &str) -> Result<Html> {
+        let body = self.fetch(url)?;
+        Ok(Html::parse_document(&String::from_utf8_lossy(&body)))
+    }
+
+    fn list(&self, list: Option<&ListScraper>, page: i64, query: &str) -> Result<Vec<MangaInfo>> {
+        let Some(list) = list else {
+            bail!("{} does not support this listing", self.source_info.name);
+        };
+        let url = self.render(&list.url, page, query, "");
+        let html = self.fetch_html(&url)?;
+
+        Ok(html
+            .select(&list.items)
+            .filter_map(|item| {
+                Some(MangaInfo {
+                    source_id: self.source_info.id,
+                    title: list.title.first(item)?,
+                    path: list.path.first(item)?,
+                    cover_url: list
+                        .cover
+                        .as_ref()
+                        .and_then(|cover| cover.first(item))
+                        .map(|cover| self.absolute(&url, &cover))
+                        .unwrap_or_default(),
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+}
+
+impl Extension for TemplateSource {
+    fn get_source_info(&self) -> SourceInfo {
+        self.source_info.clone()
+    }
+
+    fn headers(&self) -> HashMap<String, String> {
+        self.headers.clone().into_iter().collect()
+    }
+
+    fn get_popular_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.list(self.popular.as_ref(), page, "")
+    }
+
+    fn get_latest_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.list(self.latest.as_ref(), page, "")
+    }
+
+    fn search_manga(
+        &self,
+        page: i64,
+        query: Option<String>,
+        _filters: Option<Vec<Input>>,
+    ) -> Result<Vec<MangaInfo>> {
+        self.list(
+            self.search.as_ref(),
+            page,
+            query.as_deref().unwrap_or_default(),
+        )
+    }
+
+    fn get_manga_detail(&self, path: String) -> Result<MangaInfo> {
+        let detail = &self.detail;
+        let url = self.path_url(detail.url.as_deref(), &path);
+        let html = self.fetch_html(&url)?;
+        let root = html.root_element();
+        let first = |field: &Option<Extractor>| field.as_ref().and_then(|field| field.first(root));
+        let all = |field: &Option<Extractor>| {
+            field
+                .as_ref()
+                .map(|field| field.all(root))
+                .unwrap_or_default()
+        };
+
+        Ok(MangaInfo {
+            source_id: self.source_info.id,
+            title: first(&detail.title).unwrap_or_default(),
+            author: all(&detail.author),
+            artist: all(&detail.artist),
+            genre: all(&detail.genre),
+            status: first(&detail.status).and_then(|status| status.parse().ok()),
+            description: first(&detail.description),
+            cover_url: first(&detail.cover)
+                .map(|cover| self.absolute(&url, &cover))
+                .unwrap_or_default(),
+            path,
+            ..Default::default()
+        })
+    }
+
+    fn get_chapters(&self, path: String) -> Result<Vec<ChapterInfo>> {
+        let chapters = &self.chapters;
+        let html = self.fetch_html(&self.path_url(chapters.url.as_deref(), &path))?;
+        let items = html.select(&chapters.items).collect::<Vec<_>>();
+        let count = items.len();
+
+        Ok(items
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let title = chapters.title.first(item)?;
+                let number = chapters
+                    .number
+                    .as_ref()
+                    .and_then(|number| number.first(item))
+                    .and_then(|number| parse_number(&number))
+                    .or_else(|| parse_number(&title))
+                    // sites usually list the newest chapter first
+                    .unwrap_or((count - index) as f64);
+                let uploaded = chapters
+                    .uploaded
+                    .as_ref()
+                    .and_then(|uploaded| uploaded.first(item))
+                    .and_then(|uploaded| parse_date(&uploaded, chapters.date_format.as_deref()))
+                    .unwrap_or_default();
+
+                Some(ChapterInfo {
+                    source_id: self.source_info.id,
+                    path: chapters.path.first(item)?,
+                    number,
+                    scanlator: chapters
+                        .scanlator
+                        .as_ref()
+                        .and_then(|scanlator| scanlator.first(item)),
+                    uploaded,
+                    title,
+                    volume: None,
+                    language: None,
+                    page_count: None,
+                    external_url: None,
+                })
+            })
+            .collect())
+    }
+
+    fn get_pages(&self, path: String) -> Result<Vec<String>> {
+        let pages = &self.pages;
+        let url = self.path_url(pages.url.as_deref(), &path);
+        let html = self.fetch_html(&url)?;
+
+        Ok(html
+            .select(&pages.items)
+            .filter_map(|item| pages.image.first(item))
+            .map(|image| self.absolute(&url, &image))
+            .collect())
+    }
+
+    fn get_image_bytes(&self, url: String) -> Result<Bytes> {
+        self.fetch(&url)
+    }
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    static NUMBER: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    NUMBER
+        .get_or_init(|| Regex::new(r"\d+(?:\.\d+)?").expect("valid number regex"))
+        .find(value)
+        .and_then(|number| number.as_str().parse().ok())
+}
+
+fn parse_date(value: &str, format: Option<&str>) -> Option<i64> {
+    match format {
+        Some(format) => NaiveDateTime::parse_from_str(value, format)
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, format)
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+            .map(|date| date.and_utc().timestamp()),
+        None => value.parse().ok().or_else(|| {
+            chrono::DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|date| date.timestamp())
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tanoshi_lib::http::Response;
+
+    use super::*;
+
+    const TEMPLATE: &str = r#"
+id: 9001
+name: Example
+url: https://example.com
+version: 0.1.0
+headers:
+  Referer: https://example.com/
+popular:
+  url: "{url}/popular?page={page}"
+  items: div.manga
+  title: a.title
+  path: { selector: a.title, attr: href }
+  cover: { selector: img, attr: data-src }
+search:
+  url: "{url}/search?q={query}&page={page}"
+  items: div.manga
+  title: a.title
+  path: { selector: a.title, attr: href }
+detail:
+  title: h1
+  author: .authors a
+  genre: .genres a
+  status: { selector: .status, regex: "Status: (\\w+)" }
+  description: .summary
+chapters:
+  items: ul.chapters li
+  title: a
+  path: { selector: a, attr: href }
+  uploaded: { selector: time, attr: datetime }
+  date_format: "%Y-%m-%d"
+pages:
+  items: div.reader img
+  image: { attr: data-src }
+"#;
+
+    struct Pages;
+
+    impl HttpClient for Pages {
+        fn send(&self, request: Request) -> Result<Response> {
+            assert_eq!(
+                request.headers,
+                [("Referer".into(), "https://example.com/".into())]
+            );
+            let body = match request.url.as_str() {
+                "https://example.com/popular?page=2" => {
+                    r#"<div class="manga"><a class="title" href="/manga/one"> One
+                    Piece </a><img data-src="/covers/one.jpg"></div>
+                    <div class="manga"><img data-src="/covers/none.jpg"></div>"#
+                }
+                "https://example.com/search?q=one+piece&page=1" => {
+                    r#"<div class="manga"><a class="title" href="/manga/one">One Piece</a></div>"#
+                }
+                "https://example.com/manga/one" => {
+                    r#"<h1>One Piece</h1><p class="authors"><a>Oda</a></p>
+                    <p class="genres"><a>Action</a><a>Adventure</a></p>
+                    <p class="status">Status: Ongoing</p><p class="summary">Pirates</p>
+                    <ul class="chapters">
+                    <li><a href="/read/2">Chapter 2</a><time datetime="2024-01-02"></time></li>
+                    <li><a href="/read/1">Chapter 1.5</a></li>
+                    </ul>"#
+                }
+                "https://example.com/read/2" => {
+                    r#"<div class="reader"><img data-src="https://cdn.example.com/1.jpg">
+                    <img data-src="2.jpg"></div>"#
+                }
+                url => bail!("unexpected request {url}"),
+            };
+            Ok(Response {
+                status: 200,
+                headers: vec![],
+                body: body.into(),
+            })
+        }
+    }
+
+    #[test]
+    fn template_sources_scrape_lists_details_chapters_and_pages() {
+        let source = TemplateSource::parse(TEMPLATE)
+            .unwrap()
+            .with_client(Arc::new(Pages));
+        let info = source.get_source_info();
+        assert_eq!((info.id, info.version), (9001, "0.1.0"));
+        assert!(!info.capabilities.latest && info.capabilities.search_requires_query);
+
+        let popular = source.get_popular_manga(2).unwrap();
+        assert_eq!(popular.len(), 1);
+        assert_eq!(popular[0].title, "One Piece");
+        assert_eq!(popular[0].path, "/manga/one");
+        assert_eq!(popular[0].cover_url, "https://example.com/covers/one.jpg");
+        assert!(source.get_latest_manga(1).is_err());
+        let search = source
+            .search_manga(1, Some("one piece".to_string()), None)
+            .unwrap();
+        assert_eq!(search[0].path, "/manga/one");
+
+        let detail = source.get_manga_detail("/manga/one".to_string()).unwrap();
+        assert_eq!(detail.title, "One Piece");
+        assert_eq!(detail.author, ["Oda"]);
+        assert_eq!(detail.genre, ["Action", "Adventure"]);
+        assert_eq!(detail.status.map(|status| status.as_str()), Some("Ongoing"));
+        assert_eq!(detail.description.as_deref(), Some("Pirates"));
+
+        let chapters = source.get_chapters("/manga/one".to_string()).unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(
+            (chapters[0].number, chapters[0].uploaded),
+            (2.0, 1704153600)
+        );
+        assert_eq!((chapters[1].number, chapters[1].uploaded), (1.5, 0));
+
+        let pages = source.get_pages(chapters[0].path.clone()).unwrap();
+        assert_eq!(
+            pages,
+            [
+                "https://cdn.example.com/1.jpg",
+                "https://example.com/read/2.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_selectors_are_rejected_when_loading() {
+        let template = TEMPLATE.replace("items: div.reader img", "items: \"div[\"");
+        let error = TemplateSource::parse(&template).err().unwrap();
+        assert!(error.to_string().contains("invalid selector"));
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// What an extension downloaded from a repository must match before it is
+/// loaded
+#[derive(Clone, Debug, Default)]
+pub struct PluginVerification {
+    /// Name of the extension in the index
+    pub name: String,
+    /// Version of the extension in the index
+    pub version: String,
+    /// Hex encoded SHA-256 of the library, a library the index lists none
+    /// for is refused
+    pub sha256: Option<String>,
+    /// Base64 encoded ed25519 signature from [`sign_plugin`], it covers the
+    /// name, version and target along with the library, so a signed library
+    /// can't be listed as another extension or version
+    pub signature: Option<String>,
+    /// Base64 encoded ed25519 public keys of the repository. When set, the
+    /// library must also carry a signature by one of them.
+    pub trusted_keys: Vec<String>,
+}
+
+impl PluginVerification {
+    pub fn verify(&self, contents: &[u8]) -> Result<()> {
+        let Some(expected) = self.sha256.as_deref() else {
+            bail!("the index has no sha256 of the extension for this target");
+        };
+        if !self.trusted_keys.is_empty() && self.signature.is_none() {
+            bail!("the repository requires signed extensions, but the index has no signature");
+        }
+
+        let actual = sha256_hex(contents);
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            bail!("sha256 mismatch, expected {expected} but downloaded {actual}");
+        }
+
+        let Some(signature) = self.signature.as_deref() else {
+            return Ok(());
+        };
+        if self.trusted_keys.is_empty() {
+            warn!("the extension is signed, but no trusted key is configured for the repository");
+            return Ok(());
+        }
+        let signature = general_purpose::STANDARD
+            .decode(signature.trim())
+            .ok()
+            .and_then(|signature| Signature::from_slice(&signature).ok())
+            .ok_or_else(|| anyhow!("invalid ed25519 signature"))?;
+
+        let message = signed_message(&self.name, &self.version, &sha256_hex(contents));
+        let mut keys = self.trusted_keys.iter().map(|key| decode_key(key));
+        if keys.any(|key| key.is_ok_and(|key| key.verify(&message, &signature).is_ok())) {
+            Ok(())
+        } else {
+            bail!("the signature does not match any trusted key of the repository")
+        }
+    }
+}
+
+/// Hex encoded SHA-256 of an extension library, as listed in `index.json`
+pub fn sha256_hex(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Base64 encoded ed25519 signature of an extension library as `name` at
+/// `version` for this target, made with a base64 encoded 32 byte secret key
+pub fn sign_plugin(
+    name: &str,
+    version: &str,
+    contents: &[u8],
+    signing_key: &str,
+) -> Result<String> {
+    let message = signed_message(name, version, &sha256_hex(contents));
+    let signature = decode_signing_key(signing_key)?.sign(&message);
+
+    Ok(general_purpose::STANDARD.encode(signature.to_bytes()))
+}
+
+/// What a signature covers, one field per line
+fn signed_message(name: &str, version: &str, sha256: &str) -> Vec<u8> {
+    format!(
+        "tanoshi-extension-v1\n{name}\n{version}\n{}\n{sha256}",
+        env!("TARGET")
+    )
+    .into_bytes()
+}
+
+/// Base64 encoded public key of a secret key, to add to the trusted keys of
+/// a repository
+pub fn verifying_key(signing_key: &str) -> Result<String> {
+    let key = decode_signing_key(signing_key)?.verifying_key();
+
+    Ok(general_purpose::STANDARD.encode(key.as_bytes()))
+}
+
+fn decode_key_bytes(key: &str) -> Result<[u8; 32]> {
+    let bytes = general_purpose::STANDARD.decode(key.trim())?;
+    <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| anyhow!("an ed25519 key is 32 bytes"))
+}
+
+fn decode_signing_key(key: &str) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&decode_key_bytes(key)?))
+}
+
+fn decode_key(key: &str) -> Result<VerifyingKey> {
+    Ok(VerifyingKey::from_bytes(&decode_key_bytes(key)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_libraries_signed_by_a_trusted_key_are_accepted() {
+        let contents = b"extension library";
+        let trusted = general_purpose::STANDARD.encode([7; 32]);
+        let untrusted = general_purpose::STANDARD.encode([8; 32]);
+        let sha256 = sha256_hex(contents);
+
+        let verification = PluginVerification {
+            name: "Example".to_string(),
+            version: "1.0.0".to_string(),
+            sha256: Some(sha256.clone()),
+            signature: Some(sign_plugin("Example", "1.0.0", contents, &trusted).unwrap()),
+            trusted_keys: vec![
+                verifying_key(&untrusted).unwrap(),
+                verifying_key(&trusted).unwrap(),
+            ],
+        };
+        verification.verify(contents).unwrap();
+        assert!(verification.verify(b"tampered library").is_err());
+
+        // A library signed for another extension or version is refused.
+        for (name, version) in [("Other", "1.0.0"), ("Example", "0.9.0")] {
+            let relabeled = PluginVerification {
+                name: name.to_string(),
+                version: version.to_string(),
+                ..verification.clone()
+            };
+            assert!(relabeled.verify(contents).is_err());
+        }
+
+        let forged = PluginVerification {
+            signature: Some(sign_plugin("Example", "1.0.0", contents, &untrusted).unwrap()),
+            trusted_keys: vec![verifying_key(&trusted).unwrap()],
+            ..verification.clone()
+        };
+        assert!(forged.verify(contents).is_err());
+
+        let unsigned = PluginVerification {
+            signature: None,
+            ..verification
+        };
+        assert!(unsigned.verify(contents).is_err());
+
+        let checksum_only = PluginVerification {
+            sha256: Some(sha256),
+            ..Default::default()
+        };
+        checksum_only.verify(contents).unwrap();
+        assert!(checksum_only.verify(b"tampered library").is_err());
+        assert!(PluginVerification::default().verify(contents).is_err());
+    }
+}
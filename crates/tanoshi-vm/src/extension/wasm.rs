@@ -0,0 +1,582 @@
+//! Extensions compiled to WebAssembly instead of a native library. They are
+//! loaded by the extension worker like a native plugin, so the host talks to
+//! them through the same protocol, but they are not tied to the toolchain
+//! or CPU the server was built with and only reach the outside world through
+//! the functions below.
+//!
+//! A module exports:
+//!
+//! - `memory`
+//! - `tanoshi_alloc(len: i32) -> i32`, a buffer of `len` bytes the host
+//!   writes a request or an HTTP response into. The module owns it from then
+//!   on.
+//! - `tanoshi_declaration() -> i64`, the JSON
+//!   `{"lib_version": "...", "source_info": {...}}`
+//! - `tanoshi_call(ptr: i32, len: i32) -> i64`, which runs the JSON encoded
+//!   worker request at `ptr`, e.g. `{"GetPopularManga":{"page":1}}`, and
+//!   returns `{"Ok": value}` or `{"Err": "message"}`
+//! - `_initialize`, optionally, called once before anything else
+//!
+//! and may import `tanoshi.http_send(ptr: i32, len: i32) -> i64`, which sends
+//! the JSON encoded HTTP request at `ptr` through the host and returns
+//! `{"Ok": response}` or `{"Err": "message"}`. Byte bodies and images are
+//! base64 strings. Returned `i64`s are the pointer of a JSON buffer in the
+//! upper 32 bits and its length in the lower ones, buffers the module returns
+//! only have to stay valid until its next call.
+//!
+//! Modules may also import WASI preview 1. They get clocks, random numbers
+//! and the worker's stderr, but no files, environment or sockets.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex as StdMutex},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tanoshi_lib::{
+    http::HttpClient,
+    prelude::{
+        ChapterInfo, Credentials, Extension, Input, MangaInfo, PageInfo, Paginated, Preference,
+        PreferenceValues, ResolvedPath, SourceInfo, SourceSession,
+    },
+};
+use wasmtime::{
+    AsContextMut, Caller, Config, Engine, Extern, Linker, Memory, Module, Store, TypedFunc,
+};
+use wasmtime_wasi::{
+    WasiCtxBuilder,
+    preview1::{self, WasiP1Ctx},
+};
+
+use super::worker::{
+    HostHttpRequest, HostHttpResponse, WorkerRequest, WorkerSourceInfo, WorkerValue,
+};
+
+/// Stands in for the rustc version of native plugins, a module runs
+/// whatever toolchain built it
+pub(crate) const WASM_RUSTC_VERSION: &str = "wasm32";
+
+#[derive(Deserialize)]
+struct WasmDeclaration {
+    lib_version: String,
+    source_info: WorkerSourceInfo,
+}
+
+struct WasmState {
+    wasi: WasiP1Ctx,
+    http: Option<Arc<dyn HttpClient>>,
+}
+
+/// Exports the host needs to hand buffers to a module
+#[derive(Clone)]
+struct WasmExports {
+    memory: Memory,
+    alloc: TypedFunc<u32, u32>,
+}
+
+impl WasmExports {
+    fn of_caller(caller: &mut Caller<'_, WasmState>) -> Result<Self> {
+        let memory = caller
+            .get_export("memory")
+            .and_then(Extern::into_memory)
+            .ok_or_else(|| anyhow!("the extension exports no memory"))?;
+        let alloc = caller
+            .get_export("tanoshi_alloc")
+            .and_then(Extern::into_func)
+            .ok_or_else(|| anyhow!("the extension exports no tanoshi_alloc"))?
+            .typed(&*caller)?;
+        Ok(Self { memory, alloc })
+    }
+
+    fn read<T: DeserializeOwned>(
+        &self,
+        mut store: impl AsContextMut,
+        ptr: u32,
+        len: u32,
+    ) -> Result<T> {
+        let (ptr, len) = (ptr as usize, len as usize);
+        let bytes = self
+            .memory
+            .data(store.as_context_mut())
+            .get(ptr..ptr.saturating_add(len))
+            .ok_or_else(|| anyhow!("the extension returned a buffer outside its memory"))?;
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn read_packed<T: DeserializeOwned>(&self, store: impl AsContextMut, packed: u64) -> Result<T> {
+        self.read(store, (packed >> 32) as u32, packed as u32)
+    }
+
+    /// Copy `value` into a buffer from `tanoshi_alloc`, returning its pointer
+    /// and length
+    fn write<T: Serialize>(&self, mut store: impl AsContextMut, value: &T) -> Result<(u32, u32)> {
+        let json = serde_json::to_vec(value)?;
+        let len = u32::try_from(json.len())
+            .map_err(|_| anyhow!("{} bytes do not fit in an extension's memory", json.len()))?;
+        let ptr = self.alloc.call(store.as_context_mut(), len)?;
+        self.memory
+            .write(store.as_context_mut(), ptr as usize, &json)
+            .map_err(|_| {
+                anyhow!("tanoshi_alloc returned a buffer outside the extension's memory")
+            })?;
+        Ok((ptr, len))
+    }
+}
+
+struct WasmInstance {
+    store: Store<WasmState>,
+    exports: WasmExports,
+    call: TypedFunc<(u32, u32), u64>,
+}
+
+/// An [`Extension`] backed by a WebAssembly module. Calls into the module
+/// run one at a time.
+pub(crate) struct WasmExtension {
+    source_info: SourceInfo,
+    instance: StdMutex<WasmInstance>,
+}
+
+impl WasmExtension {
+    /// Compile and instantiate the module at `path`, its HTTP requests go
+    /// through `http`
+    pub(crate) fn load(path: &Path, http: Option<Arc<dyn HttpClient>>) -> Result<Self> {
+        let mut config = Config::new();
+        // Memories grow in place of a 4 GiB reservation each, which the
+        // worker's address space limit would refuse.
+        config.memory_reservation(0).memory_guard_size(0);
+        let engine = Engine::new(&config)?;
+        let wasm = std::fs::read(path)?;
+        let module = Module::new(&engine, &wasm)
+            .with_context(|| format!("{} is not a valid WebAssembly module", path.display()))?;
+
+        let mut linker = Linker::new(&engine);
+        preview1::add_to_linker_sync(&mut linker, |state: &mut WasmState| &mut state.wasi)?;
+        linker.func_wrap(
+            "tanoshi",
+            "http_send",
+            |mut caller: Caller<'_, WasmState>, ptr: u32, len: u32| -> Result<u64> {
+                let exports = WasmExports::of_caller(&mut caller)?;
+                let request: HostHttpRequest = exports.read(&mut caller, ptr, len)?;
+                let result = match caller.data().http.clone() {
+                    Some(http) => http
+                        .send(request.into())
+                        .map(HostHttpResponse::from)
+                        .map_err(|error| format!("{error:#}")),
+                    None => Err("the extension has no HTTP client".to_string()),
+                };
+                let (ptr, len) = exports.write(&mut caller, &result)?;
+                Ok(((ptr as u64) << 32) | len as u64)
+            },
+        )?;
+
+        let state = WasmState {
+            wasi: WasiCtxBuilder::new().inherit_stderr().build_p1(),
+            http,
+        };
+        let mut store = Store::new(&engine, state);
+        let instance = linker.instantiate(&mut store, &module)?;
+        if let Some(initialize) = instance.get_func(&mut store, "_initialize") {
+            initialize.typed::<(), ()>(&store)?.call(&mut store, ())?;
+        }
+        let exports = WasmExports {
+            memory: instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| anyhow!("the extension exports no memory"))?,
+            alloc: instance.get_typed_func(&mut store, "tanoshi_alloc")?,
+        };
+        let declaration = instance
+            .get_typed_func::<(), u64>(&mut store, "tanoshi_declaration")?
+            .call(&mut store, ())?;
+        let declaration: WasmDeclaration = exports.read_packed(&mut store, declaration)?;
+        if declaration.lib_version != tanoshi_lib::LIB_VERSION {
+            bail!(
+                "Version mismatch: extension.lib_version={} != tanoshi_lib.lib_version={}",
+                declaration.lib_version,
+                tanoshi_lib::LIB_VERSION
+            );
+        }
+        let call = instance.get_typed_func(&mut store, "tanoshi_call")?;
+
+        Ok(Self {
+            source_info: declaration.source_info.into_source_info(),
+            instance: StdMutex::new(WasmInstance {
+                store,
+                exports,
+                call,
+            }),
+        })
+    }
+
+    fn call(&self, request: WorkerRequest) -> Result<WorkerValue> {
+        let mut instance = self
+            .instance
+            .lock()
+            .map_err(|error| anyhow!("extension instance lock is poisoned: {error}"))?;
+        let WasmInstance {
+            store,
+            exports,
+            call,
+        } = &mut *instance;
+        let (ptr, len) = exports.write(&mut *store, &request)?;
+        let packed = call.call(&mut *store, (ptr, len))?;
+        let result: std::result::Result<WorkerValue, String> =
+            exports.read_packed(&mut *store, packed)?;
+        result.map_err(|message| anyhow!(message))
+    }
+}
+
+fn unexpected<T>(value: WorkerValue) -> Result<T> {
+    bail!("the extension answered with an unexpected {value:?}")
+}
+
+impl Extension for WasmExtension {
+    fn get_source_info(&self) -> SourceInfo {
+        self.source_info.clone()
+    }
+
+    fn filter_list(&self) -> Vec<Input> {
+        match self.call(WorkerRequest::FilterList) {
+            Ok(WorkerValue::Inputs(inputs)) => inputs,
+            Ok(value) => {
+                error!(
+                    "{} filter list: unexpected {value:?}",
+                    self.source_info.name
+                );
+                vec![]
+            }
+            Err(error) => {
+                error!("{} filter list: {error}", self.source_info.name);
+                vec![]
+            }
+        }
+    }
+
+    fn get_preferences(&self) -> Result<Vec<Input>> {
+        match self.call(WorkerRequest::GetPreferences)? {
+            WorkerValue::Inputs(inputs) => Ok(inputs),
+            value => unexpected(value),
+        }
+    }
+
+    fn set_preferences(&mut self, preferences: Vec<Input>) -> Result<()> {
+        match self.call(WorkerRequest::SetPreferences { preferences })? {
+            WorkerValue::Unit => Ok(()),
+            value => unexpected(value),
+        }
+    }
+
+    fn get_popular_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.get_popular_manga_paged(page).map(|page| page.items)
+    }
+
+    fn get_latest_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.get_latest_manga_paged(page).map(|page| page.items)
+    }
+
+    fn search_manga(
+        &self,
+        page: i64,
+        query: Option<String>,
+        filters: Option<Vec<Input>>,
+    ) -> Result<Vec<MangaInfo>> {
+        self.search_manga_paged(page, query, filters)
+            .map(|page| page.items)
+    }
+
+    fn get_manga_detail(&self, path: String) -> Result<MangaInfo> {
+        match self.call(WorkerRequest::GetMangaDetail { path })? {
+            WorkerValue::Manga(manga) => Ok(manga),
+            value => unexpected(value),
+        }
+    }
+
+    fn get_chapters(&self, path: String) -> Result<Vec<ChapterInfo>> {
+        match self.call(WorkerRequest::GetChapters { path })? {
+            WorkerValue::Chapters(chapters) => Ok(chapters),
+            value => unexpected(value),
+        }
+    }
+
+    fn get_pages(&self, path: String) -> Result<Vec<String>> {
+        self.get_page_list(path)
+            .map(|pages| pages.into_iter().map(|page| page.url).collect())
+    }
+
+    fn get_image_bytes(&self, url: String) -> Result<Bytes> {
+        self.get_page_bytes(PageInfo::from(url))
+    }
+
+    fn get_popular_manga_paged(&self, page: i64) -> Result<Paginated<MangaInfo>> {
+        match self.call(WorkerRequest::GetPopularManga { page })? {
+            WorkerValue::MangaPage(page) => Ok(page),
+            value => unexpected(value),
+        }
+    }
+
+    fn get_latest_manga_paged(&self, page: i64) -> Result<Paginated<MangaInfo>> {
+        match self.call(WorkerRequest::GetLatestManga { page })? {
+            WorkerValue::MangaPage(page) => Ok(page),
+            value => unexpected(value),
+        }
+    }
+
+    fn search_manga_paged(
+        &self,
+        page: i64,
+        query: Option<String>,
+        filters: Option<Vec<Input>>,
+    ) -> Result<Paginated<MangaInfo>> {
+        match self.call(WorkerRequest::SearchManga {
+            page,
+            query,
+            filters,
+        })? {
+            WorkerValue::MangaPage(page) => Ok(page),
+            value => unexpected(value),
+        }
+    }
+
+    fn get_page_list(&self, path: String) -> Result<Vec<PageInfo>> {
+        match self.call(WorkerRequest::GetPages { path })? {
+            WorkerValue::Pages(pages) => Ok(pages),
+            value => unexpected(value),
+        }
+    }
+
+    /// The module fetches and post-processes the page in one call, so there
+    /// is nothing left for [`Extension::process_image`] to do
+    fn get_page_bytes(&self, page: PageInfo) -> Result<Bytes> {
+        match self.call(WorkerRequest::GetImageBytes { page })? {
+            WorkerValue::Image { bytes } => Ok(bytes),
+            value => unexpected(value),
+        }
+    }
+
+    fn get_related_manga(&self, path: String) -> Result<Vec<MangaInfo>> {
+        match self.call(WorkerRequest::GetRelatedManga { path })? {
+            WorkerValue::MangaList(manga) => Ok(manga),
+            value => unexpected(value),
+        }
+    }
+
+    fn resolve_url(&self, url: String) -> Result<Option<ResolvedPath>> {
+        match self.call(WorkerRequest::ResolveUrl { url })? {
+            WorkerValue::ResolvedPath(resolved) => Ok(resolved),
+            value => unexpected(value),
+        }
+    }
+
+    fn preference_schema(&self) -> Vec<Preference> {
+        match self.call(WorkerRequest::GetPreferenceSchema) {
+            Ok(WorkerValue::PreferenceSchema(schema)) => schema,
+            Ok(value) => {
+                error!(
+                    "{} preference schema: unexpected {value:?}",
+                    self.source_info.name
+                );
+                vec![]
+            }
+            Err(error) => {
+                error!("{} preference schema: {error}", self.source_info.name);
+                vec![]
+            }
+        }
+    }
+
+    fn set_preference_values(&mut self, values: PreferenceValues) -> Result<()> {
+        match self.call(WorkerRequest::SetPreferenceValues { values })? {
+            WorkerValue::Unit => Ok(()),
+            value => unexpected(value),
+        }
+    }
+
+    fn login(&mut self, credentials: Credentials) -> Result<SourceSession> {
+        match self.call(WorkerRequest::Login { credentials })? {
+            WorkerValue::Session(session) => Ok(session),
+            value => unexpected(value),
+        }
+    }
+
+    fn restore_session(&mut self, session: SourceSession) -> Result<()> {
+        match self.call(WorkerRequest::RestoreSession { session })? {
+            WorkerValue::Unit => Ok(()),
+            value => unexpected(value),
+        }
+    }
+
+    fn logout(&mut self) -> Result<()> {
+        match self.call(WorkerRequest::Logout)? {
+            WorkerValue::Unit => Ok(()),
+            value => unexpected(value),
+        }
+    }
+
+    fn is_logged_in(&self) -> Result<bool> {
+        match self.call(WorkerRequest::IsLoggedIn)? {
+            WorkerValue::Bool(logged_in) => Ok(logged_in),
+            value => unexpected(value),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tanoshi_lib::{
+        http::{Request, Response},
+        prelude::Lang,
+    };
+
+    use super::*;
+
+    /// A module declaring itself built against `lib_version`. Every call
+    /// sends a request to `https://example.com/pages` through the host and
+    /// lists one page, or fails with "the source is down" when the request
+    /// does.
+    pub(crate) fn test_module(lib_version: &str) -> Vec<u8> {
+        let source_info = SourceInfo {
+            id: 7,
+            name: "Wasm Source".to_string(),
+            url: "https://example.com".to_string(),
+            version: "0.1.0",
+            icon: "",
+            languages: Lang::All,
+            nsfw: false,
+            ..Default::default()
+        };
+        let declaration = serde_json::json!({
+            "lib_version": lib_version,
+            "source_info": WorkerSourceInfo::from(&source_info),
+        })
+        .to_string();
+        let request = serde_json::to_string(&HostHttpRequest::from(Request::get(
+            "https://example.com/pages",
+        )))
+        .unwrap();
+        let pages: std::result::Result<_, String> = Ok(WorkerValue::Pages(vec![PageInfo::from(
+            "https://example.com/1.jpg".to_string(),
+        )]));
+        let pages = serde_json::to_string(&pages).unwrap();
+        let failed = serde_json::to_string(&std::result::Result::<WorkerValue, _>::Err(
+            "the source is down",
+        ))
+        .unwrap();
+        let data = |offset: usize, json: &str| {
+            format!(
+                "(data (i32.const {offset}) \"{}\")",
+                json.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        };
+
+        wat::parse_str(format!(
+            r#"(module
+                (import "tanoshi" "http_send" (func $http_send (param i32 i32) (result i64)))
+                (memory (export "memory") 1)
+                (global $next (mut i32) (i32.const 4096))
+                {}
+                {}
+                {}
+                {}
+                (func $packed (param $ptr i32) (param $len i32) (result i64)
+                    (i64.or
+                        (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                        (i64.extend_i32_u (local.get $len))))
+                (func (export "tanoshi_alloc") (param $len i32) (result i32)
+                    (local $ptr i32)
+                    (local.set $ptr (global.get $next))
+                    (global.set $next (i32.add (local.get $ptr) (local.get $len)))
+                    (if (i32.gt_u (global.get $next) (i32.mul (memory.size) (i32.const 65536)))
+                        (then (drop (memory.grow (i32.add
+                            (i32.div_u (local.get $len) (i32.const 65536))
+                            (i32.const 1))))))
+                    (local.get $ptr))
+                (func (export "tanoshi_declaration") (result i64)
+                    (call $packed (i32.const 0) (i32.const {})))
+                (func (export "tanoshi_call") (param $ptr i32) (param $len i32) (result i64)
+                    (local $response i64)
+                    (local.set $response (call $http_send (i32.const 1024) (i32.const {})))
+                    ;; an `{{"Err": ...}}` response
+                    (if (result i64)
+                        (i32.eq
+                            (i32.load8_u offset=2
+                                (i32.wrap_i64 (i64.shr_u (local.get $response) (i64.const 32))))
+                            (i32.const 69))
+                        (then (call $packed (i32.const 3072) (i32.const {})))
+                        (else (call $packed (i32.const 2048) (i32.const {}))))))"#,
+            data(0, &declaration),
+            data(1024, &request),
+            data(2048, &pages),
+            data(3072, &failed),
+            declaration.len(),
+            request.len(),
+            failed.len(),
+            pages.len(),
+        ))
+        .unwrap()
+    }
+
+    #[derive(Default)]
+    pub(crate) struct RecordingHttp {
+        pub(crate) urls: StdMutex<Vec<String>>,
+        pub(crate) fail: bool,
+    }
+
+    impl HttpClient for RecordingHttp {
+        fn send(&self, request: Request) -> Result<Response> {
+            self.urls.lock().unwrap().push(request.url);
+            if self.fail {
+                bail!("connection refused");
+            }
+            Ok(Response {
+                status: 200,
+                headers: vec![],
+                body: Bytes::new(),
+            })
+        }
+    }
+
+    pub(crate) fn write_module(name: &str, wasm: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "tanoshi-vm-{name}-{}.{}",
+            std::process::id(),
+            crate::WASM_PLUGIN_EXTENSION
+        ));
+        std::fs::write(&path, wasm).unwrap();
+        path
+    }
+
+    #[test]
+    fn calls_reach_the_module_and_its_requests_go_through_the_host() {
+        let path = write_module("calls", &test_module(tanoshi_lib::LIB_VERSION));
+        let http = Arc::new(RecordingHttp::default());
+        let extension = WasmExtension::load(&path, Some(http.clone())).unwrap();
+        assert_eq!(extension.get_source_info().id, 7);
+        assert_eq!(extension.get_source_info().name, "Wasm Source");
+
+        let pages = extension.get_pages("/manga/1".to_string()).unwrap();
+        assert_eq!(pages, ["https://example.com/1.jpg"]);
+        assert_eq!(*http.urls.lock().unwrap(), ["https://example.com/pages"]);
+
+        let down = Arc::new(RecordingHttp {
+            fail: true,
+            ..Default::default()
+        });
+        let extension = WasmExtension::load(&path, Some(down)).unwrap();
+        let error = extension.get_page_list("/manga/1".to_string()).unwrap_err();
+        assert_eq!(error.to_string(), "the source is down");
+        // An answer that does not fit the call is an error, not a panic.
+        assert!(extension.is_logged_in().is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn modules_built_against_another_lib_version_are_refused() {
+        let path = write_module("lib-version", &test_module("0.0.1"));
+        let error = WasmExtension::load(&path, None).err().unwrap().to_string();
+        assert!(error.contains("lib_version=0.0.1"), "{error}");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,25 +1,64 @@
 use std::{
     io::{self, BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
+    pin::Pin,
     process::Stdio,
     sync::{Arc, Mutex as StdMutex},
     time::Duration,
 };
 
 use anyhow::{Context, Result, anyhow, bail};
+use bytes::Bytes;
+use fnv::FnvHashMap;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use std::sync::atomic::{AtomicBool, Ordering};
-use tanoshi_lib::prelude::{ChapterInfo, Input, MangaInfo, PluginDeclaration, SourceInfo};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use tanoshi_lib::prelude::{
+    Capabilities, ChapterInfo, Credentials, Input, MangaInfo, PageInfo, Paginated,
+    PluginDeclaration, Preference, PreferenceValues, RateLimit, ResolvedPath, SourceInfo,
+    SourceSession,
+};
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader as AsyncBufReader},
     process::{Child, ChildStdin, ChildStdout, Command},
-    sync::{Mutex, Notify},
+    sync::{Mutex, MutexGuard, Notify, Semaphore, futures::Notified, mpsc, oneshot},
+    task::JoinHandle,
     time::Instant,
 };
 
-use super::{Source, SourceEntry, source::panic_payload_message};
+use super::{
+    Source, SourceEntry,
+    http::{HostHttp, SourceHttp},
+    sandbox::{SANDBOX_ENV, Sandbox, SandboxLayerStatus, WorkerSandbox},
+    source::{SourceHealth, panic_payload_message},
+    wasm::{WASM_RUSTC_VERSION, WasmExtension},
+};
+use crate::WASM_PLUGIN_EXTENSION;
 
-const PROTOCOL_VERSION: u32 = 1;
+/// Raise whenever a [`WorkerRequest`], [`WorkerResponse`], [`WorkerValue`]
+/// or [`WorkerSourceInfo`] changes, and gate what older workers lack on the
+/// version they announce, see [`WorkerRequest::since`]
+const PROTOCOL_VERSION: u32 = 6;
+/// Oldest worker protocol the host still speaks, such workers use JSON frames
+const MIN_PROTOCOL_VERSION: u32 = 2;
+/// First protocol version sure to understand url resolution, related manga,
+/// typed preferences and logins. They were added while workers still
+/// announced protocol 2, so the host doesn't send them to such workers.
+const SOURCE_REQUESTS_PROTOCOL_VERSION: u32 = 3;
+/// First protocol version whose frames carry byte payloads raw instead of
+/// base64 inside the JSON
+const BINARY_PROTOCOL_VERSION: u32 = 3;
+/// First protocol version that understands [`WorkerRequest::StreamImage`]
+pub(crate) const IMAGE_STREAM_PROTOCOL_VERSION: u32 = 4;
+/// First protocol version whose workers send their HTTP through the host
+const HOST_HTTP_PROTOCOL_VERSION: u32 = 6;
+/// How many requests a worker runs at once
+const WORKER_MAX_IN_FLIGHT: u32 = 8;
+/// Size of the chunks a streamed image is split into
+const IMAGE_CHUNK_SIZE: usize = 256 * 1024;
+/// How many chunks of a streamed response are buffered for a slow reader.
+/// A reader further behind has its call cancelled, the connection never
+/// waits for it.
+pub(crate) const CHUNK_BUFFER: usize = 64;
 const MAX_FRAME_SIZE: usize = 128 * 1024 * 1024;
 const WORKER_BINARY_NAME: &str = "tanoshi-extension-worker";
 pub const WORKER_MODE_FLAG: &str = "--tanoshi-extension-worker";
@@ -31,6 +70,18 @@ pub(crate) enum WorkerRequest {
     SetPreferences {
         preferences: Vec<Input>,
     },
+    GetPreferenceSchema,
+    SetPreferenceValues {
+        values: PreferenceValues,
+    },
+    Login {
+        credentials: Credentials,
+    },
+    RestoreSession {
+        session: SourceSession,
+    },
+    Logout,
+    IsLoggedIn,
     GetPopularManga {
         page: i64,
     },
@@ -48,14 +99,190 @@ pub(crate) enum WorkerRequest {
     GetChapters {
         path: String,
     },
+    GetRelatedManga {
+        path: String,
+    },
+    ResolveUrl {
+        url: String,
+    },
     GetPages {
         path: String,
     },
     GetImageBytes {
-        url: String,
+        page: PageInfo,
+    },
+    /// Like `GetImageBytes`, but the image comes back as `Chunk` responses
+    /// ended by a `Unit` result, so no frame has to hold the whole image.
+    StreamImage {
+        page: PageInfo,
+    },
+    /// Answer to a [`WorkerResponse::HttpRequest`], sent with the id the
+    /// worker gave that request
+    HttpResponse {
+        result: std::result::Result<HostHttpResponse, String>,
     },
 }
 
+impl WorkerRequest {
+    /// First protocol version that understands this request
+    fn since(&self) -> u32 {
+        match self {
+            WorkerRequest::FilterList
+            | WorkerRequest::GetPreferences
+            | WorkerRequest::SetPreferences { .. }
+            | WorkerRequest::GetPopularManga { .. }
+            | WorkerRequest::GetLatestManga { .. }
+            | WorkerRequest::SearchManga { .. }
+            | WorkerRequest::GetMangaDetail { .. }
+            | WorkerRequest::GetChapters { .. }
+            | WorkerRequest::GetPages { .. }
+            | WorkerRequest::GetImageBytes { .. } => MIN_PROTOCOL_VERSION,
+            WorkerRequest::GetPreferenceSchema
+            | WorkerRequest::SetPreferenceValues { .. }
+            | WorkerRequest::Login { .. }
+            | WorkerRequest::RestoreSession { .. }
+            | WorkerRequest::Logout
+            | WorkerRequest::IsLoggedIn
+            | WorkerRequest::GetRelatedManga { .. }
+            | WorkerRequest::ResolveUrl { .. } => SOURCE_REQUESTS_PROTOCOL_VERSION,
+            WorkerRequest::StreamImage { .. } => IMAGE_STREAM_PROTOCOL_VERSION,
+            WorkerRequest::HttpResponse { .. } => HOST_HTTP_PROTOCOL_VERSION,
+        }
+    }
+
+    /// Error for a request the worker's protocol predates, the worker would
+    /// fail to read it and exit
+    fn unsupported(&self, protocol_version: u32) -> Option<String> {
+        let since = self.since();
+        (protocol_version < since).then(|| {
+            format!(
+                "extension worker speaks protocol {protocol_version}, the request needs protocol {since}"
+            )
+        })
+    }
+}
+
+/// An HTTP request an extension sends through the host, see
+/// [`tanoshi_lib::http`]
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HostHttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    #[serde(default, with = "base64_bytes")]
+    body: Vec<u8>,
+    /// Whether `body` was set, an empty body still makes it a request with
+    /// a body
+    #[serde(default)]
+    has_body: bool,
+}
+
+impl From<tanoshi_lib::http::Request> for HostHttpRequest {
+    fn from(request: tanoshi_lib::http::Request) -> Self {
+        Self {
+            method: request.method,
+            url: request.url,
+            headers: request.headers,
+            has_body: request.body.is_some(),
+            body: request.body.map(|body| body.to_vec()).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<HostHttpRequest> for tanoshi_lib::http::Request {
+    fn from(request: HostHttpRequest) -> Self {
+        Self {
+            method: request.method,
+            url: request.url,
+            headers: request.headers,
+            body: request.has_body.then(|| Bytes::from(request.body)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HostHttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    #[serde(with = "base64_bytes")]
+    body: Vec<u8>,
+}
+
+impl From<tanoshi_lib::http::Response> for HostHttpResponse {
+    fn from(response: tanoshi_lib::http::Response) -> Self {
+        Self {
+            status: response.status,
+            headers: response.headers,
+            body: response.body.to_vec(),
+        }
+    }
+}
+
+impl From<HostHttpResponse> for tanoshi_lib::http::Response {
+    fn from(response: HostHttpResponse) -> Self {
+        Self {
+            status: response.status,
+            headers: response.headers,
+            body: Bytes::from(response.body),
+        }
+    }
+}
+
+/// Where the chunks of a streamed response are forwarded to, bounded by
+/// [`CHUNK_BUFFER`]
+pub(crate) type ChunkSender = mpsc::Sender<Result<Bytes>>;
+
+/// A request to a worker, with the sink for its chunks when the response
+/// is streamed
+pub(crate) struct WorkerCall {
+    pub request: WorkerRequest,
+    pub chunks: Option<ChunkSender>,
+}
+
+impl From<WorkerRequest> for WorkerCall {
+    fn from(request: WorkerRequest) -> Self {
+        Self {
+            request,
+            chunks: None,
+        }
+    }
+}
+
+/// How frames after `Ready` are encoded, picked from the protocol version the
+/// worker announces. `Ready` itself is always a JSON frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FrameEncoding {
+    /// `[length][json]`, byte payloads are base64 strings inside the JSON
+    Json,
+    /// `[length][header length][json header][payload]`, byte payloads are
+    /// moved out of the header and sent raw after it
+    Binary,
+}
+
+impl FrameEncoding {
+    fn for_protocol(protocol_version: u32) -> Self {
+        if protocol_version >= BINARY_PROTOCOL_VERSION {
+            Self::Binary
+        } else {
+            Self::Json
+        }
+    }
+}
+
+/// Messages that can carry a byte payload outside their JSON header
+trait FramePayload: Sized {
+    fn take_payload(&mut self) -> Bytes {
+        Bytes::new()
+    }
+
+    fn set_payload(&mut self, _payload: Bytes) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "worker frame carries a payload its header has no slot for",
+        ))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct WorkerRequestEnvelope {
     id: u64,
@@ -66,19 +293,112 @@ struct WorkerRequestEnvelope {
 pub(crate) enum WorkerResponse {
     Ready {
         protocol_version: u32,
+        /// How many requests the worker runs at once, workers before
+        /// protocol 5 handle one at a time
+        #[serde(default = "default_max_in_flight")]
+        max_in_flight: u32,
         source_info: WorkerSourceInfo,
         rustc_version: String,
         lib_version: String,
+        /// Sandbox layers the worker applied to itself, empty when it runs
+        /// unsandboxed
+        #[serde(default)]
+        sandbox: Vec<SandboxLayerStatus>,
     },
     Result {
         id: u64,
         value: WorkerValue,
     },
+    /// Part of a streamed response, always followed by a `Result` or `Error`
+    /// with the same id
+    Chunk {
+        id: u64,
+        #[serde(with = "base64_bytes")]
+        bytes: Bytes,
+    },
     Error {
         id: u64,
         kind: WorkerErrorKind,
         message: String,
     },
+    /// An HTTP request the extension makes through the host, answered with a
+    /// [`WorkerRequest::HttpResponse`] under the same id. Worker and host
+    /// requests are numbered separately.
+    HttpRequest {
+        id: u64,
+        request: HostHttpRequest,
+    },
+}
+
+impl FramePayload for WorkerRequestEnvelope {
+    fn take_payload(&mut self) -> Bytes {
+        match &mut self.request {
+            WorkerRequest::HttpResponse {
+                result: Ok(response),
+            } => Bytes::from(std::mem::take(&mut response.body)),
+            _ => Bytes::new(),
+        }
+    }
+
+    fn set_payload(&mut self, payload: Bytes) -> io::Result<()> {
+        match &mut self.request {
+            WorkerRequest::HttpResponse {
+                result: Ok(response),
+            } => {
+                response.body = Vec::from(payload);
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "worker frame carries a payload its header has no slot for",
+            )),
+        }
+    }
+}
+
+impl FramePayload for WorkerResponse {
+    fn take_payload(&mut self) -> Bytes {
+        match self {
+            WorkerResponse::Result {
+                value: WorkerValue::Image { bytes },
+                ..
+            }
+            | WorkerResponse::Chunk { bytes, .. } => std::mem::take(bytes),
+            WorkerResponse::HttpRequest {
+                request: HostHttpRequest { body, .. },
+                ..
+            } => Bytes::from(std::mem::take(body)),
+            _ => Bytes::new(),
+        }
+    }
+
+    fn set_payload(&mut self, payload: Bytes) -> io::Result<()> {
+        match self {
+            WorkerResponse::Result {
+                value: WorkerValue::Image { bytes },
+                ..
+            }
+            | WorkerResponse::Chunk { bytes, .. } => {
+                *bytes = payload;
+                Ok(())
+            }
+            WorkerResponse::HttpRequest {
+                request: HostHttpRequest { body, .. },
+                ..
+            } => {
+                *body = Vec::from(payload);
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "worker frame carries a payload its header has no slot for",
+            )),
+        }
+    }
+}
+
+fn default_max_in_flight() -> u32 {
+    1
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -92,13 +412,19 @@ pub(crate) enum WorkerErrorKind {
 pub(crate) enum WorkerValue {
     Unit,
     Inputs(Vec<Input>),
+    PreferenceSchema(Vec<Preference>),
+    Session(SourceSession),
+    Bool(bool),
+    /// Bare manga list, for related manga
     MangaList(Vec<MangaInfo>),
+    MangaPage(Paginated<MangaInfo>),
     Manga(MangaInfo),
     Chapters(Vec<ChapterInfo>),
-    Pages(Vec<String>),
+    ResolvedPath(Option<ResolvedPath>),
+    Pages(Vec<PageInfo>),
     Image {
         #[serde(with = "base64_bytes")]
-        bytes: Vec<u8>,
+        bytes: Bytes,
     },
 }
 
@@ -111,6 +437,11 @@ pub(crate) struct WorkerSourceInfo {
     pub icon: String,
     pub languages: tanoshi_lib::prelude::Lang,
     pub nsfw: bool,
+    /// Missing from protocol 2 workers built before capabilities
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
 }
 
 impl From<&SourceInfo> for WorkerSourceInfo {
@@ -123,6 +454,8 @@ impl From<&SourceInfo> for WorkerSourceInfo {
             icon: source_info.icon.to_string(),
             languages: source_info.languages.clone(),
             nsfw: source_info.nsfw,
+            capabilities: source_info.capabilities,
+            rate_limit: source_info.rate_limit,
         }
     }
 }
@@ -137,6 +470,8 @@ impl WorkerSourceInfo {
             icon: Box::leak(self.icon.into_boxed_str()),
             languages: self.languages,
             nsfw: self.nsfw,
+            capabilities: self.capabilities,
+            rate_limit: self.rate_limit,
         }
     }
 }
@@ -145,12 +480,15 @@ impl WorkerSourceInfo {
 pub(crate) enum WorkerCallError {
     /// The worker held the request past the deadline and was terminated.
     Timeout,
-    /// The deadline expired while earlier calls still occupied the worker
-    /// stream; the worker itself was left untouched.
+    /// The deadline expired while earlier calls still occupied every slot
+    /// on the worker; the worker itself was left untouched.
     QueueTimeout,
     /// The client was explicitly shut down because its source was unloaded
     /// or replaced.
     Stopped,
+    /// Another call held the shared worker past its deadline, and terminating
+    /// the worker took this call with it.
+    Interrupted,
     Crashed(String),
     Remote {
         kind: WorkerErrorKind,
@@ -166,6 +504,9 @@ impl std::fmt::Display for WorkerCallError {
                 formatter.write_str("extension worker is busy with earlier calls")
             }
             Self::Stopped => formatter.write_str("extension worker was shut down"),
+            Self::Interrupted => {
+                formatter.write_str("extension worker was terminated after another call timed out")
+            }
             Self::Crashed(message) => write!(formatter, "extension worker exited: {message}"),
             Self::Remote { kind, message } => {
                 write!(formatter, "extension worker returned {kind:?}: {message}")
@@ -176,27 +517,435 @@ impl std::fmt::Display for WorkerCallError {
 
 impl std::error::Error for WorkerCallError {}
 
+/// A freshly spawned worker, used for the handshake and the startup requests
+/// before it is shared as a [`WorkerConnection`].
 struct WorkerProcess {
     child: Child,
+    pid: Option<u32>,
+    generation: u64,
     stdin: ChildStdin,
     stdout: AsyncBufReader<ChildStdout>,
+    protocol_version: u32,
+    encoding: FrameEncoding,
     next_request_id: u64,
+    max_in_flight: usize,
+    source_info: WorkerSourceInfo,
+    rustc_version: String,
+    lib_version: String,
+}
+
+/// Why a connection stopped serving requests
+#[derive(Clone, Debug)]
+enum ConnectionClosed {
+    /// The worker exited or broke the protocol.
+    Crashed(String),
+    /// A call held the worker past its deadline and the worker was killed.
+    Terminated,
+    /// The client was shut down.
+    Stopped,
+}
+
+impl ConnectionClosed {
+    fn call_error(self) -> WorkerCallError {
+        match self {
+            Self::Crashed(message) => WorkerCallError::Crashed(message),
+            Self::Terminated => WorkerCallError::Interrupted,
+            Self::Stopped => WorkerCallError::Stopped,
+        }
+    }
+}
+
+struct PendingCall {
+    // `None` once the call was cancelled, the rest of its stream is
+    // discarded until the worker ends it.
+    response: Option<oneshot::Sender<WorkerResponse>>,
+    chunks: Option<ChunkSender>,
+}
+
+/// A running worker shared by concurrent calls. Requests are written under a
+/// lock and a reader task routes every response to its caller by id, so up
+/// to `max_in_flight` calls run at once.
+struct WorkerConnection {
+    child: Mutex<Child>,
+    pid: Option<u32>,
+    stdin: Mutex<ChildStdin>,
+    protocol_version: u32,
+    encoding: FrameEncoding,
+    // Startup state generation the worker was spawned with.
+    generation: u64,
+    next_request_id: AtomicU64,
+    max_in_flight: usize,
+    in_flight: Arc<Semaphore>,
+    // Calls routed to this worker that have not finished yet, queued or
+    // running. Only incremented under the pool lock.
+    assigned: AtomicUsize,
+    last_used: StdMutex<Instant>,
+    drained: Notify,
+    health: Arc<SourceHealth>,
+    // Serves the HTTP requests the extension sends through the host.
+    http: Option<Arc<SourceHttp>>,
+    pending: StdMutex<FnvHashMap<u64, PendingCall>>,
+    closed: StdMutex<Option<ConnectionClosed>>,
+    reader: StdMutex<Option<JoinHandle<()>>>,
     source_info: WorkerSourceInfo,
     rustc_version: String,
     lib_version: String,
 }
 
+impl WorkerConnection {
+    fn start(process: WorkerProcess, http: Option<Arc<SourceHttp>>) -> Arc<Self> {
+        let WorkerProcess {
+            child,
+            pid,
+            generation,
+            stdin,
+            stdout,
+            protocol_version,
+            encoding,
+            next_request_id,
+            max_in_flight,
+            source_info,
+            rustc_version,
+            lib_version,
+        } = process;
+        let connection = Arc::new(Self {
+            child: Mutex::new(child),
+            pid,
+            stdin: Mutex::new(stdin),
+            protocol_version,
+            encoding,
+            generation,
+            next_request_id: AtomicU64::new(next_request_id),
+            max_in_flight,
+            in_flight: Arc::new(Semaphore::new(max_in_flight)),
+            assigned: AtomicUsize::new(0),
+            last_used: StdMutex::new(Instant::now()),
+            drained: Notify::new(),
+            health: SourceHealth::new(),
+            http,
+            pending: StdMutex::new(FnvHashMap::default()),
+            closed: StdMutex::new(None),
+            reader: StdMutex::new(None),
+            source_info,
+            rustc_version,
+            lib_version,
+        });
+        let reader = tokio::spawn(Self::read_responses(connection.clone(), stdout));
+        *lock_unpoisoned(&connection.reader) = Some(reader);
+        connection
+    }
+
+    fn closed(&self) -> Option<ConnectionClosed> {
+        lock_unpoisoned(&self.closed).clone()
+    }
+
+    fn assigned(&self) -> usize {
+        self.assigned.load(Ordering::Acquire)
+    }
+
+    fn is_full(&self) -> bool {
+        self.assigned() >= self.max_in_flight
+    }
+
+    fn idle_for(&self, now: Instant) -> Option<Duration> {
+        if self.assigned() > 0 {
+            return None;
+        }
+        Some(now.saturating_duration_since(*lock_unpoisoned(&self.last_used)))
+    }
+
+    fn assign(self: &Arc<Self>) -> Assignment {
+        self.assigned.fetch_add(1, Ordering::AcqRel);
+        Assignment {
+            connection: self.clone(),
+        }
+    }
+
+    /// Stop the worker once the calls already routed to it have finished.
+    /// The caller has removed it from the pool, so none are added.
+    fn retire(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                let drained = self.drained.notified();
+                tokio::pin!(drained);
+                drained.as_mut().enable();
+                if self.assigned() == 0 {
+                    break;
+                }
+                drained.await;
+            }
+            self.terminate(ConnectionClosed::Stopped).await;
+        });
+    }
+
+    fn next_request_id(&self) -> u64 {
+        self.next_request_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Track a call before its request is written, so its response cannot
+    /// arrive first.
+    fn register(&self, id: u64, call: PendingCall) -> std::result::Result<(), ConnectionClosed> {
+        let mut pending = lock_unpoisoned(&self.pending);
+        // Checked under the pending lock: `close` sets the reason before it
+        // drains, so a call is either refused here or drained there.
+        if let Some(closed) = self.closed() {
+            return Err(closed);
+        }
+        pending.insert(id, call);
+        Ok(())
+    }
+
+    /// Mark the connection closed, the first reason wins, and fail every
+    /// pending call by dropping its response sender.
+    fn close(&self, reason: ConnectionClosed) {
+        lock_unpoisoned(&self.closed).get_or_insert(reason);
+        let pending = std::mem::take(&mut *lock_unpoisoned(&self.pending));
+        drop(pending);
+    }
+
+    async fn terminate(&self, reason: ConnectionClosed) {
+        self.close(reason);
+        let mut child = self.child.lock().await;
+        let _ = child.kill().await;
+        let _ = child.wait().await;
+    }
+
+    /// Best-effort synchronous teardown for `Drop`.
+    fn kill_now(&self) {
+        self.close(ConnectionClosed::Stopped);
+        if let Ok(mut child) = self.child.try_lock() {
+            let _ = child.start_kill();
+        }
+        if let Some(reader) = lock_unpoisoned(&self.reader).take() {
+            reader.abort();
+        }
+    }
+
+    async fn write(&self, envelope: WorkerRequestEnvelope) -> io::Result<()> {
+        let mut stdin = self.stdin.lock().await;
+        write_frame_async(&mut *stdin, envelope, self.encoding).await
+    }
+
+    async fn read_responses(connection: Arc<Self>, mut stdout: AsyncBufReader<ChildStdout>) {
+        let reason = loop {
+            let response =
+                match read_frame_async::<_, WorkerResponse>(&mut stdout, connection.encoding).await
+                {
+                    Ok(response) => response,
+                    Err(error) => break error.to_string(),
+                };
+            if let Err(message) = connection.dispatch(response) {
+                break message;
+            }
+        };
+        connection
+            .terminate(ConnectionClosed::Crashed(reason))
+            .await;
+    }
+
+    /// Routes a response to its caller. Never waits on a caller, so one slow
+    /// stream can't hold back the other calls on the worker.
+    fn dispatch(self: &Arc<Self>, response: WorkerResponse) -> std::result::Result<(), String> {
+        let mut pending = lock_unpoisoned(&self.pending);
+        match response {
+            WorkerResponse::Chunk { id, bytes } => {
+                let Some(call) = pending.get_mut(&id) else {
+                    return Err(format!("worker sent a chunk for unknown request {id}"));
+                };
+                if call.response.is_none() {
+                    return Ok(());
+                }
+                let Some(chunks) = call.chunks.as_ref() else {
+                    return Err(format!(
+                        "worker sent a chunk for request {id}, which is not streaming"
+                    ));
+                };
+                // A caller that went away still has the rest of the stream
+                // drained, keeping the worker in sync.
+                if let Err(mpsc::error::TrySendError::Full(_)) = chunks.try_send(Ok(bytes)) {
+                    call.chunks = None;
+                    if let Some(response) = call.response.take() {
+                        let _ = response.send(WorkerResponse::Error {
+                            id,
+                            kind: WorkerErrorKind::Operation,
+                            message: format!(
+                                "image reader fell more than {CHUNK_BUFFER} chunks behind"
+                            ),
+                        });
+                    }
+                }
+                Ok(())
+            }
+            WorkerResponse::Result { id, .. } | WorkerResponse::Error { id, .. } => {
+                match pending.remove(&id) {
+                    Some(call) => {
+                        if let Some(caller) = call.response {
+                            let _ = caller.send(response);
+                        }
+                        Ok(())
+                    }
+                    None => Err(format!("worker answered unknown request {id}")),
+                }
+            }
+            WorkerResponse::Ready { .. } => {
+                Err("worker sent an unexpected readiness response".to_string())
+            }
+            WorkerResponse::HttpRequest { id, request } => {
+                drop(pending);
+                self.serve_http(id, request);
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs an extension's HTTP request on the host and writes the answer
+    /// back. The worker thread that sent it waits meanwhile, so the call's
+    /// own deadline still bounds the request.
+    fn serve_http(self: &Arc<Self>, id: u64, request: HostHttpRequest) {
+        let connection = self.clone();
+        tokio::spawn(async move {
+            let envelope = WorkerRequestEnvelope {
+                id,
+                request: WorkerRequest::HttpResponse {
+                    result: send_host_http(connection.http.as_deref(), request).await,
+                },
+            };
+            if let Err(error) = connection.write(envelope).await {
+                connection
+                    .terminate(ConnectionClosed::Crashed(error.to_string()))
+                    .await;
+            }
+        });
+    }
+}
+
+async fn send_host_http(http: Option<&SourceHttp>, request: HostHttpRequest) -> HostHttpResult {
+    match http {
+        Some(http) => http
+            .send(request.into())
+            .await
+            .map(HostHttpResponse::from)
+            .map_err(|error| format!("{error:#}")),
+        None => Err("the host does not offer HTTP to this extension".to_string()),
+    }
+}
+
+/// A call routed to a pooled worker, counted against it until dropped
+struct Assignment {
+    connection: Arc<WorkerConnection>,
+}
+
+impl Drop for Assignment {
+    fn drop(&mut self) {
+        *lock_unpoisoned(&self.connection.last_used) = Instant::now();
+        if self.connection.assigned.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.connection.drained.notify_waiters();
+        }
+    }
+}
+
+/// The pooled worker process that served a call, so the manager can charge
+/// failures to that process instead of the whole source
+#[derive(Clone)]
+pub(crate) struct WorkerProcessHandle {
+    connection: Arc<WorkerConnection>,
+}
+
+impl WorkerProcessHandle {
+    pub(crate) fn health(&self) -> &SourceHealth {
+        &self.connection.health
+    }
+
+    pub(crate) fn pid(&self) -> Option<u32> {
+        self.connection.pid
+    }
+}
+
+/// Outcome of [`WorkerClient::request`]. `process` is `None` when the call
+/// never reached a worker.
+pub(crate) struct WorkerReply {
+    pub(crate) process: Option<WorkerProcessHandle>,
+    pub(crate) result: std::result::Result<WorkerValue, WorkerCallError>,
+}
+
+/// How many worker processes a source may run, see [`WorkerClient`]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WorkerPoolOptions {
+    pub(crate) min_processes: usize,
+    pub(crate) max_processes: usize,
+    pub(crate) idle_timeout: Duration,
+}
+
+fn lock_unpoisoned<T>(mutex: &StdMutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Preferences as saved next to the plugin, files written before the typed
+/// schema hold a bare `Input` list
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SavedPreferences {
+    Values(PreferenceValues),
+    Inputs(Vec<Input>),
+}
+
+impl SavedPreferences {
+    pub(crate) fn into_request(self) -> WorkerRequest {
+        match self {
+            SavedPreferences::Values(values) => WorkerRequest::SetPreferenceValues { values },
+            SavedPreferences::Inputs(preferences) => WorkerRequest::SetPreferences { preferences },
+        }
+    }
+}
+
+/// Preferences file of a source with secret values, which are sealed by
+/// [`super::ExtensionManagerOptions::preference_key`] as a JSON
+/// [`PreferenceValues`]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SealedPreferences {
+    pub(crate) values: PreferenceValues,
+    pub(crate) secrets: String,
+}
+
+/// Runs a source in a pool of worker processes. The pool starts with
+/// `min_processes` and spawns another worker, up to `max_processes`, whenever
+/// a call would otherwise queue behind full workers. Workers beyond the
+/// minimum are stopped once idle for `idle_timeout`.
 pub(crate) struct WorkerClient {
     plugin_path: PathBuf,
     worker_path: PathBuf,
     startup_timeout: Duration,
     cleanup_path: Option<PathBuf>,
+    pool_options: WorkerPoolOptions,
+    sandbox: Option<WorkerSandbox>,
+    http: Option<Arc<HostHttp>>,
     stopped: AtomicBool,
-    process: Mutex<Option<WorkerProcess>>,
+    processes: Mutex<Vec<Arc<WorkerConnection>>>,
+    // Workers being spawned outside the pool lock, counted against
+    // `max_processes` until they join the pool.
+    spawning: AtomicUsize,
+    spawn_finished: Notify,
     shutdown: Notify,
+    reaper: StdMutex<Option<JoinHandle<()>>>,
     // Saved preferences to re-apply whenever a replacement worker spawns, so
     // a respawned worker never serves requests with default preferences.
-    startup_preferences: StdMutex<Option<Vec<Input>>>,
+    startup_preferences: StdMutex<Option<SavedPreferences>>,
+    // Login session to restore after the preferences, for the same reason.
+    startup_session: StdMutex<Option<SourceSession>>,
+    // Bumped whenever the startup state changes. Workers spawned with an
+    // older generation are drained and replaced, so every process in the
+    // pool serves with the same preferences and session.
+    generation: AtomicU64,
+    // Protocol version of the last spawned worker, zero before the first.
+    protocol_version: AtomicU32,
+    // Sandbox layers the last spawned worker reported.
+    sandbox_status: StdMutex<Vec<SandboxLayerStatus>>,
+    // Workers that exited on their own, were recycled or restarted.
+    restarts: AtomicU64,
 }
 
 impl WorkerClient {
@@ -205,44 +954,70 @@ impl WorkerClient {
         worker_path: PathBuf,
         startup_timeout: Duration,
         cleanup_path: Option<PathBuf>,
+        pool_options: WorkerPoolOptions,
+        sandbox: Option<WorkerSandbox>,
+        http: Option<Arc<HostHttp>>,
     ) -> Arc<Self> {
         Arc::new(Self {
             plugin_path,
             worker_path,
             startup_timeout,
             cleanup_path,
+            pool_options,
+            sandbox,
+            http,
             stopped: AtomicBool::new(false),
-            process: Mutex::new(None),
+            processes: Mutex::new(Vec::new()),
+            spawning: AtomicUsize::new(0),
+            spawn_finished: Notify::new(),
             shutdown: Notify::new(),
+            reaper: StdMutex::new(None),
             startup_preferences: StdMutex::new(None),
+            startup_session: StdMutex::new(None),
+            generation: AtomicU64::new(0),
+            protocol_version: AtomicU32::new(0),
+            sandbox_status: StdMutex::new(Vec::new()),
+            restarts: AtomicU64::new(0),
         })
     }
 
-    pub(crate) fn set_startup_preferences(&self, preferences: Vec<Input>) {
-        let mut guard = match self.startup_preferences.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
-        };
-        *guard = Some(preferences);
+    pub(crate) fn set_startup_preferences(&self, preferences: SavedPreferences) {
+        *lock_unpoisoned(&self.startup_preferences) = Some(preferences);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn set_startup_session(&self, session: Option<SourceSession>) {
+        *lock_unpoisoned(&self.startup_session) = session;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn protocol_version(&self) -> u32 {
+        self.protocol_version.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn sandbox_status(&self) -> Vec<SandboxLayerStatus> {
+        lock_unpoisoned(&self.sandbox_status).clone()
+    }
+
+    pub(crate) fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Acquire)
     }
 
-    pub(crate) async fn start(&self) -> Result<(WorkerSourceInfo, String, String)> {
-        let mut process = self.process.lock().await;
+    pub(crate) async fn start(self: &Arc<Self>) -> Result<(WorkerSourceInfo, String, String)> {
+        let mut processes = self.processes.lock().await;
         if self.stopped.load(Ordering::Acquire) {
             bail!("extension worker is shut down");
         }
-        if process.is_none() {
-            let (worker, source_info, rustc_version, lib_version) =
-                tokio::time::timeout(self.startup_timeout, self.spawn_process())
-                    .await
-                    .context("extension worker startup timed out")??;
-            *process = Some(worker);
-            return Ok((source_info, rustc_version, lib_version));
+        processes.retain(|process| process.closed().is_none());
+        while processes.len() < self.pool_options.min_processes {
+            let spawned = tokio::time::timeout(self.startup_timeout, self.spawn_connection())
+                .await
+                .context("extension worker startup timed out")??;
+            processes.push(spawned);
         }
+        self.start_reaper();
 
-        let Some(process) = process.as_mut() else {
-            unreachable!("worker process was inserted above");
-        };
+        let process = &processes[0];
         Ok((
             process.source_info.clone(),
             process.rustc_version.clone(),
@@ -250,13 +1025,9 @@ impl WorkerClient {
         ))
     }
 
-    pub(crate) async fn request(
-        &self,
-        request: WorkerRequest,
-        timeout: Duration,
-    ) -> std::result::Result<WorkerValue, WorkerCallError> {
-        // The deadline covers the wait for the per-source stream as well as
-        // the request itself, so a caller never waits longer than its own
+    pub(crate) async fn request(&self, call: WorkerCall, timeout: Duration) -> WorkerReply {
+        // The deadline covers the wait for a free slot on a worker as well
+        // as the request itself, so a caller never waits longer than its own
         // timeout behind earlier calls.
         let deadline = Instant::now() + timeout;
         // Register interest in shutdown before checking `stopped` so a
@@ -265,111 +1036,288 @@ impl WorkerClient {
         tokio::pin!(shutdown);
         shutdown.as_mut().enable();
         if self.stopped.load(Ordering::Acquire) {
-            return Err(WorkerCallError::Stopped);
+            return WorkerReply {
+                process: None,
+                result: Err(WorkerCallError::Stopped),
+            };
+        }
+
+        let assignment = tokio::select! {
+            assignment = self.process_before(deadline) => assignment,
+            _ = &mut shutdown => Err(WorkerCallError::Stopped),
+        };
+        let assignment = match assignment {
+            Ok(assignment) => assignment,
+            Err(error) => {
+                return WorkerReply {
+                    process: None,
+                    result: Err(error),
+                };
+            }
+        };
+        let connection = assignment.connection.clone();
+        let result = Self::request_on(&connection, call, deadline, shutdown).await;
+        drop(assignment);
+
+        WorkerReply {
+            process: Some(WorkerProcessHandle { connection }),
+            result,
         }
+    }
 
-        let mut process_guard = tokio::select! {
-            guard = tokio::time::timeout_at(deadline, self.process.lock()) => match guard {
-                Ok(guard) => guard,
+    async fn request_on(
+        connection: &Arc<WorkerConnection>,
+        call: WorkerCall,
+        deadline: Instant,
+        mut shutdown: Pin<&mut Notified<'_>>,
+    ) -> std::result::Result<WorkerValue, WorkerCallError> {
+        let WorkerCall { request, chunks } = call;
+        if let Some(message) = request.unsupported(connection.protocol_version) {
+            return Err(WorkerCallError::Remote {
+                kind: WorkerErrorKind::Operation,
+                message,
+            });
+        }
+        let permit = connection.in_flight.clone().acquire_owned();
+        let _permit = tokio::select! {
+            permit = tokio::time::timeout_at(deadline, permit) => match permit {
+                Ok(Ok(permit)) => permit,
+                Ok(Err(_)) => return Err(WorkerCallError::Stopped),
                 Err(_) => return Err(WorkerCallError::QueueTimeout),
             },
             _ = &mut shutdown => return Err(WorkerCallError::Stopped),
         };
-        if self.stopped.load(Ordering::Acquire) {
-            return Err(WorkerCallError::Stopped);
-        }
-        if process_guard.is_none() {
-            // Respawn after an earlier failure. Repeated failures are bounded
-            // by the source's health policy: once the entry is quarantined,
-            // admission stops before another spawn is attempted.
-            let spawn = tokio::time::timeout_at(deadline, self.spawn_process());
-            tokio::pin!(spawn);
-            let result = tokio::select! {
-                result = &mut spawn => result,
-                _ = &mut shutdown => return Err(WorkerCallError::Stopped),
-            };
-            match result {
-                Ok(Ok((worker, _, _, _))) => *process_guard = Some(worker),
-                Ok(Err(error)) => return Err(WorkerCallError::Crashed(error.to_string())),
-                Err(_) => return Err(WorkerCallError::QueueTimeout),
-            }
-        }
         if Instant::now() >= deadline {
-            // The spawned or running worker stays usable for later callers.
+            // The worker stays usable for later callers.
             return Err(WorkerCallError::QueueTimeout);
         }
 
-        let process = process_guard
-            .as_mut()
-            .expect("worker process was initialized above");
-        let id = process.next_request_id;
-        process.next_request_id = process.next_request_id.wrapping_add(1);
+        let id = connection.next_request_id();
+        let (response_sender, response_receiver) = oneshot::channel();
+        connection
+            .register(
+                id,
+                PendingCall {
+                    response: Some(response_sender),
+                    chunks,
+                },
+            )
+            .map_err(ConnectionClosed::call_error)?;
         let envelope = WorkerRequestEnvelope { id, request };
 
         let response = tokio::select! {
             response = tokio::time::timeout_at(deadline, async {
-                write_frame_async(&mut process.stdin, &envelope).await?;
-                read_frame_async::<_, WorkerResponse>(&mut process.stdout).await
-            }) => match response {
-            Ok(Ok(response)) => response,
+                connection.write(envelope).await?;
+                Ok::<_, io::Error>(response_receiver.await)
+            }) => response,
+            // `pause` terminates the connection itself.
+            _ = &mut shutdown => return Err(WorkerCallError::Stopped),
+        };
+
+        match response {
+            Ok(Ok(Ok(WorkerResponse::Result { value, .. }))) => Ok(value),
+            Ok(Ok(Ok(WorkerResponse::Error { kind, message, .. }))) => {
+                Err(WorkerCallError::Remote { kind, message })
+            }
+            Ok(Ok(Ok(other))) => Err(WorkerCallError::Crashed(format!(
+                "worker sent an unexpected response {other:?}"
+            ))),
+            // The connection closed while the call was pending.
+            Ok(Ok(Err(_))) => Err(connection
+                .closed()
+                .unwrap_or(ConnectionClosed::Crashed(
+                    "response channel closed".to_string(),
+                ))
+                .call_error()),
             Ok(Err(error)) => {
-                terminate_process(process).await;
-                *process_guard = None;
-                return Err(WorkerCallError::Crashed(error.to_string()));
+                connection
+                    .terminate(ConnectionClosed::Crashed(error.to_string()))
+                    .await;
+                Err(WorkerCallError::Crashed(error.to_string()))
             }
             Err(_) => {
-                terminate_process(process).await;
-                *process_guard = None;
-                return Err(WorkerCallError::Timeout);
+                // The request may be stuck in native code, which only ends
+                // with the process.
+                connection.terminate(ConnectionClosed::Terminated).await;
+                Err(WorkerCallError::Timeout)
             }
-            },
-            _ = &mut shutdown => {
-                terminate_process(process).await;
-                *process_guard = None;
-                return Err(WorkerCallError::Stopped);
+        }
+    }
+
+    /// Routes a call to the least busy worker. Another worker is spawned
+    /// when every worker is full and the pool is below its maximum, which
+    /// also replaces workers that crashed or were recycled. The pool lock is
+    /// released while the worker starts, so other calls keep being routed to
+    /// the running workers. Repeated failures are bounded by the source's
+    /// health policy: once the entry is quarantined, admission stops before
+    /// another spawn is attempted.
+    async fn process_before(
+        &self,
+        deadline: Instant,
+    ) -> std::result::Result<Assignment, WorkerCallError> {
+        let mut slot = loop {
+            let processes = self.lock_processes_before(deadline).await?;
+            let least_busy = processes
+                .iter()
+                .min_by_key(|process| process.assigned())
+                .cloned();
+            let spawning = self.spawning.load(Ordering::Acquire);
+            match least_busy {
+                Some(process) if !process.is_full() => return Ok(process.assign()),
+                Some(process) if processes.len() + spawning >= self.pool_options.max_processes => {
+                    return Ok(process.assign());
+                }
+                // Every worker the pool may run is still starting.
+                None if spawning >= self.pool_options.max_processes => {
+                    let spawn_finished = self.spawn_finished.notified();
+                    tokio::pin!(spawn_finished);
+                    spawn_finished.as_mut().enable();
+                    drop(processes);
+                    tokio::time::timeout_at(deadline, spawn_finished)
+                        .await
+                        .map_err(|_| WorkerCallError::QueueTimeout)?;
+                }
+                _ => break SpawnSlot::take(self, &processes),
             }
         };
 
-        match response {
-            WorkerResponse::Result {
-                id: response_id,
-                value,
-            } if response_id == id => Ok(value),
-            WorkerResponse::Error {
-                id: response_id,
-                kind,
-                message,
-            } if response_id == id => Err(WorkerCallError::Remote { kind, message }),
-            WorkerResponse::Result {
-                id: response_id, ..
+        let error = match tokio::time::timeout_at(deadline, self.spawn_connection()).await {
+            Ok(Ok(spawned)) => {
+                let spawned = slot.spawned.insert(spawned).clone();
+                let mut processes = self.processes.lock().await;
+                if self.stopped.load(Ordering::Acquire) {
+                    return Err(WorkerCallError::Stopped);
+                }
+                slot.pool(&mut processes);
+                return Ok(spawned.assign());
             }
-            | WorkerResponse::Error {
-                id: response_id, ..
-            } => {
-                terminate_process(process).await;
-                *process_guard = None;
-                Err(WorkerCallError::Crashed(format!(
-                    "response id {response_id} did not match request id {id}"
-                )))
+            Ok(Err(error)) => error,
+            Err(_) => return Err(WorkerCallError::QueueTimeout),
+        };
+        drop(slot);
+
+        let processes = self.lock_processes_before(deadline).await?;
+        match processes.iter().min_by_key(|process| process.assigned()) {
+            // Queue on a running worker rather than fail the call.
+            Some(process) => {
+                log::warn!(
+                    "failed to scale up extension workers for {}: {error}",
+                    self.plugin_path.display()
+                );
+                Ok(process.assign())
             }
-            WorkerResponse::Ready { .. } => {
-                terminate_process(process).await;
-                *process_guard = None;
-                Err(WorkerCallError::Crashed(
-                    "worker sent an unexpected readiness response".to_string(),
-                ))
+            None => Err(WorkerCallError::Crashed(error.to_string())),
+        }
+    }
+
+    async fn lock_processes_before(
+        &self,
+        deadline: Instant,
+    ) -> std::result::Result<MutexGuard<'_, Vec<Arc<WorkerConnection>>>, WorkerCallError> {
+        let mut processes = tokio::time::timeout_at(deadline, self.processes.lock())
+            .await
+            .map_err(|_| WorkerCallError::QueueTimeout)?;
+        if self.stopped.load(Ordering::Acquire) {
+            return Err(WorkerCallError::Stopped);
+        }
+        self.prune(&mut processes);
+        Ok(processes)
+    }
+
+    /// Forgets workers that exited and drains those spawned before the
+    /// startup state last changed.
+    fn prune(&self, processes: &mut Vec<Arc<WorkerConnection>>) {
+        let generation = self.generation.load(Ordering::Acquire);
+        let mut index = 0;
+        while index < processes.len() {
+            if processes[index].closed().is_some() {
+                processes.swap_remove(index);
+                self.restarts.fetch_add(1, Ordering::AcqRel);
+            } else if processes[index].generation != generation {
+                processes.swap_remove(index).retire();
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Takes a misbehaving worker out of the pool. Calls already routed to
+    /// it finish first, and the next call that needs it spawns a
+    /// replacement.
+    pub(crate) async fn recycle(&self, process: &WorkerProcessHandle) {
+        let mut processes = self.processes.lock().await;
+        if let Some(index) = processes
+            .iter()
+            .position(|running| Arc::ptr_eq(running, &process.connection))
+        {
+            processes.swap_remove(index).retire();
+            self.restarts.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Replaces every worker. Calls already routed to the old workers finish
+    /// first, new calls go to the fresh ones.
+    pub(crate) async fn restart(self: &Arc<Self>) -> Result<()> {
+        let processes = std::mem::take(&mut *self.processes.lock().await);
+        self.restarts
+            .fetch_add(processes.len() as u64, Ordering::AcqRel);
+        for process in processes {
+            process.retire();
+        }
+
+        self.start().await.map(|_| ())
+    }
+
+    fn start_reaper(self: &Arc<Self>) {
+        let mut reaper = lock_unpoisoned(&self.reaper);
+        if reaper.is_some() {
+            return;
+        }
+        let client = Arc::downgrade(self);
+        let period = (self.pool_options.idle_timeout / 2).max(Duration::from_secs(1));
+        *reaper = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(client) = client.upgrade() else {
+                    break;
+                };
+                client.scale_down();
             }
+        }));
+    }
+
+    /// Stops idle workers beyond the pool minimum. Skipped while a call holds
+    /// the pool lock, the next tick retries.
+    fn scale_down(&self) {
+        let Ok(mut processes) = self.processes.try_lock() else {
+            return;
+        };
+        if self.stopped.load(Ordering::Acquire) {
+            return;
+        }
+        self.prune(&mut processes);
+        let now = Instant::now();
+        while processes.len() > self.pool_options.min_processes {
+            let Some(index) = processes.iter().position(|process| {
+                process
+                    .idle_for(now)
+                    .is_some_and(|idle| idle >= self.pool_options.idle_timeout)
+            }) else {
+                break;
+            };
+            processes.swap_remove(index).retire();
         }
     }
 
     pub(crate) async fn pause(&self) {
         self.stopped.store(true, Ordering::Release);
         self.shutdown.notify_waiters();
-        let mut process = self.process.lock().await;
-        if let Some(process) = process.as_mut() {
-            terminate_process(process).await;
+        let processes = std::mem::take(&mut *self.processes.lock().await);
+        for process in processes {
+            process.terminate(ConnectionClosed::Stopped).await;
         }
-        *process = None;
     }
 
     pub(crate) fn resume(&self) {
@@ -395,8 +1343,29 @@ impl WorkerClient {
         }
     }
 
-    async fn spawn_process(&self) -> Result<(WorkerProcess, WorkerSourceInfo, String, String)> {
-        let mut child = Command::new(&self.worker_path)
+    async fn spawn_process(&self) -> Result<WorkerProcess> {
+        // Read before the startup state, so a change made while this worker
+        // starts marks it stale rather than being missed.
+        let generation = self.generation.load(Ordering::Acquire);
+        let mut command = Command::new(&self.worker_path);
+        if let Some(sandbox) = self.sandbox.as_ref() {
+            command.env(SANDBOX_ENV, serde_json::to_string(sandbox)?);
+        }
+        // A session of its own leaves the worker without a controlling
+        // terminal, it writes to the inherited stderr but can't push input
+        // into the server's terminal.
+        #[cfg(target_os = "linux")]
+        // SAFETY: `setsid` is async-signal-safe and touches no memory of the
+        // parent.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = command
             .arg(WORKER_MODE_FLAG)
             .arg("--plugin")
             .arg(&self.plugin_path)
@@ -421,10 +1390,15 @@ impl WorkerClient {
             .take()
             .ok_or_else(|| anyhow!("extension worker stdout was not piped"))?;
         let mut worker = WorkerProcess {
+            pid: child.id(),
             child,
+            generation,
             stdin,
             stdout: AsyncBufReader::new(stdout),
+            protocol_version: 0,
+            encoding: FrameEncoding::Json,
             next_request_id: 1,
+            max_in_flight: 1,
             source_info: WorkerSourceInfo {
                 id: 0,
                 name: String::new(),
@@ -433,91 +1407,232 @@ impl WorkerClient {
                 icon: String::new(),
                 languages: tanoshi_lib::prelude::Lang::All,
                 nsfw: false,
+                capabilities: Capabilities::default(),
+                rate_limit: None,
             },
             rustc_version: String::new(),
             lib_version: String::new(),
         };
 
-        let response = read_frame_async::<_, WorkerResponse>(&mut worker.stdout)
-            .await
-            .context("failed to read extension worker readiness")?;
-        let (source_info, rustc_version, lib_version) = match response {
+        let response =
+            read_frame_async::<_, WorkerResponse>(&mut worker.stdout, FrameEncoding::Json)
+                .await
+                .context("failed to read extension worker readiness")?;
+        match response {
             WorkerResponse::Ready {
                 protocol_version,
+                max_in_flight,
                 source_info,
                 rustc_version,
                 lib_version,
-            } if protocol_version == PROTOCOL_VERSION => (source_info, rustc_version, lib_version),
+                sandbox,
+            } if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) => {
+                // Older worker binaries left next to the host keep talking
+                // JSON, one call at a time.
+                worker.protocol_version = protocol_version;
+                worker.encoding = FrameEncoding::for_protocol(protocol_version);
+                worker.max_in_flight = max_in_flight.max(1) as usize;
+                worker.source_info = source_info;
+                worker.rustc_version = rustc_version;
+                worker.lib_version = lib_version;
+                self.protocol_version
+                    .store(protocol_version, Ordering::Release);
+                *lock_unpoisoned(&self.sandbox_status) = sandbox;
+            }
             WorkerResponse::Ready {
                 protocol_version, ..
             } => {
                 terminate_process(&mut worker).await;
                 bail!(
-                    "extension worker protocol mismatch: worker={protocol_version} host={PROTOCOL_VERSION}"
+                    "extension worker protocol mismatch: worker={protocol_version} host={MIN_PROTOCOL_VERSION}..={PROTOCOL_VERSION}"
                 );
             }
             other => {
                 terminate_process(&mut worker).await;
                 bail!("extension worker did not send readiness: {other:?}");
             }
-        };
-        worker.source_info = source_info.clone();
-        worker.rustc_version = rustc_version.clone();
-        worker.lib_version = lib_version.clone();
+        }
 
-        if let Err(error) = self.apply_startup_preferences(&mut worker).await {
-            terminate_process(&mut worker).await;
+        Ok(worker)
+    }
+
+    async fn spawn_connection(&self) -> Result<Arc<WorkerConnection>> {
+        let mut process = self.spawn_process().await?;
+        let source_info = &process.source_info;
+        let http = self
+            .http
+            .as_ref()
+            .map(|http| http.source(source_info.id, &source_info.name, source_info.rate_limit))
+            .transpose();
+        let http = match http {
+            Ok(http) => http,
+            Err(error) => {
+                terminate_process(&mut process).await;
+                return Err(error);
+            }
+        };
+        // The extension may already send HTTP requests while it applies
+        // them, so the startup state goes in once the source has a client.
+        if let Err(error) = self
+            .apply_startup_preferences(&mut process, http.as_deref())
+            .await
+        {
+            terminate_process(&mut process).await;
             return Err(error);
         }
-
-        Ok((worker, source_info, rustc_version, lib_version))
+        if let Err(error) = self
+            .apply_startup_session(&mut process, http.as_deref())
+            .await
+        {
+            terminate_process(&mut process).await;
+            return Err(error);
+        }
+        Ok(WorkerConnection::start(process, http))
     }
 
     /// Re-applies the saved preferences to a freshly spawned worker before it
     /// serves any request.
-    async fn apply_startup_preferences(&self, worker: &mut WorkerProcess) -> Result<()> {
-        let preferences = match self.startup_preferences.lock() {
-            Ok(guard) => guard.clone(),
-            Err(poisoned) => poisoned.into_inner().clone(),
-        };
+    async fn apply_startup_preferences(
+        &self,
+        worker: &mut WorkerProcess,
+        http: Option<&SourceHttp>,
+    ) -> Result<()> {
+        let preferences = lock_unpoisoned(&self.startup_preferences).clone();
         let Some(preferences) = preferences else {
             return Ok(());
         };
 
-        let id = worker.next_request_id;
-        worker.next_request_id = worker.next_request_id.wrapping_add(1);
-        let envelope = WorkerRequestEnvelope {
-            id,
-            request: WorkerRequest::SetPreferences { preferences },
+        send_startup_request(
+            worker,
+            http,
+            preferences.into_request(),
+            "saved preferences",
+        )
+        .await
+    }
+
+    /// Restores the login session after the saved preferences, so a crashed
+    /// worker comes back logged in.
+    async fn apply_startup_session(
+        &self,
+        worker: &mut WorkerProcess,
+        http: Option<&SourceHttp>,
+    ) -> Result<()> {
+        let session = lock_unpoisoned(&self.startup_session).clone();
+        let Some(session) = session else {
+            return Ok(());
         };
-        let response = async {
-            write_frame_async(&mut worker.stdin, &envelope).await?;
-            read_frame_async::<_, WorkerResponse>(&mut worker.stdout).await
-        }
+
+        send_startup_request(
+            worker,
+            http,
+            WorkerRequest::RestoreSession { session },
+            "saved login session",
+        )
         .await
-        .context("failed to apply saved preferences to the extension worker")?;
+    }
+}
+
+/// Sends a request to a worker that isn't shared yet and waits for its
+/// answer, serving the HTTP requests the extension makes meanwhile.
+async fn send_startup_request(
+    worker: &mut WorkerProcess,
+    http: Option<&SourceHttp>,
+    request: WorkerRequest,
+    what: &str,
+) -> Result<()> {
+    if let Some(message) = request.unsupported(worker.protocol_version) {
+        bail!("failed to apply {what}: {message}");
+    }
+    let id = worker.next_request_id;
+    worker.next_request_id = worker.next_request_id.wrapping_add(1);
+    let envelope = WorkerRequestEnvelope { id, request };
+    let encoding = worker.encoding;
+    write_frame_async(&mut worker.stdin, envelope, encoding)
+        .await
+        .with_context(|| format!("failed to apply {what} to the extension worker"))?;
 
+    loop {
+        let response = read_frame_async::<_, WorkerResponse>(&mut worker.stdout, encoding)
+            .await
+            .with_context(|| format!("failed to apply {what} to the extension worker"))?;
         match response {
             WorkerResponse::Result {
                 id: response_id,
                 value: WorkerValue::Unit,
-            } if response_id == id => Ok(()),
+            } if response_id == id => return Ok(()),
             WorkerResponse::Error { kind, message, .. } => {
-                bail!("extension worker rejected saved preferences ({kind:?}): {message}")
+                bail!("extension worker rejected {what} ({kind:?}): {message}")
+            }
+            WorkerResponse::HttpRequest {
+                id: http_id,
+                request,
+            } => {
+                let envelope = WorkerRequestEnvelope {
+                    id: http_id,
+                    request: WorkerRequest::HttpResponse {
+                        result: send_host_http(http, request).await,
+                    },
+                };
+                write_frame_async(&mut worker.stdin, envelope, encoding)
+                    .await
+                    .with_context(|| format!("failed to apply {what} to the extension worker"))?;
             }
             other => {
-                bail!(
-                    "extension worker sent an unexpected response to saved preferences: {other:?}"
-                )
+                bail!("extension worker sent an unexpected response to {what}: {other:?}")
             }
         }
     }
 }
 
+/// A worker [`WorkerClient::process_before`] spawns, counted against the
+/// pool until it joins it. Dropping the slot first, when the spawn failed or
+/// the call went away, frees it and stops the worker if it already started.
+struct SpawnSlot<'a> {
+    client: &'a WorkerClient,
+    spawned: Option<Arc<WorkerConnection>>,
+}
+
+impl<'a> SpawnSlot<'a> {
+    /// Taken under the pool lock, so the count and the pool agree
+    fn take(
+        client: &'a WorkerClient,
+        _processes: &MutexGuard<'_, Vec<Arc<WorkerConnection>>>,
+    ) -> Self {
+        client.spawning.fetch_add(1, Ordering::AcqRel);
+        Self {
+            client,
+            spawned: None,
+        }
+    }
+
+    fn pool(self, processes: &mut Vec<Arc<WorkerConnection>>) {
+        let mut slot = self;
+        processes.extend(slot.spawned.take());
+    }
+}
+
+impl Drop for SpawnSlot<'_> {
+    fn drop(&mut self) {
+        self.client.spawning.fetch_sub(1, Ordering::AcqRel);
+        self.client.spawn_finished.notify_waiters();
+        if let Some(spawned) = self.spawned.take() {
+            spawned.kill_now();
+        }
+    }
+}
+
 impl Drop for WorkerClient {
     fn drop(&mut self) {
-        if let Ok(mut process) = self.process.try_lock() {
-            process.take();
+        if let Some(reaper) = lock_unpoisoned(&self.reaper).take() {
+            reaper.abort();
+        }
+        // The reader tasks keep the connections alive, so kill the workers
+        // explicitly instead of relying on `kill_on_drop`.
+        if let Ok(mut processes) = self.processes.try_lock() {
+            for process in processes.drain(..) {
+                process.kill_now();
+            }
         }
         self.cleanup_path();
     }
@@ -552,45 +1667,260 @@ pub fn run_worker(plugin_path: PathBuf) -> Result<()> {
     let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
         .try_init();
 
-    let entry = load_worker_entry(&plugin_path)?;
+    // Limits and namespaces apply before the plugin's code runs, the
+    // filesystem and syscall restrictions once it is loaded.
+    let mut sandbox = Sandbox::from_env()?;
+    if let Some(sandbox) = sandbox.as_mut() {
+        sandbox.enter();
+    }
+    let http = Arc::new(HostHttpBridge::default());
+    let entry = load_worker_entry(&plugin_path, http.clone())?;
+    let sandbox = match sandbox {
+        Some(sandbox) => sandbox.lock_down()?,
+        None => Vec::new(),
+    };
     let mut input = BufReader::new(io::stdin().lock());
-    let mut output = BufWriter::new(io::stdout().lock());
     write_frame_sync(
-        &mut output,
-        &WorkerResponse::Ready {
+        &mut BufWriter::new(io::stdout().lock()),
+        WorkerResponse::Ready {
             protocol_version: PROTOCOL_VERSION,
+            max_in_flight: WORKER_MAX_IN_FLIGHT,
             source_info: WorkerSourceInfo::from(&entry.source_info),
             rustc_version: entry.rustc_version.clone(),
             lib_version: entry.lib_version.clone(),
+            sandbox,
         },
+        FrameEncoding::Json,
     )?;
+    let encoding = FrameEncoding::for_protocol(PROTOCOL_VERSION);
 
-    while let Some(request) = read_frame_sync::<_, WorkerRequestEnvelope>(&mut input)? {
-        let response = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            execute_request(&entry, request.request)
-        })) {
-            Ok(Ok(value)) => WorkerResponse::Result {
-                id: request.id,
-                value,
-            },
-            Ok(Err(error)) => WorkerResponse::Error {
-                id: request.id,
+    // Requests run on a pool of threads and their responses go through a
+    // single writer, so frames never interleave. The host matches them to
+    // requests by id, in whatever order they finish.
+    let (responses, pending_responses) = std::sync::mpsc::channel::<WorkerResponse>();
+    let writer = std::thread::spawn(move || -> io::Result<()> {
+        let mut output = BufWriter::new(io::stdout().lock());
+        for response in pending_responses {
+            write_frame_sync(&mut output, response, encoding)?;
+        }
+        Ok(())
+    });
+    http.connect(responses.clone());
+    let (requests, pending_requests) = std::sync::mpsc::channel::<WorkerRequestEnvelope>();
+    let pending_requests = Arc::new(StdMutex::new(pending_requests));
+    let pool = (0..WORKER_MAX_IN_FLIGHT)
+        .map(|_| {
+            let entry = entry.clone();
+            let responses = responses.clone();
+            let pending_requests = pending_requests.clone();
+            std::thread::spawn(move || {
+                loop {
+                    let request = lock_unpoisoned(&pending_requests).recv();
+                    let Ok(request) = request else {
+                        break;
+                    };
+                    handle_request(&entry, request, &responses);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(responses);
+
+    let read_result = loop {
+        match read_frame_sync::<_, WorkerRequestEnvelope>(&mut input, encoding) {
+            Ok(Some(WorkerRequestEnvelope {
+                id,
+                request: WorkerRequest::HttpResponse { result },
+            })) => http.answer(id, result),
+            Ok(Some(request)) => {
+                if requests.send(request).is_err() {
+                    break Ok(());
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(error) => break Err(error),
+        }
+    };
+    drop(requests);
+    // Fails the HTTP requests still waiting for the host, so their threads
+    // can finish, and lets the writer stop.
+    http.disconnect();
+    for thread in pool {
+        let _ = thread.join();
+    }
+    match writer.join() {
+        Ok(result) => result?,
+        Err(payload) => bail!(
+            "extension worker writer panicked: {}",
+            panic_payload_message(&*payload)
+        ),
+    }
+    read_result?;
+
+    Ok(())
+}
+
+fn handle_request(
+    entry: &Arc<SourceEntry>,
+    request: WorkerRequestEnvelope,
+    responses: &std::sync::mpsc::Sender<WorkerResponse>,
+) {
+    let WorkerRequestEnvelope { id, request } = request;
+    if let WorkerRequest::StreamImage { page } = request {
+        stream_image(entry, id, page, responses);
+        return;
+    }
+    let response = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        execute_request(entry, request)
+    })) {
+        Ok(Ok(value)) => WorkerResponse::Result { id, value },
+        Ok(Err(error)) => WorkerResponse::Error {
+            id,
+            kind: WorkerErrorKind::Operation,
+            message: error.to_string(),
+        },
+        Err(payload) => WorkerResponse::Error {
+            id,
+            kind: WorkerErrorKind::Panic,
+            message: panic_payload_message(&*payload),
+        },
+    };
+    // Only fails once the writer is gone, when nobody is listening anymore.
+    let _ = responses.send(response);
+}
+
+/// Serve a `StreamImage` request. The extension still returns the image in
+/// one piece, but the host receives it in bounded frames it can forward as
+/// they arrive.
+fn stream_image(
+    entry: &Arc<SourceEntry>,
+    id: u64,
+    page: PageInfo,
+    responses: &std::sync::mpsc::Sender<WorkerResponse>,
+) {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        execute_request(entry, WorkerRequest::GetImageBytes { page })
+    }));
+    let bytes = match result {
+        Ok(Ok(WorkerValue::Image { bytes })) => bytes,
+        Ok(Ok(value)) => {
+            let _ = responses.send(WorkerResponse::Error {
+                id,
+                kind: WorkerErrorKind::Protocol,
+                message: format!("unexpected image value {value:?}"),
+            });
+            return;
+        }
+        Ok(Err(error)) => {
+            let _ = responses.send(WorkerResponse::Error {
+                id,
                 kind: WorkerErrorKind::Operation,
                 message: error.to_string(),
-            },
-            Err(payload) => WorkerResponse::Error {
-                id: request.id,
+            });
+            return;
+        }
+        Err(payload) => {
+            let _ = responses.send(WorkerResponse::Error {
+                id,
                 kind: WorkerErrorKind::Panic,
                 message: panic_payload_message(&*payload),
-            },
-        };
-        write_frame_sync(&mut output, &response)?;
+            });
+            return;
+        }
+    };
+
+    for start in (0..bytes.len()).step_by(IMAGE_CHUNK_SIZE) {
+        let end = (start + IMAGE_CHUNK_SIZE).min(bytes.len());
+        let _ = responses.send(WorkerResponse::Chunk {
+            id,
+            bytes: bytes.slice(start..end),
+        });
     }
+    let _ = responses.send(WorkerResponse::Result {
+        id,
+        value: WorkerValue::Unit,
+    });
+}
 
-    Ok(())
+/// Worker side of [`tanoshi_lib::http`]. Requests go to the host through the
+/// response writer, and the calling extension thread blocks until the reader
+/// hands it the host's answer.
+#[derive(Default)]
+struct HostHttpBridge {
+    state: StdMutex<HostHttpBridgeState>,
+    next_id: AtomicU64,
 }
 
-fn load_worker_entry(plugin_path: &Path) -> Result<Arc<SourceEntry>> {
+#[derive(Default)]
+struct HostHttpBridgeState {
+    // `None` until the worker is ready and again once the host is gone.
+    responses: Option<std::sync::mpsc::Sender<WorkerResponse>>,
+    pending: FnvHashMap<u64, std::sync::mpsc::SyncSender<HostHttpResult>>,
+}
+
+type HostHttpResult = std::result::Result<HostHttpResponse, String>;
+
+impl HostHttpBridge {
+    fn connect(&self, responses: std::sync::mpsc::Sender<WorkerResponse>) {
+        lock_unpoisoned(&self.state).responses = Some(responses);
+    }
+
+    fn answer(&self, id: u64, result: HostHttpResult) {
+        if let Some(waiting) = lock_unpoisoned(&self.state).pending.remove(&id) {
+            let _ = waiting.send(result);
+        }
+    }
+
+    fn disconnect(&self) {
+        let mut state = lock_unpoisoned(&self.state);
+        state.responses = None;
+        state.pending.clear();
+    }
+}
+
+impl tanoshi_lib::http::HttpClient for HostHttpBridge {
+    fn send(&self, request: tanoshi_lib::http::Request) -> Result<tanoshi_lib::http::Response> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (waiting, answer) = std::sync::mpsc::sync_channel(1);
+        let responses = {
+            let mut state = lock_unpoisoned(&self.state);
+            let Some(responses) = state.responses.clone() else {
+                bail!("the host is not connected to the extension worker");
+            };
+            state.pending.insert(id, waiting);
+            responses
+        };
+        let request = WorkerResponse::HttpRequest {
+            id,
+            request: request.into(),
+        };
+        if responses.send(request).is_err() {
+            lock_unpoisoned(&self.state).pending.remove(&id);
+            bail!("the host is not connected to the extension worker");
+        }
+        match answer.recv() {
+            Ok(Ok(response)) => Ok(response.into()),
+            Ok(Err(message)) => Err(anyhow!(message)),
+            Err(_) => bail!("the host disconnected before answering an HTTP request"),
+        }
+    }
+}
+
+fn load_worker_entry(
+    plugin_path: &Path,
+    http: Arc<dyn tanoshi_lib::http::HttpClient>,
+) -> Result<Arc<SourceEntry>> {
+    if plugin_path
+        .extension()
+        .is_some_and(|extension| extension == WASM_PLUGIN_EXTENSION)
+    {
+        let extension = WasmExtension::load(plugin_path, Some(http))?;
+        let mut source =
+            Source::from(Box::new(extension)).with_plugin_path(plugin_path.to_path_buf());
+        source.rustc_version = WASM_RUSTC_VERSION.to_string();
+        return Ok(Arc::new(source.into_entry(1)?));
+    }
+
     let library = unsafe { libloading::Library::new(plugin_path) }?;
     let declaration = unsafe {
         library
@@ -613,7 +1943,8 @@ fn load_worker_entry(plugin_path: &Path) -> Result<Arc<SourceEntry>> {
     }
 
     let mut source = Source::new(library, declaration.rustc_version, declaration.core_version)
-        .with_plugin_path(plugin_path.to_path_buf());
+        .with_plugin_path(plugin_path.to_path_buf())
+        .with_http_client(http);
     std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
         (declaration.register)(&mut source);
     }))
@@ -638,33 +1969,61 @@ fn execute_request(entry: &Arc<SourceEntry>, request: WorkerRequest) -> Result<W
         WorkerRequest::SetPreferences { preferences } => entry
             .with_extension_mut(|extension| extension.set_preferences(preferences))
             .map(|()| WorkerValue::Unit),
+        WorkerRequest::GetPreferenceSchema => entry.with_extension(|extension| {
+            Ok(WorkerValue::PreferenceSchema(extension.preference_schema()))
+        }),
+        WorkerRequest::SetPreferenceValues { values } => entry
+            .with_extension_mut(|extension| extension.set_preference_values(values))
+            .map(|()| WorkerValue::Unit),
+        WorkerRequest::Login { credentials } => entry
+            .with_extension_mut(|extension| extension.login(credentials))
+            .map(WorkerValue::Session),
+        WorkerRequest::RestoreSession { session } => entry
+            .with_extension_mut(|extension| extension.restore_session(session))
+            .map(|()| WorkerValue::Unit),
+        WorkerRequest::Logout => entry
+            .with_extension_mut(|extension| extension.logout())
+            .map(|()| WorkerValue::Unit),
+        WorkerRequest::IsLoggedIn => entry
+            .with_extension(|extension| extension.is_logged_in())
+            .map(WorkerValue::Bool),
         WorkerRequest::GetPopularManga { page } => entry
-            .with_extension(|extension| extension.get_popular_manga(page))
-            .map(WorkerValue::MangaList),
+            .with_extension(|extension| extension.get_popular_manga_paged(page))
+            .map(WorkerValue::MangaPage),
         WorkerRequest::GetLatestManga { page } => entry
-            .with_extension(|extension| extension.get_latest_manga(page))
-            .map(WorkerValue::MangaList),
+            .with_extension(|extension| extension.get_latest_manga_paged(page))
+            .map(WorkerValue::MangaPage),
         WorkerRequest::SearchManga {
             page,
             query,
             filters,
         } => entry
-            .with_extension(|extension| extension.search_manga(page, query, filters))
-            .map(WorkerValue::MangaList),
+            .with_extension(|extension| extension.search_manga_paged(page, query, filters))
+            .map(WorkerValue::MangaPage),
         WorkerRequest::GetMangaDetail { path } => entry
             .with_extension(|extension| extension.get_manga_detail(path))
             .map(WorkerValue::Manga),
         WorkerRequest::GetChapters { path } => entry
             .with_extension(|extension| extension.get_chapters(path))
             .map(WorkerValue::Chapters),
+        WorkerRequest::GetRelatedManga { path } => entry
+            .with_extension(|extension| extension.get_related_manga(path))
+            .map(WorkerValue::MangaList),
+        WorkerRequest::ResolveUrl { url } => entry
+            .with_extension(|extension| extension.resolve_url(url))
+            .map(WorkerValue::ResolvedPath),
         WorkerRequest::GetPages { path } => entry
-            .with_extension(|extension| extension.get_pages(path))
+            .with_extension(|extension| extension.get_page_list(path))
             .map(WorkerValue::Pages),
-        WorkerRequest::GetImageBytes { url } => entry
-            .with_extension(|extension| extension.get_image_bytes(url))
-            .map(|bytes| WorkerValue::Image {
-                bytes: bytes.to_vec(),
-            }),
+        WorkerRequest::GetImageBytes { page } | WorkerRequest::StreamImage { page } => entry
+            .with_extension(|extension| {
+                let bytes = extension.get_page_bytes(page.clone())?;
+                extension.process_image(&page, bytes)
+            })
+            .map(|bytes| WorkerValue::Image { bytes }),
+        WorkerRequest::HttpResponse { .. } => {
+            bail!("HTTP responses are not extension requests")
+        }
     }
 }
 
@@ -673,56 +2032,59 @@ async fn terminate_process(process: &mut WorkerProcess) {
     let _ = process.child.wait().await;
 }
 
-async fn write_frame_async<W, T>(writer: &mut W, value: &T) -> io::Result<()>
+async fn write_frame_async<W, T>(
+    writer: &mut W,
+    value: T,
+    encoding: FrameEncoding,
+) -> io::Result<()>
 where
     W: AsyncWrite + Unpin,
-    T: Serialize,
+    T: Serialize + FramePayload,
 {
-    let frame = serialize_frame(value).map_err(io::Error::other)?;
-    writer.write_all(&frame).await?;
+    let frame = encode_frame(value, encoding).map_err(io::Error::other)?;
+    writer.write_all(&frame.head).await?;
+    writer.write_all(&frame.payload).await?;
     writer.flush().await
 }
 
-async fn read_frame_async<R, T>(reader: &mut R) -> io::Result<T>
-where
-    R: AsyncRead + Unpin,
-    T: DeserializeOwned,
-{
-    let bytes = read_frame_async_bytes(reader).await?;
-    serde_json::from_slice(&bytes).map_err(io::Error::other)
-}
-
-async fn read_frame_async_bytes<R>(reader: &mut R) -> io::Result<Vec<u8>>
+async fn read_frame_async<R, T>(reader: &mut R, encoding: FrameEncoding) -> io::Result<T>
 where
     R: AsyncRead + Unpin,
+    T: DeserializeOwned + FramePayload,
 {
     let mut length = [0; 4];
     reader.read_exact(&mut length).await?;
-    let length = u32::from_be_bytes(length) as usize;
-    if length > MAX_FRAME_SIZE {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("worker frame exceeds {MAX_FRAME_SIZE} bytes"),
-        ));
-    }
-    let mut bytes = vec![0; length];
-    reader.read_exact(&mut bytes).await?;
-    Ok(bytes)
+    let length = frame_length(length)?;
+    let header_length = match encoding {
+        FrameEncoding::Json => length,
+        FrameEncoding::Binary => {
+            let mut header_length = [0; 4];
+            reader.read_exact(&mut header_length).await?;
+            header_length_within(header_length, length)?
+        }
+    };
+    let mut header = vec![0; header_length];
+    reader.read_exact(&mut header).await?;
+    let mut payload = vec![0; payload_length(length, header_length, encoding)];
+    reader.read_exact(&mut payload).await?;
+    decode_frame(&header, payload)
 }
 
-fn write_frame_sync<W, T>(writer: &mut W, value: &T) -> io::Result<()>
+fn write_frame_sync<W, T>(writer: &mut W, value: T, encoding: FrameEncoding) -> io::Result<()>
 where
     W: Write,
-    T: Serialize,
+    T: Serialize + FramePayload,
 {
-    writer.write_all(&serialize_frame(value).map_err(io::Error::other)?)?;
+    let frame = encode_frame(value, encoding).map_err(io::Error::other)?;
+    writer.write_all(&frame.head)?;
+    writer.write_all(&frame.payload)?;
     writer.flush()
 }
 
-fn read_frame_sync<R, T>(reader: &mut R) -> io::Result<Option<T>>
+fn read_frame_sync<R, T>(reader: &mut R, encoding: FrameEncoding) -> io::Result<Option<T>>
 where
     R: Read,
-    T: DeserializeOwned,
+    T: DeserializeOwned + FramePayload,
 {
     let mut length = [0; 4];
     let first = reader.read(&mut length[..1])?;
@@ -730,6 +2092,23 @@ where
         return Ok(None);
     }
     reader.read_exact(&mut length[1..])?;
+    let length = frame_length(length)?;
+    let header_length = match encoding {
+        FrameEncoding::Json => length,
+        FrameEncoding::Binary => {
+            let mut header_length = [0; 4];
+            reader.read_exact(&mut header_length)?;
+            header_length_within(header_length, length)?
+        }
+    };
+    let mut header = vec![0; header_length];
+    reader.read_exact(&mut header)?;
+    let mut payload = vec![0; payload_length(length, header_length, encoding)];
+    reader.read_exact(&mut payload)?;
+    decode_frame(&header, payload).map(Some)
+}
+
+fn frame_length(length: [u8; 4]) -> io::Result<usize> {
     let length = u32::from_be_bytes(length) as usize;
     if length > MAX_FRAME_SIZE {
         return Err(io::Error::new(
@@ -737,41 +2116,756 @@ where
             format!("worker frame exceeds {MAX_FRAME_SIZE} bytes"),
         ));
     }
-    let mut bytes = vec![0; length];
-    reader.read_exact(&mut bytes)?;
-    serde_json::from_slice(&bytes)
-        .map(Some)
-        .map_err(io::Error::other)
+    Ok(length)
+}
+
+fn header_length_within(header_length: [u8; 4], length: usize) -> io::Result<usize> {
+    let header_length = u32::from_be_bytes(header_length) as usize;
+    if header_length > length.saturating_sub(4) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "worker frame header exceeds the frame",
+        ));
+    }
+    Ok(header_length)
 }
 
-fn serialize_frame<T: Serialize>(value: &T) -> Result<Vec<u8>> {
-    let payload = serde_json::to_vec(value)?;
-    let length = u32::try_from(payload.len()).context("worker frame is too large")?;
-    if payload.len() > MAX_FRAME_SIZE {
+fn payload_length(length: usize, header_length: usize, encoding: FrameEncoding) -> usize {
+    match encoding {
+        FrameEncoding::Json => 0,
+        FrameEncoding::Binary => length - 4 - header_length,
+    }
+}
+
+fn decode_frame<T>(header: &[u8], payload: Vec<u8>) -> io::Result<T>
+where
+    T: DeserializeOwned + FramePayload,
+{
+    let mut value: T = serde_json::from_slice(header).map_err(io::Error::other)?;
+    if !payload.is_empty() {
+        value.set_payload(Bytes::from(payload))?;
+    }
+    Ok(value)
+}
+
+/// A frame split so a raw payload is written as is, without copying it into
+/// the header buffer
+struct EncodedFrame {
+    head: Vec<u8>,
+    payload: Bytes,
+}
+
+fn encode_frame<T>(mut value: T, encoding: FrameEncoding) -> Result<EncodedFrame>
+where
+    T: Serialize + FramePayload,
+{
+    let payload = match encoding {
+        FrameEncoding::Json => Bytes::new(),
+        FrameEncoding::Binary => value.take_payload(),
+    };
+    let header = serde_json::to_vec(&value)?;
+    let body_length = match encoding {
+        FrameEncoding::Json => header.len(),
+        FrameEncoding::Binary => 4 + header.len() + payload.len(),
+    };
+    if body_length > MAX_FRAME_SIZE {
         bail!("worker frame exceeds {MAX_FRAME_SIZE} bytes");
     }
-    let mut frame = Vec::with_capacity(payload.len() + 4);
-    frame.extend_from_slice(&length.to_be_bytes());
-    frame.extend_from_slice(&payload);
-    Ok(frame)
+    let length = u32::try_from(body_length).context("worker frame is too large")?;
+
+    let mut head = Vec::with_capacity(header.len() + 8);
+    head.extend_from_slice(&length.to_be_bytes());
+    if encoding == FrameEncoding::Binary {
+        head.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    }
+    head.extend_from_slice(&header);
+    Ok(EncodedFrame { head, payload })
+}
+
+/// Frame encoding entry points for `benches/frames.rs`, not a stable API
+#[doc(hidden)]
+pub mod bench {
+    use bytes::Bytes;
+
+    use super::{FrameEncoding, WorkerResponse, WorkerValue, read_frame_sync, write_frame_sync};
+
+    fn encoding(binary: bool) -> FrameEncoding {
+        if binary {
+            FrameEncoding::Binary
+        } else {
+            FrameEncoding::Json
+        }
+    }
+
+    /// Write an image result frame into `buffer`
+    pub fn write_image_frame(buffer: &mut Vec<u8>, image: Vec<u8>, binary: bool) {
+        let response = WorkerResponse::Result {
+            id: 1,
+            value: WorkerValue::Image {
+                bytes: Bytes::from(image),
+            },
+        };
+        write_frame_sync(buffer, response, encoding(binary)).expect("image frame encodes");
+    }
+
+    /// Read back an image result frame written by [`write_image_frame`]
+    pub fn read_image_frame(mut frame: &[u8], binary: bool) -> Bytes {
+        match read_frame_sync::<_, WorkerResponse>(&mut frame, encoding(binary)) {
+            Ok(Some(WorkerResponse::Result {
+                value: WorkerValue::Image { bytes },
+                ..
+            })) => bytes,
+            other => panic!("unexpected frame {other:?}"),
+        }
+    }
 }
 
 mod base64_bytes {
     use base64::{Engine, engine::general_purpose::STANDARD};
     use serde::{Deserialize, Deserializer, Serializer, de::Error};
 
-    pub fn serialize<S>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
+    pub fn serialize<S, T>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
+        T: AsRef<[u8]>,
     {
         serializer.serialize_str(&STANDARD.encode(bytes))
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
     where
         D: Deserializer<'de>,
+        T: From<Vec<u8>>,
     {
         let encoded = String::deserialize(deserializer)?;
-        STANDARD.decode(encoded).map_err(D::Error::custom)
+        STANDARD
+            .decode(encoded)
+            .map(T::from)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_response(bytes: Vec<u8>) -> WorkerResponse {
+        WorkerResponse::Result {
+            id: 7,
+            value: WorkerValue::Image {
+                bytes: Bytes::from(bytes),
+            },
+        }
+    }
+
+    fn round_trip(response: WorkerResponse, encoding: FrameEncoding) -> WorkerResponse {
+        let mut buffer = Vec::new();
+        write_frame_sync(&mut buffer, response, encoding).unwrap();
+        read_frame_sync::<_, WorkerResponse>(&mut buffer.as_slice(), encoding)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn frames_round_trip_image_bytes_in_both_encodings() {
+        let image: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        for encoding in [FrameEncoding::Json, FrameEncoding::Binary] {
+            match round_trip(image_response(image.clone()), encoding) {
+                WorkerResponse::Result {
+                    id: 7,
+                    value: WorkerValue::Image { bytes },
+                } => assert_eq!(bytes, image, "{encoding:?}"),
+                other => panic!("unexpected response {other:?}"),
+            }
+        }
+
+        let mut json = Vec::new();
+        write_frame_sync(
+            &mut json,
+            image_response(image.clone()),
+            FrameEncoding::Json,
+        )
+        .unwrap();
+        let mut binary = Vec::new();
+        write_frame_sync(&mut binary, image_response(image), FrameEncoding::Binary).unwrap();
+        assert!(binary.len() < json.len());
+    }
+
+    #[test]
+    fn http_bodies_round_trip_in_both_directions() {
+        let body: Vec<u8> = (0..=255).cycle().take(1024).collect();
+        for encoding in [FrameEncoding::Json, FrameEncoding::Binary] {
+            let request = tanoshi_lib::http::Request::post("https://example.com/search")
+                .header("Content-Type", "application/octet-stream")
+                .body(body.clone());
+            let mut frame = Vec::new();
+            write_frame_sync(
+                &mut frame,
+                WorkerResponse::HttpRequest {
+                    id: 3,
+                    request: request.clone().into(),
+                },
+                encoding,
+            )
+            .unwrap();
+            match read_frame_sync::<_, WorkerResponse>(&mut frame.as_slice(), encoding) {
+                Ok(Some(WorkerResponse::HttpRequest {
+                    id: 3,
+                    request: sent,
+                })) => {
+                    assert_eq!(
+                        tanoshi_lib::http::Request::from(sent),
+                        request,
+                        "{encoding:?}"
+                    )
+                }
+                other => panic!("unexpected frame {other:?}"),
+            }
+
+            let mut frame = Vec::new();
+            write_frame_sync(
+                &mut frame,
+                WorkerRequestEnvelope {
+                    id: 3,
+                    request: WorkerRequest::HttpResponse {
+                        result: Ok(HostHttpResponse {
+                            status: 200,
+                            headers: vec![("ETag".to_string(), "\"v1\"".to_string())],
+                            body: body.clone(),
+                        }),
+                    },
+                },
+                encoding,
+            )
+            .unwrap();
+            match read_frame_sync::<_, WorkerRequestEnvelope>(&mut frame.as_slice(), encoding) {
+                Ok(Some(WorkerRequestEnvelope {
+                    id: 3,
+                    request:
+                        WorkerRequest::HttpResponse {
+                            result: Ok(response),
+                        },
+                })) => assert_eq!(response.body, body, "{encoding:?}"),
+                other => panic!("unexpected frame {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn wasm_plugins_load_in_place_of_a_native_library() {
+        use super::super::wasm::tests::{RecordingHttp, test_module, write_module};
+
+        let path = write_module("worker-entry", &test_module(tanoshi_lib::LIB_VERSION));
+        let http = Arc::new(RecordingHttp::default());
+        let entry = load_worker_entry(&path, http.clone()).unwrap();
+        assert_eq!(entry.source_info.id, 7);
+        assert_eq!(entry.rustc_version, WASM_RUSTC_VERSION);
+        assert_eq!(entry.lib_version, tanoshi_lib::LIB_VERSION);
+
+        let page = PageInfo::from("https://example.com/1.jpg".to_string());
+        match execute_request(
+            &entry,
+            WorkerRequest::GetPages {
+                path: "/manga/1".to_string(),
+            },
+        ) {
+            Ok(WorkerValue::Pages(pages)) => assert_eq!(pages, [page]),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(http.urls.lock().unwrap().len(), 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// A pooled connection around a process that never answers, enough to
+    /// exercise routing and scaling without a worker binary.
+    #[cfg(unix)]
+    fn idle_connection(generation: u64) -> Arc<WorkerConnection> {
+        idle_connection_with_slots(generation, 1)
+    }
+
+    #[cfg(unix)]
+    fn idle_connection_with_slots(generation: u64, max_in_flight: usize) -> Arc<WorkerConnection> {
+        idle_connection_on(generation, max_in_flight, PROTOCOL_VERSION)
+    }
+
+    #[cfg(unix)]
+    fn idle_connection_on(
+        generation: u64,
+        max_in_flight: usize,
+        protocol_version: u32,
+    ) -> Arc<WorkerConnection> {
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        WorkerConnection::start(
+            fake_process(command, generation, max_in_flight, protocol_version),
+            None,
+        )
+    }
+
+    /// A process that went through the handshake, `command` stands in for
+    /// the worker
+    #[cfg(unix)]
+    fn fake_process(
+        mut command: Command,
+        generation: u64,
+        max_in_flight: usize,
+        protocol_version: u32,
+    ) -> WorkerProcess {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        WorkerProcess {
+            pid: child.id(),
+            child,
+            generation,
+            stdin,
+            stdout: AsyncBufReader::new(stdout),
+            protocol_version,
+            encoding: FrameEncoding::for_protocol(protocol_version),
+            next_request_id: 1,
+            max_in_flight,
+            source_info: WorkerSourceInfo::from(&SourceInfo::default()),
+            rustc_version: String::new(),
+            lib_version: String::new(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn startup_requests_serve_the_extensions_http_requests() {
+        // The worker asks the host for a page before it takes the session.
+        let mut output = Vec::new();
+        for response in [
+            WorkerResponse::HttpRequest {
+                id: 0,
+                request: HostHttpRequest::from(tanoshi_lib::http::Request::get(
+                    "https://example.com/account",
+                )),
+            },
+            WorkerResponse::Result {
+                id: 1,
+                value: WorkerValue::Unit,
+            },
+        ] {
+            write_frame_sync(&mut output, response, FrameEncoding::Binary).unwrap();
+        }
+        let frames =
+            std::env::temp_dir().join(format!("tanoshi-vm-startup-frames-{}", std::process::id()));
+        std::fs::write(&frames, output).unwrap();
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("cat \"$0\"; exec sleep 30")
+            .arg(&frames);
+        let mut worker = fake_process(command, 0, 1, PROTOCOL_VERSION);
+
+        send_startup_request(
+            &mut worker,
+            None,
+            WorkerRequest::RestoreSession {
+                session: SourceSession::default(),
+            },
+            "saved login session",
+        )
+        .await
+        .unwrap();
+        assert_eq!(worker.next_request_id, 2);
+        terminate_process(&mut worker).await;
+        let _ = std::fs::remove_file(frames);
+    }
+
+    #[cfg(unix)]
+    fn pool_client(
+        min_processes: usize,
+        max_processes: usize,
+        idle_timeout: Duration,
+    ) -> Arc<WorkerClient> {
+        pool_client_running(
+            PathBuf::from("/nonexistent/tanoshi-extension-worker"),
+            min_processes,
+            max_processes,
+            idle_timeout,
+        )
+    }
+
+    #[cfg(unix)]
+    fn pool_client_running(
+        worker_path: PathBuf,
+        min_processes: usize,
+        max_processes: usize,
+        idle_timeout: Duration,
+    ) -> Arc<WorkerClient> {
+        WorkerClient::new(
+            PathBuf::from("missing-plugin"),
+            worker_path,
+            Duration::from_secs(1),
+            None,
+            WorkerPoolOptions {
+                min_processes,
+                max_processes,
+                idle_timeout,
+            },
+            None,
+            None,
+        )
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn calls_go_to_the_least_busy_worker_until_the_pool_is_full() {
+        let client = pool_client(1, 2, Duration::from_secs(60));
+        let busy = idle_connection(0);
+        let free = idle_connection(0);
+        client
+            .processes
+            .lock()
+            .await
+            .extend([busy.clone(), free.clone()]);
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        let first = client.process_before(deadline).await.unwrap();
+        let second = client.process_before(deadline).await.unwrap();
+        assert!(!Arc::ptr_eq(&first.connection, &second.connection));
+        // Both workers are full and the pool is at its maximum, so the call
+        // queues on a running worker instead of spawning another.
+        let third = client.process_before(deadline).await.unwrap();
+        assert_eq!(third.connection.assigned(), 2);
+        assert_eq!(client.processes.lock().await.len(), 2);
+
+        drop((first, second, third));
+        assert_eq!(busy.assigned() + free.assigned(), 0);
+        client.pause().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn calls_reach_running_workers_while_another_starts() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A worker that never gets ready.
+        let worker_path =
+            std::env::temp_dir().join(format!("tanoshi-vm-slow-worker-{}.sh", std::process::id()));
+        std::fs::write(&worker_path, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        std::fs::set_permissions(&worker_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let client = pool_client_running(worker_path.clone(), 1, 2, Duration::from_secs(60));
+        let running = idle_connection(0);
+        let busy = running.assign();
+        client.processes.lock().await.push(running.clone());
+
+        let starting = tokio::spawn({
+            let client = client.clone();
+            async move {
+                let deadline = Instant::now() + Duration::from_secs(10);
+                client.process_before(deadline).await.map(|_| ())
+            }
+        });
+        while client.spawning.load(Ordering::Acquire) == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // The starting worker fills the pool, so calls queue on the running
+        // one instead of waiting for the spawn.
+        let deadline = Instant::now() + Duration::from_millis(200);
+        let queued = client.process_before(deadline).await.unwrap();
+        assert!(Arc::ptr_eq(&queued.connection, &running));
+        drop((busy, queued));
+        let free = client.process_before(deadline).await.unwrap();
+        assert!(Arc::ptr_eq(&free.connection, &running));
+        assert!(!starting.is_finished());
+
+        // Giving up on the spawn frees its place in the pool.
+        starting.abort();
+        let _ = starting.await;
+        assert_eq!(client.spawning.load(Ordering::Acquire), 0);
+        assert_eq!(client.processes.lock().await.len(), 1);
+
+        drop(free);
+        client.pause().await;
+        let _ = std::fs::remove_file(worker_path);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn idle_and_stale_workers_are_drained_and_stopped() {
+        let client = pool_client(1, 3, Duration::from_millis(50));
+        let kept = idle_connection(0);
+        let idle = idle_connection(0);
+        let busy = idle_connection(0);
+        let assignment = busy.assign();
+        client
+            .processes
+            .lock()
+            .await
+            .extend([kept.clone(), idle.clone(), busy.clone()]);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        *lock_unpoisoned(&kept.last_used) = Instant::now();
+
+        client.scale_down();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(client.processes.lock().await.len(), 2);
+        assert!(idle.closed().is_some());
+        assert!(busy.closed().is_none());
+
+        // A session change drains the remaining workers; the busy one
+        // finishes its call first.
+        client.set_startup_session(None);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert!(matches!(
+            client.process_before(deadline).await,
+            Err(WorkerCallError::Crashed(_))
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(client.processes.lock().await.is_empty());
+        assert!(kept.closed().is_some());
+        assert!(busy.closed().is_none());
+
+        drop(assignment);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(busy.closed().is_some());
+    }
+
+    /// Start a call on `connection` and wait until its request is pending,
+    /// so calls get their ids in the order they are started.
+    #[cfg(unix)]
+    async fn pending_call(
+        connection: &Arc<WorkerConnection>,
+        chunks: Option<ChunkSender>,
+    ) -> JoinHandle<std::result::Result<WorkerValue, WorkerCallError>> {
+        let pending = lock_unpoisoned(&connection.pending).len();
+        let call = tokio::spawn({
+            let connection = connection.clone();
+            async move {
+                let shutdown = Notify::new();
+                let shutdown = shutdown.notified();
+                tokio::pin!(shutdown);
+                let call = WorkerCall {
+                    request: WorkerRequest::FilterList,
+                    chunks,
+                };
+                let deadline = Instant::now() + Duration::from_secs(5);
+                WorkerClient::request_on(&connection, call, deadline, shutdown).await
+            }
+        });
+        while lock_unpoisoned(&connection.pending).len() == pending {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        call
+    }
+
+    #[cfg(unix)]
+    fn image_result(id: u64, bytes: &[u8]) -> WorkerResponse {
+        WorkerResponse::Result {
+            id,
+            value: WorkerValue::Image {
+                bytes: Bytes::copy_from_slice(bytes),
+            },
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn responses_reach_their_callers_in_any_order() {
+        let connection = idle_connection_with_slots(0, 2);
+        let (chunks, mut received) = mpsc::channel(CHUNK_BUFFER);
+        let streamed = pending_call(&connection, Some(chunks)).await;
+        let plain = pending_call(&connection, None).await;
+
+        for response in [
+            WorkerResponse::Chunk {
+                id: 1,
+                bytes: Bytes::from_static(b"ab"),
+            },
+            image_result(2, b"second"),
+            WorkerResponse::Chunk {
+                id: 1,
+                bytes: Bytes::from_static(b"cd"),
+            },
+            image_result(1, b"first"),
+        ] {
+            connection.dispatch(response).unwrap();
+        }
+
+        assert!(matches!(
+            plain.await.unwrap(),
+            Ok(WorkerValue::Image { bytes }) if &bytes[..] == b"second"
+        ));
+        assert!(matches!(
+            streamed.await.unwrap(),
+            Ok(WorkerValue::Image { bytes }) if &bytes[..] == b"first"
+        ));
+        assert_eq!(received.recv().await.unwrap().unwrap(), "ab");
+        assert_eq!(received.recv().await.unwrap().unwrap(), "cd");
+        assert!(lock_unpoisoned(&connection.pending).is_empty());
+        connection.kill_now();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn chunks_for_unknown_or_plain_requests_are_protocol_errors() {
+        let connection = idle_connection_with_slots(0, 2);
+        let plain = pending_call(&connection, None).await;
+
+        let chunk = |id| WorkerResponse::Chunk {
+            id,
+            bytes: Bytes::from_static(b"ab"),
+        };
+        assert!(connection.dispatch(chunk(42)).is_err());
+        assert!(connection.dispatch(chunk(1)).is_err());
+        assert!(connection.dispatch(image_result(42, b"")).is_err());
+
+        // The call itself is still pending until the worker answers it.
+        connection.dispatch(image_result(1, b"answer")).unwrap();
+        assert!(plain.await.unwrap().is_ok());
+        connection.kill_now();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_slow_stream_is_cancelled_without_holding_back_other_calls() {
+        let connection = idle_connection_with_slots(0, 2);
+        let (chunks, mut received) = mpsc::channel(1);
+        let streamed = pending_call(&connection, Some(chunks)).await;
+        let plain = pending_call(&connection, None).await;
+
+        let chunk = || WorkerResponse::Chunk {
+            id: 1,
+            bytes: Bytes::from_static(b"ab"),
+        };
+        // Nobody reads the stream, its second chunk overflows the buffer.
+        connection.dispatch(chunk()).unwrap();
+        connection.dispatch(chunk()).unwrap();
+        assert!(matches!(
+            streamed.await.unwrap(),
+            Err(WorkerCallError::Remote {
+                kind: WorkerErrorKind::Operation,
+                ..
+            })
+        ));
+
+        // The rest of the cancelled stream is discarded while the other call
+        // still gets its answer.
+        connection.dispatch(image_result(2, b"plain")).unwrap();
+        connection.dispatch(chunk()).unwrap();
+        connection.dispatch(image_result(1, b"")).unwrap();
+        assert!(matches!(
+            plain.await.unwrap(),
+            Ok(WorkerValue::Image { bytes }) if &bytes[..] == b"plain"
+        ));
+        assert!(lock_unpoisoned(&connection.pending).is_empty());
+        assert_eq!(received.recv().await.unwrap().unwrap(), "ab");
+        assert!(received.recv().await.is_none());
+        assert!(connection.closed().is_none());
+        connection.kill_now();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn pending_calls_fail_when_the_worker_goes_away() {
+        let connection = idle_connection_with_slots(0, 2);
+        let (chunks, mut received) = mpsc::channel(CHUNK_BUFFER);
+        let streamed = pending_call(&connection, Some(chunks)).await;
+        let plain = pending_call(&connection, None).await;
+
+        // The reader sees the worker's stdout close and fails every call.
+        connection.child.lock().await.start_kill().unwrap();
+        for call in [streamed, plain] {
+            assert!(matches!(
+                call.await.unwrap(),
+                Err(WorkerCallError::Crashed(_))
+            ));
+        }
+        assert!(received.recv().await.is_none());
+        assert!(matches!(
+            connection.closed(),
+            Some(ConnectionClosed::Crashed(_))
+        ));
+
+        // Later calls are refused instead of waiting for an answer.
+        let shutdown = Notify::new();
+        let shutdown = shutdown.notified();
+        tokio::pin!(shutdown);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert!(matches!(
+            WorkerClient::request_on(
+                &connection,
+                WorkerRequest::FilterList.into(),
+                deadline,
+                shutdown
+            )
+            .await,
+            Err(WorkerCallError::Crashed(_))
+        ));
+    }
+
+    #[test]
+    fn older_workers_get_json_frames_and_one_call_at_a_time() {
+        let ready = serde_json::json!({
+            "Ready": {
+                "protocol_version": 2,
+                "source_info": {
+                    "id": 1,
+                    "name": "old",
+                    "url": "https://example.com",
+                    "version": "0.1.0",
+                    "icon": "",
+                    "languages": "All",
+                    "nsfw": false,
+                },
+                "rustc_version": "",
+                "lib_version": "",
+            }
+        });
+        let Ok(WorkerResponse::Ready {
+            protocol_version,
+            max_in_flight,
+            source_info,
+            ..
+        }) = serde_json::from_value(ready)
+        else {
+            panic!("protocol 2 readiness did not parse");
+        };
+        assert_eq!(max_in_flight, 1);
+        assert_eq!(source_info.capabilities, Capabilities::default());
+        assert_eq!(
+            FrameEncoding::for_protocol(protocol_version),
+            FrameEncoding::Json
+        );
+        assert_eq!(
+            FrameEncoding::for_protocol(PROTOCOL_VERSION),
+            FrameEncoding::Binary
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn requests_a_worker_predates_never_reach_it() {
+        let connection = idle_connection_on(0, 1, MIN_PROTOCOL_VERSION);
+        for request in [
+            WorkerRequest::IsLoggedIn,
+            WorkerRequest::StreamImage {
+                page: PageInfo::default(),
+            },
+        ] {
+            let shutdown = Notify::new();
+            let shutdown = shutdown.notified();
+            tokio::pin!(shutdown);
+            let deadline = Instant::now() + Duration::from_secs(5);
+            assert!(matches!(
+                WorkerClient::request_on(&connection, request.into(), deadline, shutdown).await,
+                Err(WorkerCallError::Remote {
+                    kind: WorkerErrorKind::Operation,
+                    ..
+                })
+            ));
+        }
+        assert!(lock_unpoisoned(&connection.pending).is_empty());
+        assert!(connection.closed().is_none());
+        connection.kill_now();
     }
 }
@@ -10,3 +10,5 @@ pub const PLUGIN_EXTENSION: &str = "dll";
 pub const PLUGIN_EXTENSION: &str = "dylib";
 #[cfg(target_os = "linux")]
 pub const PLUGIN_EXTENSION: &str = "so";
+/// Extension of plugins compiled to WebAssembly, which load on any target
+pub const WASM_PLUGIN_EXTENSION: &str = "wasm";
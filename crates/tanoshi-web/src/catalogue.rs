@@ -7,10 +7,12 @@ use crate::{
     utils::{history, local_storage, window, AsyncLoader},
 };
 use dominator::{clone, events, html, routing, svg, with_node, Dom, EventOptions};
+use futures_signals::map_ref;
 use futures_signals::signal::{Mutable, Signal, SignalExt};
 use futures_signals::signal_vec::{MutableVec, SignalVecExt};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::{JsValue, UnwrapThrowExt};
+use wasm_bindgen_futures::spawn_local;
 use web_sys::HtmlInputElement;
 
 pub const STORAGE_KEY: &str = "catalogue";
@@ -19,6 +21,7 @@ macro_rules! map_data_to_cover {
     ($data:ident, $catalogue:ident, $field:tt) => {
         let covers = $data
             .$field
+            .manga
             .iter()
             .map(|item| {
                 Cover::new(
@@ -33,6 +36,7 @@ macro_rules! map_data_to_cover {
                 )
             })
             .collect();
+        $catalogue.has_next_page.set_neq($data.$field.has_next_page);
         let mut cover_list = $catalogue.cover_list.lock_mut();
         if $catalogue.page.get() == 1 {
             cover_list.replace_cloned(covers);
@@ -50,11 +54,16 @@ pub struct Catalogue {
     keyword: Mutable<Option<String>>,
     latest: Mutable<bool>,
     page: Mutable<i64>,
+    has_next_page: Mutable<bool>,
     is_search: Mutable<bool>,
     is_filter: Mutable<bool>,
     cover_list: MutableVec<Cover>,
     input_list_modal: Rc<InputList>,
     #[serde(skip)]
+    can_search: Mutable<bool>,
+    #[serde(skip)]
+    can_filter: Mutable<bool>,
+    #[serde(skip)]
     loader: AsyncLoader,
     #[serde(skip)]
     spinner: Rc<Spinner>,
@@ -72,10 +81,13 @@ impl Default for Catalogue {
             keyword: Mutable::new(None),
             latest: Mutable::new(false),
             page: Mutable::new(1),
+            has_next_page: Mutable::new(true),
             is_search: Mutable::new(false),
             is_filter: Mutable::new(false),
             cover_list: MutableVec::new(),
             input_list_modal: Rc::new(InputList::new(true)),
+            can_search: Mutable::new(true),
+            can_filter: Mutable::new(true),
             spinner: Spinner::new(),
             loader: AsyncLoader::new(),
             migration_state: Mutable::new(None),
@@ -111,6 +123,7 @@ impl Catalogue {
         if catalogue.source_id != source_id || entering_migrate_mode {
             catalogue.cover_list = MutableVec::new();
             catalogue.page = Mutable::new(1);
+            catalogue.has_next_page = Mutable::new(true);
             catalogue.latest = Mutable::new(false);
             catalogue.keyword = Mutable::new(None);
             catalogue.is_filter = Mutable::new(false);
@@ -142,6 +155,21 @@ impl Catalogue {
         }))
     }
 
+    fn fetch_source_capabilities(catalogue: Rc<Self>) {
+        spawn_local(clone!(catalogue => async move {
+            match query::fetch_source_capabilities(catalogue.source_id).await {
+                Ok(capabilities) => {
+                    catalogue.can_search.set_neq(capabilities.search);
+                    // the filter modal searches without a query
+                    catalogue.can_filter.set_neq(capabilities.filters && !capabilities.search_requires_query);
+                }
+                Err(e) => {
+                    error!("Fetch source capabilities failed: {}", e);
+                }
+            }
+        }));
+    }
+
     pub fn fetch_mangas(catalogue: Rc<Self>) {
         catalogue.replace_state_with_url();
         catalogue.spinner.set_active(true);
@@ -329,6 +357,7 @@ impl Catalogue {
                         .children(&mut [
                             html!("button", {
                                 .attr("id", "search")
+                                .visible_signal(catalogue.can_search.signal())
                                 .event(clone!(catalogue => move |_: events::Click| {
                                     catalogue.is_search.set_neq(true);
                                 }))
@@ -353,6 +382,7 @@ impl Catalogue {
                             html!("button", {
                                 .attr("id", "filter")
                                 .style("margin-left", "0.5rem")
+                                .visible_signal(catalogue.can_filter.signal())
                                 .event(clone!(catalogue => move |_: events::Click| {
                                     if catalogue.input_list_modal.input_list.lock_ref().is_empty() {
                                         Self::fetch_source_filters(catalogue.clone());
@@ -403,6 +433,15 @@ impl Catalogue {
             })
     }
 
+    fn load_more_signal(&self) -> impl Signal<Item = (bool, bool)> + use<> {
+        map_ref! {
+            let loading = self.spinner.signal(),
+            let has_next_page = self.has_next_page.signal() =>
+
+            (*loading, *has_next_page)
+        }
+    }
+
     pub fn render_main(catalogue: Rc<Self>) -> Dom {
         use futures_signals::signal::SignalExt;
 
@@ -456,9 +495,11 @@ impl Catalogue {
 
                 html!("div", {
                     .class("load-more-btn")
-                    .child_signal(catalogue.spinner.signal().map(clone!(catalogue => move |x| {
-                        if x {
+                    .child_signal(catalogue.load_more_signal().map(clone!(catalogue => move |(loading, has_next_page)| {
+                        if loading {
                             Some(Spinner::render(catalogue.spinner.clone()))
+                        } else if !has_next_page {
+                            None
                         } else {
                             Some(html!("button", {
                                 .text("Load More")
@@ -481,6 +522,7 @@ impl Catalogue {
         self.keyword.set_neq(query);
         self.latest.set_neq(latest);
 
+        Self::fetch_source_capabilities(self.clone());
         if self.cover_list.lock_ref().is_empty() {
             Self::fetch_mangas(self.clone());
         }
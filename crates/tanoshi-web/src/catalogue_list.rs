@@ -26,6 +26,9 @@ struct Source {
     name: String,
     version: String,
     icon: String,
+    popular: bool,
+    latest: bool,
+    search: bool,
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -77,7 +80,7 @@ impl CatalogueList {
     }
 
     pub fn fetch_manga_from_all_sources(catalogue: Rc<Self>) {
-        let sources: Vec<Source> = catalogue.sources.lock_ref().iter().cloned().collect();
+        let sources: Vec<Source> = catalogue.sources.lock_ref().iter().filter(|source| source.search).cloned().collect();
         let keyword = catalogue.keyword.get_cloned();
         catalogue.loader.load(clone!(catalogue => async move {
             for source in sources {
@@ -85,6 +88,7 @@ impl CatalogueList {
                     Ok(data) => {
                         let covers = data
                             .browse_source
+                            .manga
                             .iter()
                             .map(|item| {
                                 Cover::new(
@@ -122,10 +126,13 @@ impl CatalogueList {
                         name: s.name.clone(),
                         version: s.version.clone(),
                         icon: s.icon.clone(),
+                        popular: s.capabilities.popular,
+                        latest: s.capabilities.latest,
+                        search: s.capabilities.search,
                     }).collect();
 
                     let mut cover_list_map = catalogue.cover_list_map.lock_mut();
-                    for source in sources.iter() {
+                    for source in sources.iter().filter(|source| source.search) {
                         cover_list_map.insert_cloned(source.id, SourceManga{name: source.name.clone(), covers: vec![]});
                     }
 
@@ -237,7 +244,7 @@ impl CatalogueList {
         html!("li", {
             .class("list-item")
             .children(&mut [
-                link!(Route::Catalogue{id: source.id, latest: false, query: None}.url(), {
+                link!(Route::Catalogue{id: source.id, latest: !source.popular && source.latest, query: None}.url(), {
                     .class("source-item")
                     .children(&mut [
                         html!("img", {
@@ -251,6 +258,7 @@ impl CatalogueList {
                 }),
                 link!(Route::Catalogue{id: source.id, latest: true, query: None}.url(), {
                     .class("source-action")
+                    .visible(source.popular && source.latest)
                     .text("latest")
                 }),
             ])
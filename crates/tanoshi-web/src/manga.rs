@@ -1,6 +1,6 @@
 use crate::{
     common::{
-        ChapterSettings, ChapterSort, Filter,  Order, Route, Sort, Spinner, snackbar, SelectCategoryModal, SelectTrackMangaModal, TrackerStatus, icons
+        ChapterSettings, ChapterSort, Cover, Filter,  Order, Route, Sort, Spinner, snackbar, SelectCategoryModal, SelectTrackMangaModal, TrackerStatus, icons
     }, 
     query, 
     utils::{AsyncLoader, proxied_image_url, window, format_number_title}
@@ -11,6 +11,7 @@ use futures_signals::{signal::{self, Mutable, SignalExt}, signal_vec::{MutableVe
 use gloo_timers::callback::Timeout;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
 use web_sys::HtmlInputElement;
 
 #[derive(Clone)]
@@ -56,6 +57,7 @@ pub struct Manga {
     is_favorite: Mutable<bool>,
     next_chapter: Mutable<Option<Chapter>>,
     chapters: MutableVec<Rc<Chapter>>,
+    related: MutableVec<Cover>,
     is_edit_chapter: Mutable<bool>,
     selected_chapters: MutableBTreeMap<i64, ()>,
     is_tracker_available: Mutable<bool>,
@@ -90,6 +92,7 @@ impl Manga {
             is_favorite: Mutable::new(false),
             next_chapter: Mutable::new(None),
             chapters: MutableVec::new(),
+            related: MutableVec::new(),
             is_edit_chapter: Mutable::new(false),
             selected_chapters: MutableBTreeMap::new(),
             is_tracker_available: Mutable::new(false),
@@ -115,13 +118,14 @@ impl Manga {
                 Ok(result) => {
                     manga.source_id.set(result.source.id);
                     manga.source_name.set(result.source.name);
+                    manga.path.set_neq(result.path);
                     manga.title.set_neq(Some(result.title));
                     manga.author.lock_mut().replace_cloned(result.author);
                     manga.genre.lock_mut().replace_cloned(result.genre);
                     manga.cover_url.set_neq(Some(result.cover_url));
                     manga.description.set_neq(result.description);
                     manga.link.set_neq(Some(result.link));
-                    manga.status.set_neq(result.status);
+                    manga.status.set_neq(result.status.map(|status| status.to_string()));
                     manga.is_favorite.set_neq(result.is_favorite);
                     manga.next_chapter.set(result.next_chapter.map(|chapter| Chapter {
                         id: chapter.id,
@@ -156,6 +160,7 @@ impl Manga {
                     })).collect());
 
                     manga.chapter_settings.load_by_manga_id(manga.id.get());                    
+                    Self::fetch_related(manga.clone());
                 },
                 Err(err) => {
                     snackbar::show(format!("{err}"));
@@ -176,7 +181,7 @@ impl Manga {
                     manga.cover_url.set_neq(Some(result.cover_url));
                     manga.description.set_neq(result.description);
                     manga.link.set_neq(Some(result.link));
-                    manga.status.set_neq(result.status);
+                    manga.status.set_neq(result.status.map(|status| status.to_string()));
                     manga.is_favorite.set_neq(result.is_favorite);
                     manga.next_chapter.set(result.next_chapter.map(|chapter| Chapter {
                         id: chapter.id,
@@ -211,6 +216,7 @@ impl Manga {
                     })).collect());
 
                     manga.chapter_settings.load_by_manga_id(manga.id.get());
+                    Self::fetch_related(manga.clone());
                 },
                 Err(err) => {
                     snackbar::show(format!("{err}"));
@@ -219,6 +225,30 @@ impl Manga {
         }));
     }
 
+    fn fetch_related(manga: Rc<Self>) {
+        // related manga are optional, load them without blocking the page
+        spawn_local(clone!(manga => async move {
+            match query::fetch_related_manga(manga.source_id.get(), manga.path.get_cloned()).await {
+                Ok(result) => {
+                    let source_id = manga.source_id.get();
+                    manga.related.lock_mut().replace_cloned(result.into_iter().map(|item| Cover::new(
+                        item.id,
+                        source_id,
+                        item.path,
+                        item.title,
+                        item.cover_url,
+                        item.is_favorite,
+                        None,
+                        0,
+                    )).collect());
+                }
+                Err(err) => {
+                    debug!("failed to fetch related manga: {err}");
+                }
+            }
+        }));
+    }
+
     fn mark_chapter_as_read(manga: Rc<Self>) {
         let selected_chapter_id: Vec<i64> = manga.selected_chapters.lock_ref().keys().cloned().collect();
 
@@ -747,6 +777,26 @@ impl Manga {
         })
     }
 
+    pub fn render_related(manga: Rc<Self>) -> Dom {
+        html!("div", {
+            .attr("id", "related")
+            .style("display", "flex")
+            .style("flex-direction", "column")
+            .style("margin", "0.5rem")
+            .visible_signal(manga.related.signal_vec_cloned().len().map(|len| len > 0))
+            .children(&mut [
+                html!("span", {
+                    .class("header")
+                    .text("Related")
+                }),
+                html!("div", {
+                    .class("manga-carousel")
+                    .children_signal_vec(manga.related.signal_vec_cloned().map(|cover| cover.render()))
+                })
+            ])
+        })
+    }
+
     pub fn render_chapters(manga: Rc<Self>) -> Dom {
         let is_edit_chapter = manga.is_edit_chapter.clone();
         let filter = manga.chapter_settings.filter.clone();
@@ -938,6 +988,7 @@ impl Manga {
                         Self::render_header(manga_page.clone()),
                         Self::render_action(manga_page.clone()),
                         Self::render_description(manga_page.clone()),
+                        Self::render_related(manga_page.clone()),
                         html!("div", {
                             .style("height", "2.5rem")
                         })
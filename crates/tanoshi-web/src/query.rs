@@ -93,6 +93,14 @@ pub async fn fetch_source_filters(
     Ok(data)
 }
 
+pub async fn fetch_source_capabilities(
+    source_id: i64,
+) -> Result<fetch_source_capabilities::FetchSourceCapabilitiesSourceCapabilities, Box<dyn Error>> {
+    let var = fetch_source_capabilities::Variables { source_id };
+    let data = post_graphql::<FetchSourceCapabilities>(var).await?;
+    Ok(data.source.capabilities)
+}
+
 pub async fn fetch_manga_from_favorite(
     category_id: Option<i64>,
 ) -> Result<Vec<Cover>, Box<dyn Error>> {
@@ -145,6 +153,19 @@ pub async fn fetch_manga_detail(
     Ok(data.manga)
 }
 
+pub async fn fetch_related_manga(
+    source_id: i64,
+    path: String,
+) -> Result<Vec<fetch_related_manga::FetchRelatedMangaMangaBySourcePathRelated>, Box<dyn Error>> {
+    let var = fetch_related_manga::Variables {
+        source_id: Some(source_id),
+        path: Some(path),
+    };
+    let data = post_graphql::<FetchRelatedManga>(var).await?;
+
+    Ok(data.manga_by_source_path.related)
+}
+
 pub async fn fetch_chapter(
     chapter_id: i64,
 ) -> Result<fetch_chapter::FetchChapterChapter, Box<dyn Error>> {
@@ -372,6 +393,26 @@ pub async fn uninstall_source(source_id: i64) -> Result<i64, Box<dyn Error>> {
     Ok(data.uninstall_source)
 }
 
+pub async fn source_login(
+    source_id: i64,
+    username: String,
+    password: String,
+) -> Result<(), Box<dyn Error>> {
+    let var = source_login::Variables {
+        source_id,
+        username,
+        password,
+    };
+    let _ = post_graphql::<SourceLogin>(var).await?;
+    Ok(())
+}
+
+pub async fn source_logout(source_id: i64) -> Result<(), Box<dyn Error>> {
+    let var = source_logout::Variables { source_id };
+    let _ = post_graphql::<SourceLogout>(var).await?;
+    Ok(())
+}
+
 pub async fn user_login(username: String, password: String) -> Result<String, Box<dyn Error>> {
     let var = user_login::Variables {
         login: user_login::LoginInput { username, password },
@@ -3,14 +3,22 @@ use crate::{
     query,
     utils::AsyncLoader,
 };
-use dominator::{clone, html, routing, Dom};
-use futures_signals::signal::{self, Mutable, SignalExt};
+use dominator::{clone, html, routing, with_node, Dom, EventOptions};
+use futures_signals::{
+    map_ref,
+    signal::{self, Mutable, SignalExt},
+};
 use std::rc::Rc;
+use web_sys::HtmlInputElement;
 
 pub struct SettingsSource {
     source_id: i64,
     source: Mutable<Option<Source>>,
     input_list: Rc<InputList>,
+    requires_login: Mutable<bool>,
+    is_logged_in: Mutable<bool>,
+    username: Mutable<String>,
+    password: Mutable<String>,
     loader: AsyncLoader,
 }
 
@@ -20,6 +28,10 @@ impl SettingsSource {
             source_id,
             input_list: Rc::new(InputList::new(false)),
             source: Mutable::new(None),
+            requires_login: Mutable::new(false),
+            is_logged_in: Mutable::new(false),
+            username: Mutable::new("".to_string()),
+            password: Mutable::new("".to_string()),
             loader: AsyncLoader::new(),
         }
     }
@@ -37,6 +49,8 @@ impl SettingsSource {
                         installed: true,
                     }));
                     settings.input_list.set(s.preferences);
+                    settings.requires_login.set_neq(s.capabilities.requires_login);
+                    settings.is_logged_in.set_neq(s.is_logged_in);
                 },
                 Err(err) => {
                     snackbar::show(format!("{err}"));
@@ -56,6 +70,104 @@ impl SettingsSource {
         }));
     }
 
+    fn login(settings: Rc<Self>) {
+        settings.loader.load(clone!(settings => async move {
+            match query::source_login(settings.source_id, settings.username.get_cloned(), settings.password.get_cloned()).await {
+                Ok(()) => {
+                    settings.password.set("".to_string());
+                    settings.is_logged_in.set_neq(true);
+                }
+                Err(err) => {
+                    snackbar::show(format!("{err}"));
+                }
+            }
+        }));
+    }
+
+    fn logout(settings: Rc<Self>) {
+        settings.loader.load(clone!(settings => async move {
+            match query::source_logout(settings.source_id).await {
+                Ok(()) => {
+                    settings.is_logged_in.set_neq(false);
+                }
+                Err(err) => {
+                    snackbar::show(format!("{err}"));
+                }
+            }
+        }));
+    }
+
+    fn render_login(settings: Rc<Self>) -> Dom {
+        html!("div", {
+            .style("display", "flex")
+            .style("flex-direction", "column")
+            .style("width", "100%")
+            .visible_signal(map_ref! {
+                let requires_login = settings.requires_login.signal(),
+                let is_logged_in = settings.is_logged_in.signal() =>
+                *requires_login || *is_logged_in
+            })
+            .child_signal(settings.is_logged_in.signal().map(clone!(settings => move |is_logged_in| if is_logged_in {
+                Some(html!("div", {
+                    .style("display", "flex")
+                    .style("justify-content", "space-between")
+                    .style("align-items", "center")
+                    .children(&mut [
+                        html!("span", {
+                            .text("Logged in")
+                        }),
+                        html!("button", {
+                            .text("Logout")
+                            .event(clone!(settings => move |_: events::Click| {
+                                Self::logout(settings.clone());
+                            }))
+                        })
+                    ])
+                }))
+            } else {
+                Some(html!("form", {
+                    .style("display", "flex")
+                    .style("flex-direction", "column")
+                    .children(&mut [
+                        html!("input" => HtmlInputElement, {
+                            .attr("type", "username")
+                            .attr("placeholder", "Username")
+                            .prop_signal("value", settings.username.signal_cloned())
+                            .with_node!(input => {
+                                .event(clone!(settings => move |_: events::Input| {
+                                    settings.username.set(input.value());
+                                }))
+                            })
+                        }),
+                        html!("input" => HtmlInputElement, {
+                            .attr("type", "password")
+                            .attr("placeholder", "Password")
+                            .prop_signal("value", settings.password.signal_cloned())
+                            .with_node!(input => {
+                                .event(clone!(settings => move |_: events::Input| {
+                                    settings.password.set(input.value());
+                                }))
+                            })
+                        }),
+                        html!("div", {
+                            .style("display", "flex")
+                            .style("justify-content", "flex-end")
+                            .children(&mut [
+                                html!("button", {
+                                    .text("Login")
+                                    .event_with_options(&EventOptions::preventable(), clone!(settings => move |e: events::Click| {
+                                        e.prevent_default();
+                                        Self::login(settings.clone());
+                                    }))
+                                })
+                            ])
+                        })
+                    ])
+                }))
+            })))
+        })
+    }
+
     fn uninstall_source(settings: Rc<Self>, id: i64) {
         settings.loader.load(async move {
             match query::uninstall_source(id).await {
@@ -96,6 +208,7 @@ impl SettingsSource {
                 ])
             }))))
             .children(&mut [
+                Self::render_login(settings.clone()),
                 InputList::render(settings.input_list.clone(), clone!(settings => move || {
                     Self::set_source_preferences(settings.clone());
                 }))
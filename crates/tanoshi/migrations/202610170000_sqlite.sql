@@ -0,0 +1,4 @@
+ALTER TABLE manga ADD COLUMN alternative_titles TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE manga ADD COLUMN artist TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE manga ADD COLUMN content_rating TEXT;
+ALTER TABLE manga ADD COLUMN external_ids TEXT NOT NULL DEFAULT '{}';
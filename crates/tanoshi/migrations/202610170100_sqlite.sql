@@ -0,0 +1,4 @@
+ALTER TABLE chapter ADD COLUMN volume TEXT;
+ALTER TABLE chapter ADD COLUMN language TEXT;
+ALTER TABLE chapter ADD COLUMN page_count INTEGER;
+ALTER TABLE chapter ADD COLUMN external_url TEXT;
@@ -0,0 +1,6 @@
+CREATE TABLE source_credential (
+    source_id INTEGER PRIMARY KEY,
+    credentials TEXT NOT NULL,
+    session TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
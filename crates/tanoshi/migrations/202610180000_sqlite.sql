@@ -0,0 +1,16 @@
+CREATE TABLE source_install_event (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_id INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    version TEXT NOT NULL,
+    previous_version TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_source_install_event_source_id ON source_install_event(source_id, created_at);
+
+CREATE TABLE source_pin (
+    source_id INTEGER PRIMARY KEY,
+    version TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -13,7 +13,9 @@ use tokio::task::JoinHandle;
 use tanoshi_notifier::{gotify::Gotify, pushover::Pushover, telegram::Telegram};
 use tanoshi_tracker::{AniList, MyAnimeList};
 use tanoshi_vm::{
-    extension::{ExtensionManager, ExtensionManagerOptions},
+    extension::{
+        ExtensionManager, ExtensionManagerOptions, HttpOptions, SandboxOptions, SecretKey,
+    },
     prelude::Source,
 };
 
@@ -67,14 +69,46 @@ pub async fn bootstrap(config: Config) -> Result<App> {
             admission_timeout: Duration::from_millis(config.extension.admission_timeout_ms),
             metadata_timeout: Duration::from_secs(config.extension.metadata_timeout_secs),
             image_timeout: Duration::from_secs(config.extension.image_timeout_secs),
+            min_worker_processes: config.extension.min_worker_processes,
+            max_worker_processes: config.extension.max_worker_processes,
+            worker_idle_timeout: Duration::from_secs(config.extension.worker_idle_timeout_secs),
+            keep_versions: config.extension.keep_versions,
+            hot_reload: config.extension.hot_reload,
+            preference_key: Some(SecretKey::new(&config.secret, "source preferences")?),
+            sandbox: SandboxOptions {
+                memory_limit_mb: config.extension.sandbox.memory_limit_mb,
+                cpu_time_limit_secs: config.extension.sandbox.cpu_time_limit_secs,
+                max_open_files: config.extension.sandbox.max_open_files,
+                seccomp: config.extension.sandbox.seccomp,
+                landlock: config.extension.sandbox.landlock,
+                private_network: config.extension.sandbox.private_network,
+                allowed_private_hosts: config.extension.sandbox.allowed_private_hosts.clone(),
+                strict: config.extension.sandbox.strict,
+            },
+            http: HttpOptions {
+                user_agent: config
+                    .extension
+                    .http
+                    .user_agent
+                    .clone()
+                    .unwrap_or_else(|| format!("Tanoshi/{}", env!("CARGO_PKG_VERSION"))),
+                proxy: config.extension.http.proxy.clone(),
+                timeout: Duration::from_secs(config.extension.http.timeout_secs),
+                cache_size: (config.extension.http.cache_size_mb as usize)
+                    .saturating_mul(1024 * 1024),
+                fixtures: None,
+            },
         },
     );
 
     info!("loading extensions from {}", config.plugin_path);
     extension_manager.load_all().await?;
+    if let Err(e) = extension_manager.watch() {
+        warn!("failed to watch {} for changes: {e}", config.plugin_path);
+    }
 
-    let source_repo = SourceRepositoryImpl::new(extension_manager.clone());
-    let source_svc = SourceService::new(source_repo);
+    let source_repo = SourceRepositoryImpl::new(pool.clone(), extension_manager.clone());
+    let source_svc = SourceService::new(source_repo.clone());
 
     let manga_repo = MangaRepositoryImpl::new(pool.clone());
     let manga_svc = MangaService::new(manga_repo.clone(), extension_manager.clone());
@@ -113,6 +147,18 @@ pub async fn bootstrap(config: Config) -> Result<App> {
         }
     }
 
+    // Logins may each wait on a slow site, don't hold up startup for them.
+    tokio::spawn({
+        let source_svc = source_svc.clone();
+        let secret = config.secret.clone();
+        async move {
+            if let Err(e) = source_svc.restore_logins(&secret).await {
+                error!("failed to restore source logins: {e}");
+            }
+        }
+    });
+    source_svc.restore_request_overrides(&config.secret).await?;
+
     let mut notifier_builder = notification::Builder::new(user_repo.clone());
 
     let mut telegram_bot: Option<BoxFuture<'static, ()>> = None;
@@ -137,6 +183,8 @@ pub async fn bootstrap(config: Config) -> Result<App> {
 
     let notifier = notifier_builder.finish();
 
+    worker::challenges::start(extension_manager.clone(), notifier.clone());
+
     // validate tracker configuration before spawning workers so a config
     // error cannot leave detached workers running against the pool
     let mal_client = if let Some(mal_cfg) = config.myanimelist.as_ref() {
@@ -180,9 +228,10 @@ pub async fn bootstrap(config: Config) -> Result<App> {
             library_repo.clone(),
             manga_repo.clone(),
             chapter_repo.clone(),
+            source_repo,
             extension_manager.clone(),
             notifier.clone(),
-            config.extension_repository.clone(),
+            config.extension_repositories.clone(),
             &config.cache_path,
         );
 
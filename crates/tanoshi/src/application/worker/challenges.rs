@@ -0,0 +1,59 @@
+use tanoshi_vm::extension::{ExtensionManager, RequestOverrideKind, SourceChallenge};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::infrastructure::{
+    domain::repositories::user::UserRepositoryImpl, notification::Notification,
+};
+
+/// Notify admins when a source starts getting anti-bot challenge pages, so
+/// they can set fresh clearance cookies for it
+pub fn start(
+    extensions: ExtensionManager,
+    notifier: Notification<UserRepositoryImpl>,
+) -> JoinHandle<()> {
+    let mut challenges = extensions.subscribe_challenges();
+
+    tokio::spawn(async move {
+        loop {
+            let challenge = match challenges.recv().await {
+                Ok(challenge) => challenge,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("missed {skipped} source challenge notifications");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let message = challenge_message(&extensions, &challenge);
+            if let Err(e) = notifier.send_all_to_admins(None, &message).await {
+                error!("failed to send source challenge notification to admin, {e}");
+            }
+        }
+    })
+}
+
+fn challenge_message(extensions: &ExtensionManager, challenge: &SourceChallenge) -> String {
+    let mut message = format!(
+        "{} is getting challenge pages (HTTP {}) from {}",
+        challenge.source_name, challenge.status, challenge.url
+    );
+
+    let now = chrono::Utc::now().timestamp();
+    let expired = extensions
+        .request_overrides(challenge.source_id)
+        .0
+        .iter()
+        .filter(|entry| entry.kind == RequestOverrideKind::Cookie && entry.is_expired(now))
+        .map(|entry| entry.name.clone())
+        .collect::<Vec<_>>();
+    if expired.is_empty() {
+        message.push_str("\nSet clearance cookies for the source to restore it");
+    } else {
+        message.push_str(&format!(
+            "\nIts override cookies expired: {}",
+            expired.join(", ")
+        ));
+    }
+
+    message
+}
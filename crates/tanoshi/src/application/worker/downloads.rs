@@ -175,7 +175,7 @@ where
                 chapter_id: chapter.id,
                 chapter_title: chapter_title.clone(),
                 rank: rank as _,
-                url: page.clone(),
+                page: page.clone(),
                 priority,
                 date_added,
             });
@@ -229,7 +229,7 @@ where
                 Some(manga.author.clone())
             },
             genre: Some(manga.genre.clone()),
-            status: manga.status.clone(),
+            status: manga.status.map(|status| status.to_string()),
             description: manga.description.clone(),
             cover_path: None,
         };
@@ -246,9 +246,9 @@ where
             return Ok(());
         };
 
-        debug!("got {}", queue.url);
+        debug!("got {}", queue.page.url);
 
-        let url = Url::parse(&queue.url)?;
+        let url = Url::parse(&queue.page.url)?;
 
         let filename = format!(
             "{:04}_{}",
@@ -274,14 +274,18 @@ where
 
             let mut attempts = 0;
             let data = loop {
-                let results = self
+                let results = match self
                     .ext
-                    .get_image_bytes(queue.source_id, url.to_string())
-                    .await;
+                    .get_image_bytes(queue.source_id, queue.page.clone())
+                    .await
+                {
+                    Ok(stream) => stream.collect_bytes().await,
+                    Err(e) => Err(e),
+                };
                 match results {
                     Ok(bytes) => break bytes,
                     Err(e) => {
-                        error!("failed to download {} (attempt {}/{MAX_RETRIES}), reason: {e}", queue.url, attempts + 1);
+                        error!("failed to download {} (attempt {}/{MAX_RETRIES}), reason: {e}", queue.page.url, attempts + 1);
                     }
                 }
                 attempts += 1;
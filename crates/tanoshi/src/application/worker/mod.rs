@@ -1,2 +1,3 @@
+pub mod challenges;
 pub mod downloads;
 pub mod updates;
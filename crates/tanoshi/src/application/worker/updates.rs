@@ -15,11 +15,12 @@ use tanoshi_vm::extension::{ExtensionError, ExtensionManager};
 
 use crate::{
     domain::{
-        entities::{chapter::Chapter, manga::Manga},
+        entities::{chapter::Chapter, manga::Manga, source::ExtensionRepository},
         repositories::{
             chapter::{ChapterRepository, ChapterRepositoryError},
             library::{LibraryRepository, LibraryRepositoryError},
             manga::MangaRepository,
+            source::SourceRepository,
         },
     },
     infrastructure::{domain::repositories::user::UserRepositoryImpl, notification::Notification},
@@ -150,11 +151,12 @@ async fn forward_manga_stream(
     }
 }
 
-struct UpdatesWorker<C, M, L>
+struct UpdatesWorker<C, M, L, S>
 where
     C: ChapterRepository + 'static,
     M: MangaRepository + 'static,
     L: LibraryRepository + 'static,
+    S: SourceRepository + 'static,
 {
     period: u64,
     max_concurrent_sources: usize,
@@ -162,19 +164,21 @@ where
     library_repo: L,
     manga_repo: M,
     chapter_repo: C,
+    source_repo: S,
     extensions: ExtensionManager,
     notifier: Notification<UserRepositoryImpl>,
-    extension_repository: String,
+    extension_repositories: Vec<ExtensionRepository>,
     cache_path: PathBuf,
     broadcast_tx: ChapterUpdateSender,
     command_rx: ChapterUpdateCommandReceiver,
 }
 
-impl<C, M, L> UpdatesWorker<C, M, L>
+impl<C, M, L, S> UpdatesWorker<C, M, L, S>
 where
     C: ChapterRepository + 'static,
     M: MangaRepository + 'static,
     L: LibraryRepository + 'static,
+    S: SourceRepository + 'static,
 {
     #[allow(clippy::too_many_arguments)]
     fn new<P: AsRef<Path>>(
@@ -183,9 +187,10 @@ where
         library_repo: L,
         manga_repo: M,
         chapter_repo: C,
+        source_repo: S,
         extensions: ExtensionManager,
         notifier: Notification<UserRepositoryImpl>,
-        extension_repository: String,
+        extension_repositories: Vec<ExtensionRepository>,
         broadcast_tx: ChapterUpdateSender,
         cache_path: P,
     ) -> (Self, ChapterUpdateCommandSender) {
@@ -214,9 +219,10 @@ where
                 library_repo,
                 manga_repo,
                 chapter_repo,
+                source_repo,
                 extensions,
                 notifier,
-                extension_repository,
+                extension_repositories,
                 cache_path: PathBuf::new().join(cache_path),
                 broadcast_tx,
                 command_rx,
@@ -511,22 +517,44 @@ where
     }
 
     async fn check_extension_update(&self) -> Result<(), anyhow::Error> {
-        let url = format!("{}/index.json", self.extension_repository);
+        let mut repositories = self.extension_repositories.iter().collect::<Vec<_>>();
+        repositories.sort_by_key(|repository| std::cmp::Reverse(repository.priority));
+
+        // a source offered by several repositories is updated from the one
+        // with the highest priority
+        let mut available_sources_map = HashMap::new();
+        for repository in repositories {
+            let url = format!("{}/index.json", repository.url);
+            let index = match self.client.get(&url).send().await {
+                Ok(response) => response.json::<Vec<SourceInfo>>().await,
+                Err(e) => Err(e),
+            };
+            match index {
+                Ok(index) => {
+                    for source in index {
+                        available_sources_map.entry(source.id).or_insert(source);
+                    }
+                }
+                Err(e) => warn!(
+                    "failed to fetch index of repository {}: {e}",
+                    repository.name
+                ),
+            }
+        }
 
-        let available_sources_map = self
-            .client
-            .get(&url)
-            .send()
-            .await?
-            .json::<Vec<SourceInfo>>()
+        // pinned sources stay on their version, so they are not reported
+        let pinned = self
+            .source_repo
+            .get_pinned_source_ids()
             .await?
-            .into_par_iter()
-            .map(|source| (source.id, source))
-            .collect::<HashMap<i64, SourceInfo>>();
-
+            .into_iter()
+            .collect::<HashSet<_>>();
         let installed_sources = self.extensions.list().await?;
 
         for source in installed_sources {
+            if pinned.contains(&source.id) {
+                continue;
+            }
             if available_sources_map
                 .get(&source.id)
                 .and_then(|index| Version::from_str(&index.version).ok())
@@ -696,15 +724,16 @@ where
 }
 
 #[allow(clippy::too_many_arguments)]
-pub fn start<C, M, L, P>(
+pub fn start<C, M, L, S, P>(
     period: u64,
     max_concurrent_sources: usize,
     library_repo: L,
     manga_repo: M,
     chapter_repo: C,
+    source_repo: S,
     extensions: ExtensionManager,
     notifier: Notification<UserRepositoryImpl>,
-    extension_repository: String,
+    extension_repositories: Vec<ExtensionRepository>,
     cache_path: P,
 ) -> (
     ChapterUpdateReceiver,
@@ -715,6 +744,7 @@ where
     C: ChapterRepository + 'static,
     M: MangaRepository + 'static,
     L: LibraryRepository + 'static,
+    S: SourceRepository + 'static,
     P: AsRef<Path>,
 {
     let (broadcast_tx, broadcast_rx) = tokio::sync::broadcast::channel(10);
@@ -724,9 +754,10 @@ where
         library_repo,
         manga_repo,
         chapter_repo,
+        source_repo,
         extensions,
         notifier,
-        extension_repository,
+        extension_repositories,
         broadcast_tx,
         cache_path,
     );
@@ -9,6 +9,10 @@ pub struct Chapter {
     pub path: String,
     pub number: f64,
     pub scanlator: String,
+    pub volume: Option<String>,
+    pub language: Option<String>,
+    pub page_count: Option<i64>,
+    pub external_url: Option<String>,
     pub uploaded: NaiveDateTime,
     pub date_added: NaiveDateTime,
     pub downloaded_path: Option<String>,
@@ -27,6 +31,10 @@ impl From<tanoshi_lib::models::ChapterInfo> for Chapter {
             path: ch.path,
             number: ch.number,
             scanlator: ch.scanlator.unwrap_or_default(),
+            volume: ch.volume,
+            language: ch.language,
+            page_count: ch.page_count,
+            external_url: ch.external_url,
             uploaded: NaiveDateTime::from_timestamp_opt(ch.uploaded, 0).unwrap_or_default(),
             date_added: Utc::now().naive_utc(),
             downloaded_path: None,
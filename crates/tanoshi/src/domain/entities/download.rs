@@ -1,4 +1,5 @@
 use chrono::NaiveDateTime;
+use tanoshi_lib::prelude::PageInfo;
 
 #[derive(Debug, Clone)]
 pub struct DownloadQueue {
@@ -10,7 +11,7 @@ pub struct DownloadQueue {
     pub chapter_id: i64,
     pub chapter_title: String,
     pub rank: i64,
-    pub url: String,
+    pub page: PageInfo,
     pub priority: i64,
     pub date_added: NaiveDateTime,
 }
@@ -37,6 +38,10 @@ pub struct DownloadedChapter {
     pub path: String,
     pub number: f64,
     pub scanlator: String,
+    pub volume: Option<String>,
+    pub language: Option<String>,
+    pub page_count: Option<i64>,
+    pub external_url: Option<String>,
     pub uploaded: NaiveDateTime,
     pub date_added: NaiveDateTime,
     pub downloaded_path: Option<String>,
@@ -3,9 +3,11 @@ use anyhow::anyhow;
 use base64::{engine::general_purpose, Engine};
 use bytes::Bytes;
 use fancy_regex::Regex;
+use futures::{stream::BoxStream, StreamExt};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
+use tanoshi_lib::prelude::PageInfo;
 
 use crate::infrastructure::local::SUPPORTED_FILES;
 
@@ -14,7 +16,7 @@ type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
 type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
 
 pub enum ImageUri {
-    Remote(String),
+    Remote(PageInfo),
     File(String),
     Archive(String, String),
 }
@@ -24,7 +26,7 @@ impl TryFrom<&str> for ImageUri {
 
     fn try_from(uri: &str) -> Result<Self, Self::Error> {       
         let uri = if uri.starts_with("http") {
-            Self::Remote(uri.to_string())
+            Self::Remote(PageInfo::new(uri))
         } else if !uri.is_empty() {
             let path = std::path::PathBuf::from(uri);
             if path.is_file() {
@@ -50,6 +52,18 @@ impl TryFrom<&str> for ImageUri {
     }
 }
 
+impl TryFrom<PageInfo> for ImageUri {
+    type Error = anyhow::Error;
+
+    fn try_from(page: PageInfo) -> Result<Self, Self::Error> {
+        if page.url.starts_with("http") {
+            Ok(Self::Remote(page))
+        } else {
+            Self::try_from(page.url.as_str())
+        }
+    }
+}
+
 impl ImageUri {
     pub fn from_encrypted(secret: &str, encrypted: &str) -> Result<Self, anyhow::Error> {
         let mut decoded = general_purpose::URL_SAFE_NO_PAD.decode(encrypted)?;
@@ -64,13 +78,21 @@ impl ImageUri {
             .to_vec();
 
         let url = String::from_utf8(bytes)?;
-        let uri = ImageUri::try_from(url.as_str())?;
+        // pages that need more than an url are encrypted as json
+        let uri = if url.starts_with('{') {
+            ImageUri::try_from(serde_json::from_str::<PageInfo>(&url)?)?
+        } else {
+            ImageUri::try_from(url.as_str())?
+        };
 
         Ok(uri)
     }
 
     pub fn into_encrypted(self, secret: &str) -> Result<String, anyhow::Error> {
-        let uri = self.to_string();
+        let uri = match &self {
+            ImageUri::Remote(page) if !page.is_plain() => serde_json::to_string(page)?,
+            _ => self.to_string(),
+        };
         let pos = uri.len();
 
         let mut buffer = vec![0_u8; pos * 2];
@@ -91,7 +113,7 @@ impl ImageUri {
 impl std::fmt::Display for ImageUri {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ImageUri::Remote(url) => write!(f, "{url}"),
+            ImageUri::Remote(page) => write!(f, "{}", page.url),
             ImageUri::File(path) => write!(f, "{path}"),
             ImageUri::Archive(archive, filename) => write!(f, "{archive}/{filename}"),
         }
@@ -103,3 +125,70 @@ pub struct Image {
     pub content_type: String,
     pub data: Bytes,
 }
+
+/// An image served while it is still arriving, so large pages are never
+/// buffered whole
+pub struct ImageStream {
+    pub content_type: String,
+    pub content_length: Option<usize>,
+    pub data: BoxStream<'static, Result<Bytes, anyhow::Error>>,
+}
+
+impl From<Image> for ImageStream {
+    fn from(image: Image) -> Self {
+        Self {
+            content_type: image.content_type,
+            content_length: Some(image.data.len()),
+            data: futures::stream::once(futures::future::ready(Ok(image.data))).boxed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef";
+
+    fn round_trip(uri: ImageUri) -> ImageUri {
+        let encrypted = uri.into_encrypted(SECRET).unwrap();
+        ImageUri::from_encrypted(SECRET, &encrypted).unwrap()
+    }
+
+    #[test]
+    fn test_plain_url_roundtrip() {
+        let url = "https://example.com/chapter/1/page-01.jpg?token=abc";
+        match round_trip(ImageUri::Remote(PageInfo::new(url))) {
+            ImageUri::Remote(page) => assert_eq!(page, PageInfo::new(url)),
+            _ => panic!("expected a remote image"),
+        }
+    }
+
+    #[test]
+    fn test_page_with_request_roundtrip() {
+        let page = PageInfo {
+            url: "https://example.com/image".to_string(),
+            headers: [
+                ("Referer".to_string(), "https://example.com/".to_string()),
+                ("X-Requested-With".to_string(), "XMLHttpRequest".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            method: Some("POST".to_string()),
+            body: Some(r#"{"page":1,"quality":"high"}"#.to_string()),
+            token: Some("descramble-key".to_string()),
+        };
+        match round_trip(ImageUri::Remote(page.clone())) {
+            ImageUri::Remote(decrypted) => assert_eq!(decrypted, page),
+            _ => panic!("expected a remote image"),
+        }
+    }
+
+    #[test]
+    fn test_wrong_secret_fails() {
+        let encrypted = ImageUri::Remote(PageInfo::new("https://example.com/1.jpg"))
+            .into_encrypted(SECRET)
+            .unwrap();
+        assert!(ImageUri::from_encrypted("fedcba9876543210", &encrypted).is_err());
+    }
+}
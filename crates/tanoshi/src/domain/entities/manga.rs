@@ -1,17 +1,23 @@
+use std::collections::BTreeMap;
+
 use chrono::NaiveDateTime;
-use tanoshi_lib::prelude::Input;
+use tanoshi_lib::prelude::{ContentRating, Input, MangaStatus};
 
 #[derive(Debug, Clone, Default)]
 pub struct Manga {
     pub id: i64,
     pub source_id: i64,
     pub title: String,
+    pub alternative_titles: Vec<String>,
     pub author: Vec<String>,
+    pub artist: Vec<String>,
     pub genre: Vec<String>,
-    pub status: Option<String>,
+    pub status: Option<MangaStatus>,
+    pub content_rating: Option<ContentRating>,
     pub description: Option<String>,
     pub path: String,
     pub cover_url: String,
+    pub external_ids: BTreeMap<String, String>,
     pub date_added: NaiveDateTime,
     pub last_uploaded_at: Option<NaiveDateTime>,
 }
@@ -22,12 +28,16 @@ impl From<tanoshi_lib::models::MangaInfo> for Manga {
             id: 0,
             source_id: m.source_id,
             title: m.title,
+            alternative_titles: m.alternative_titles,
             author: m.author,
+            artist: m.artist,
             genre: m.genre,
             status: m.status,
+            content_rating: m.content_rating,
             description: m.description,
             path: m.path,
             cover_url: m.cover_url,
+            external_ids: m.external_ids,
             date_added: NaiveDateTime::default(),
             last_uploaded_at: None,
         }
@@ -1,3 +1,11 @@
+use chrono::NaiveDateTime;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tanoshi_lib::prelude::Capabilities;
+use tanoshi_vm::extension::SecretKey;
+
+/// What the key sealing source secrets is derived for, see [`SecretKey`]
+const SOURCE_SECRET_PURPOSE: &str = "source secrets";
+
 pub struct Source {
     pub id: i64,
     pub name: String,
@@ -7,6 +15,9 @@ pub struct Source {
     pub lib_version: String,
     pub icon: String,
     pub has_update: bool,
+    pub capabilities: Capabilities,
+    /// Name of the repository the source is available from
+    pub repository: Option<String>,
 }
 
 impl From<tanoshi_lib::models::SourceInfo> for Source {
@@ -20,6 +31,124 @@ impl From<tanoshi_lib::models::SourceInfo> for Source {
             lib_version: String::new(),
             icon: s.icon.to_string(),
             has_update: false,
+            capabilities: s.capabilities,
+            repository: None,
         }
     }
 }
+
+/// A repository extensions are installed from. It serves an `index.json` and
+/// the libraries under `<target>/<name>.<extension>`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExtensionRepository {
+    pub name: String,
+    pub url: String,
+    /// A source offered by several repositories is installed from the one
+    /// with the highest priority
+    #[serde(default)]
+    pub priority: i64,
+    /// Base64 encoded ed25519 public keys. When set, every extension must
+    /// carry a signature by one of them along with its SHA-256, which every
+    /// repository has to list.
+    #[serde(default)]
+    pub trusted_keys: Vec<String>,
+}
+
+/// Login of a source, `credentials` and `session` are encrypted with the
+/// server secret, see [`encrypt_secret`]
+#[derive(Debug, Clone)]
+pub struct SourceCredential {
+    pub source_id: i64,
+    pub credentials: String,
+    pub session: Option<String>,
+}
+
+/// A cookie or header sent with every request of a source, `value` is
+/// encrypted with the server secret, see [`encrypt_secret`]
+#[derive(Debug, Clone)]
+pub struct SourceRequestOverride {
+    pub source_id: i64,
+    /// `cookie` or `header`
+    pub kind: String,
+    pub name: String,
+    pub value: String,
+    pub expires_at: Option<i64>,
+}
+
+/// An install, update, rollback or uninstall of a source
+#[derive(Debug, Clone)]
+pub struct SourceInstallEvent {
+    pub source_id: i64,
+    /// `install`, `update`, `rollback` or `uninstall`
+    pub event: String,
+    pub version: String,
+    /// Version installed before the event
+    pub previous_version: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Serialize a value and seal it with AES-GCM under a key derived from the
+/// server secret, so it can't be read or changed without the secret
+pub fn encrypt_secret<T: Serialize>(secret: &str, value: &T) -> Result<String, anyhow::Error> {
+    SecretKey::new(secret, SOURCE_SECRET_PURPOSE)?.seal(&serde_json::to_vec(value)?)
+}
+
+pub fn decrypt_secret<T: DeserializeOwned>(
+    secret: &str,
+    encrypted: &str,
+) -> Result<T, anyhow::Error> {
+    let plaintext = SecretKey::new(secret, SOURCE_SECRET_PURPOSE)?.open(encrypted)?;
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tanoshi_lib::prelude::{Credentials, SourceSession};
+
+    const SECRET: &str = "0123456789abcdef";
+
+    #[test]
+    fn test_secret_roundtrip() {
+        let mut credentials = Credentials::new("reader", "hunter2");
+        credentials
+            .extra
+            .insert("otp".to_string(), "123456".to_string());
+
+        let encrypted = encrypt_secret(SECRET, &credentials).unwrap();
+        assert!(!encrypted.contains("hunter2"));
+        assert_ne!(encrypt_secret(SECRET, &credentials).unwrap(), encrypted);
+        assert_eq!(
+            decrypt_secret::<Credentials>(SECRET, &encrypted).unwrap(),
+            credentials
+        );
+
+        let session = SourceSession {
+            data: "cookie=abc".to_string(),
+            expires_at: Some(1_700_000_000),
+        };
+        let encrypted = encrypt_secret(SECRET, &session).unwrap();
+        assert_eq!(
+            decrypt_secret::<SourceSession>(SECRET, &encrypted).unwrap(),
+            session
+        );
+    }
+
+    #[test]
+    fn test_secret_needs_the_same_secret() {
+        let encrypted = encrypt_secret(SECRET, &"value").unwrap();
+        assert!(decrypt_secret::<String>("fedcba9876543210", &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_changed_secret_is_rejected() {
+        let encrypted = encrypt_secret(SECRET, &"value").unwrap();
+        let mut changed = encrypted.into_bytes();
+        let middle = changed.len() / 2;
+        changed[middle] = if changed[middle] == b'A' { b'B' } else { b'A' };
+
+        let changed = String::from_utf8(changed).unwrap();
+        assert!(decrypt_secret::<String>(SECRET, &changed).is_err());
+    }
+}
@@ -2,9 +2,10 @@ use std::path::Path;
 
 use async_trait::async_trait;
 
+use tanoshi_lib::prelude::PageInfo;
 use thiserror::Error;
 
-use crate::domain::entities::image::Image;
+use crate::domain::entities::image::{Image, ImageStream};
 
 #[derive(Debug, Error)]
 pub enum ImageRepositoryError {
@@ -18,9 +19,9 @@ pub enum ImageRepositoryError {
 pub trait ImageRepository: Send + Sync {
     async fn fetch_image_from_url(
         &self,
-        url: &str,
+        page: &PageInfo,
         source_id: i64,
-    ) -> Result<Image, ImageRepositoryError>;
+    ) -> Result<ImageStream, ImageRepositoryError>;
     async fn fetch_image_from_file<P>(&self, path: P) -> Result<Image, ImageRepositoryError>
     where
         P: AsRef<Path> + std::marker::Send;
@@ -15,7 +15,25 @@ pub enum ImageCacheRepositoryError {
 
 #[async_trait]
 pub trait ImageCacheRepository {
+    type Writer: ImageCacheWriter;
+
     async fn set(&self, key: &str, image: &Image) -> Result<(), ImageCacheRepositoryError>;
 
     async fn get(&self, key: &str) -> Result<Image, ImageCacheRepositoryError>;
+
+    /// Start an entry that is filled while the image streams in, it only
+    /// becomes visible to [`Self::get`] once finished
+    async fn writer(
+        &self,
+        key: &str,
+        content_type: &str,
+    ) -> Result<Self::Writer, ImageCacheRepositoryError>;
+}
+
+/// A cache entry being written, dropping it unfinished discards the entry
+#[async_trait]
+pub trait ImageCacheWriter: Send + Sized + 'static {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), ImageCacheRepositoryError>;
+
+    async fn finish(self) -> Result<(), ImageCacheRepositoryError>;
 }
@@ -2,7 +2,13 @@ use async_trait::async_trait;
 
 use thiserror::Error;
 
-use crate::domain::entities::source::Source;
+use tanoshi_lib::prelude::{Credentials, SourceSession};
+
+use tanoshi_vm::extension::RequestOverrides;
+
+use crate::domain::entities::source::{
+    ExtensionRepository, Source, SourceCredential, SourceInstallEvent, SourceRequestOverride,
+};
 
 #[derive(Debug, Error)]
 pub enum SourceRepositoryError {
@@ -12,6 +18,8 @@ pub enum SourceRepositoryError {
     VersionError(#[from] tanoshi_lib::error::Error),
     #[error("request return error: {0}")]
     RequestError(#[from] reqwest::Error),
+    #[error("database return error: {0}")]
+    DbError(#[from] sqlx::Error),
     #[error("source not found")]
     NotFound,
     #[error("other error: {0}")]
@@ -22,16 +30,112 @@ pub enum SourceRepositoryError {
 pub trait SourceRepository: Send + Sync {
     async fn installed_sources(&self) -> Result<Vec<Source>, SourceRepositoryError>;
 
+    /// Sources of every repository, a source offered by several comes from
+    /// the one with the highest priority
     async fn available_sources(
         &self,
-        repo_url: &str,
+        repositories: &[ExtensionRepository],
         filter_installed: bool,
     ) -> Result<Vec<Source>, SourceRepositoryError>;
     async fn get_source_by_id(&self, id: i64) -> Result<Source, SourceRepositoryError>;
 
-    async fn install_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError>;
+    async fn install_source(
+        &self,
+        repositories: &[ExtensionRepository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError>;
 
-    async fn update_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError>;
+    async fn update_source(
+        &self,
+        repositories: &[ExtensionRepository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError>;
 
     async fn uninstall_source(&self, id: i64) -> Result<(), SourceRepositoryError>;
+
+    /// Reinstall a version of the source kept when it was replaced
+    async fn rollback_source(&self, id: i64, version: &str) -> Result<(), SourceRepositoryError>;
+
+    /// Versions of the source that can be rolled back to, most recent first
+    async fn source_versions(&self, id: i64) -> Result<Vec<String>, SourceRepositoryError>;
+
+    async fn get_source_install_events(
+        &self,
+        id: i64,
+    ) -> Result<Vec<SourceInstallEvent>, SourceRepositoryError>;
+
+    async fn insert_source_install_event(
+        &self,
+        event: SourceInstallEvent,
+    ) -> Result<(), SourceRepositoryError>;
+
+    /// Version a source is pinned to, updates skip pinned sources
+    async fn get_source_pin(&self, id: i64) -> Result<Option<String>, SourceRepositoryError>;
+
+    async fn get_pinned_source_ids(&self) -> Result<Vec<i64>, SourceRepositoryError>;
+
+    async fn insert_source_pin(&self, id: i64, version: &str) -> Result<(), SourceRepositoryError>;
+
+    async fn delete_source_pin(&self, id: i64) -> Result<(), SourceRepositoryError>;
+
+    async fn login(
+        &self,
+        id: i64,
+        credentials: Credentials,
+    ) -> Result<SourceSession, SourceRepositoryError>;
+
+    async fn restore_session(
+        &self,
+        id: i64,
+        session: SourceSession,
+    ) -> Result<(), SourceRepositoryError>;
+
+    async fn logout(&self, id: i64) -> Result<(), SourceRepositoryError>;
+
+    async fn is_logged_in(&self, id: i64) -> Result<bool, SourceRepositoryError>;
+
+    async fn get_source_credentials(&self) -> Result<Vec<SourceCredential>, SourceRepositoryError>;
+
+    async fn get_source_credential(
+        &self,
+        id: i64,
+    ) -> Result<Option<SourceCredential>, SourceRepositoryError>;
+
+    async fn insert_source_credential(
+        &self,
+        credential: SourceCredential,
+    ) -> Result<(), SourceRepositoryError>;
+
+    async fn delete_source_credential(&self, id: i64) -> Result<(), SourceRepositoryError>;
+
+    async fn get_source_request_overrides(
+        &self,
+    ) -> Result<Vec<SourceRequestOverride>, SourceRepositoryError>;
+
+    async fn get_source_request_overrides_by_source_id(
+        &self,
+        id: i64,
+    ) -> Result<Vec<SourceRequestOverride>, SourceRepositoryError>;
+
+    async fn insert_source_request_override(
+        &self,
+        request_override: SourceRequestOverride,
+    ) -> Result<(), SourceRepositoryError>;
+
+    async fn delete_source_request_override(
+        &self,
+        id: i64,
+        kind: &str,
+        name: &str,
+    ) -> Result<(), SourceRepositoryError>;
+
+    async fn delete_source_request_overrides(&self, id: i64) -> Result<(), SourceRepositoryError>;
+
+    /// Replace the overrides the extension manager sends with the source's
+    /// requests
+    async fn set_request_overrides(
+        &self,
+        id: i64,
+        overrides: RequestOverrides,
+    ) -> Result<(), SourceRepositoryError>;
 }
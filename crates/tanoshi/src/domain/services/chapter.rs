@@ -9,6 +9,7 @@ use crate::{
 };
 
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use tanoshi_lib::prelude::PageInfo;
 use tanoshi_vm::prelude::ExtensionManager;
 use thiserror::Error;
 use tokio::task::JoinError;
@@ -97,7 +98,7 @@ where
         source_id: i64,
         path: &str,
         downloaded_path: &Option<String>,
-    ) -> Result<Vec<String>, ChapterError> {
+    ) -> Result<Vec<PageInfo>, ChapterError> {
         let pages = if let Some(downloaded_path) =
             downloaded_path.as_ref().map(|p| PathBuf::new().join(p))
         {
@@ -105,6 +106,9 @@ where
                 local::get_pages_from_archive(downloaded_path.as_path())
             })
             .await??
+            .into_iter()
+            .map(PageInfo::from)
+            .collect()
         } else {
             self.extension_manager
                 .get_pages(source_id, path.to_string())
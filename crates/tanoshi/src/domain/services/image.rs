@@ -1,11 +1,14 @@
 use crate::domain::{
-    entities::image::{Image, ImageUri},
+    entities::image::{ImageStream, ImageUri},
     repositories::{
         image::{ImageRepository, ImageRepositoryError},
-        image_cache::{ImageCacheRepository, ImageCacheRepositoryError},
+        image_cache::{ImageCacheRepository, ImageCacheRepositoryError, ImageCacheWriter},
     },
 };
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt};
 use std::convert::TryFrom;
+use tanoshi_lib::prelude::PageInfo;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -39,34 +42,44 @@ where
         Self { repo, cache_repo }
     }
 
+    /// Fetch an image, remote images are streamed to the client and written
+    /// to the cache at the same time
     pub async fn fetch_image(
         &self,
         secret: &str,
         encrypted_url: &str,
         source_id: i64,
-    ) -> Result<Image, ImageError> {
+    ) -> Result<ImageStream, ImageError> {
         if let Ok(image) = self.cache_repo.get(encrypted_url).await {
-            return Ok(image);
+            return Ok(image.into());
         }
 
         let uri = ImageUri::from_encrypted(secret, encrypted_url)
             .map_err(|e| ImageError::Other(anyhow::anyhow!("{e}")))?;
 
         let image = match uri {
-            ImageUri::Remote(url) => {
-                let image = self.repo.fetch_image_from_url(&url, source_id).await?;
-                if let Err(e) = self.cache_repo.set(encrypted_url, &image).await {
-                    error!("error cache image {encrypted_url}: {e}");
+            ImageUri::Remote(page) => {
+                let mut image = self.repo.fetch_image_from_url(&page, source_id).await?;
+                match self
+                    .cache_repo
+                    .writer(encrypted_url, &image.content_type)
+                    .await
+                {
+                    Ok(writer) => {
+                        image.data =
+                            cache_while_streaming(image.data, writer, encrypted_url.to_string());
+                    }
+                    Err(e) => error!("error cache image {encrypted_url}: {e}"),
                 }
 
                 image
             }
-            ImageUri::File(path) => self.repo.fetch_image_from_file(&path).await?,
-            ImageUri::Archive(archive, filename) => {
-                self.repo
-                    .fetch_image_from_archive(&archive, &filename)
-                    .await?
-            }
+            ImageUri::File(path) => self.repo.fetch_image_from_file(&path).await?.into(),
+            ImageUri::Archive(archive, filename) => self
+                .repo
+                .fetch_image_from_archive(&archive, &filename)
+                .await?
+                .into(),
         };
 
         Ok(image)
@@ -77,4 +90,48 @@ where
 
         Ok(image_uri.into_encrypted(secret)?)
     }
+
+    /// Like [`Self::encrypt_image_url`], but keeps everything the source needs
+    /// to fetch the page
+    pub fn encrypt_page(&self, secret: &str, page: PageInfo) -> Result<String, ImageError> {
+        let image_uri = ImageUri::try_from(page)?;
+
+        Ok(image_uri.into_encrypted(secret)?)
+    }
+}
+
+/// Write every chunk to the cache as it passes through. The entry is only
+/// kept when the stream ends cleanly; an error or a client that goes away
+/// drops the writer, which discards it.
+fn cache_while_streaming<W: ImageCacheWriter>(
+    data: BoxStream<'static, Result<Bytes, anyhow::Error>>,
+    writer: W,
+    key: String,
+) -> BoxStream<'static, Result<Bytes, anyhow::Error>> {
+    futures::stream::unfold(
+        (data, Some(writer), key),
+        |(mut data, mut writer, key)| async move {
+            match data.next().await {
+                Some(Ok(chunk)) => {
+                    if let Some(w) = writer.as_mut()
+                        && let Err(e) = w.write(&chunk).await
+                    {
+                        error!("error cache image {key}: {e}");
+                        writer = None;
+                    }
+                    Some((Ok(chunk), (data, writer, key)))
+                }
+                Some(Err(e)) => Some((Err(e), (data, None, key))),
+                None => {
+                    if let Some(writer) = writer
+                        && let Err(e) = writer.finish().await
+                    {
+                        error!("error cache image {key}: {e}");
+                    }
+                    None
+                }
+            }
+        },
+    )
+    .boxed()
 }
@@ -1,5 +1,7 @@
 use anyhow::anyhow;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use reqwest::Url;
+use tanoshi_lib::prelude::Paginated;
 use tanoshi_vm::prelude::ExtensionManager;
 use thiserror::Error;
 
@@ -42,32 +44,36 @@ where
         &self,
         source_id: i64,
         page: i64,
-    ) -> Result<Vec<Manga>, MangaError> {
-        let fetched_manga = self
-            .sources
-            .get_popular_manga(source_id, page)
-            .await?
-            .into_par_iter()
-            .map(Manga::from)
-            .collect();
-
-        Ok(fetched_manga)
+    ) -> Result<Paginated<Manga>, MangaError> {
+        let fetched_manga = self.sources.get_popular_manga(source_id, page).await?;
+
+        Ok(Paginated {
+            items: fetched_manga
+                .items
+                .into_par_iter()
+                .map(Manga::from)
+                .collect(),
+            has_next_page: fetched_manga.has_next_page,
+            total: fetched_manga.total,
+        })
     }
 
     pub async fn fetch_source_latest_manga(
         &self,
         source_id: i64,
         page: i64,
-    ) -> Result<Vec<Manga>, MangaError> {
-        let fetched_manga = self
-            .sources
-            .get_latest_manga(source_id, page)
-            .await?
-            .into_par_iter()
-            .map(Manga::from)
-            .collect();
-
-        Ok(fetched_manga)
+    ) -> Result<Paginated<Manga>, MangaError> {
+        let fetched_manga = self.sources.get_latest_manga(source_id, page).await?;
+
+        Ok(Paginated {
+            items: fetched_manga
+                .items
+                .into_par_iter()
+                .map(Manga::from)
+                .collect(),
+            has_next_page: fetched_manga.has_next_page,
+            total: fetched_manga.total,
+        })
     }
 
     pub async fn fetch_source_manga(
@@ -76,16 +82,34 @@ where
         page: i64,
         query: Option<String>,
         filters: Option<InputList>,
+    ) -> Result<Paginated<Manga>, MangaError> {
+        let fetched_manga = self.sources.search_manga(source_id, page, query, filters).await?;
+
+        Ok(Paginated {
+            items: fetched_manga
+                .items
+                .into_par_iter()
+                .map(Manga::from)
+                .collect(),
+            has_next_page: fetched_manga.has_next_page,
+            total: fetched_manga.total,
+        })
+    }
+
+    pub async fn fetch_related_manga(
+        &self,
+        source_id: i64,
+        path: &str,
     ) -> Result<Vec<Manga>, MangaError> {
-        let fetched_manga = self
+        let manga = self
             .sources
-            .search_manga(source_id, page, query, filters)
+            .get_related_manga(source_id, path.to_string())
             .await?
             .into_par_iter()
             .map(Manga::from)
             .collect();
 
-        Ok(fetched_manga)
+        Ok(manga)
     }
 
     pub async fn fetch_manga_by_source_path(
@@ -110,6 +134,39 @@ where
         Ok(manga)
     }
 
+    /// Find the manga a website url points to by asking every installed source
+    /// on the same host. Returns the manga with the chapter path when the url
+    /// points to a chapter.
+    pub async fn resolve_url(
+        &self,
+        url: &str,
+    ) -> Result<Option<(Manga, Option<String>)>, MangaError> {
+        let host = url_host(url).ok_or_else(|| anyhow!("invalid url {url}"))?;
+
+        for source in self.sources.list().await? {
+            if url_host(&source.url).as_deref() != Some(host.as_str()) {
+                continue;
+            }
+
+            let resolved = match self.sources.resolve_url(source.id, url.to_string()).await {
+                Ok(Some(resolved)) => resolved,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("source {} failed to resolve {url}: {e}", source.id);
+                    continue;
+                }
+            };
+
+            let manga = self
+                .fetch_manga_by_source_path(source.id, &resolved.manga_path)
+                .await?;
+
+            return Ok(Some((manga, resolved.chapter_path)));
+        }
+
+        Ok(None)
+    }
+
     pub async fn fetch_manga_by_id(&self, id: i64, refresh: bool) -> Result<Manga, MangaError> {
         let mut manga = self.repo.get_manga_by_id(id).await?;
         if refresh {
@@ -126,3 +183,13 @@ where
         Ok(manga)
     }
 }
+
+/// Host of an url without the `www.` prefix, so links match sources either way
+fn url_host(url: &str) -> Option<String> {
+    let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+
+    Some(match host.strip_prefix("www.") {
+        Some(host) => host.to_string(),
+        None => host,
+    })
+}
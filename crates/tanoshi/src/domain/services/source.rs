@@ -1,11 +1,18 @@
 use std::{collections::HashMap, str::FromStr};
 
+use anyhow::anyhow;
+use futures::StreamExt;
+
 use crate::domain::{
-    entities::source::Source,
+    entities::source::{
+        decrypt_secret, encrypt_secret, ExtensionRepository, Source, SourceCredential,
+        SourceInstallEvent, SourceRequestOverride,
+    },
     repositories::source::{SourceRepository, SourceRepositoryError},
 };
 
-use tanoshi_lib::prelude::Version;
+use tanoshi_lib::prelude::{Credentials, SourceSession, Version};
+use tanoshi_vm::extension::{RequestOverride, RequestOverrideKind, RequestOverrides};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -36,7 +43,7 @@ where
 
     pub async fn get_installed_sources(
         &self,
-        repo_url: &str,
+        repositories: &[ExtensionRepository],
         check_update: bool,
     ) -> Result<Vec<Source>, SourceError> {
         let mut sources = self.repo.installed_sources().await?;
@@ -44,7 +51,7 @@ where
         if check_update {
             let available_sources: HashMap<i64, Source> = self
                 .repo
-                .available_sources(repo_url, false)
+                .available_sources(repositories, false)
                 .await?
                 .into_iter()
                 .map(|s| (s.id, s))
@@ -52,6 +59,7 @@ where
 
             for source in &mut sources {
                 if let Some(available_source) = available_sources.get(&source.id) {
+                    source.repository = available_source.repository.clone();
                     let available_version = Version::from_str(&available_source.version)?;
                     let installed_version = Version::from_str(&source.version)?;
 
@@ -67,8 +75,11 @@ where
         Ok(sources)
     }
 
-    pub async fn get_available_sources(&self, repo_url: &str) -> Result<Vec<Source>, SourceError> {
-        let sources = self.repo.available_sources(repo_url, true).await?;
+    pub async fn get_available_sources(
+        &self,
+        repositories: &[ExtensionRepository],
+    ) -> Result<Vec<Source>, SourceError> {
+        let sources = self.repo.available_sources(repositories, true).await?;
 
         Ok(sources)
     }
@@ -79,21 +90,306 @@ where
         Ok(source)
     }
 
-    pub async fn install_source(&self, repo_url: &str, id: i64) -> Result<(), SourceError> {
-        self.repo.install_source(repo_url, id).await?;
+    pub async fn install_source(
+        &self,
+        repositories: &[ExtensionRepository],
+        id: i64,
+    ) -> Result<(), SourceError> {
+        self.repo.install_source(repositories, id).await?;
+        self.record_install_event(id, "install", None).await;
+
+        Ok(())
+    }
+
+    pub async fn update_source(
+        &self,
+        repositories: &[ExtensionRepository],
+        id: i64,
+    ) -> Result<(), SourceError> {
+        if let Some(version) = self.repo.get_source_pin(id).await? {
+            return Err(anyhow!("source is pinned to {version}, unpin it to update").into());
+        }
+
+        let previous = self.repo.get_source_by_id(id).await?;
+        self.repo.update_source(repositories, id).await?;
+        self.record_install_event(id, "update", Some(previous.version))
+            .await;
 
         Ok(())
     }
 
-    pub async fn update_source(&self, repo_url: &str, id: i64) -> Result<(), SourceError> {
-        self.repo.update_source(repo_url, id).await?;
+    /// Reinstall a version kept when the source was updated, the installed
+    /// version is kept in its place
+    pub async fn rollback_source(&self, id: i64, version: &str) -> Result<(), SourceError> {
+        let previous = self.repo.get_source_by_id(id).await?;
+        self.repo.rollback_source(id, version).await?;
+        self.record_install_event(id, "rollback", Some(previous.version))
+            .await;
+
+        if self.repo.get_source_pin(id).await?.is_some() {
+            self.repo.insert_source_pin(id, version).await?;
+        }
 
         Ok(())
     }
 
+    pub async fn get_source_versions(&self, id: i64) -> Result<Vec<String>, SourceError> {
+        Ok(self.repo.source_versions(id).await?)
+    }
+
+    /// Installs, updates, rollbacks and uninstalls of a source, most recent
+    /// first
+    pub async fn get_install_history(
+        &self,
+        id: i64,
+    ) -> Result<Vec<SourceInstallEvent>, SourceError> {
+        Ok(self.repo.get_source_install_events(id).await?)
+    }
+
+    pub async fn get_pinned_version(&self, id: i64) -> Result<Option<String>, SourceError> {
+        Ok(self.repo.get_source_pin(id).await?)
+    }
+
+    /// Pin a source to its installed version, so it is neither updated nor
+    /// reported as updatable
+    pub async fn pin_source(&self, id: i64) -> Result<(), SourceError> {
+        let source = self.repo.get_source_by_id(id).await?;
+        self.repo.insert_source_pin(id, &source.version).await?;
+
+        Ok(())
+    }
+
+    pub async fn unpin_source(&self, id: i64) -> Result<(), SourceError> {
+        self.repo.delete_source_pin(id).await?;
+
+        Ok(())
+    }
+
+    /// History is best effort, a failure to record it does not fail the
+    /// install
+    async fn record_install_event(&self, id: i64, event: &str, previous_version: Option<String>) {
+        let version = match self.repo.get_source_by_id(id).await {
+            Ok(source) => source.version,
+            Err(e) => {
+                warn!("failed to record {event} of source {id}: {e}");
+                return;
+            }
+        };
+
+        self.insert_install_event(id, event, version, previous_version)
+            .await;
+    }
+
+    async fn insert_install_event(
+        &self,
+        id: i64,
+        event: &str,
+        version: String,
+        previous_version: Option<String>,
+    ) {
+        let install_event = SourceInstallEvent {
+            source_id: id,
+            event: event.to_string(),
+            version,
+            previous_version,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        if let Err(e) = self.repo.insert_source_install_event(install_event).await {
+            warn!("failed to record {event} of source {id}: {e}");
+        }
+    }
+
     pub async fn uninstall_source(&self, id: i64) -> Result<(), SourceError> {
+        let previous = self.repo.get_source_by_id(id).await?;
         self.repo.uninstall_source(id).await?;
+        self.insert_install_event(id, "uninstall", previous.version, None)
+            .await;
+        self.repo.delete_source_pin(id).await?;
+        self.repo.delete_source_credential(id).await?;
+        self.repo.delete_source_request_overrides(id).await?;
+        self.repo
+            .set_request_overrides(id, RequestOverrides::default())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Log in to a source and store the credentials and session encrypted
+    /// with `secret`, so the login survives restarts.
+    pub async fn login(
+        &self,
+        secret: &str,
+        id: i64,
+        credentials: Credentials,
+    ) -> Result<(), SourceError> {
+        let session = self.repo.login(id, credentials.clone()).await?;
+
+        self.repo
+            .insert_source_credential(SourceCredential {
+                source_id: id,
+                credentials: encrypt_secret(secret, &credentials)?,
+                session: Some(encrypt_secret(secret, &session)?),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn logout(&self, id: i64) -> Result<(), SourceError> {
+        self.repo.logout(id).await?;
+        self.repo.delete_source_credential(id).await?;
 
         Ok(())
     }
+
+    pub async fn is_logged_in(&self, id: i64) -> Result<bool, SourceError> {
+        Ok(self.repo.is_logged_in(id).await?)
+    }
+
+    /// Restore every stored login concurrently, as each may go to the
+    /// network. A source that fails is logged and skipped.
+    pub async fn restore_logins(&self, secret: &str) -> Result<(), SourceError> {
+        futures::stream::iter(self.repo.get_source_credentials().await?)
+            .for_each_concurrent(None, |credential| async move {
+                let source_id = credential.source_id;
+                if let Err(e) = self.restore_credential(secret, credential).await {
+                    warn!("failed to restore login for source {source_id}: {e}");
+                }
+            })
+            .await;
+
+        Ok(())
+    }
+
+    /// Restore the stored login of a source, e.g. after it is reinstalled
+    pub async fn restore_login(&self, secret: &str, id: i64) -> Result<(), SourceError> {
+        if let Some(credential) = self.repo.get_source_credential(id).await? {
+            self.restore_credential(secret, credential).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Cookies and headers sent with every request of a source, decrypted
+    pub async fn get_request_overrides(
+        &self,
+        secret: &str,
+        id: i64,
+    ) -> Result<Vec<RequestOverride>, SourceError> {
+        self.repo
+            .get_source_request_overrides_by_source_id(id)
+            .await?
+            .into_iter()
+            .map(|request_override| decrypt_request_override(secret, request_override))
+            .collect()
+    }
+
+    /// Store a cookie or header override, replacing one of the same kind and
+    /// name, and apply it to the source's next request
+    pub async fn set_request_override(
+        &self,
+        secret: &str,
+        id: i64,
+        request_override: RequestOverride,
+    ) -> Result<(), SourceError> {
+        self.repo
+            .insert_source_request_override(SourceRequestOverride {
+                source_id: id,
+                kind: request_override.kind.as_str().to_string(),
+                name: request_override.name,
+                value: encrypt_secret(secret, &request_override.value)?,
+                expires_at: request_override.expires_at,
+            })
+            .await?;
+
+        self.apply_request_overrides(secret, id).await
+    }
+
+    pub async fn delete_request_override(
+        &self,
+        secret: &str,
+        id: i64,
+        kind: RequestOverrideKind,
+        name: &str,
+    ) -> Result<(), SourceError> {
+        self.repo
+            .delete_source_request_override(id, kind.as_str(), name)
+            .await?;
+
+        self.apply_request_overrides(secret, id).await
+    }
+
+    /// Apply every stored override at startup, a source that fails is logged
+    /// and skipped
+    pub async fn restore_request_overrides(&self, secret: &str) -> Result<(), SourceError> {
+        let mut source_ids = self
+            .repo
+            .get_source_request_overrides()
+            .await?
+            .into_iter()
+            .map(|request_override| request_override.source_id)
+            .collect::<Vec<_>>();
+        source_ids.sort_unstable();
+        source_ids.dedup();
+
+        for source_id in source_ids {
+            if let Err(e) = self.apply_request_overrides(secret, source_id).await {
+                warn!("failed to restore request overrides for source {source_id}: {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn apply_request_overrides(&self, secret: &str, id: i64) -> Result<(), SourceError> {
+        let overrides = self.get_request_overrides(secret, id).await?;
+        self.repo
+            .set_request_overrides(id, RequestOverrides(overrides))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Prefer the stored session, log in again when it is missing, expired
+    /// or rejected by the extension.
+    async fn restore_credential(
+        &self,
+        secret: &str,
+        credential: SourceCredential,
+    ) -> Result<(), SourceError> {
+        let now = chrono::Utc::now().timestamp();
+        let session = credential
+            .session
+            .as_deref()
+            .map(|session| decrypt_secret::<SourceSession>(secret, session))
+            .transpose()?
+            .filter(|session| !session.is_expired(now));
+
+        if let Some(session) = session {
+            match self.repo.restore_session(credential.source_id, session).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    debug!(
+                        "source {} rejected stored session, logging in again: {e}",
+                        credential.source_id
+                    );
+                }
+            }
+        }
+
+        let credentials = decrypt_secret::<Credentials>(secret, &credential.credentials)?;
+        self.login(secret, credential.source_id, credentials).await
+    }
+}
+
+fn decrypt_request_override(
+    secret: &str,
+    request_override: SourceRequestOverride,
+) -> Result<RequestOverride, SourceError> {
+    Ok(RequestOverride {
+        kind: request_override.kind.parse()?,
+        name: request_override.name,
+        value: decrypt_secret(secret, &request_override.value)?,
+        expires_at: request_override.expires_at,
+    })
 }
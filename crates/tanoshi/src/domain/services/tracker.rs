@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use tanoshi_tracker::{Session, TrackerManga, TrackerStatus};
 use thiserror::Error;
 
@@ -250,6 +252,31 @@ where
         Ok(())
     }
 
+    /// Link a manga to every tracker the user logged in to but hasn't tracked
+    /// it on yet, using the ids the source reported for that tracker.
+    pub async fn track_manga_by_external_ids(
+        &self,
+        user_id: i64,
+        manga_id: i64,
+        external_ids: &BTreeMap<String, String>,
+    ) -> Result<(), TrackerError> {
+        let tracked_manga = self.repo.get_tracked_manga_id(user_id, manga_id).await?;
+
+        for manga in tracked_manga {
+            if manga.tracker_manga_id.is_some() {
+                continue;
+            }
+
+            if let Some(tracker_manga_id) = external_ids.get(&manga.tracker) {
+                self.repo
+                    .update_tracker_manga_id(user_id, manga_id, &manga.tracker, tracker_manga_id)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn untrack_manga(
         &self,
         user_id: i64,
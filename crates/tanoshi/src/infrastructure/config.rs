@@ -5,9 +5,13 @@ use std::path::Path;
 use std::path::PathBuf;
 use directories::ProjectDirs;
 use tanoshi_vm::extension::manager::{
-    DEFAULT_ADMISSION_TIMEOUT, DEFAULT_IMAGE_TIMEOUT, DEFAULT_MAX_CONCURRENT_CALLS,
-    DEFAULT_METADATA_TIMEOUT,
+    DEFAULT_ADMISSION_TIMEOUT, DEFAULT_IMAGE_TIMEOUT, DEFAULT_KEEP_VERSIONS,
+    DEFAULT_MAX_CONCURRENT_CALLS, DEFAULT_MAX_WORKER_PROCESSES, DEFAULT_METADATA_TIMEOUT,
+    DEFAULT_MIN_WORKER_PROCESSES, DEFAULT_WORKER_IDLE_TIMEOUT,
 };
+use tanoshi_vm::extension::{DEFAULT_HTTP_CACHE_SIZE, DEFAULT_HTTP_TIMEOUT};
+
+use crate::domain::entities::source::ExtensionRepository;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct TelegramConfig {
@@ -60,6 +64,92 @@ pub struct ExtensionConfig {
     pub metadata_timeout_secs: u64,
     #[serde(default = "default_extension_image_timeout_secs")]
     pub image_timeout_secs: u64,
+    #[serde(default = "default_extension_min_worker_processes")]
+    pub min_worker_processes: usize,
+    #[serde(default = "default_extension_max_worker_processes")]
+    pub max_worker_processes: usize,
+    #[serde(default = "default_extension_worker_idle_timeout_secs")]
+    pub worker_idle_timeout_secs: u64,
+    /// Replaced versions of each extension kept to roll back to
+    #[serde(default = "default_extension_keep_versions")]
+    pub keep_versions: usize,
+    /// Reload extension libraries when they change in the plugin directory,
+    /// for extension development
+    #[serde(default)]
+    pub hot_reload: bool,
+    #[serde(default)]
+    pub sandbox: ExtensionSandboxConfig,
+    #[serde(default)]
+    pub http: ExtensionHttpConfig,
+}
+
+/// Hardening of extension worker processes, layers the system does not
+/// support are skipped and reported unless `strict` is set
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ExtensionSandboxConfig {
+    #[serde(default = "default_sandbox_memory_limit_mb")]
+    pub memory_limit_mb: Option<u64>,
+    /// CPU seconds over the whole life of a worker, not per call, a worker
+    /// that runs out is killed and replaced
+    #[serde(default)]
+    pub cpu_time_limit_secs: Option<u64>,
+    #[serde(default = "default_sandbox_max_open_files")]
+    pub max_open_files: Option<u64>,
+    #[serde(default = "default_sandbox_seccomp")]
+    pub seccomp: bool,
+    #[serde(default = "default_sandbox_landlock")]
+    pub landlock: bool,
+    /// Needs unprivileged user namespaces, which some container runtimes
+    /// do not allow
+    #[serde(default)]
+    pub private_network: bool,
+    #[serde(default)]
+    pub allowed_private_hosts: Vec<String>,
+    #[serde(default)]
+    pub strict: bool,
+}
+
+impl Default for ExtensionSandboxConfig {
+    fn default() -> Self {
+        Self {
+            memory_limit_mb: default_sandbox_memory_limit_mb(),
+            cpu_time_limit_secs: None,
+            max_open_files: default_sandbox_max_open_files(),
+            seccomp: default_sandbox_seccomp(),
+            landlock: default_sandbox_landlock(),
+            private_network: false,
+            allowed_private_hosts: vec![],
+            strict: false,
+        }
+    }
+}
+
+/// The HTTP client extensions reach their websites through
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ExtensionHttpConfig {
+    /// Defaults to `Tanoshi/<version>`
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// HTTP or HTTPS proxy for every extension request
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Responses kept per source to revalidate with their ETag or
+    /// Last-Modified, 0 disables the cache
+    #[serde(default = "default_http_cache_size_mb")]
+    pub cache_size_mb: u64,
+}
+
+impl Default for ExtensionHttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: None,
+            proxy: None,
+            timeout_secs: default_http_timeout_secs(),
+            cache_size_mb: default_http_cache_size_mb(),
+        }
+    }
 }
 
 impl Default for ExtensionConfig {
@@ -69,6 +159,13 @@ impl Default for ExtensionConfig {
             admission_timeout_ms: default_extension_admission_timeout_ms(),
             metadata_timeout_secs: default_extension_metadata_timeout_secs(),
             image_timeout_secs: default_extension_image_timeout_secs(),
+            min_worker_processes: default_extension_min_worker_processes(),
+            max_worker_processes: default_extension_max_worker_processes(),
+            worker_idle_timeout_secs: default_extension_worker_idle_timeout_secs(),
+            keep_versions: default_extension_keep_versions(),
+            hot_reload: false,
+            sandbox: ExtensionSandboxConfig::default(),
+            http: ExtensionHttpConfig::default(),
         }
     }
 }
@@ -77,8 +174,9 @@ impl Default for ExtensionConfig {
 pub struct Config {
     #[serde(skip)]
     path: PathBuf,
-    #[serde(skip, default = "default_extension_repository")]
-    pub extension_repository: String,
+    /// Repositories extensions are installed from
+    #[serde(default = "default_extension_repositories")]
+    pub extension_repositories: Vec<ExtensionRepository>,
     #[serde(default)]
     pub base_url: Option<String>,
     #[serde(default = "default_port")]
@@ -118,7 +216,7 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             path: tanoshi_home().join("config.yml"),
-            extension_repository: default_extension_repository(),
+            extension_repositories: default_extension_repositories(),
             base_url: None,
             port: default_port(),
             database_path: default_database_path(),
@@ -166,11 +264,16 @@ fn default_port() -> u16 {
     80
 }
 
-fn default_extension_repository() -> String {
-    format!(
-        "https://raw.githubusercontent.com/luigi311/tanoshi-extensions/{}",
-        tanoshi_lib::RUSTC_VERSION
-    )
+fn default_extension_repositories() -> Vec<ExtensionRepository> {
+    vec![ExtensionRepository {
+        name: "tanoshi-extensions".to_string(),
+        url: format!(
+            "https://raw.githubusercontent.com/luigi311/tanoshi-extensions/{}",
+            tanoshi_lib::RUSTC_VERSION
+        ),
+        priority: 0,
+        trusted_keys: vec![],
+    }]
 }
 
 fn default_update_interval() -> u64 {
@@ -197,6 +300,46 @@ fn default_extension_image_timeout_secs() -> u64 {
     DEFAULT_IMAGE_TIMEOUT.as_secs()
 }
 
+fn default_extension_min_worker_processes() -> usize {
+    DEFAULT_MIN_WORKER_PROCESSES
+}
+
+fn default_extension_max_worker_processes() -> usize {
+    DEFAULT_MAX_WORKER_PROCESSES
+}
+
+fn default_extension_worker_idle_timeout_secs() -> u64 {
+    DEFAULT_WORKER_IDLE_TIMEOUT.as_secs()
+}
+
+fn default_extension_keep_versions() -> usize {
+    DEFAULT_KEEP_VERSIONS
+}
+
+fn default_sandbox_memory_limit_mb() -> Option<u64> {
+    Some(4096)
+}
+
+fn default_sandbox_max_open_files() -> Option<u64> {
+    Some(1024)
+}
+
+fn default_sandbox_seccomp() -> bool {
+    true
+}
+
+fn default_sandbox_landlock() -> bool {
+    true
+}
+
+fn default_http_timeout_secs() -> u64 {
+    DEFAULT_HTTP_TIMEOUT.as_secs()
+}
+
+fn default_http_cache_size_mb() -> u64 {
+    (DEFAULT_HTTP_CACHE_SIZE / (1024 * 1024)) as u64
+}
+
 fn default_secret() -> String {
     let mut rng = rng();
     (0..16).map(|_| char::from(rng.sample(Alphanumeric))).collect()
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use chrono::Utc;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use sqlx::{Row, SqlitePool};
+use sqlx::{Row, SqlitePool, sqlite::SqliteRow};
 
 use crate::{
     domain::{
@@ -22,6 +22,29 @@ impl ChapterRepositoryImpl {
     }
 }
 
+/// Map a row selecting `chapter.*` by column name, `next` and `prev` are left
+/// for queries that compute them.
+fn chapter_from_row(row: &SqliteRow) -> Chapter {
+    Chapter {
+        id: row.get("id"),
+        source_id: row.get("source_id"),
+        manga_id: row.get("manga_id"),
+        title: row.get("title"),
+        path: row.get("path"),
+        number: row.get("number"),
+        scanlator: row.get("scanlator"),
+        volume: row.get("volume"),
+        language: row.get("language"),
+        page_count: row.get("page_count"),
+        external_url: row.get("external_url"),
+        uploaded: row.get("uploaded"),
+        date_added: row.get("date_added"),
+        downloaded_path: row.get("downloaded_path"),
+        next: None,
+        prev: None,
+    }
+}
+
 #[async_trait]
 impl ChapterRepository for ChapterRepositoryImpl {
     async fn insert_chapters(&self, chapters: &[Chapter]) -> Result<(), ChapterRepositoryError> {
@@ -30,7 +53,7 @@ impl ChapterRepository for ChapterRepositoryImpl {
         }
 
         let mut values = vec![];
-        values.resize(chapters.len(), "(?, ?, ?, ?, ?, ?, ?, ?)");
+        values.resize(chapters.len(), "(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)");
 
         let query_str = format!(
             r#"INSERT INTO chapter(
@@ -40,6 +63,10 @@ impl ChapterRepository for ChapterRepositoryImpl {
             path,
             number,
             scanlator,
+            volume,
+            language,
+            page_count,
+            external_url,
             uploaded,
             date_added
         ) VALUES {} ON CONFLICT(source_id, path) DO UPDATE SET
@@ -47,6 +74,10 @@ impl ChapterRepository for ChapterRepositoryImpl {
             title=excluded.title,
             number=excluded.number,
             scanlator=excluded.scanlator,
+            volume=excluded.volume,
+            language=excluded.language,
+            page_count=excluded.page_count,
+            external_url=excluded.external_url,
             uploaded=excluded.uploaded,
             date_added=excluded.date_added
         "#,
@@ -62,6 +93,10 @@ impl ChapterRepository for ChapterRepositoryImpl {
                 .bind(&chapter.path)
                 .bind(chapter.number)
                 .bind(&chapter.scanlator)
+                .bind(&chapter.volume)
+                .bind(&chapter.language)
+                .bind(chapter.page_count)
+                .bind(&chapter.external_url)
                 .bind(chapter.uploaded)
                 .bind(Utc::now().naive_utc());
         }
@@ -84,18 +119,9 @@ impl ChapterRepository for ChapterRepositoryImpl {
         .await?;
 
         Ok(Chapter {
-            id: row.get(0),
-            source_id: row.get(1),
-            manga_id: row.get(2),
-            title: row.get(3),
-            path: row.get(4),
-            number: row.get(5),
-            scanlator: row.get(6),
-            uploaded: row.get(7),
-            date_added: row.get(8),
-            downloaded_path: row.get(9),
-            next: row.get(10),
-            prev: row.get(11),
+            next: row.get("next"),
+            prev: row.get("prev"),
+            ..chapter_from_row(&row)
         })
     }
 
@@ -117,18 +143,9 @@ impl ChapterRepository for ChapterRepositoryImpl {
         .await?;
 
         Ok(Chapter {
-            id: row.get(0),
-            source_id: row.get(1),
-            manga_id: row.get(2),
-            title: row.get(3),
-            path: row.get(4),
-            number: row.get(5),
-            scanlator: row.get(6),
-            uploaded: row.get(7),
-            date_added: row.get(8),
-            downloaded_path: row.get(9),
-            next: row.get(10),
-            prev: row.get(11),
+            next: row.get("next"),
+            prev: row.get("prev"),
+            ..chapter_from_row(&row)
         })
     }
 
@@ -158,18 +175,9 @@ impl ChapterRepository for ChapterRepositoryImpl {
             .await?
             .into_par_iter()
             .map(|row| Chapter {
-                id: row.get(0),
-                source_id: row.get(1),
-                manga_id: row.get(2),
-                title: row.get(3),
-                path: row.get(4),
-                number: row.get(5),
-                scanlator: row.get(6),
-                uploaded: row.get(7),
-                date_added: row.get(8),
-                downloaded_path: row.get(9),
-                next: row.get(10),
-                prev: row.get(11),
+                next: row.get("next"),
+                prev: row.get("prev"),
+                ..chapter_from_row(&row)
             })
             .collect();
 
@@ -238,20 +246,7 @@ impl ChapterRepository for ChapterRepositoryImpl {
             .fetch_all(&self.pool as &SqlitePool)
             .await?
             .into_par_iter()
-            .map(|row| Chapter {
-                id: row.get(0),
-                source_id: row.get(1),
-                manga_id: row.get(2),
-                title: row.get(3),
-                path: row.get(4),
-                number: row.get(5),
-                scanlator: row.get(6),
-                uploaded: row.get(7),
-                date_added: row.get(8),
-                downloaded_path: row.get(9),
-                next: None,
-                prev: None,
-            })
+            .map(|row| chapter_from_row(&row))
             .collect();
 
         Ok(chapters)
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use sqlx::{Row, SqlitePool};
+use tanoshi_lib::prelude::PageInfo;
 
 use crate::{
     domain::{
@@ -57,6 +58,10 @@ impl DownloadRepository for DownloadRepositoryImpl {
             path: row.get(4),
             number: row.get(5),
             scanlator: row.get(6),
+            volume: row.get("volume"),
+            language: row.get("language"),
+            page_count: row.get("page_count"),
+            external_url: row.get("external_url"),
             uploaded: row.get(7),
             date_added: row.get(8),
             downloaded_path: row.get(9),
@@ -102,6 +107,10 @@ impl DownloadRepository for DownloadRepositoryImpl {
             path: row.get(4),
             number: row.get(5),
             scanlator: row.get(6),
+            volume: row.get("volume"),
+            language: row.get("language"),
+            page_count: row.get("page_count"),
+            external_url: row.get("external_url"),
             uploaded: row.get(7),
             date_added: row.get(8),
             downloaded_path: row.get(9),
@@ -143,6 +152,10 @@ impl DownloadRepository for DownloadRepositoryImpl {
             path: row.get(4),
             number: row.get(5),
             scanlator: row.get(6),
+            volume: row.get("volume"),
+            language: row.get("language"),
+            page_count: row.get("page_count"),
+            external_url: row.get("external_url"),
             uploaded: row.get(7),
             date_added: row.get(8),
             downloaded_path: row.get(9),
@@ -191,7 +204,7 @@ impl DownloadRepository for DownloadRepositoryImpl {
         }
 
         let mut values = vec![];
-        values.resize(items.len(), "(?, ?, ?, ?, ?, ?, ?, ?, ?, ?)");
+        values.resize(items.len(), "(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)");
 
         let query_str = format!(
             r#"INSERT OR IGNORE INTO download_queue(
@@ -203,6 +216,7 @@ impl DownloadRepository for DownloadRepositoryImpl {
                 chapter_title,
                 rank,
                 url,
+                page,
                 priority,
                 date_added 
         ) VALUES {}"#,
@@ -219,7 +233,8 @@ impl DownloadRepository for DownloadRepositoryImpl {
                 .bind(item.chapter_id)
                 .bind(&item.chapter_title)
                 .bind(item.rank)
-                .bind(&item.url)
+                .bind(&item.page.url)
+                .bind(serde_json::to_string(&item.page).ok())
                 .bind(item.priority)
                 .bind(item.date_added.and_utc().timestamp());
         }
@@ -244,7 +259,8 @@ impl DownloadRepository for DownloadRepositoryImpl {
                     rank,
                     url,
                     priority,
-                    date_added 
+                    date_added,
+                    page
                 FROM download_queue
                 WHERE downloaded IS NOT true
                 ORDER BY priority ASC, date_added ASC, chapter_id ASC, rank ASC
@@ -261,7 +277,10 @@ impl DownloadRepository for DownloadRepositoryImpl {
             chapter_id: row.get(5),
             chapter_title: row.get(6),
             rank: row.get(7),
-            url: row.get(8),
+            page: row
+                .get::<Option<String>, _>(11)
+                .and_then(|page| serde_json::from_str(&page).ok())
+                .unwrap_or_else(|| PageInfo::new(row.get::<String, _>(8))),
             priority: row.get(9),
             date_added: row.get(10),
         });
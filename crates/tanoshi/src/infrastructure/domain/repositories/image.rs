@@ -1,11 +1,13 @@
 use std::path::Path;
 
 use async_trait::async_trait;
+use futures::StreamExt;
 
-use tanoshi_vm::extension::ExtensionManager;
+use tanoshi_lib::prelude::PageInfo;
+use tanoshi_vm::extension::{ExtensionManager, detect_image_type};
 
 use crate::domain::{
-    entities::image::Image,
+    entities::image::{Image, ImageStream},
     repositories::image::{ImageRepository, ImageRepositoryError},
 };
 use crate::infrastructure::archive::ArchiveReader;
@@ -25,29 +27,40 @@ impl ImageRepositoryImpl {
 impl ImageRepository for ImageRepositoryImpl {
     async fn fetch_image_from_url(
         &self,
-        url: &str,
+        page: &PageInfo,
         source_id: i64,
-    ) -> Result<Image, ImageRepositoryError> {
+    ) -> Result<ImageStream, ImageRepositoryError> {
+        let url = page.url.as_str();
         if url.is_empty() {
             return Err(ImageRepositoryError::Other(
                 "url cannot be empty".to_string(),
             ));
         }
 
-        // determine content type from the URL
-        let content_type = extract_image_type_from_url(url);
+        let mut page = page.clone();
+        let overrides = self.extension.request_overrides(source_id);
+        if !overrides.is_empty() {
+            overrides.apply_to_page_headers(&mut page.headers);
+        }
 
-        let bytes = self
+        let stream = self
             .extension
-            .get_image_bytes(source_id, url.to_string())
+            .get_image_bytes(source_id, page)
             .await
             .map_err(|e| ImageRepositoryError::Other(format!("{e}")))?;
 
+        // extensions may re-encode pages in process_image, so trust the
+        // bytes over the URL
+        let content_type = detect_image_type(stream.first_chunk())
+            .map(ToString::to_string)
+            .unwrap_or_else(|| extract_image_type_from_url(url));
+
         // url.get avoids panicking when byte 80 is not a char boundary
-        debug!("fetched image from extension source_id={source_id}, url='{}...', content_type={content_type}, size={} bytes", url.get(..80).unwrap_or(url), bytes.len());
-        Ok(Image {
+        debug!("streaming image from extension source_id={source_id}, url='{}...', content_type={content_type}", url.get(..80).unwrap_or(url));
+        Ok(ImageStream {
             content_type,
-            data: bytes,
+            content_length: None,
+            data: stream.boxed(),
         })
     }
 
@@ -1,15 +1,48 @@
 use async_trait::async_trait;
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::io::AsyncWriteExt;
 
 use crate::domain::{
     entities::image::Image,
-    repositories::image_cache::{ImageCacheRepository, ImageCacheRepositoryError},
+    repositories::image_cache::{
+        ImageCacheRepository, ImageCacheRepositoryError, ImageCacheWriter,
+    },
 };
 
 // Header identifying the postcard cache format. Files without it predate the
 // switch from bincode; they are discarded instead of decoded because a bincode
 // payload can accidentally parse as valid postcard and yield a corrupt image.
 const MAGIC: &[u8; 4] = b"TIC1";
+// Header of the streamable format: the content type prefixed with its u32
+// length, then the raw image up to the end of the file. Unlike postcard it
+// does not need the image length up front.
+const STREAM_MAGIC: &[u8; 4] = b"TIC2";
+
+static PARTIAL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn stream_header(content_type: &str) -> Vec<u8> {
+    let mut header = Vec::with_capacity(STREAM_MAGIC.len() + 4 + content_type.len());
+    header.extend_from_slice(STREAM_MAGIC);
+    header.extend_from_slice(&(content_type.len() as u32).to_be_bytes());
+    header.extend_from_slice(content_type.as_bytes());
+    header
+}
+
+fn decode_stream_entry(payload: &[u8]) -> Option<Image> {
+    let (length, rest) = payload.split_first_chunk::<4>()?;
+    let length = u32::from_be_bytes(*length) as usize;
+    if rest.len() < length {
+        return None;
+    }
+    let (content_type, data) = rest.split_at(length);
+    Some(Image {
+        content_type: String::from_utf8(content_type.to_vec()).ok()?,
+        data: data.to_vec().into(),
+    })
+}
 
 #[derive(Clone)]
 pub struct ImageCacheRepositoryImpl {
@@ -26,16 +59,12 @@ impl ImageCacheRepositoryImpl {
 
 #[async_trait]
 impl ImageCacheRepository for ImageCacheRepositoryImpl {
-    async fn set(&self, key: &str, image: &Image) -> Result<(), ImageCacheRepositoryError> {
-        let path = self.path.join(key);
-
-        let payload = postcard::to_allocvec(image)?;
-        let mut encoded = Vec::with_capacity(MAGIC.len() + payload.len());
-        encoded.extend_from_slice(MAGIC);
-        encoded.extend_from_slice(&payload);
-        tokio::fs::write(&path, encoded).await?;
+    type Writer = ImageCacheWriterImpl;
 
-        Ok(())
+    async fn set(&self, key: &str, image: &Image) -> Result<(), ImageCacheRepositoryError> {
+        let mut writer = self.writer(key, &image.content_type).await?;
+        writer.write(&image.data).await?;
+        writer.finish().await
     }
 
     async fn get(&self, key: &str) -> Result<Image, ImageCacheRepositoryError> {
@@ -43,6 +72,12 @@ impl ImageCacheRepository for ImageCacheRepositoryImpl {
 
         let encoded = tokio::fs::read(&path).await?;
 
+        if let Some(payload) = encoded.strip_prefix(STREAM_MAGIC) {
+            return decode_stream_entry(payload).ok_or_else(|| {
+                ImageCacheRepositoryError::Other("corrupt image cache entry".to_string())
+            });
+        }
+
         let Some(payload) = encoded.strip_prefix(MAGIC) else {
             // Old bincode-era entry: remove it so the caller refetches the
             // image and rewrites the cache in the current format.
@@ -56,6 +91,70 @@ impl ImageCacheRepository for ImageCacheRepositoryImpl {
 
         Ok(decoded)
     }
+
+    async fn writer(
+        &self,
+        key: &str,
+        content_type: &str,
+    ) -> Result<ImageCacheWriterImpl, ImageCacheRepositoryError> {
+        // concurrent fetches of the same image each write their own file,
+        // the last one to finish wins
+        let partial_path = self.path.join(format!(
+            "{key}.{}-{}.part",
+            std::process::id(),
+            PARTIAL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = tokio::fs::File::create(&partial_path).await?;
+        let mut writer = ImageCacheWriterImpl {
+            file: Some(file),
+            partial_path,
+            path: self.path.join(key),
+        };
+        writer.write(&stream_header(content_type)).await?;
+
+        Ok(writer)
+    }
+}
+
+pub struct ImageCacheWriterImpl {
+    file: Option<tokio::fs::File>,
+    partial_path: PathBuf,
+    path: PathBuf,
+}
+
+#[async_trait]
+impl ImageCacheWriter for ImageCacheWriterImpl {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), ImageCacheRepositoryError> {
+        let Some(file) = self.file.as_mut() else {
+            return Err(ImageCacheRepositoryError::Other(
+                "image cache entry is already finished".to_string(),
+            ));
+        };
+        file.write_all(chunk).await?;
+
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<(), ImageCacheRepositoryError> {
+        if let Some(mut file) = self.file.take() {
+            file.flush().await?;
+        }
+        if let Err(e) = tokio::fs::rename(&self.partial_path, &self.path).await {
+            let _ = tokio::fs::remove_file(&self.partial_path).await;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ImageCacheWriterImpl {
+    fn drop(&mut self) {
+        // unfinished, e.g. the client went away or the source failed mid-image
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.partial_path);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -110,4 +209,47 @@ mod tests {
 
         let _ = std::fs::remove_dir_all(&dir);
     }
+
+    #[tokio::test]
+    async fn test_postcard_entry_is_still_read() {
+        let dir = temp_cache_dir("postcard");
+        let repo = ImageCacheRepositoryImpl::new(&dir);
+
+        let image = Image {
+            content_type: "image/png".to_string(),
+            data: Bytes::from_static(b"png"),
+        };
+        let mut encoded = MAGIC.to_vec();
+        encoded.extend_from_slice(&postcard::to_allocvec(&image).unwrap());
+        std::fs::write(dir.join("key"), encoded).unwrap();
+
+        let cached = repo.get("key").await.unwrap();
+        assert_eq!(cached.content_type, image.content_type);
+        assert_eq!(cached.data, image.data);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_entry_is_only_kept_when_finished() {
+        let dir = temp_cache_dir("stream");
+        let repo = ImageCacheRepositoryImpl::new(&dir);
+
+        let mut writer = repo.writer("dropped", "image/webp").await.unwrap();
+        writer.write(b"half").await.unwrap();
+        drop(writer);
+        assert!(repo.get("dropped").await.is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        let mut writer = repo.writer("key", "image/webp").await.unwrap();
+        writer.write(b"first ").await.unwrap();
+        writer.write(b"second").await.unwrap();
+        writer.finish().await.unwrap();
+
+        let cached = repo.get("key").await.unwrap();
+        assert_eq!(cached.content_type, "image/webp");
+        assert_eq!(cached.data, Bytes::from_static(b"first second"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -13,7 +13,7 @@ use crate::{
         },
         repositories::library::{LibraryRepository, LibraryRepositoryError},
     },
-    infrastructure::database::Pool,
+    infrastructure::{database::Pool, domain::repositories::manga::manga_from_row},
 };
 
 #[derive(Clone)]
@@ -199,17 +199,8 @@ impl LibraryRepository for LibraryRepositoryImpl {
         .fetch(&self.pool as &SqlitePool)
         .map(|row| {
             row.map(|row| Manga {
-                id: row.get(0),
-                source_id: row.get(1),
-                title: row.get(2),
-                author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-                genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-                status: row.get(5),
-                description: row.get(6),
-                path: row.get(7),
-                cover_url: row.get(8),
-                date_added: row.get(9),
-                last_uploaded_at: row.get(10),
+                last_uploaded_at: row.get("last_uploaded"),
+                ..manga_from_row(&row)
             })
             .map_err(LibraryRepositoryError::DbError)
         })
@@ -237,17 +228,8 @@ impl LibraryRepository for LibraryRepositoryImpl {
         .fetch(&self.pool as &SqlitePool)
         .map(|row| {
             row.map(|row| Manga {
-                id: row.get(0),
-                source_id: row.get(1),
-                title: row.get(2),
-                author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-                genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-                status: row.get(5),
-                description: row.get(6),
-                path: row.get(7),
-                cover_url: row.get(8),
-                date_added: row.get(9),
-                last_uploaded_at: row.get(10),
+                last_uploaded_at: row.get("last_uploaded"),
+                ..manga_from_row(&row)
             })
             .map_err(LibraryRepositoryError::DbError)
         })
@@ -275,17 +257,8 @@ impl LibraryRepository for LibraryRepositoryImpl {
         .fetch(&self.pool as &SqlitePool)
         .map(|row| {
             row.map(|row| Manga {
-                id: row.get(0),
-                source_id: row.get(1),
-                title: row.get(2),
-                author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-                genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-                status: row.get(5),
-                description: row.get(6),
-                path: row.get(7),
-                cover_url: row.get(8),
-                date_added: row.get(9),
-                last_uploaded_at: row.get(10),
+                last_uploaded_at: row.get("last_uploaded"),
+                ..manga_from_row(&row)
             })
             .map_err(LibraryRepositoryError::DbError)
         })
@@ -313,19 +286,7 @@ impl LibraryRepository for LibraryRepositoryImpl {
         .fetch_all(&self.pool as &SqlitePool)
         .await?
         .into_par_iter()
-        .map(|row| Manga {
-            id: row.get(0),
-            source_id: row.get(1),
-            title: row.get(2),
-            author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-            genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-            status: row.get(5),
-            description: row.get(6),
-            path: row.get(7),
-            cover_url: row.get(8),
-            date_added: row.get(9),
-            last_uploaded_at: None,
-        })
+        .map(|row| manga_from_row(&row))
         .collect();
 
         Ok(manga)
@@ -355,19 +316,7 @@ impl LibraryRepository for LibraryRepositoryImpl {
         .fetch_all(&self.pool as &SqlitePool)
         .await?
         .into_par_iter()
-        .map(|row| Manga {
-            id: row.get(0),
-            source_id: row.get(1),
-            title: row.get(2),
-            author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-            genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-            status: row.get(5),
-            description: row.get(6),
-            path: row.get(7),
-            cover_url: row.get(8),
-            date_added: row.get(9),
-            last_uploaded_at: None,
-        })
+        .map(|row| manga_from_row(&row))
         .collect();
 
         Ok(manga)
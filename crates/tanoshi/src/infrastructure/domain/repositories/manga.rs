@@ -7,7 +7,7 @@ use crate::{
 };
 use async_trait::async_trait;
 use chrono::Utc;
-use sqlx::{Row, SqlitePool};
+use sqlx::{Row, SqlitePool, sqlite::SqliteRow};
 
 #[derive(Clone)]
 pub struct MangaRepositoryImpl {
@@ -20,6 +20,36 @@ impl MangaRepositoryImpl {
     }
 }
 
+/// Map a row selecting `manga.*` by column name, queries may append their own
+/// columns after it.
+pub fn manga_from_row(row: &SqliteRow) -> Manga {
+    Manga {
+        id: row.get("id"),
+        source_id: row.get("source_id"),
+        title: row.get("title"),
+        alternative_titles: serde_json::from_str(
+            row.get::<String, _>("alternative_titles").as_str(),
+        )
+        .unwrap_or_default(),
+        author: serde_json::from_str(row.get::<String, _>("author").as_str()).unwrap_or_default(),
+        artist: serde_json::from_str(row.get::<String, _>("artist").as_str()).unwrap_or_default(),
+        genre: serde_json::from_str(row.get::<String, _>("genre").as_str()).unwrap_or_default(),
+        status: row
+            .get::<Option<String>, _>("status")
+            .and_then(|status| status.parse().ok()),
+        content_rating: row
+            .get::<Option<String>, _>("content_rating")
+            .and_then(|rating| rating.parse().ok()),
+        description: row.get("description"),
+        path: row.get("path"),
+        cover_url: row.get("cover_url"),
+        external_ids: serde_json::from_str(row.get::<String, _>("external_ids").as_str())
+            .unwrap_or_default(),
+        date_added: row.get("date_added"),
+        last_uploaded_at: None,
+    }
+}
+
 #[async_trait]
 impl MangaRepository for MangaRepositoryImpl {
     async fn get_manga_by_id(&self, id: i64) -> Result<Manga, MangaRepositoryError> {
@@ -28,19 +58,7 @@ impl MangaRepository for MangaRepositoryImpl {
             .fetch_one(&self.pool as &SqlitePool)
             .await?;
 
-        Ok(Manga {
-            id: row.get(0),
-            source_id: row.get(1),
-            title: row.get(2),
-            author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-            genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-            status: row.get(5),
-            description: row.get(6),
-            path: row.get(7),
-            cover_url: row.get(8),
-            date_added: row.get(9),
-            last_uploaded_at: None,
-        })
+        Ok(manga_from_row(&row))
     }
 
     async fn get_manga_by_ids(&self, ids: &[i64]) -> Result<Vec<Manga>, MangaRepositoryError> {
@@ -56,19 +74,7 @@ impl MangaRepository for MangaRepositoryImpl {
             .fetch_all(&self.pool as &SqlitePool)
             .await?
             .iter()
-            .map(|row| Manga {
-                id: row.get(0),
-                source_id: row.get(1),
-                title: row.get(2),
-                author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-                genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-                status: row.get(5),
-                description: row.get(6),
-                path: row.get(7),
-                cover_url: row.get(8),
-                date_added: row.get(9),
-                last_uploaded_at: None,
-            })
+            .map(manga_from_row)
             .collect();
 
         Ok(manga)
@@ -85,19 +91,7 @@ impl MangaRepository for MangaRepositoryImpl {
             .fetch_one(&self.pool as &SqlitePool)
             .await?;
 
-        Ok(Manga {
-            id: row.get(0),
-            source_id: row.get(1),
-            title: row.get(2),
-            author: serde_json::from_str(row.get::<String, _>(3).as_str()).unwrap_or_default(),
-            genre: serde_json::from_str(row.get::<String, _>(4).as_str()).unwrap_or_default(),
-            status: row.get(5),
-            description: row.get(6),
-            path: row.get(7),
-            cover_url: row.get(8),
-            date_added: row.get(9),
-            last_uploaded_at: None,
-        })
+        Ok(manga_from_row(&row))
     }
 
     async fn insert_manga(&self, manga: &mut Manga) -> Result<(), MangaRepositoryError> {
@@ -106,33 +100,45 @@ impl MangaRepository for MangaRepositoryImpl {
             INSERT INTO manga(
                 source_id,
                 title,
+                alternative_titles,
                 author,
+                artist,
                 genre,
                 status,
+                content_rating,
                 description,
                 path,
                 cover_url,
+                external_ids,
                 date_added
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             ON CONFLICT(source_id, path)
             DO UPDATE SET
                 title=excluded.title,
+                alternative_titles=excluded.alternative_titles,
                 author=excluded.author,
+                artist=excluded.artist,
                 genre=excluded.genre,
                 status=excluded.status,
+                content_rating=excluded.content_rating,
                 description=excluded.description,
                 date_added=excluded.date_added,
-                cover_url=excluded.cover_url
+                cover_url=excluded.cover_url,
+                external_ids=excluded.external_ids
         "#,
         )
         .bind(manga.source_id)
         .bind(&manga.title)
+        .bind(serde_json::to_string(&manga.alternative_titles).unwrap_or_else(|_| "[]".to_string()))
         .bind(serde_json::to_string(&manga.author).unwrap_or_else(|_| "[]".to_string()))
+        .bind(serde_json::to_string(&manga.artist).unwrap_or_else(|_| "[]".to_string()))
         .bind(serde_json::to_string(&manga.genre).unwrap_or_else(|_| "[]".to_string()))
-        .bind(&manga.status)
+        .bind(manga.status.map(|status| status.to_string()))
+        .bind(manga.content_rating.map(|rating| rating.to_string()))
         .bind(&manga.description)
         .bind(&manga.path)
         .bind(&manga.cover_url)
+        .bind(serde_json::to_string(&manga.external_ids).unwrap_or_else(|_| "{}".to_string()))
         .bind(Utc::now().naive_utc())
         .execute(&self.pool as &SqlitePool)
         .await?
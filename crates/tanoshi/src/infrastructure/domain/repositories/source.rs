@@ -1,13 +1,23 @@
-use std::str::FromStr;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
 use async_trait::async_trait;
 use serde::Deserialize;
-use tanoshi_lib::prelude::Version;
-use tanoshi_vm::prelude::ExtensionManager;
+use sqlx::{Row, SqlitePool};
+use tanoshi_lib::prelude::{Capabilities, Credentials, SourceSession, Version};
+use tanoshi_vm::prelude::{ExtensionManager, PluginVerification, RequestOverrides};
 
-use crate::domain::{
-    entities::source::Source,
-    repositories::source::{SourceRepository, SourceRepositoryError},
+use crate::{
+    domain::{
+        entities::source::{
+            ExtensionRepository, Source, SourceCredential, SourceInstallEvent,
+            SourceRequestOverride,
+        },
+        repositories::source::{SourceRepository, SourceRepositoryError},
+    },
+    infrastructure::database::Pool,
 };
 
 #[derive(Deserialize)]
@@ -19,19 +29,143 @@ pub struct SourceDto {
     pub rustc_version: String,
     pub lib_version: String,
     pub icon: String,
+    #[serde(default)]
+    pub sha256: Option<TargetValue>,
+    #[serde(default)]
+    pub signature: Option<TargetValue>,
+}
+
+/// A value of an `index.json` entry, shared by every target or keyed by
+/// target triple
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum TargetValue {
+    All(String),
+    PerTarget(HashMap<String, String>),
+}
+
+impl TargetValue {
+    fn for_target(&self) -> Option<String> {
+        match self {
+            TargetValue::All(value) => Some(value.clone()),
+            TargetValue::PerTarget(values) => values.get(env!("TARGET")).cloned(),
+        }
+    }
+}
+
+/// An `index.json` entry and the repository it is installed from
+struct IndexedSource<'a> {
+    repository: &'a ExtensionRepository,
+    source: SourceDto,
+}
+
+impl IndexedSource<'_> {
+    fn verification(&self) -> PluginVerification {
+        PluginVerification {
+            name: self.source.name.clone(),
+            version: self.source.version.clone(),
+            sha256: self
+                .source
+                .sha256
+                .as_ref()
+                .and_then(TargetValue::for_target),
+            signature: self
+                .source
+                .signature
+                .as_ref()
+                .and_then(TargetValue::for_target),
+            trusted_keys: self.repository.trusted_keys.clone(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct SourceRepositoryImpl {
+    pool: Pool,
     extension_manager: ExtensionManager,
 }
 
 impl SourceRepositoryImpl {
-    pub fn new(ext: ExtensionManager) -> Self {
+    pub fn new<P: Into<Pool>>(pool: P, ext: ExtensionManager) -> Self {
         Self {
+            pool: pool.into(),
             extension_manager: ext,
         }
     }
+
+    /// Merge the indexes of every repository. A repository that cannot be
+    /// reached is skipped, unless none can.
+    async fn fetch_indexes<'a>(
+        &self,
+        repositories: &'a [ExtensionRepository],
+    ) -> Result<Vec<IndexedSource<'a>>, SourceRepositoryError> {
+        let mut repositories = repositories.iter().collect::<Vec<_>>();
+        // stable, so repositories of equal priority keep the configured order
+        repositories.sort_by_key(|repository| std::cmp::Reverse(repository.priority));
+
+        let mut seen = HashSet::new();
+        let mut sources = vec![];
+        let mut last_error = None;
+        for repository in repositories {
+            let index = match fetch_index(repository).await {
+                Ok(index) => index,
+                Err(e) => {
+                    warn!(
+                        "failed to fetch index of repository {}: {e}",
+                        repository.name
+                    );
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+
+            for source in index {
+                if seen.insert(source.id) {
+                    sources.push(IndexedSource { repository, source });
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if sources.is_empty() => Err(e),
+            _ => Ok(sources),
+        }
+    }
+
+    async fn find_indexed_source<'a>(
+        &self,
+        repositories: &'a [ExtensionRepository],
+        id: i64,
+    ) -> Result<IndexedSource<'a>, SourceRepositoryError> {
+        let indexed = self
+            .fetch_indexes(repositories)
+            .await?
+            .into_iter()
+            .find(|indexed| indexed.source.id == id)
+            .ok_or(SourceRepositoryError::NotFound)?;
+
+        if indexed.source.rustc_version != tanoshi_lib::RUSTC_VERSION
+            || indexed.source.lib_version != tanoshi_lib::LIB_VERSION
+        {
+            return Err(SourceRepositoryError::Other(
+                "Incompatible version, update tanoshi server".to_string(),
+            ));
+        }
+
+        Ok(indexed)
+    }
+}
+
+async fn fetch_index(
+    repository: &ExtensionRepository,
+) -> Result<Vec<SourceDto>, SourceRepositoryError> {
+    let index = reqwest::get(format!("{}/index.json", repository.url))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(index)
 }
 
 #[async_trait]
@@ -52,29 +186,28 @@ impl SourceRepository for SourceRepositoryImpl {
 
     async fn available_sources(
         &self,
-        repo_url: &str,
+        repositories: &[ExtensionRepository],
         filter_installed: bool,
     ) -> Result<Vec<Source>, SourceRepositoryError> {
-        let source_indexes: Vec<SourceDto> = reqwest::get(&format!("{repo_url}/index.json"))
-            .await?
-            .json()
-            .await?;
+        let source_indexes = self.fetch_indexes(repositories).await?;
 
         let mut sources: Vec<Source> = vec![];
-        for index in source_indexes {
-            if filter_installed && self.extension_manager.exists(index.id).await? {
+        for IndexedSource { repository, source } in source_indexes {
+            if filter_installed && self.extension_manager.exists(source.id).await? {
                 continue;
             }
 
             sources.push(Source {
-                id: index.id,
-                name: index.name,
-                url: index.url,
-                version: index.version,
-                rustc_version: index.rustc_version,
-                lib_version: index.lib_version,
-                icon: index.icon,
+                id: source.id,
+                name: source.name,
+                url: source.url,
+                version: source.version,
+                rustc_version: source.rustc_version,
+                lib_version: source.lib_version,
+                icon: source.icon,
                 has_update: false,
+                capabilities: Capabilities::default(),
+                repository: Some(repository.name.clone()),
             });
         }
 
@@ -86,73 +219,420 @@ impl SourceRepository for SourceRepositoryImpl {
         Ok(source.into())
     }
 
-    async fn install_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError> {
+    async fn install_source(
+        &self,
+        repositories: &[ExtensionRepository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError> {
         if self.extension_manager.exists(id).await? {
             return Err(SourceRepositoryError::Other(
                 "source installed, use updateSource to update".to_string(),
             ));
         }
 
-        let source_indexes: Vec<SourceDto> = reqwest::get(format!("{repo_url}/index.json"))
-            .await?
-            .json()
+        let indexed = self.find_indexed_source(repositories, id).await?;
+
+        self.extension_manager
+            .install(
+                &indexed.repository.url,
+                &indexed.source.name,
+                &indexed.verification(),
+            )
             .await?;
 
-        let source = source_indexes
-            .iter()
-            .find(|index| index.id == id)
-            .ok_or(SourceRepositoryError::NotFound)?;
+        Ok(())
+    }
+
+    async fn update_source(
+        &self,
+        repositories: &[ExtensionRepository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError> {
+        let installed_source = self.extension_manager.get_source_info(id)?;
+
+        let indexed = self.find_indexed_source(repositories, id).await?;
 
-        if source.rustc_version != tanoshi_lib::RUSTC_VERSION
-            || source.lib_version != tanoshi_lib::LIB_VERSION
+        if Version::from_str(installed_source.version)?
+            == Version::from_str(&indexed.source.version)?
         {
-            return Err(SourceRepositoryError::Other(
-                "Incompatible version, update tanoshi server".to_string(),
-            ));
+            return Err(SourceRepositoryError::Other("No new version".to_string()));
         }
 
+        // install replaces the loaded source only once the download passed
+        // verification and loads, a failed update keeps the installed one
         self.extension_manager
-            .install(repo_url, &source.name)
+            .install(
+                &indexed.repository.url,
+                &indexed.source.name,
+                &indexed.verification(),
+            )
             .await?;
 
         Ok(())
     }
 
-    async fn update_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError> {
-        let installed_source = self.extension_manager.get_source_info(id)?;
+    async fn uninstall_source(&self, id: i64) -> Result<(), SourceRepositoryError> {
+        self.extension_manager.remove(id).await?;
+
+        Ok(())
+    }
+
+    async fn rollback_source(&self, id: i64, version: &str) -> Result<(), SourceRepositoryError> {
+        self.extension_manager.rollback(id, version).await?;
+
+        Ok(())
+    }
+
+    async fn source_versions(&self, id: i64) -> Result<Vec<String>, SourceRepositoryError> {
+        Ok(self.extension_manager.source_versions(id)?)
+    }
+
+    async fn get_source_install_events(
+        &self,
+        id: i64,
+    ) -> Result<Vec<SourceInstallEvent>, SourceRepositoryError> {
+        let events = sqlx::query(
+            r#"SELECT source_id, event, version, previous_version, created_at
+            FROM source_install_event
+            WHERE source_id = ?
+            ORDER BY created_at DESC, id DESC"#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool as &SqlitePool)
+        .await?
+        .into_iter()
+        .map(|row| SourceInstallEvent {
+            source_id: row.get("source_id"),
+            event: row.get("event"),
+            version: row.get("version"),
+            previous_version: row.get("previous_version"),
+            created_at: row.get("created_at"),
+        })
+        .collect();
 
-        let source_indexes: Vec<SourceDto> = reqwest::get(format!("{repo_url}/index.json"))
+        Ok(events)
+    }
+
+    async fn insert_source_install_event(
+        &self,
+        event: SourceInstallEvent,
+    ) -> Result<(), SourceRepositoryError> {
+        sqlx::query(
+            r#"INSERT INTO source_install_event(
+                source_id,
+                event,
+                version,
+                previous_version,
+                created_at
+            ) VALUES (?, ?, ?, ?, ?)"#,
+        )
+        .bind(event.source_id)
+        .bind(event.event)
+        .bind(event.version)
+        .bind(event.previous_version)
+        .bind(event.created_at)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_source_pin(&self, id: i64) -> Result<Option<String>, SourceRepositoryError> {
+        let version = sqlx::query("SELECT version FROM source_pin WHERE source_id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool as &SqlitePool)
             .await?
-            .json()
+            .map(|row| row.get("version"));
+
+        Ok(version)
+    }
+
+    async fn get_pinned_source_ids(&self) -> Result<Vec<i64>, SourceRepositoryError> {
+        let source_ids = sqlx::query("SELECT source_id FROM source_pin")
+            .fetch_all(&self.pool as &SqlitePool)
+            .await?
+            .into_iter()
+            .map(|row| row.get("source_id"))
+            .collect();
+
+        Ok(source_ids)
+    }
+
+    async fn insert_source_pin(&self, id: i64, version: &str) -> Result<(), SourceRepositoryError> {
+        sqlx::query(
+            r#"INSERT INTO source_pin(source_id, version) VALUES (?, ?)
+            ON CONFLICT(source_id) DO UPDATE SET
+            version = excluded.version,
+            created_at = CURRENT_TIMESTAMP"#,
+        )
+        .bind(id)
+        .bind(version)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_source_pin(&self, id: i64) -> Result<(), SourceRepositoryError> {
+        sqlx::query("DELETE FROM source_pin WHERE source_id = ?")
+            .bind(id)
+            .execute(&self.pool as &SqlitePool)
             .await?;
-        let source = source_indexes
-            .iter()
-            .find(|index| index.id == id)
-            .ok_or(SourceRepositoryError::NotFound)?;
 
-        if Version::from_str(installed_source.version)? == Version::from_str(&source.version)? {
-            return Err(SourceRepositoryError::Other("No new version".to_string()));
-        }
+        Ok(())
+    }
 
-        if source.rustc_version != tanoshi_lib::RUSTC_VERSION
-            || source.lib_version != tanoshi_lib::LIB_VERSION
-        {
-            return Err(SourceRepositoryError::Other(
-                "Incompatible version, update tanoshi server".to_string(),
-            ));
-        }
+    async fn login(
+        &self,
+        id: i64,
+        credentials: Credentials,
+    ) -> Result<SourceSession, SourceRepositoryError> {
+        Ok(self.extension_manager.login(id, credentials).await?)
+    }
 
-        self.extension_manager.remove(id).await?;
-        self.extension_manager
-            .install(repo_url, &source.name)
+    async fn restore_session(
+        &self,
+        id: i64,
+        session: SourceSession,
+    ) -> Result<(), SourceRepositoryError> {
+        Ok(self.extension_manager.restore_session(id, session).await?)
+    }
+
+    async fn logout(&self, id: i64) -> Result<(), SourceRepositoryError> {
+        Ok(self.extension_manager.logout(id).await?)
+    }
+
+    async fn is_logged_in(&self, id: i64) -> Result<bool, SourceRepositoryError> {
+        Ok(self.extension_manager.is_logged_in(id).await?)
+    }
+
+    async fn get_source_credentials(&self) -> Result<Vec<SourceCredential>, SourceRepositoryError> {
+        let credentials =
+            sqlx::query("SELECT source_id, credentials, session FROM source_credential")
+                .fetch_all(&self.pool as &SqlitePool)
+                .await?
+                .into_iter()
+                .map(|row| SourceCredential {
+                    source_id: row.get("source_id"),
+                    credentials: row.get("credentials"),
+                    session: row.get("session"),
+                })
+                .collect();
+
+        Ok(credentials)
+    }
+
+    async fn get_source_credential(
+        &self,
+        id: i64,
+    ) -> Result<Option<SourceCredential>, SourceRepositoryError> {
+        let credential = sqlx::query(
+            "SELECT source_id, credentials, session FROM source_credential WHERE source_id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool as &SqlitePool)
+        .await?
+        .map(|row| SourceCredential {
+            source_id: row.get("source_id"),
+            credentials: row.get("credentials"),
+            session: row.get("session"),
+        });
+
+        Ok(credential)
+    }
+
+    async fn insert_source_credential(
+        &self,
+        credential: SourceCredential,
+    ) -> Result<(), SourceRepositoryError> {
+        sqlx::query(
+            r#"INSERT INTO source_credential(
+                source_id,
+                credentials,
+                session
+            ) VALUES (?, ?, ?)
+            ON CONFLICT(source_id) DO UPDATE SET
+            credentials = excluded.credentials,
+            session = excluded.session,
+            updated_at = CURRENT_TIMESTAMP"#,
+        )
+        .bind(credential.source_id)
+        .bind(credential.credentials)
+        .bind(credential.session)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_source_credential(&self, id: i64) -> Result<(), SourceRepositoryError> {
+        sqlx::query("DELETE FROM source_credential WHERE source_id = ?")
+            .bind(id)
+            .execute(&self.pool as &SqlitePool)
             .await?;
 
         Ok(())
     }
 
-    async fn uninstall_source(&self, id: i64) -> Result<(), SourceRepositoryError> {
-        self.extension_manager.remove(id).await?;
+    async fn get_source_request_overrides(
+        &self,
+    ) -> Result<Vec<SourceRequestOverride>, SourceRepositoryError> {
+        let overrides = sqlx::query(
+            "SELECT source_id, kind, name, value, expires_at FROM source_request_override",
+        )
+        .fetch_all(&self.pool as &SqlitePool)
+        .await?
+        .into_iter()
+        .map(|row| SourceRequestOverride {
+            source_id: row.get("source_id"),
+            kind: row.get("kind"),
+            name: row.get("name"),
+            value: row.get("value"),
+            expires_at: row.get("expires_at"),
+        })
+        .collect();
+
+        Ok(overrides)
+    }
+
+    async fn get_source_request_overrides_by_source_id(
+        &self,
+        id: i64,
+    ) -> Result<Vec<SourceRequestOverride>, SourceRepositoryError> {
+        let overrides = sqlx::query(
+            r#"SELECT source_id, kind, name, value, expires_at FROM source_request_override
+            WHERE source_id = ?
+            ORDER BY kind, name"#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool as &SqlitePool)
+        .await?
+        .into_iter()
+        .map(|row| SourceRequestOverride {
+            source_id: row.get("source_id"),
+            kind: row.get("kind"),
+            name: row.get("name"),
+            value: row.get("value"),
+            expires_at: row.get("expires_at"),
+        })
+        .collect();
+
+        Ok(overrides)
+    }
+
+    async fn insert_source_request_override(
+        &self,
+        request_override: SourceRequestOverride,
+    ) -> Result<(), SourceRepositoryError> {
+        sqlx::query(
+            r#"INSERT INTO source_request_override(
+                source_id,
+                kind,
+                name,
+                value,
+                expires_at
+            ) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(source_id, kind, name) DO UPDATE SET
+            value = excluded.value,
+            expires_at = excluded.expires_at,
+            updated_at = CURRENT_TIMESTAMP"#,
+        )
+        .bind(request_override.source_id)
+        .bind(request_override.kind)
+        .bind(request_override.name)
+        .bind(request_override.value)
+        .bind(request_override.expires_at)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_source_request_override(
+        &self,
+        id: i64,
+        kind: &str,
+        name: &str,
+    ) -> Result<(), SourceRepositoryError> {
+        sqlx::query(
+            "DELETE FROM source_request_override WHERE source_id = ? AND kind = ? AND name = ?",
+        )
+        .bind(id)
+        .bind(kind)
+        .bind(name)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
 
         Ok(())
     }
+
+    async fn delete_source_request_overrides(&self, id: i64) -> Result<(), SourceRepositoryError> {
+        sqlx::query("DELETE FROM source_request_override WHERE source_id = ?")
+            .bind(id)
+            .execute(&self.pool as &SqlitePool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_request_overrides(
+        &self,
+        id: i64,
+        overrides: RequestOverrides,
+    ) -> Result<(), SourceRepositoryError> {
+        self.extension_manager.set_request_overrides(id, overrides);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tanoshi_vm::prelude::sha256_hex;
+
+    use super::*;
+    use crate::infrastructure::config::Config;
+
+    fn index_entry(sha256: Option<TargetValue>) -> SourceDto {
+        SourceDto {
+            id: 1,
+            name: "Example".to_string(),
+            url: "https://example.com".to_string(),
+            version: "1.0.0".to_string(),
+            rustc_version: tanoshi_lib::RUSTC_VERSION.to_string(),
+            lib_version: tanoshi_lib::LIB_VERSION.to_string(),
+            icon: String::new(),
+            sha256,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn the_default_repository_refuses_tampered_or_unlisted_libraries() {
+        let library = b"extension library";
+        let repositories = Config::default().extension_repositories;
+        let indexed = |sha256| IndexedSource {
+            repository: &repositories[0],
+            source: index_entry(sha256),
+        };
+
+        let listed = indexed(Some(TargetValue::All(sha256_hex(library))));
+        listed.verification().verify(library).unwrap();
+        assert!(
+            listed
+                .verification()
+                .verify(b"tampered library")
+                .is_err()
+        );
+
+        // An index without a checksum for this target can't vouch for the
+        // library either.
+        let other_target = TargetValue::PerTarget(HashMap::from([(
+            "other-target".to_string(),
+            sha256_hex(library),
+        )]));
+        for unlisted in [indexed(None), indexed(Some(other_target))] {
+            assert!(unlisted.verification().verify(library).is_err());
+        }
+    }
 }
@@ -11,7 +11,9 @@ use bytes::Bytes;
 use fancy_regex::Regex;
 use mime_guess::mime;
 use serde::{Deserialize, Serialize};
-use tanoshi_lib::prelude::{ChapterInfo, Extension, Input, Lang, MangaInfo, SourceInfo};
+use tanoshi_lib::prelude::{
+    ChapterInfo, Extension, Input, Lang, MangaInfo, Paginated, SourceInfo,
+};
 
 use crate::infrastructure::archive::ArchiveReader;
 
@@ -228,6 +230,7 @@ fn map_entry_to_chapter(source_id: i64, path: &Path) -> Option<ChapterInfo> {
         number,
         scanlator: None,
         uploaded: modified as i64,
+        ..Default::default()
     })
 }
 
@@ -242,6 +245,7 @@ impl Extension for Local {
             icon: "/icons/192.png",
             languages: Lang::All,
             nsfw: false,
+            ..Default::default()
         }
     }
 
@@ -273,8 +277,25 @@ impl Extension for Local {
         &self,
         page: i64,
         query: Option<String>,
-        _filters: Option<Vec<Input>>,
+        filters: Option<Vec<Input>>,
     ) -> Result<Vec<MangaInfo>> {
+        self.search_manga_paged(page, query, filters).map(|page| page.items)
+    }
+
+    fn get_popular_manga_paged(&self, page: i64) -> Result<Paginated<MangaInfo>> {
+        self.search_manga_paged(page, None, None)
+    }
+
+    fn get_latest_manga_paged(&self, page: i64) -> Result<Paginated<MangaInfo>> {
+        self.search_manga_paged(page, None, None)
+    }
+
+    fn search_manga_paged(
+        &self,
+        page: i64,
+        query: Option<String>,
+        _filters: Option<Vec<Input>>,
+    ) -> Result<Paginated<MangaInfo>> {
         let id = self.id;
         let path = self.path.clone();
         let offset = (page - 1) * 20;
@@ -302,7 +323,11 @@ impl Extension for Local {
             }));
         }
 
-        let manga = data
+        let entries = data.collect::<Vec<_>>();
+        let total = entries.len() as i64;
+
+        let manga = entries
+            .into_iter()
             .skip(offset as _)
             .take(20)
             .map(|entry| MangaInfo {
@@ -312,16 +337,13 @@ impl Extension for Local {
                     .file_stem()
                     .map(|s| s.to_string_lossy().to_string())
                     .unwrap_or_default(),
-                author: vec![],
-                genre: vec![],
-                status: None,
-                description: None,
                 path: entry.path().to_string_lossy().to_string(),
                 cover_url: find_cover_url(&entry.path()),
+                ..Default::default()
             })
             .collect::<Vec<_>>();
 
-        Ok(manga)
+        Ok(Paginated::new(manga, offset + 20 < total).with_total(total))
     }
 
     fn get_manga_detail(&self, path: String) -> Result<MangaInfo> {
@@ -337,12 +359,10 @@ impl Extension for Local {
         let mut manga = MangaInfo {
             source_id: id,
             title: title.clone(),
-            author: vec![],
-            genre: vec![],
-            status: Some(String::new()),
             description: Some(title),
             path: path.display().to_string(),
             cover_url,
+            ..Default::default()
         };
 
         if let Some(info) = find_details(&path)
@@ -360,6 +380,9 @@ impl Extension for Local {
             if let Some(genre) = info.genre {
                 manga.genre = genre;
             }
+            if let Some(status) = info.status {
+                manga.status = status.parse().ok();
+            }
             if let Some(description) = info.description {
                 manga.description = Some(description);
             }
@@ -481,6 +504,16 @@ mod test {
         assert_eq!(manga.unwrap().len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_get_popular_manga_paged_reports_last_page() {
+        let local = Local::new(1, "Local".to_string(), "../../test/data/manga");
+        let manga = local.get_popular_manga_paged(1).unwrap();
+
+        assert_eq!(manga.items.len(), 3);
+        assert!(!manga.has_next_page);
+        assert_eq!(manga.total, Some(3));
+    }
+
     #[tokio::test]
     async fn test_get_manga_detail_single_archive() {
         let local = Local::new(1, "Local".to_string(), "../../test/data/manga");
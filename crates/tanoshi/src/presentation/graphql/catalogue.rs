@@ -7,8 +7,35 @@ use crate::{
     },
 };
 
-use async_graphql::{Context, Object, Result};
+use async_graphql::{Context, Object, Result, SimpleObject};
 use rayon::prelude::*;
+use tanoshi_lib::prelude::Paginated;
+
+/// A page of manga from a source listing
+#[derive(Debug, SimpleObject)]
+pub struct PaginatedManga {
+    pub manga: Vec<Manga>,
+    pub has_next_page: bool,
+    pub total: Option<i64>,
+}
+
+impl From<Paginated<crate::domain::entities::manga::Manga>> for PaginatedManga {
+    fn from(page: Paginated<crate::domain::entities::manga::Manga>) -> Self {
+        Self {
+            manga: page.items.into_par_iter().map(Manga::from).collect(),
+            has_next_page: page.has_next_page,
+            total: page.total,
+        }
+    }
+}
+
+/// A manga, and the chapter when the link points to one, resolved from a
+/// source website url
+#[derive(Debug, SimpleObject)]
+pub struct ResolvedUrl {
+    pub manga: Manga,
+    pub chapter: Option<Chapter>,
+}
 
 #[derive(Default)]
 pub struct CatalogueRoot;
@@ -20,32 +47,26 @@ impl CatalogueRoot {
         ctx: &Context<'_>,
         #[graphql(desc = "source id")] source_id: i64,
         #[graphql(desc = "page")] page: i64,
-    ) -> Result<Vec<Manga>> {
+    ) -> Result<PaginatedManga> {
         let fetched_manga = ctx
             .data::<MangaService<MangaRepositoryImpl>>()?
             .fetch_source_popular_manga(source_id, page)
-            .await?
-            .into_par_iter()
-            .map(Manga::from)
-            .collect();
+            .await?;
 
-        Ok(fetched_manga)
+        Ok(fetched_manga.into())
     }
     async fn get_latest_manga(
         &self,
         ctx: &Context<'_>,
         #[graphql(desc = "source id")] source_id: i64,
         #[graphql(desc = "page")] page: i64,
-    ) -> Result<Vec<Manga>> {
+    ) -> Result<PaginatedManga> {
         let fetched_manga = ctx
             .data::<MangaService<MangaRepositoryImpl>>()?
             .fetch_source_latest_manga(source_id, page)
-            .await?
-            .into_par_iter()
-            .map(Manga::from)
-            .collect();
+            .await?;
 
-        Ok(fetched_manga)
+        Ok(fetched_manga.into())
     }
 
     async fn browse_source(
@@ -55,16 +76,13 @@ impl CatalogueRoot {
         #[graphql(desc = "page")] page: i64,
         #[graphql(desc = "query")] query: Option<String>,
         #[graphql(desc = "filters")] filters: Option<InputList>,
-    ) -> Result<Vec<Manga>> {
+    ) -> Result<PaginatedManga> {
         let fetched_manga = ctx
             .data::<MangaService<MangaRepositoryImpl>>()?
             .fetch_source_manga(source_id, page, query, filters.map(|filters| filters.0))
-            .await?
-            .into_par_iter()
-            .map(Manga::from)
-            .collect();
+            .await?;
 
-        Ok(fetched_manga)
+        Ok(fetched_manga.into())
     }
 
     async fn manga_by_source_path(
@@ -81,6 +99,36 @@ impl CatalogueRoot {
         Ok(manga.into())
     }
 
+    async fn resolve_url(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "link to a manga or chapter on a source website")] url: String,
+    ) -> Result<Option<ResolvedUrl>> {
+        let Some((manga, chapter_path)) = ctx
+            .data::<MangaService<MangaRepositoryImpl>>()?
+            .resolve_url(&url)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let chapter = match chapter_path {
+            Some(chapter_path) => ctx
+                .data::<ChapterService<ChapterRepositoryImpl>>()?
+                .fetch_chapters_by_manga_id(manga.source_id, &manga.path, manga.id, false)
+                .await?
+                .into_iter()
+                .find(|chapter| chapter.path == chapter_path)
+                .map(Chapter::from),
+            None => None,
+        };
+
+        Ok(Some(ResolvedUrl {
+            manga: manga.into(),
+            chapter,
+        }))
+    }
+
     async fn manga(
         &self,
         ctx: &Context<'_>,
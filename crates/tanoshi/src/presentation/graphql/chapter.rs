@@ -23,7 +23,7 @@ use crate::{
 };
 use async_graphql::{dataloader::DataLoader, Context, Object, Result};
 use chrono::{NaiveDateTime, Utc};
-use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 pub enum DownloadStatus {
     Downloading,
@@ -40,6 +40,10 @@ pub struct Chapter {
     pub path: String,
     pub number: f64,
     pub scanlator: String,
+    pub volume: Option<String>,
+    pub language: Option<String>,
+    pub page_count: Option<i64>,
+    pub external_url: Option<String>,
     pub uploaded: chrono::NaiveDateTime,
     pub date_added: chrono::NaiveDateTime,
     pub read_progress: Option<ReadProgress>,
@@ -59,6 +63,10 @@ impl From<tanoshi_lib::models::ChapterInfo> for Chapter {
             path: ch.path,
             number: ch.number,
             scanlator: ch.scanlator.unwrap_or_default(),
+            volume: ch.volume,
+            language: ch.language,
+            page_count: ch.page_count,
+            external_url: ch.external_url,
             uploaded: chrono::NaiveDateTime::from_timestamp_opt(ch.uploaded, 0).unwrap_or_default(),
             date_added: Utc::now().naive_utc(),
             read_progress: None,
@@ -79,6 +87,10 @@ impl From<crate::domain::entities::chapter::Chapter> for Chapter {
             path: val.path,
             number: val.number,
             scanlator: val.scanlator,
+            volume: val.volume,
+            language: val.language,
+            page_count: val.page_count,
+            external_url: val.external_url,
             uploaded: val.uploaded,
             date_added: val.date_added,
             read_progress: None,
@@ -99,6 +111,10 @@ impl From<Chapter> for crate::domain::entities::chapter::Chapter {
             path: val.path,
             number: val.number,
             scanlator: val.scanlator,
+            volume: val.volume,
+            language: val.language,
+            page_count: val.page_count,
+            external_url: val.external_url,
             uploaded: val.uploaded,
             date_added: val.date_added,
             downloaded_path: val.downloaded_path,
@@ -130,6 +146,22 @@ impl Chapter {
         self.scanlator.clone()
     }
 
+    async fn volume(&self) -> Option<String> {
+        self.volume.clone()
+    }
+
+    async fn language(&self) -> Option<String> {
+        self.language.clone()
+    }
+
+    async fn page_count(&self) -> Option<i64> {
+        self.page_count
+    }
+
+    async fn external_url(&self) -> Option<String> {
+        self.external_url.clone()
+    }
+
     async fn prev(&self) -> Option<i64> {
         self.prev
     }
@@ -179,7 +211,7 @@ impl Chapter {
         #[graphql(desc = "fetch from source", default = false)] _fetch: bool,
         #[graphql(desc = "encrypt url", default = true)] encrypt: bool,
     ) -> Result<Vec<String>> {
-        let mut pages = ctx
+        let pages = ctx
             .data::<ChapterService<ChapterRepositoryImpl>>()?
             .fetch_chapter_pages(self.source_id, &self.path, &self.downloaded_path)
             .await?;
@@ -187,14 +219,16 @@ impl Chapter {
         let image_svc =
             ctx.data::<ImageService<ImageCacheRepositoryImpl, ImageRepositoryImpl>>()?;
 
-        if encrypt {
-            let secret = &ctx.data::<Config>()?.secret;
-            pages.par_iter_mut().try_for_each(|p| -> std::result::Result<(), ImageError> {
-                *p = image_svc.encrypt_image_url(secret, p)?;
-                Ok(())
-            })?;
+        if !encrypt {
+            return Ok(pages.into_iter().map(|page| page.url).collect());
         }
 
+        let secret = &ctx.data::<Config>()?.secret;
+        let pages = pages
+            .into_par_iter()
+            .map(|page| image_svc.encrypt_page(secret, page))
+            .collect::<std::result::Result<Vec<_>, ImageError>>()?;
+
         Ok(pages)
     }
 
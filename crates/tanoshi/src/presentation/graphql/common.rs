@@ -33,3 +33,14 @@ pub struct ReadProgress {
 pub struct InputList(pub Vec<Input>);
 
 scalar!(InputList);
+
+#[derive(Deserialize, Serialize)]
+pub struct PreferenceValue(pub tanoshi_lib::prelude::PreferenceValue);
+
+scalar!(PreferenceValue);
+
+/// Preference values keyed by preference key
+#[derive(Deserialize, Serialize)]
+pub struct PreferenceValues(pub tanoshi_lib::prelude::PreferenceValues);
+
+scalar!(PreferenceValues);
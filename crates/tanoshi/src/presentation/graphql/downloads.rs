@@ -140,6 +140,10 @@ impl DownloadRoot {
                             path: e.path,
                             number: e.number,
                             scanlator: e.scanlator,
+                            volume: e.volume,
+                            language: e.language,
+                            page_count: e.page_count,
+                            external_url: e.external_url,
                             uploaded: e.uploaded,
                             date_added: e.date_added,
                             read_progress: None,
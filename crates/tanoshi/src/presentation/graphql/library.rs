@@ -232,6 +232,19 @@ impl LibraryMutationRoot {
             .insert_manga_to_library(claims.sub, manga_id, category_ids)
             .await?;
 
+        let manga = ctx
+            .data::<MangaService<MangaRepositoryImpl>>()?
+            .fetch_manga_by_id(manga_id, false)
+            .await?;
+        if !manga.external_ids.is_empty()
+            && let Err(e) = ctx
+                .data::<TrackerService<TrackerRepositoryImpl>>()?
+                .track_manga_by_external_ids(claims.sub, manga_id, &manga.external_ids)
+                .await
+        {
+            warn!("failed to match manga {manga_id} to trackers: {e}");
+        }
+
         Ok(1)
     }
 
@@ -8,7 +8,7 @@ use super::{
 use crate::{
     domain::services::{
         chapter::ChapterService, history::HistoryService, image::ImageService,
-        source::SourceService,
+        manga::MangaService, source::SourceService,
     },
     infrastructure::{
         auth::Claims,
@@ -16,14 +16,15 @@ use crate::{
         domain::repositories::{
             chapter::ChapterRepositoryImpl, history::HistoryRepositoryImpl,
             image::ImageRepositoryImpl, image_cache::ImageCacheRepositoryImpl,
-            source::SourceRepositoryImpl,
+            manga::MangaRepositoryImpl, source::SourceRepositoryImpl,
         },
     },
     presentation::graphql::schema::DatabaseLoader,
 };
-use async_graphql::{dataloader::DataLoader, Context, Object, Result, SimpleObject};
+use async_graphql::{dataloader::DataLoader, Context, Enum, Object, Result, SimpleObject};
 use chrono::NaiveDateTime;
 use rayon::prelude::*;
+use std::collections::BTreeMap;
 use tanoshi_vm::extension::ExtensionManager;
 
 #[derive(Debug, SimpleObject)]
@@ -32,6 +33,33 @@ pub struct Tracker {
     pub tracker_manga_id: Option<String>,
 }
 
+/// Id of a manga on another site, e.g. myanimelist or anilist
+#[derive(Debug, SimpleObject)]
+pub struct ExternalId {
+    pub site: String,
+    pub id: String,
+}
+
+/// Publication status of a manga
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(remote = "tanoshi_lib::prelude::MangaStatus")]
+pub enum MangaStatus {
+    Ongoing,
+    Completed,
+    Hiatus,
+    Cancelled,
+}
+
+/// Content rating of a manga, from least to most explicit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(remote = "tanoshi_lib::prelude::ContentRating")]
+pub enum ContentRating {
+    Safe,
+    Suggestive,
+    Erotica,
+    Pornographic,
+}
+
 /// A type represent manga details, normalized across source
 #[derive(Debug, Clone)]
 #[derive(Default)]
@@ -39,12 +67,16 @@ pub struct Manga {
     pub id: i64,
     pub source_id: i64,
     pub title: String,
+    pub alternative_titles: Vec<String>,
     pub author: Vec<String>,
+    pub artist: Vec<String>,
     pub genre: Vec<String>,
-    pub status: Option<String>,
+    pub status: Option<tanoshi_lib::prelude::MangaStatus>,
+    pub content_rating: Option<tanoshi_lib::prelude::ContentRating>,
     pub description: Option<String>,
     pub path: String,
     pub cover_url: String,
+    pub external_ids: BTreeMap<String, String>,
     pub date_added: chrono::NaiveDateTime,
 }
 
@@ -55,12 +87,16 @@ impl From<tanoshi_lib::models::MangaInfo> for Manga {
             id: 0,
             source_id: m.source_id,
             title: m.title,
+            alternative_titles: m.alternative_titles,
             author: m.author,
+            artist: m.artist,
             genre: m.genre,
             status: m.status,
+            content_rating: m.content_rating,
             description: m.description,
             path: m.path,
             cover_url: m.cover_url,
+            external_ids: m.external_ids,
             date_added: NaiveDateTime::default(),
         }
     }
@@ -72,12 +108,16 @@ impl From<crate::domain::entities::manga::Manga> for Manga {
             id: val.id,
             source_id: val.source_id,
             title: val.title,
+            alternative_titles: val.alternative_titles,
             author: val.author,
+            artist: val.artist,
             genre: val.genre,
             status: val.status,
+            content_rating: val.content_rating,
             description: val.description,
             path: val.path,
             cover_url: val.cover_url,
+            external_ids: val.external_ids,
             date_added: val.date_added,
         }
     }
@@ -93,16 +133,38 @@ impl Manga {
         self.title.clone()
     }
 
+    async fn alternative_titles(&self) -> Vec<String> {
+        self.alternative_titles.clone()
+    }
+
     async fn author(&self) -> Vec<String> {
         self.author.clone()
     }
 
+    async fn artist(&self) -> Vec<String> {
+        self.artist.clone()
+    }
+
     async fn genre(&self) -> Vec<String> {
         self.genre.clone()
     }
 
-    async fn status(&self) -> Option<String> {
-        self.status.clone()
+    async fn status(&self) -> Option<MangaStatus> {
+        self.status.map(MangaStatus::from)
+    }
+
+    async fn content_rating(&self) -> Option<ContentRating> {
+        self.content_rating.map(ContentRating::from)
+    }
+
+    async fn external_ids(&self) -> Vec<ExternalId> {
+        self.external_ids
+            .iter()
+            .map(|(site, id)| ExternalId {
+                site: site.clone(),
+                id: id.clone(),
+            })
+            .collect()
     }
 
     async fn description(&self) -> Option<String> {
@@ -178,16 +240,36 @@ impl Manga {
         Ok(source)
     }
 
+    async fn related(&self, ctx: &Context<'_>) -> Result<Vec<Manga>> {
+        let manga = ctx
+            .data::<MangaService<MangaRepositoryImpl>>()?
+            .fetch_related_manga(self.source_id, &self.path)
+            .await?
+            .into_par_iter()
+            .map(Manga::from)
+            .collect();
+
+        Ok(manga)
+    }
+
     async fn chapters(
         &self,
         ctx: &Context<'_>,
         #[graphql(desc = "refresh data from source", default = false)] refresh: bool,
+        #[graphql(desc = "only chapters in these languages, chapters without language are kept")]
+        languages: Option<Vec<String>>,
+        #[graphql(desc = "only chapters in this volume")] volume: Option<String>,
     ) -> Result<Vec<Chapter>> {
         let chapters = ctx
             .data::<ChapterService<ChapterRepositoryImpl>>()?
             .fetch_chapters_by_manga_id(self.source_id, &self.path, self.id, refresh)
             .await?
             .into_par_iter()
+            .filter(|chapter| match (&languages, &chapter.language) {
+                (Some(languages), Some(language)) => languages.contains(language),
+                _ => true,
+            })
+            .filter(|chapter| volume.is_none() || chapter.volume == volume)
             .map(Into::into)
             .collect::<Vec<Chapter>>();
 
@@ -11,7 +11,7 @@ use super::{
     downloads::{DownloadMutationRoot, DownloadRoot},
     library::{LibraryMutationRoot, LibraryRoot, LibrarySubscriptionRoot},
     notification::NotificationRoot,
-    source::{SourceMutationRoot, SourceRoot},
+    source::{SourceMutationRoot, SourceRoot, SourceSubscriptionRoot},
     status::StatusRoot,
     tracking::{TrackingMutationRoot, TrackingRoot},
     user::{UserMutationRoot, UserRoot},
@@ -47,7 +47,7 @@ pub struct MutationRoot(
 );
 
 #[derive(MergedSubscription, Default)]
-pub struct SubscriptionRoot(LibrarySubscriptionRoot);
+pub struct SubscriptionRoot(LibrarySubscriptionRoot, SourceSubscriptionRoot);
 
 pub type DatabaseLoader = crate::presentation::graphql::loader::DatabaseLoader<
     HistoryRepositoryImpl,
@@ -1,13 +1,23 @@
-use super::{common::InputList, guard::AdminGuard};
+use super::{
+    common::{InputList, PreferenceValue, PreferenceValues},
+    guard::AdminGuard,
+};
 use crate::{
     domain::services::source::SourceService,
     infrastructure::{
         auth::Claims, config::Config, domain::repositories::source::SourceRepositoryImpl,
     },
 };
-use async_graphql::{Context, Object, Result};
+use async_graphql::{
+    Context, Error, ErrorExtensions, InputObject, Object, Result, SimpleObject, Subscription,
+};
+use futures::{Stream, StreamExt};
 use serde::Deserialize;
-use tanoshi_vm::extension::ExtensionManager;
+use tanoshi_lib::prelude::{Capabilities, Credentials, PreferenceErrors, PreferenceKind};
+use tanoshi_vm::extension::{
+    ExtensionManager, ExtensionReloadKind, OperationLatency, RequestOverride, RequestOverrideKind,
+    SandboxLayer, SandboxLayerStatus, SandboxState, SourceHealthReport, SourceHealthState,
+};
 
 #[derive(Clone, Deserialize)]
 pub struct Source {
@@ -20,6 +30,180 @@ pub struct Source {
     pub icon: String,
     #[serde(default)]
     pub has_update: bool,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    #[serde(default)]
+    pub repository: Option<String>,
+}
+
+/// Modes a source supports, clients should hide the rest
+#[derive(Debug, SimpleObject)]
+pub struct SourceCapabilities {
+    pub popular: bool,
+    pub latest: bool,
+    pub search: bool,
+    pub search_requires_query: bool,
+    pub filters: bool,
+    pub requires_login: bool,
+}
+
+impl From<Capabilities> for SourceCapabilities {
+    fn from(capabilities: Capabilities) -> Self {
+        Self {
+            popular: capabilities.popular,
+            latest: capabilities.latest,
+            search: capabilities.search,
+            search_requires_query: capabilities.search_requires_query,
+            filters: capabilities.filters,
+            requires_login: capabilities.requires_login,
+        }
+    }
+}
+
+/// A hardening layer of the source's worker process
+#[derive(Debug, SimpleObject)]
+pub struct SourceSandboxLayer {
+    /// `memoryLimit`, `cpuTimeLimit`, `openFileLimit`, `seccomp`, `landlock`
+    /// or `privateNetwork`
+    pub layer: String,
+    /// `applied`, `disabled` or `unavailable`
+    pub state: String,
+    /// Why an enabled layer could not be applied
+    pub reason: Option<String>,
+}
+
+impl From<SandboxLayerStatus> for SourceSandboxLayer {
+    fn from(status: SandboxLayerStatus) -> Self {
+        let layer = match status.layer {
+            SandboxLayer::MemoryLimit => "memoryLimit",
+            SandboxLayer::CpuTimeLimit => "cpuTimeLimit",
+            SandboxLayer::OpenFileLimit => "openFileLimit",
+            SandboxLayer::Seccomp => "seccomp",
+            SandboxLayer::Landlock => "landlock",
+            SandboxLayer::PrivateNetwork => "privateNetwork",
+        };
+        let (state, reason) = match status.state {
+            SandboxState::Applied => ("applied", None),
+            SandboxState::Disabled => ("disabled", None),
+            SandboxState::Unavailable(reason) => ("unavailable", Some(reason)),
+        };
+        Self {
+            layer: layer.to_string(),
+            state: state.to_string(),
+            reason,
+        }
+    }
+}
+
+/// Health of a source and its most recent calls
+#[derive(Debug, SimpleObject)]
+pub struct SourceHealth {
+    /// `healthy`, `degraded`, `circuitOpen` or `quarantined`, the last two
+    /// reject calls
+    pub state: String,
+    /// Timed-out calls still running in process
+    pub abandoned_calls: i64,
+    pub recent_calls: i64,
+    pub recent_errors: i64,
+    /// Share of the recent calls that failed, from 0 to 1
+    pub error_rate: f64,
+    pub operations: Vec<SourceOperationLatency>,
+    pub last_error: Option<String>,
+    /// Unix timestamp of `last_error`
+    pub last_error_at: Option<i64>,
+    pub worker_restarts: i64,
+}
+
+impl From<SourceHealthReport> for SourceHealth {
+    fn from(report: SourceHealthReport) -> Self {
+        let state = match report.state {
+            SourceHealthState::Healthy => "healthy",
+            SourceHealthState::Degraded => "degraded",
+            SourceHealthState::CircuitOpen => "circuitOpen",
+            SourceHealthState::Quarantined => "quarantined",
+        };
+        Self {
+            state: state.to_string(),
+            abandoned_calls: report.abandoned_calls as i64,
+            recent_calls: report.recent_calls as i64,
+            recent_errors: report.recent_errors as i64,
+            error_rate: report.error_rate(),
+            operations: report
+                .operations
+                .into_iter()
+                .map(SourceOperationLatency::from)
+                .collect(),
+            last_error: report.last_error,
+            last_error_at: report.last_error_at,
+            worker_restarts: report.worker_restarts as i64,
+        }
+    }
+}
+
+/// Latency of an operation's recent calls, in milliseconds
+#[derive(Debug, SimpleObject)]
+pub struct SourceOperationLatency {
+    pub operation: String,
+    pub calls: i64,
+    pub p50: f64,
+    pub p95: f64,
+}
+
+impl From<OperationLatency> for SourceOperationLatency {
+    fn from(latency: OperationLatency) -> Self {
+        Self {
+            operation: latency.operation.to_string(),
+            calls: latency.calls as i64,
+            p50: latency.p50.as_secs_f64() * 1000.0,
+            p95: latency.p95.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// A cookie or header an admin set for a source, sent with every request it
+/// makes, e.g. clearance cookies of an anti-bot challenge
+#[derive(Debug, SimpleObject)]
+pub struct SourceRequestOverride {
+    /// `cookie` or `header`
+    pub kind: String,
+    pub name: String,
+    pub value: String,
+    /// Unix timestamp after which the override is no longer sent
+    pub expires_at: Option<i64>,
+    pub expired: bool,
+}
+
+impl From<RequestOverride> for SourceRequestOverride {
+    fn from(request_override: RequestOverride) -> Self {
+        Self {
+            kind: request_override.kind.as_str().to_string(),
+            expired: request_override.is_expired(chrono::Utc::now().timestamp()),
+            name: request_override.name,
+            value: request_override.value,
+            expires_at: request_override.expires_at,
+        }
+    }
+}
+
+/// An install, update, rollback or uninstall of a source
+#[derive(Debug, SimpleObject)]
+pub struct SourceInstallEvent {
+    /// `install`, `update`, `rollback` or `uninstall`
+    pub event: String,
+    pub version: String,
+    pub previous_version: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl From<crate::domain::entities::source::SourceInstallEvent> for SourceInstallEvent {
+    fn from(event: crate::domain::entities::source::SourceInstallEvent) -> Self {
+        Self {
+            event: event.event,
+            version: event.version,
+            previous_version: event.previous_version,
+            created_at: event.created_at,
+        }
+    }
 }
 
 impl From<crate::domain::entities::source::Source> for Source {
@@ -33,6 +217,8 @@ impl From<crate::domain::entities::source::Source> for Source {
             lib_version: s.lib_version,
             icon: s.icon,
             has_update: s.has_update,
+            capabilities: s.capabilities,
+            repository: s.repository,
         }
     }
 }
@@ -63,6 +249,90 @@ impl Source {
         self.has_update
     }
 
+    async fn capabilities(&self) -> SourceCapabilities {
+        self.capabilities.into()
+    }
+
+    /// Name of the repository the source is available from, for installed
+    /// sources only known when checking for updates
+    async fn repository(&self) -> Option<String> {
+        self.repository.clone()
+    }
+
+    /// Version the source is pinned to, pinned sources are not updated
+    async fn pinned_version(&self, ctx: &Context<'_>) -> Result<Option<String>> {
+        let version = ctx
+            .data::<SourceService<SourceRepositoryImpl>>()?
+            .get_pinned_version(self.id)
+            .await?;
+
+        Ok(version)
+    }
+
+    /// Replaced versions the source can be rolled back to, most recent first
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn versions(&self, ctx: &Context<'_>) -> Result<Vec<String>> {
+        let versions = ctx
+            .data::<SourceService<SourceRepositoryImpl>>()?
+            .get_source_versions(self.id)
+            .await?;
+
+        Ok(versions)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn install_history(&self, ctx: &Context<'_>) -> Result<Vec<SourceInstallEvent>> {
+        let events = ctx
+            .data::<SourceService<SourceRepositoryImpl>>()?
+            .get_install_history(self.id)
+            .await?;
+
+        Ok(events.into_iter().map(SourceInstallEvent::from).collect())
+    }
+
+    async fn is_logged_in(&self, ctx: &Context<'_>) -> Result<bool> {
+        let is_logged_in = ctx
+            .data::<SourceService<SourceRepositoryImpl>>()?
+            .is_logged_in(self.id)
+            .await?;
+
+        Ok(is_logged_in)
+    }
+
+    /// Sandbox layers of the source's worker process, empty when it runs
+    /// unsandboxed
+    async fn sandbox(&self, ctx: &Context<'_>) -> Result<Vec<SourceSandboxLayer>> {
+        let status = ctx.data::<ExtensionManager>()?.sandbox_status(self.id)?;
+
+        Ok(status.into_iter().map(SourceSandboxLayer::from).collect())
+    }
+
+    async fn health(&self, ctx: &Context<'_>) -> Result<SourceHealth> {
+        let report = ctx.data::<ExtensionManager>()?.source_health(self.id)?;
+
+        Ok(report.into())
+    }
+
+    /// Whether the last response the source got was an anti-bot challenge
+    /// page
+    async fn is_challenged(&self, ctx: &Context<'_>) -> Result<bool> {
+        Ok(ctx.data::<ExtensionManager>()?.is_challenged(self.id))
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn request_overrides(&self, ctx: &Context<'_>) -> Result<Vec<SourceRequestOverride>> {
+        let secret = &ctx.data::<Config>()?.secret;
+        let overrides = ctx
+            .data::<SourceService<SourceRepositoryImpl>>()?
+            .get_request_overrides(secret, self.id)
+            .await?;
+
+        Ok(overrides
+            .into_iter()
+            .map(SourceRequestOverride::from)
+            .collect())
+    }
+
     async fn filters(&self, ctx: &Context<'_>) -> Result<InputList> {
         let filters = ctx.data::<ExtensionManager>()?.filter_list(self.id).await?;
 
@@ -77,6 +347,92 @@ impl Source {
 
         Ok(InputList(preferences))
     }
+
+    async fn preference_schema(&self, ctx: &Context<'_>) -> Result<Vec<Preference>> {
+        let manager = ctx.data::<ExtensionManager>()?;
+        let schema = manager.get_preference_schema(self.id).await?;
+        if schema.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut values = manager.get_preference_values(self.id).await?;
+
+        Ok(schema
+            .into_iter()
+            .map(|preference| Preference {
+                value: values.remove(&preference.key),
+                preference,
+            })
+            .collect())
+    }
+}
+
+/// A typed source preference with its current value, secrets are masked
+pub struct Preference {
+    pub preference: tanoshi_lib::prelude::Preference,
+    pub value: Option<tanoshi_lib::prelude::PreferenceValue>,
+}
+
+#[Object]
+impl Preference {
+    async fn key(&self) -> String {
+        self.preference.key.clone()
+    }
+
+    async fn name(&self) -> String {
+        self.preference.name.clone()
+    }
+
+    async fn description(&self) -> Option<String> {
+        self.preference.description.clone()
+    }
+
+    async fn kind(&self) -> String {
+        self.preference.kind.as_str().to_string()
+    }
+
+    async fn required(&self) -> bool {
+        self.preference.required
+    }
+
+    async fn secret(&self) -> bool {
+        self.preference.is_secret()
+    }
+
+    async fn max_length(&self) -> Option<i64> {
+        match self.preference.kind {
+            PreferenceKind::Text { max_length } => max_length.map(|max_length| max_length as i64),
+            _ => None,
+        }
+    }
+
+    async fn min(&self) -> Option<i64> {
+        match self.preference.kind {
+            PreferenceKind::Integer { min, .. } => min,
+            _ => None,
+        }
+    }
+
+    async fn max(&self) -> Option<i64> {
+        match self.preference.kind {
+            PreferenceKind::Integer { max, .. } => max,
+            _ => None,
+        }
+    }
+
+    async fn options(&self) -> Vec<String> {
+        match &self.preference.kind {
+            PreferenceKind::Select { options } => options.clone(),
+            _ => vec![],
+        }
+    }
+
+    async fn default_value(&self) -> Option<PreferenceValue> {
+        self.preference.default.clone().map(PreferenceValue)
+    }
+
+    async fn value(&self) -> Option<PreferenceValue> {
+        self.value.clone().map(PreferenceValue)
+    }
 }
 
 #[derive(Default)]
@@ -91,11 +447,11 @@ impl SourceRoot {
     ) -> Result<Vec<Source>> {
         let _ = ctx.data::<Claims>()?;
 
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
 
         let sources = ctx
             .data::<SourceService<SourceRepositoryImpl>>()?
-            .get_installed_sources(repo_url, check_update)
+            .get_installed_sources(repositories, check_update)
             .await?
             .into_iter()
             .map(Source::from)
@@ -107,11 +463,11 @@ impl SourceRoot {
     async fn available_sources(&self, ctx: &Context<'_>) -> Result<Vec<Source>> {
         let _ = ctx.data::<Claims>()?;
 
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
 
         let sources = ctx
             .data::<SourceService<SourceRepositoryImpl>>()?
-            .get_available_sources(repo_url)
+            .get_available_sources(repositories)
             .await?
             .into_iter()
             .map(Source::from)
@@ -133,6 +489,14 @@ impl SourceRoot {
     }
 }
 
+/// Another field a source's login form asks for, e.g. a one-time code
+#[derive(InputObject)]
+pub struct CredentialFieldInput {
+    pub name: String,
+    #[graphql(secret)]
+    pub value: String,
+}
+
 #[derive(Default)]
 pub struct SourceMutationRoot;
 
@@ -144,11 +508,15 @@ impl SourceMutationRoot {
             return Err("source installed, use updateSource to update".into());
         }
 
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
 
-        ctx.data::<SourceService<SourceRepositoryImpl>>()?
-            .install_source(repo_url, source_id)
-            .await?;
+        let source_svc = ctx.data::<SourceService<SourceRepositoryImpl>>()?;
+        source_svc.install_source(repositories, source_id).await?;
+
+        let secret = &ctx.data::<Config>()?.secret;
+        if let Err(e) = source_svc.restore_login(secret, source_id).await {
+            warn!("failed to restore login for source {source_id}: {e}");
+        }
 
         Ok(source_id)
     }
@@ -164,10 +532,54 @@ impl SourceMutationRoot {
 
     #[graphql(guard = "AdminGuard::new()")]
     async fn update_source(&self, ctx: &Context<'_>, source_id: i64) -> Result<i64> {
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
+
+        let source_svc = ctx.data::<SourceService<SourceRepositoryImpl>>()?;
+        source_svc.update_source(repositories, source_id).await?;
+
+        let secret = &ctx.data::<Config>()?.secret;
+        if let Err(e) = source_svc.restore_login(secret, source_id).await {
+            warn!("failed to restore login for source {source_id}: {e}");
+        }
+
+        Ok(source_id)
+    }
 
+    /// Reinstall a version listed in `Source.versions`, a pinned source stays
+    /// pinned to it
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn rollback_source(
+        &self,
+        ctx: &Context<'_>,
+        source_id: i64,
+        version: String,
+    ) -> Result<i64> {
+        let source_svc = ctx.data::<SourceService<SourceRepositoryImpl>>()?;
+        source_svc.rollback_source(source_id, &version).await?;
+
+        let secret = &ctx.data::<Config>()?.secret;
+        if let Err(e) = source_svc.restore_login(secret, source_id).await {
+            warn!("failed to restore login for source {source_id}: {e}");
+        }
+
+        Ok(source_id)
+    }
+
+    /// Keep the source on its installed version, updates skip it until it is
+    /// unpinned
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn pin_source(&self, ctx: &Context<'_>, source_id: i64) -> Result<i64> {
         ctx.data::<SourceService<SourceRepositoryImpl>>()?
-            .update_source(repo_url, source_id)
+            .pin_source(source_id)
+            .await?;
+
+        Ok(source_id)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn unpin_source(&self, ctx: &Context<'_>, source_id: i64) -> Result<i64> {
+        ctx.data::<SourceService<SourceRepositoryImpl>>()?
+            .unpin_source(source_id)
             .await?;
 
         Ok(source_id)
@@ -178,12 +590,182 @@ impl SourceMutationRoot {
         &self,
         ctx: &Context<'_>,
         source_id: i64,
-        preferences: InputList,
+        preferences: Option<InputList>,
+        values: Option<PreferenceValues>,
     ) -> Result<i64> {
-        ctx.data::<ExtensionManager>()?
-            .set_preferences(source_id, preferences.0)
+        let manager = ctx.data::<ExtensionManager>()?;
+        match (preferences, values) {
+            (_, Some(values)) => manager
+                .set_preference_values(source_id, values.0)
+                .await
+                .map_err(preference_error)?,
+            (Some(preferences), None) => manager.set_preferences(source_id, preferences.0).await?,
+            (None, None) => return Err("either preferences or values is required".into()),
+        }
+
+        Ok(source_id)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn source_login(
+        &self,
+        ctx: &Context<'_>,
+        source_id: i64,
+        username: String,
+        #[graphql(secret)] password: String,
+        #[graphql(default)] extra: Vec<CredentialFieldInput>,
+    ) -> Result<i64> {
+        let secret = &ctx.data::<Config>()?.secret;
+        let mut credentials = Credentials::new(username, password);
+        credentials.extra = extra
+            .into_iter()
+            .map(|field| (field.name, field.value))
+            .collect();
+        ctx.data::<SourceService<SourceRepositoryImpl>>()?
+            .login(secret, source_id, credentials)
             .await?;
 
         Ok(source_id)
     }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn source_logout(&self, ctx: &Context<'_>, source_id: i64) -> Result<i64> {
+        ctx.data::<SourceService<SourceRepositoryImpl>>()?
+            .logout(source_id)
+            .await?;
+
+        Ok(source_id)
+    }
+
+    /// Let a quarantined source accept calls again
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn unquarantine_source(&self, ctx: &Context<'_>, source_id: i64) -> Result<i64> {
+        ctx.data::<ExtensionManager>()?.unquarantine(source_id)?;
+
+        Ok(source_id)
+    }
+
+    /// Replace the worker processes of a source and lift its quarantine
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn restart_source(&self, ctx: &Context<'_>, source_id: i64) -> Result<i64> {
+        ctx.data::<ExtensionManager>()?.restart(source_id).await?;
+
+        Ok(source_id)
+    }
+
+    /// Set a cookie or header sent with every request of the source,
+    /// replacing one of the same kind and name
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn set_source_request_override(
+        &self,
+        ctx: &Context<'_>,
+        source_id: i64,
+        #[graphql(desc = "`cookie` or `header`")] kind: String,
+        name: String,
+        value: String,
+        #[graphql(desc = "unix timestamp after which the override is no longer sent")]
+        expires_at: Option<i64>,
+    ) -> Result<i64> {
+        let kind = kind.parse::<RequestOverrideKind>()?;
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err("name cannot be empty".into());
+        }
+
+        let secret = &ctx.data::<Config>()?.secret;
+        ctx.data::<SourceService<SourceRepositoryImpl>>()?
+            .set_request_override(
+                secret,
+                source_id,
+                RequestOverride {
+                    kind,
+                    name,
+                    value,
+                    expires_at,
+                },
+            )
+            .await?;
+
+        Ok(source_id)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn delete_source_request_override(
+        &self,
+        ctx: &Context<'_>,
+        source_id: i64,
+        kind: String,
+        name: String,
+    ) -> Result<i64> {
+        let kind = kind.parse::<RequestOverrideKind>()?;
+
+        let secret = &ctx.data::<Config>()?.secret;
+        ctx.data::<SourceService<SourceRepositoryImpl>>()?
+            .delete_request_override(secret, source_id, kind, &name)
+            .await?;
+
+        Ok(source_id)
+    }
+}
+
+/// Field errors are listed in the `fields` extension as `{ key, message }`
+fn preference_error(error: anyhow::Error) -> Error {
+    match error.downcast_ref::<PreferenceErrors>() {
+        Some(errors) => Error::new(errors.to_string()).extend_with(|_, e| {
+            e.set(
+                "fields",
+                async_graphql::to_value(&errors.0).unwrap_or_default(),
+            )
+        }),
+        None => error.into(),
+    }
+}
+
+/// A file in the plugin directory the server applied after it changed
+#[derive(Debug, SimpleObject)]
+pub struct ExtensionReload {
+    /// Name of the changed file
+    pub file: String,
+    pub source_id: Option<i64>,
+    /// `loaded`, `reloaded` or `unloaded`
+    pub kind: String,
+    /// Why the change was not applied, the previous version keeps serving
+    pub error: Option<String>,
+}
+
+impl From<tanoshi_vm::extension::ExtensionReload> for ExtensionReload {
+    fn from(reload: tanoshi_vm::extension::ExtensionReload) -> Self {
+        let kind = match reload.kind {
+            ExtensionReloadKind::Loaded => "loaded",
+            ExtensionReloadKind::Reloaded => "reloaded",
+            ExtensionReloadKind::Unloaded => "unloaded",
+        };
+        Self {
+            file: reload.file,
+            source_id: reload.source_id,
+            kind: kind.to_string(),
+            error: reload.error,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SourceSubscriptionRoot;
+
+#[Subscription]
+impl SourceSubscriptionRoot {
+    /// Template sources, and extensions with `extension.hot_reload`, reloaded
+    /// after their files changed in the plugin directory
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn extension_reloads_subscription(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<impl Stream<Item = ExtensionReload> + use<>> {
+        let receiver = ctx.data::<ExtensionManager>()?.subscribe_reloads();
+
+        let stream = tokio_stream::wrappers::BroadcastStream::new(receiver)
+            .filter_map(|reload| async move { reload.ok().map(ExtensionReload::from) });
+
+        Ok(stream)
+    }
 }
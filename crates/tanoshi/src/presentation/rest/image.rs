@@ -31,10 +31,14 @@ pub async fn fetch_image(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Response::builder()
+    let mut response = Response::builder()
         .header("Content-Type", image.content_type)
-        .header("Content-Length", image.data.len())
-        .header("Cache-Control", "max-age=864000")
-        .body(Body::from(image.data))
+        .header("Cache-Control", "max-age=864000");
+    if let Some(content_length) = image.content_length {
+        response = response.header("Content-Length", content_length);
+    }
+
+    response
+        .body(Body::from_stream(image.data))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }